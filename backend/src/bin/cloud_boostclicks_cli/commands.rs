@@ -0,0 +1,119 @@
+use cloud_boostclicks::{
+    client::api::ApiClient, models::files::FSElement, schemas::files::BatchOperation,
+};
+
+use crate::{
+    errors::{CliError, CliResult},
+    remote::Remote,
+};
+
+fn print_element(element: &FSElement) {
+    let kind = if element.is_file { "-" } else { "d" };
+    println!(
+        "{kind}\t{}\t{}\t{}",
+        element.size,
+        element.updated_at.format("%Y-%m-%d %H:%M:%S"),
+        element.name
+    );
+}
+
+/// Clouds of the user without a path, otherwise the folder or the file, one per line with tabs between columns
+pub async fn ls(api: &ApiClient, remote: Option<&str>) -> CliResult<()> {
+    let Some(remote) = remote else {
+        for storage in api.storages().await? {
+            println!(
+                "{}\t{}\t{}\t{}",
+                storage.id, storage.files_amount, storage.size, storage.name
+            );
+        }
+        return Ok(());
+    };
+
+    let remote = Remote::resolve(api, remote).await?;
+    if let Some(file) = remote
+        .stat(api, &remote.path)
+        .await?
+        .filter(|element| element.is_file)
+    {
+        print_element(&file);
+        return Ok(());
+    }
+
+    for element in api.list_dir(remote.storage.id, &remote.path).await? {
+        print_element(&element);
+    }
+    Ok(())
+}
+
+pub async fn rm(api: &ApiClient, remote: &str) -> CliResult<()> {
+    let remote = Remote::resolve(api, remote).await?;
+    if remote.path.is_empty() {
+        return Err(CliError::Usage(
+            "the root of a cloud can't be removed".to_string(),
+        ));
+    }
+
+    api.delete(remote.storage.id, &remote.path).await?;
+    Ok(())
+}
+
+/// Moves like mv(1) does, into the destination when it's a folder
+pub async fn mv(api: &ApiClient, remote: &str, destination: &str) -> CliResult<()> {
+    let remote = Remote::resolve(api, remote).await?;
+    let destination = Remote::resolve(api, destination).await?;
+    if remote.storage.id != destination.storage.id {
+        return Err(CliError::Usage(
+            "files can be moved only inside one cloud".to_string(),
+        ));
+    }
+    let name = remote.path.rsplit('/').next().unwrap_or_default();
+    if name.is_empty() {
+        return Err(CliError::Usage(
+            "the root of a cloud can't be moved".to_string(),
+        ));
+    }
+
+    let into_folder = destination.is_folder
+        || destination.path.is_empty()
+        || destination
+            .stat(api, &destination.path)
+            .await?
+            .is_some_and(|element| !element.is_file);
+    let destination_path = match into_folder {
+        true => destination.join(name),
+        false => destination.path.clone(),
+    };
+
+    api.batch(
+        remote.storage.id,
+        vec![BatchOperation::Move {
+            path: remote.path,
+            destination_path,
+            if_match: None,
+            overwrite: false,
+        }],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Prints the public link the web interface gives for the file or the folder
+pub async fn share(api: &ApiClient, remote: &str) -> CliResult<()> {
+    let remote = Remote::resolve(api, remote).await?;
+    let is_folder = match remote.stat(api, &remote.path).await? {
+        Some(element) => !element.is_file,
+        None if remote.path.is_empty() => true,
+        None => return Err(CliError::DoesNotExist(remote.path)),
+    };
+    let path = match is_folder {
+        true => format!("{}/", remote.path),
+        false => remote.path,
+    };
+
+    let share_id = api
+        .create_share(remote.storage.id, &path, is_folder)
+        .await?;
+    let origin = api.base_url().trim_end_matches("/api");
+    println!("{origin}/share/{share_id}");
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::{env, fs, io::Write, path::PathBuf};
+
+use cloud_boostclicks::client::api::ApiClient;
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::errors::{CliError, CliResult};
+
+const KEYRING_SERVICE: &str = "cloud_boostclicks";
+
+/// The account the CLI signs in with.
+///
+/// The URL and the email are kept in the config folder, the password only in the keyring of the OS.
+/// `CLOUD_BOOSTCLICKS_URL`, `CLOUD_BOOSTCLICKS_EMAIL`, `CLOUD_BOOSTCLICKS_PASSWORD` and
+/// `CLOUD_BOOSTCLICKS_TOKEN` take precedence, so the CLI works where there is no keyring
+#[derive(Serialize, Deserialize)]
+pub struct Credentials {
+    pub url: String,
+    pub email: String,
+}
+
+impl Credentials {
+    fn path() -> CliResult<PathBuf> {
+        dirs::config_dir()
+            .map(|dir| dir.join("cloud_boostclicks").join("cli.json"))
+            .ok_or_else(|| CliError::Usage("there is no config folder".to_string()))
+    }
+
+    fn keyring_entry(&self) -> CliResult<Entry> {
+        Ok(Entry::new(
+            KEYRING_SERVICE,
+            &format!("{} {}", self.email, self.url),
+        )?)
+    }
+
+    pub fn load() -> CliResult<Option<Self>> {
+        match fs::read(Self::path()?) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the account, the password is kept only if the keyring is available
+    pub fn save(&self, password: &str) -> CliResult<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut options = fs::File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&path)?
+            .write_all(&serde_json::to_vec_pretty(self)?)?;
+
+        if let Err(e) = self.keyring_entry()?.set_password(password) {
+            eprintln!("warning: the password isn't saved ({e}), set CLOUD_BOOSTCLICKS_PASSWORD");
+        }
+        Ok(())
+    }
+
+    pub fn remove() -> CliResult<()> {
+        let Some(credentials) = Self::load()? else {
+            return Ok(());
+        };
+        match credentials.keyring_entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => (),
+            Err(e) => eprintln!("warning: can't remove the password from the keyring: {e}"),
+        }
+        fs::remove_file(Self::path()?)?;
+        Ok(())
+    }
+
+    fn password(&self) -> CliResult<String> {
+        if let Ok(password) = env::var("CLOUD_BOOSTCLICKS_PASSWORD") {
+            return Ok(password);
+        }
+        match self.keyring_entry()?.get_password() {
+            Ok(password) => Ok(password),
+            Err(keyring::Error::NoEntry) => Err(CliError::NotSignedIn),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A client signed in with the token from the environment or with the saved account
+pub fn sign_in(runtime: &Runtime) -> CliResult<ApiClient> {
+    let saved = Credentials::load()?;
+    let url = env::var("CLOUD_BOOSTCLICKS_URL")
+        .ok()
+        .or_else(|| saved.as_ref().map(|credentials| credentials.url.clone()))
+        .ok_or(CliError::NotSignedIn)?;
+
+    if let Ok(token) = env::var("CLOUD_BOOSTCLICKS_TOKEN") {
+        return Ok(ApiClient::new(&url, token));
+    }
+
+    let credentials = match (env::var("CLOUD_BOOSTCLICKS_EMAIL"), saved) {
+        (Ok(email), _) => Credentials { url, email },
+        (Err(_), Some(saved)) => Credentials { url, ..saved },
+        (Err(_), None) => return Err(CliError::NotSignedIn),
+    };
+    let password = credentials.password()?;
+
+    Ok(runtime.block_on(ApiClient::login(
+        &credentials.url,
+        credentials.email,
+        password,
+    ))?)
+}
@@ -0,0 +1,26 @@
+use std::io;
+
+use cloud_boostclicks::client::api::ApiError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("{0}")]
+    Api(#[from] ApiError),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("keyring: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("not signed in, run `cloud_boostclicks_cli login <url>` first")]
+    NotSignedIn,
+    #[error("the file was changed while it was downloaded, run `get` again")]
+    ChangedWhileDownloading,
+    #[error("{0} doesn't exist")]
+    DoesNotExist(String),
+    #[error("{0}")]
+    Usage(String),
+}
+
+pub type CliResult<T> = Result<T, CliError>;
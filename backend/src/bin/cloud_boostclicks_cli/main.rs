@@ -0,0 +1,126 @@
+//! Command line client of the HTTP API, see the README for the usage
+
+mod commands;
+mod credentials;
+mod errors;
+mod remote;
+mod transfers;
+
+use std::{io::Write, path::PathBuf, process};
+
+use clap::{Parser, Subcommand};
+use cloud_boostclicks::client::api::ApiClient;
+use tokio::runtime::Runtime;
+
+use crate::{
+    credentials::Credentials,
+    errors::{CliError, CliResult},
+};
+
+/// Files of cloud.boostclicks from the command line.
+///
+/// Remote paths are written as `cloud:path`, the cloud is given by its name or id
+#[derive(Parser)]
+#[command(name = "cloud_boostclicks_cli", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Signs in, the password is kept in the keyring of the OS
+    Login {
+        /// URL of the API, e.g. https://cloud.example.com/api
+        url: String,
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Forgets the saved account
+    Logout,
+    /// Lists clouds or the content of a folder
+    Ls { remote: Option<String> },
+    /// Uploads a file, an interrupted upload continues from the last sent chunk
+    Put { local: PathBuf, remote: String },
+    /// Downloads a file, an interrupted download continues where it stopped
+    Get {
+        remote: String,
+        local: Option<PathBuf>,
+    },
+    /// Moves a file or a folder into the trash
+    Rm { remote: String },
+    /// Moves or renames a file or a folder inside one cloud
+    Mv { remote: String, destination: String },
+    /// Prints a public link to a file or a folder
+    Share { remote: String },
+    /// Uploads new and changed files of a local folder into a remote one
+    Sync {
+        local: PathBuf,
+        remote: String,
+        /// Moves remote files missing locally into the trash
+        #[arg(long)]
+        delete: bool,
+        /// Prints what would be done
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn login(runtime: &Runtime, url: String, email: Option<String>) -> CliResult<()> {
+    let email = match email {
+        Some(email) => email,
+        None => {
+            print!("Email: ");
+            std::io::stdout().flush()?;
+            let mut email = String::new();
+            std::io::stdin().read_line(&mut email)?;
+            email.trim().to_string()
+        }
+    };
+    let password = rpassword::prompt_password("Password: ")?;
+
+    // checking the password before saving it
+    runtime.block_on(ApiClient::login(&url, email.clone(), password.clone()))?;
+    Credentials { url, email }.save(&password)
+}
+
+fn run(command: Command) -> CliResult<()> {
+    let runtime = Runtime::new()?;
+    let api = match command {
+        Command::Login { url, email } => return login(&runtime, url, email),
+        Command::Logout => return Credentials::remove(),
+        _ => credentials::sign_in(&runtime)?,
+    };
+
+    runtime.block_on(async {
+        match command {
+            Command::Ls { remote } => commands::ls(&api, remote.as_deref()).await,
+            Command::Put { local, remote } => transfers::put(&api, &local, &remote).await,
+            Command::Get { remote, local } => transfers::get(&api, &remote, local.as_deref()).await,
+            Command::Rm { remote } => commands::rm(&api, &remote).await,
+            Command::Mv {
+                remote,
+                destination,
+            } => commands::mv(&api, &remote, &destination).await,
+            Command::Share { remote } => commands::share(&api, &remote).await,
+            Command::Sync {
+                local,
+                remote,
+                delete,
+                dry_run,
+            } => transfers::sync(&api, &local, &remote, delete, dry_run).await,
+            Command::Login { .. } | Command::Logout => Ok(()),
+        }
+    })
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse().command) {
+        eprintln!("error: {e}");
+        let code = match e {
+            CliError::Usage(_) => 2,
+            _ => 1,
+        };
+        process::exit(code);
+    }
+}
@@ -0,0 +1,75 @@
+use cloud_boostclicks::{
+    client::api::ApiClient,
+    models::{files::FSElement, storages::StorageWithInfo},
+};
+
+use crate::errors::{CliError, CliResult};
+
+/// A path in a cloud written as `cloud:path`, the cloud is given by its name or id
+pub struct Remote {
+    pub storage: StorageWithInfo,
+    /// Relative to the root of the cloud, without slashes around
+    pub path: String,
+    /// The path was written with a trailing slash, so it's a folder
+    pub is_folder: bool,
+}
+
+impl Remote {
+    pub async fn resolve(api: &ApiClient, remote: &str) -> CliResult<Self> {
+        let Some((storage, path)) = remote.split_once(':') else {
+            return Err(CliError::Usage(format!(
+                "`{remote}` isn't a remote path, write it as `cloud:path`"
+            )));
+        };
+
+        let mut storages: Vec<StorageWithInfo> = api
+            .storages()
+            .await?
+            .into_iter()
+            .filter(|candidate| candidate.id.to_string() == storage || candidate.name == storage)
+            .collect();
+        let storage = match storages.len() {
+            0 => return Err(CliError::DoesNotExist(format!("cloud `{storage}`"))),
+            1 => storages.remove(0),
+            _ => {
+                return Err(CliError::Usage(format!(
+                    "there are several clouds named `{storage}`, use the id"
+                )))
+            }
+        };
+
+        Ok(Self {
+            storage,
+            path: path.trim_matches('/').to_string(),
+            is_folder: path.ends_with('/'),
+        })
+    }
+
+    pub fn join(&self, name: &str) -> String {
+        join(&self.path, name)
+    }
+
+    /// The file or the folder at the path, `None` for the root and for missing paths
+    pub async fn stat(&self, api: &ApiClient, path: &str) -> CliResult<Option<FSElement>> {
+        let Some(name) = path.rsplit('/').next().filter(|name| !name.is_empty()) else {
+            return Ok(None);
+        };
+        let parent = path
+            .rsplit_once('/')
+            .map(|(parent, _)| parent)
+            .unwrap_or("");
+
+        match api.list_dir(self.storage.id, parent).await {
+            Ok(elements) => Ok(elements.into_iter().find(|element| element.name == name)),
+            Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+pub fn join(parent: &str, name: &str) -> String {
+    match parent {
+        "" => name.to_string(),
+        parent => format!("{parent}/{name}"),
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use cloud_boostclicks::{
+    client::api::{ApiClient, ApiError, UploadChunk, UPLOAD_CHUNK_SIZE},
+    common::hashing::sha256_hex,
+    models::files::FSElement,
+    schemas::files::BatchOperation,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    errors::{CliError, CliResult},
+    remote::{join, Remote},
+};
+
+/// Progress of an upload, it's kept until the last chunk is sent so a new run continues it
+#[derive(Serialize, Deserialize)]
+struct UploadState {
+    local: PathBuf,
+    size: u64,
+    modified: u64,
+    file_id: Option<Uuid>,
+    next_chunk: usize,
+    /// Where the chunks go when the file replaces another one, it's moved in place at the end
+    #[serde(default)]
+    temp_path: Option<String>,
+}
+
+impl UploadState {
+    fn path(storage_id: Uuid, path: &str) -> CliResult<PathBuf> {
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| CliError::Usage("there is no cache folder".to_string()))?
+            .join("cloud_boostclicks")
+            .join("uploads");
+        fs::create_dir_all(&dir)?;
+        Ok(dir.join(sha256_hex(format!("{storage_id}:{path}").as_bytes())))
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&fs::read(path).ok()?).ok()
+    }
+
+    fn save(&self, path: &Path) -> CliResult<()> {
+        Ok(fs::write(path, serde_json::to_vec(self)?)?)
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Uploads the file in chunks, the file which is replaced goes to the trash unless the cloud keeps versions.
+/// It's replaced only once the new content is uploaded, so a failed upload leaves it as it was
+async fn upload_file(
+    api: &ApiClient,
+    remote: &Remote,
+    local: &Path,
+    path: &str,
+    existing: Option<&FSElement>,
+) -> CliResult<()> {
+    let metadata = fs::metadata(local)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let state_path = UploadState::path(remote.storage.id, path)?;
+    let temp_path = (existing.is_some() && !remote.storage.settings.versioning).then(|| {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        join(parent, &format!(".{name}.{}.part", Uuid::new_v4()))
+    });
+    let fresh = UploadState {
+        local: local.canonicalize()?,
+        size: metadata.len(),
+        modified,
+        file_id: None,
+        next_chunk: 0,
+        temp_path,
+    };
+
+    // 0. continuing the upload of the same file or starting over
+    let mut state = match UploadState::load(&state_path) {
+        Some(state)
+            if state.file_id.is_some()
+                && (&state.local, state.size, state.modified)
+                    == (&fresh.local, fresh.size, fresh.modified) =>
+        {
+            eprintln!("continuing {path} from chunk {}", state.next_chunk + 1);
+            state
+        }
+        _ => fresh,
+    };
+    let upload_path = state.temp_path.clone().unwrap_or_else(|| path.to_string());
+
+    // 1. sending chunks, the progress is saved after each one
+    let total_chunks = (state.size.div_ceil(UPLOAD_CHUNK_SIZE as u64) as usize).max(1);
+    let mut file = File::open(local)?;
+    for chunk_index in state.next_chunk..total_chunks {
+        let start = (chunk_index * UPLOAD_CHUNK_SIZE) as u64;
+        let mut chunk = vec![0; (state.size - start).min(UPLOAD_CHUNK_SIZE as u64) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+
+        let file_id = api
+            .upload_chunk(
+                remote.storage.id,
+                UploadChunk {
+                    path: upload_path.clone(),
+                    size: state.size,
+                    file_id: state.file_id,
+                    chunk_index,
+                    total_chunks,
+                    chunk,
+                },
+            )
+            .await?;
+        state.file_id = Some(file_id);
+        state.next_chunk = chunk_index + 1;
+        state.save(&state_path)?;
+        if total_chunks > 1 {
+            eprintln!("{path}: {}/{total_chunks}", chunk_index + 1);
+        }
+    }
+
+    // 2. the replaced file goes to the trash in the same transaction the new one takes its place
+    if let Some(temp_path) = &state.temp_path {
+        let operation = BatchOperation::Move {
+            path: temp_path.clone(),
+            destination_path: path.to_string(),
+            if_match: None,
+            overwrite: true,
+        };
+        api.batch(remote.storage.id, vec![operation]).await?;
+    }
+
+    let _ = fs::remove_file(state_path);
+    Ok(())
+}
+
+/// Uploads a file, a path ending with a slash or an existing folder gets the name of the local file
+pub async fn put(api: &ApiClient, local: &Path, remote: &str) -> CliResult<()> {
+    if fs::metadata(local)?.is_dir() {
+        return Err(CliError::Usage(format!(
+            "{} is a folder, use `sync` for folders",
+            local.display()
+        )));
+    }
+    let remote = Remote::resolve(api, remote).await?;
+    let name = local
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut existing = remote.stat(api, &remote.path).await?;
+    let path = if remote.is_folder
+        || remote.path.is_empty()
+        || existing.as_ref().is_some_and(|element| !element.is_file)
+    {
+        let path = remote.join(&name);
+        existing = remote.stat(api, &path).await?;
+        path
+    } else {
+        remote.path.clone()
+    };
+
+    upload_file(api, &remote, local, &path, existing.as_ref()).await
+}
+
+/// Downloads a file into `<local>.part` first, an existing part is continued with a range request
+pub async fn get(api: &ApiClient, remote: &str, local: Option<&Path>) -> CliResult<()> {
+    let remote = Remote::resolve(api, remote).await?;
+    let element = match remote.stat(api, &remote.path).await? {
+        Some(element) if element.is_file => element,
+        Some(_) => {
+            return Err(CliError::Usage(format!(
+                "{} is a folder, only files are downloaded",
+                remote.path
+            )))
+        }
+        None => return Err(CliError::DoesNotExist(remote.path)),
+    };
+
+    let local = match local {
+        Some(local) if local.is_dir() => local.join(&element.name),
+        Some(local) => local.to_path_buf(),
+        None => PathBuf::from(&element.name),
+    };
+    let mut part_name = local.clone().into_os_string();
+    part_name.push(".part");
+    let part = PathBuf::from(part_name);
+
+    // 0. continuing the part unless it's already too long
+    let size = element.size.max(0) as u64;
+    let mut offset = fs::metadata(&part)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if offset >= size {
+        offset = 0;
+    }
+
+    // 1. downloading, a server without ranges sends the whole file
+    let mut response = api
+        .download_from(remote.storage.id, &remote.path, offset)
+        .await?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        offset = 0;
+    }
+    let mut file = File::options().create(true).append(true).open(&part)?;
+    file.set_len(offset)?;
+    if offset > 0 {
+        eprintln!("continuing {} from {offset} bytes", local.display());
+    }
+    while let Some(chunk) = response.chunk().await.map_err(ApiError::from)? {
+        file.write_all(&chunk)?;
+    }
+    drop(file);
+
+    // 2. checking that the part and the rest are of the same file
+    let changed = match &element.sha256 {
+        Some(sha256) if !remote.storage.settings.client_encrypted => hash_file(&part)? != *sha256,
+        _ => fs::metadata(&part)?.len() != size,
+    };
+    if changed {
+        fs::remove_file(&part)?;
+        return Err(CliError::ChangedWhileDownloading);
+    }
+
+    fs::rename(&part, &local)?;
+    Ok(())
+}
+
+/// Files and folders under the local folder, paths are relative and use `/`
+fn walk_local(root: &Path) -> CliResult<BTreeMap<String, Option<PathBuf>>> {
+    let mut entries = BTreeMap::new();
+    let mut dirs = vec![(root.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = join(&prefix, &name);
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                entries.insert(path.clone(), None);
+                dirs.push((entry.path(), path));
+            } else if file_type.is_file() {
+                entries.insert(path, Some(entry.path()));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Files and folders under the remote folder, paths are relative to it
+async fn walk_remote(api: &ApiClient, remote: &Remote) -> CliResult<BTreeMap<String, FSElement>> {
+    let mut elements = BTreeMap::new();
+    let mut dirs = vec![String::new()];
+
+    while let Some(dir) = dirs.pop() {
+        for element in api
+            .list_dir(remote.storage.id, remote.join(&dir).trim_matches('/'))
+            .await?
+        {
+            let path = join(&dir, &element.name);
+            if !element.is_file {
+                dirs.push(path.clone());
+            }
+            elements.insert(path, element);
+        }
+    }
+    Ok(elements)
+}
+
+/// Whether the remote file is another one than the local, by the hash when the server knows it
+fn is_changed(local: &Path, remote: &FSElement, client_encrypted: bool) -> CliResult<bool> {
+    let metadata = fs::metadata(local)?;
+    if metadata.len() != remote.size.max(0) as u64 {
+        return Ok(true);
+    }
+    match &remote.sha256 {
+        Some(sha256) if !client_encrypted => Ok(hash_file(local)? != *sha256),
+        _ => {
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+            Ok(modified > remote.updated_at.and_utc().timestamp())
+        }
+    }
+}
+
+/// Makes the remote folder look like the local one: uploads new and changed files,
+/// with `delete` moves the ones missing locally into the trash
+pub async fn sync(
+    api: &ApiClient,
+    local: &Path,
+    remote: &str,
+    delete: bool,
+    dry_run: bool,
+) -> CliResult<()> {
+    if !local.is_dir() {
+        return Err(CliError::Usage(format!(
+            "{} isn't a folder",
+            local.display()
+        )));
+    }
+    let remote = Remote::resolve(api, remote).await?;
+    let client_encrypted = remote.storage.settings.client_encrypted;
+
+    // 0. the remote folder is created with its parents
+    let mut missing_root = Vec::new();
+    let mut path = remote.path.as_str();
+    while !path.is_empty() && remote.stat(api, path).await?.is_none() {
+        missing_root.push(path.to_string());
+        path = path
+            .rsplit_once('/')
+            .map(|(parent, _)| parent)
+            .unwrap_or("");
+    }
+    let remote_elements = match missing_root.is_empty() {
+        true => walk_remote(api, &remote).await?,
+        false => BTreeMap::new(),
+    };
+    let local_entries = walk_local(local)?;
+
+    let (mut uploaded, mut skipped, mut deleted) = (0, 0, 0);
+    for folder in missing_root.iter().rev() {
+        let (parent, name) = folder.rsplit_once('/').unwrap_or(("", folder));
+        println!("mkdir {folder}");
+        if !dry_run {
+            api.create_folder(remote.storage.id, parent, name).await?;
+        }
+    }
+
+    // 1. folders first, `BTreeMap` puts parents before their content
+    for (path, local_path) in &local_entries {
+        let remote_path = remote.join(path);
+        let existing = remote_elements.get(path);
+
+        match local_path {
+            None if existing.is_some_and(|element| !element.is_file) => (),
+            None => {
+                println!("mkdir {remote_path}");
+                if !dry_run {
+                    if existing.is_some() {
+                        api.delete(remote.storage.id, &remote_path).await?;
+                    }
+                    let (parent, name) = remote_path.rsplit_once('/').unwrap_or(("", &remote_path));
+                    api.create_folder(remote.storage.id, parent, name).await?;
+                }
+            }
+            Some(local_path) => {
+                let changed = match existing {
+                    Some(element) if element.is_file => {
+                        is_changed(local_path, element, client_encrypted)?
+                    }
+                    _ => true,
+                };
+                if !changed {
+                    skipped += 1;
+                    continue;
+                }
+
+                println!("put {remote_path}");
+                if !dry_run {
+                    // a folder in place of the file goes to the trash as a whole
+                    let existing = match existing {
+                        Some(element) if !element.is_file => {
+                            api.delete(remote.storage.id, &remote_path).await?;
+                            None
+                        }
+                        existing => existing,
+                    };
+                    upload_file(api, &remote, local_path, &remote_path, existing).await?;
+                }
+                uploaded += 1;
+            }
+        }
+    }
+
+    // 2. removing what's gone locally, a removed folder takes its content with it
+    if delete {
+        let mut removed: BTreeSet<&str> = BTreeSet::new();
+        for path in remote_elements.keys() {
+            let under_removed = removed
+                .iter()
+                .any(|folder| path.starts_with(&format!("{folder}/")));
+            if local_entries.contains_key(path) || under_removed {
+                continue;
+            }
+
+            let remote_path = remote.join(path);
+            println!("rm {remote_path}");
+            if !dry_run {
+                api.delete(remote.storage.id, &remote_path).await?;
+            }
+            removed.insert(path);
+            deleted += 1;
+        }
+    }
+
+    eprintln!("uploaded {uploaded}, unchanged {skipped}, removed {deleted}");
+    Ok(())
+}
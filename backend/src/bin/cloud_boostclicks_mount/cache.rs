@@ -0,0 +1,105 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Files are cached in blocks of this size, ranges are requested in whole blocks
+pub const BLOCK_SIZE: u64 = 1024 * 1024;
+
+/// Blocks of downloaded files on the local disk.
+///
+/// Blocks are keyed by the content hash of the file, so a changed file never hits stale blocks.
+/// The least recently used blocks are dropped when the cache grows over its limit
+pub struct BlockCache {
+    dir: PathBuf,
+    max_size: u64,
+    size: u64,
+}
+
+impl BlockCache {
+    pub fn open(dir: &Path, max_size: u64) -> io::Result<Self> {
+        let dir = dir.join("blocks");
+        fs::create_dir_all(&dir)?;
+
+        let mut cache = Self {
+            dir,
+            max_size,
+            size: 0,
+        };
+        cache.size = cache.blocks()?.iter().map(|(_, size, _)| size).sum();
+        cache.prune();
+        Ok(cache)
+    }
+
+    fn path(&self, etag: &str, index: u64) -> PathBuf {
+        self.dir.join(format!("{etag}.{index}"))
+    }
+
+    pub fn contains(&self, etag: &str, index: u64) -> bool {
+        self.path(etag, index).exists()
+    }
+
+    pub fn get(&self, etag: &str, index: u64) -> Option<Vec<u8>> {
+        let path = self.path(etag, index);
+        let data = fs::read(&path).ok()?;
+
+        // the modification time is the last use
+        if let Ok(file) = fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    pub fn put(&mut self, etag: &str, index: u64, data: &[u8]) {
+        if let Err(e) = fs::write(self.path(etag, index), data) {
+            tracing::warn!("can't cache a block: {e}");
+            return;
+        }
+
+        self.size += data.len() as u64;
+        if self.size > self.max_size {
+            self.prune();
+        }
+    }
+
+    /// Paths, sizes and last uses of the cached blocks
+    fn blocks(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut blocks = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            blocks.push((entry.path(), metadata.len(), used));
+        }
+        Ok(blocks)
+    }
+
+    /// Drops the least recently used blocks until the cache takes 3/4 of its limit
+    fn prune(&mut self) {
+        if self.size <= self.max_size {
+            return;
+        }
+
+        let mut blocks = match self.blocks() {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                tracing::warn!("can't list cached blocks: {e}");
+                return;
+            }
+        };
+        blocks.sort_by_key(|(_, _, used)| *used);
+
+        self.size = blocks.iter().map(|(_, size, _)| size).sum();
+        for (path, size, _) in blocks {
+            if self.size <= self.max_size / 4 * 3 {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                self.size -= size;
+            }
+        }
+    }
+}
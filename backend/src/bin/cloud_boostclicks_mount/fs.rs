@@ -0,0 +1,986 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fs::{self, File},
+    io,
+    os::unix::fs::FileExt,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use cloud_boostclicks::{
+    client::api::{ApiClient, ApiError, UploadChunk, UPLOAD_CHUNK_SIZE},
+    common::hashing::sha256_hex,
+    models::files::FSElement,
+    schemas::files::BatchOperation,
+};
+use fuser::{
+    consts::FUSE_ATOMIC_O_TRUNC, FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr,
+    ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs,
+    ReplyWrite, Request, TimeOrNow, FUSE_ROOT_ID,
+};
+use reqwest::StatusCode;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+use crate::cache::{BlockCache, BLOCK_SIZE};
+
+/// The kernel keeps entries and attributes for this long
+const ATTR_TTL: Duration = Duration::from_secs(1);
+/// Read-ahead starts with one block and doubles on sequential reads up to this amount of blocks
+const MAX_READ_AHEAD_BLOCKS: u64 = 32;
+const RENAME_NOREPLACE: u32 = 1;
+
+type FsResult<T> = Result<T, i32>;
+
+fn errno(e: ApiError) -> i32 {
+    tracing::debug!("{e}");
+    match e.status() {
+        Some(StatusCode::NOT_FOUND) => libc::ENOENT,
+        Some(StatusCode::UNAUTHORIZED) | Some(StatusCode::FORBIDDEN) => libc::EACCES,
+        Some(StatusCode::CONFLICT) => libc::EEXIST,
+        Some(StatusCode::BAD_REQUEST) => libc::EINVAL,
+        Some(StatusCode::PAYLOAD_TOO_LARGE) => libc::EFBIG,
+        _ => libc::EIO,
+    }
+}
+
+fn io_errno(e: io::Error) -> i32 {
+    tracing::warn!("{e}");
+    e.raw_os_error().unwrap_or(libc::EIO)
+}
+
+fn join(parent: &str, name: &str) -> String {
+    match parent {
+        "" => name.to_string(),
+        parent => format!("{parent}/{name}"),
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+#[derive(Clone)]
+struct Entry {
+    is_dir: bool,
+    size: u64,
+    mtime: i64,
+    /// Key of the cached blocks, the content hash when the server knows it
+    etag: String,
+}
+
+impl Entry {
+    fn dir() -> Self {
+        Self {
+            is_dir: true,
+            size: 0,
+            mtime: 0,
+            etag: String::new(),
+        }
+    }
+
+    fn file(size: u64) -> Self {
+        Self {
+            is_dir: false,
+            size,
+            mtime: chrono::Utc::now().timestamp(),
+            etag: String::new(),
+        }
+    }
+}
+
+impl From<&FSElement> for Entry {
+    fn from(element: &FSElement) -> Self {
+        Self {
+            is_dir: !element.is_file,
+            size: element.size.max(0) as u64,
+            mtime: element.updated_at.and_utc().timestamp(),
+            etag: element.sha256.clone().unwrap_or_else(|| {
+                sha256_hex(format!("{}:{}", element.path, element.updated_at).as_bytes())
+            }),
+        }
+    }
+}
+
+struct CachedDir {
+    fetched: Instant,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Node ids the kernel knows, the root is always there
+struct Inodes {
+    paths: HashMap<u64, (String, u64)>,
+    ids: HashMap<String, u64>,
+    next: u64,
+}
+
+impl Inodes {
+    fn new() -> Self {
+        Self {
+            paths: HashMap::from([(FUSE_ROOT_ID, (String::new(), 1))]),
+            ids: HashMap::from([(String::new(), FUSE_ROOT_ID)]),
+            next: FUSE_ROOT_ID + 1,
+        }
+    }
+
+    fn path(&self, id: u64) -> FsResult<String> {
+        self.paths
+            .get(&id)
+            .map(|(path, _)| path.clone())
+            .ok_or(libc::ENOENT)
+    }
+
+    /// The id of the path, `lookup` tells that the kernel keeps a reference to it
+    fn id(&mut self, path: &str, lookup: bool) -> u64 {
+        let id = *self.ids.entry(path.to_string()).or_insert_with(|| {
+            self.next += 1;
+            self.next - 1
+        });
+        let (_, lookups) = self.paths.entry(id).or_insert((path.to_string(), 0));
+        if lookup {
+            *lookups += 1;
+        }
+        id
+    }
+
+    fn forget(&mut self, id: u64, amount: u64) {
+        let Some((path, lookups)) = self.paths.get_mut(&id) else {
+            return;
+        };
+        *lookups = lookups.saturating_sub(amount);
+        if *lookups == 0 && id != FUSE_ROOT_ID {
+            self.ids.remove(path.as_str());
+            self.paths.remove(&id);
+        }
+    }
+
+    /// Moves the path and everything under it, the ids stay the same
+    fn rename(&mut self, from: &str, to: &str) {
+        let prefix = format!("{from}/");
+        let moved: Vec<(String, u64)> = self
+            .ids
+            .iter()
+            .filter(|(path, _)| *path == from || path.starts_with(&prefix))
+            .map(|(path, id)| (path.clone(), *id))
+            .collect();
+
+        for (path, id) in moved {
+            let new_path = format!("{to}{}", &path[from.len()..]);
+            self.ids.remove(&path);
+            if let Some(replaced) = self.ids.insert(new_path.clone(), id) {
+                self.paths.remove(&replaced);
+            }
+            if let Some((path, _)) = self.paths.get_mut(&id) {
+                *path = new_path;
+            }
+        }
+    }
+}
+
+/// An opened file, it's read from the cache until it's written.
+///
+/// The first write copies the content into a local spool file which is uploaded on close
+struct OpenFile {
+    path: String,
+    entry: Entry,
+    spool: Option<File>,
+    dirty: bool,
+    read_ahead: u64,
+    next_offset: u64,
+}
+
+pub struct CloudFs {
+    runtime: Runtime,
+    api: ApiClient,
+    storage_id: Uuid,
+    versioning: bool,
+    uid: u32,
+    gid: u32,
+    cache_dir: PathBuf,
+    cache: BlockCache,
+    dir_ttl: Duration,
+    dirs: HashMap<String, CachedDir>,
+    inodes: Inodes,
+    handles: HashMap<u64, OpenFile>,
+    next_handle: u64,
+}
+
+impl CloudFs {
+    /// Files belong to `owner`, the uid and the gid of the mountpoint
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        runtime: Runtime,
+        api: ApiClient,
+        storage_id: Uuid,
+        versioning: bool,
+        owner: (u32, u32),
+        cache_dir: PathBuf,
+        cache: BlockCache,
+        dir_ttl: Duration,
+    ) -> Self {
+        Self {
+            runtime,
+            api,
+            storage_id,
+            versioning,
+            uid: owner.0,
+            gid: owner.1,
+            cache_dir,
+            cache,
+            dir_ttl,
+            dirs: HashMap::new(),
+            inodes: Inodes::new(),
+            handles: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Uploads files which were written but not closed
+    fn shutdown(&mut self) {
+        let handles: Vec<u64> = self.handles.keys().copied().collect();
+        for handle in handles {
+            if let Err(errno) = self.upload(handle) {
+                tracing::error!("can't upload a file left opened: errno {errno}");
+            }
+        }
+    }
+
+    /////////////////////////////////////////////////////////////////////
+    ////    Entries
+    /////////////////////////////////////////////////////////////////////
+
+    /// Members of the folder, listings are kept for `dir_ttl`
+    fn list(&mut self, path: &str) -> FsResult<&BTreeMap<String, Entry>> {
+        let fresh = self
+            .dirs
+            .get(path)
+            .is_some_and(|dir| dir.fetched.elapsed() < self.dir_ttl);
+        if !fresh {
+            let elements = self
+                .runtime
+                .block_on(self.api.list_dir(self.storage_id, path))
+                .map_err(errno)?;
+            let entries = elements
+                .iter()
+                .map(|element| (element.name.clone(), Entry::from(element)))
+                .collect();
+            self.dirs.insert(
+                path.to_string(),
+                CachedDir {
+                    fetched: Instant::now(),
+                    entries,
+                },
+            );
+        }
+
+        Ok(&self.dirs[path].entries)
+    }
+
+    /// Forgets cached listings of the folder and of everything inside of it
+    fn invalidate(&mut self, path: &str) {
+        let prefix = format!("{path}/");
+        self.dirs
+            .retain(|dir, _| dir != path && (path.is_empty() || !dir.starts_with(&prefix)));
+    }
+
+    /// Files being written aren't on the server yet, their local state wins
+    fn local_entry(&self, path: &str) -> Option<Entry> {
+        self.handles
+            .values()
+            .find(|handle| handle.path == path && handle.spool.is_some())
+            .map(|handle| Entry {
+                size: handle
+                    .spool
+                    .as_ref()
+                    .and_then(|spool| spool.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .unwrap_or_default(),
+                ..handle.entry.clone()
+            })
+    }
+
+    fn entry(&mut self, path: &str) -> FsResult<Option<Entry>> {
+        if path.is_empty() {
+            return Ok(Some(Entry::dir()));
+        }
+        if let Some(entry) = self.local_entry(path) {
+            return Ok(Some(entry));
+        }
+
+        let name = path.rsplit('/').next().unwrap_or_default();
+        Ok(self.list(parent(path))?.get(name).cloned())
+    }
+
+    fn attr(&self, id: u64, entry: &Entry) -> FileAttr {
+        let (kind, perm, nlink) = match entry.is_dir {
+            true => (FileType::Directory, 0o755, 2),
+            false => (FileType::RegularFile, 0o644, 1),
+        };
+        let time = UNIX_EPOCH + Duration::from_secs(entry.mtime.max(0) as u64);
+        FileAttr {
+            ino: id,
+            size: entry.size,
+            blocks: entry.size.div_ceil(512),
+            atime: time,
+            mtime: time,
+            ctime: time,
+            crtime: time,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+
+    /// Attributes of the entry, counts the reference the kernel keeps
+    fn lookup_attr(&mut self, path: &str, entry: &Entry) -> FileAttr {
+        let id = self.inodes.id(path, true);
+        self.attr(id, entry)
+    }
+
+    fn child_path(&self, parent_id: u64, name: &OsStr) -> FsResult<String> {
+        let name = name.to_str().ok_or(libc::EINVAL)?;
+        Ok(join(&self.inodes.path(parent_id)?, name))
+    }
+
+    fn lookup_child(&mut self, parent_id: u64, name: &OsStr) -> FsResult<FileAttr> {
+        let path = self.child_path(parent_id, name)?;
+        let entry = self.entry(&path)?.ok_or(libc::ENOENT)?;
+        Ok(self.lookup_attr(&path, &entry))
+    }
+
+    fn attr_of(&mut self, id: u64) -> FsResult<FileAttr> {
+        let path = self.inodes.path(id)?;
+        let entry = self.entry(&path)?.ok_or(libc::ENOENT)?;
+        Ok(self.attr(id, &entry))
+    }
+
+    /// Only the size can be changed, other attributes are accepted and ignored
+    fn set_size(&mut self, id: u64, fh: Option<u64>, size: Option<u64>) -> FsResult<FileAttr> {
+        if let Some(size) = size {
+            let path = self.inodes.path(id)?;
+            let handle = match fh.and_then(|fh| self.handles.get(&fh).map(|handle| (fh, handle))) {
+                Some((fh, handle)) if handle.path == path => fh,
+                _ => self.open_handle(&path, false)?,
+            };
+            self.spool(handle)?.set_len(size).map_err(io_errno)?;
+            self.handles.get_mut(&handle).ok_or(libc::EBADF)?.dirty = true;
+
+            // truncating without an opened file is uploaded at once
+            if Some(handle) != fh {
+                let result = self.upload(handle);
+                self.handles.remove(&handle);
+                result?;
+            }
+        }
+
+        self.attr_of(id)
+    }
+
+    fn make_dir(&mut self, parent_id: u64, name: &OsStr) -> FsResult<FileAttr> {
+        let name = name.to_str().ok_or(libc::EINVAL)?;
+        let parent_path = self.inodes.path(parent_id)?;
+
+        self.runtime
+            .block_on(self.api.create_folder(self.storage_id, &parent_path, name))
+            .map_err(errno)?;
+        self.invalidate(&parent_path);
+
+        let path = join(&parent_path, name);
+        let entry = self.entry(&path)?.unwrap_or_else(Entry::dir);
+        Ok(self.lookup_attr(&path, &entry))
+    }
+
+    fn remove_file(&mut self, parent_id: u64, name: &OsStr) -> FsResult<()> {
+        let path = self.child_path(parent_id, name)?;
+        match self.entry(&path)? {
+            Some(entry) if entry.is_dir => return Err(libc::EISDIR),
+            Some(_) => (),
+            None => return Err(libc::ENOENT),
+        }
+
+        self.runtime
+            .block_on(self.api.delete(self.storage_id, &path))
+            .map_err(errno)?;
+        self.invalidate(parent(&path));
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, parent_id: u64, name: &OsStr) -> FsResult<()> {
+        let path = self.child_path(parent_id, name)?;
+        match self.entry(&path)? {
+            Some(entry) if !entry.is_dir => return Err(libc::ENOTDIR),
+            Some(_) => (),
+            None => return Err(libc::ENOENT),
+        }
+        if !self.list(&path)?.is_empty() {
+            return Err(libc::ENOTEMPTY);
+        }
+
+        self.runtime
+            .block_on(self.api.delete(self.storage_id, &path))
+            .map_err(errno)?;
+        self.invalidate(&path);
+        self.invalidate(parent(&path));
+        Ok(())
+    }
+
+    /// Replaces the destination like rename(2) does, the replaced one goes to the trash
+    /// in the transaction of the move
+    fn move_entry(
+        &mut self,
+        (parent_id, name): (u64, &OsStr),
+        (new_parent_id, new_name): (u64, &OsStr),
+        flags: u32,
+    ) -> FsResult<()> {
+        if flags & !RENAME_NOREPLACE != 0 {
+            return Err(libc::EINVAL);
+        }
+        let path = self.child_path(parent_id, name)?;
+        let new_path = self.child_path(new_parent_id, new_name)?;
+
+        let entry = self.entry(&path)?.ok_or(libc::ENOENT)?;
+        let replaced = self.entry(&new_path)?;
+        if let Some(replaced) = &replaced {
+            if flags & RENAME_NOREPLACE != 0 {
+                return Err(libc::EEXIST);
+            }
+            match (entry.is_dir, replaced.is_dir) {
+                (true, false) => return Err(libc::ENOTDIR),
+                (false, true) => return Err(libc::EISDIR),
+                (true, true) if !self.list(&new_path)?.is_empty() => return Err(libc::ENOTEMPTY),
+                _ => (),
+            }
+        }
+
+        // a file which is still being written exists only here, only the replaced one is removed
+        let is_local = self.local_entry(&path).is_some();
+        let operation = if !is_local
+            || self
+                .list(parent(&path))?
+                .contains_key(path.rsplit('/').next().unwrap_or_default())
+        {
+            Some(BatchOperation::Move {
+                path: path.clone(),
+                destination_path: new_path.clone(),
+                if_match: None,
+                overwrite: replaced.is_some(),
+            })
+        } else {
+            replaced.map(|_| BatchOperation::Delete {
+                path: new_path.clone(),
+                if_match: None,
+            })
+        };
+        if let Some(operation) = operation {
+            self.runtime
+                .block_on(self.api.batch(self.storage_id, vec![operation]))
+                .map_err(errno)?;
+        }
+
+        let prefix = format!("{path}/");
+        for handle in self.handles.values_mut() {
+            if handle.path == path || handle.path.starts_with(&prefix) {
+                handle.path = format!("{new_path}{}", &handle.path[path.len()..]);
+            }
+        }
+        self.inodes.rename(&path, &new_path);
+        for invalidated in [&path, &new_path, parent(&path), parent(&new_path)] {
+            self.invalidate(invalidated);
+        }
+        Ok(())
+    }
+
+    /// Members of the folder with `.` and `..`, files still being written included
+    fn dir_entries(&mut self, id: u64) -> FsResult<Vec<(String, u64, FileType)>> {
+        let path = self.inodes.path(id)?;
+        let mut names: Vec<(String, bool)> = self
+            .list(&path)?
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.is_dir))
+            .collect();
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{path}/")
+        };
+        for handle in self.handles.values() {
+            let local_name = handle
+                .path
+                .strip_prefix(&prefix)
+                .filter(|name| !name.contains('/'));
+            if let Some(name) =
+                local_name.filter(|name| !names.iter().any(|(other, _)| other == name))
+            {
+                names.push((name.to_string(), false));
+            }
+        }
+
+        let parent_id = self.inodes.id(parent(&path), false);
+        let entries = [
+            (".".to_string(), id, FileType::Directory),
+            ("..".to_string(), parent_id, FileType::Directory),
+        ]
+        .into_iter()
+        .chain(names.into_iter().map(|(name, is_dir)| {
+            let child_id = self.inodes.id(&join(&path, &name), false);
+            let kind = match is_dir {
+                true => FileType::Directory,
+                false => FileType::RegularFile,
+            };
+            (name, child_id, kind)
+        }))
+        .collect();
+        Ok(entries)
+    }
+
+    /////////////////////////////////////////////////////////////////////
+    ////    Opened files
+    /////////////////////////////////////////////////////////////////////
+
+    fn open_handle(&mut self, path: &str, truncate: bool) -> FsResult<u64> {
+        let entry = match self.entry(path)? {
+            Some(entry) if entry.is_dir => return Err(libc::EISDIR),
+            Some(entry) => entry,
+            None => return Err(libc::ENOENT),
+        };
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(
+            handle,
+            OpenFile {
+                path: path.to_string(),
+                entry,
+                spool: None,
+                dirty: false,
+                read_ahead: 1,
+                next_offset: 0,
+            },
+        );
+
+        if truncate {
+            self.spool(handle)?.set_len(0).map_err(io_errno)?;
+            self.handles.get_mut(&handle).ok_or(libc::EBADF)?.dirty = true;
+        }
+        Ok(handle)
+    }
+
+    fn open_file(&mut self, id: u64, flags: i32) -> FsResult<u64> {
+        let path = self.inodes.path(id)?;
+        let writable = flags & libc::O_ACCMODE != libc::O_RDONLY;
+
+        self.open_handle(&path, writable && flags & libc::O_TRUNC != 0)
+    }
+
+    fn create_file(&mut self, parent_id: u64, name: &OsStr, flags: i32) -> FsResult<(FileAttr, u64)> {
+        let path = self.child_path(parent_id, name)?;
+
+        let handle = match self.entry(&path)? {
+            Some(_) if flags & libc::O_EXCL != 0 => return Err(libc::EEXIST),
+            Some(_) => self.open_handle(&path, true)?,
+            None => {
+                let handle = self.next_handle;
+                self.next_handle += 1;
+                self.handles.insert(
+                    handle,
+                    OpenFile {
+                        path: path.clone(),
+                        entry: Entry::file(0),
+                        spool: None,
+                        dirty: true,
+                        read_ahead: 1,
+                        next_offset: 0,
+                    },
+                );
+                self.spool(handle)?;
+                handle
+            }
+        };
+
+        let entry = self.local_entry(&path).unwrap_or_else(|| Entry::file(0));
+        Ok((self.lookup_attr(&path, &entry), handle))
+    }
+
+    /// The local copy of the opened file, it's made from the cached blocks on the first use
+    fn spool(&mut self, handle: u64) -> FsResult<&File> {
+        let opened = self.handles.get(&handle).ok_or(libc::EBADF)?;
+        if opened.spool.is_none() {
+            let dir = self.cache_dir.join("spool");
+            fs::create_dir_all(&dir).map_err(io_errno)?;
+            let path = dir.join(Uuid::new_v4().to_string());
+            let spool = File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(io_errno)?;
+            // the file is gone once it's closed
+            let _ = fs::remove_file(&path);
+
+            let (file_path, entry, dirty) =
+                (opened.path.clone(), opened.entry.clone(), opened.dirty);
+            if !dirty {
+                for index in 0..entry.size.div_ceil(BLOCK_SIZE) {
+                    let block = self.block(&file_path, &entry, index, MAX_READ_AHEAD_BLOCKS)?;
+                    spool
+                        .write_all_at(&block, index * BLOCK_SIZE)
+                        .map_err(io_errno)?;
+                }
+            }
+            self.handles.get_mut(&handle).ok_or(libc::EBADF)?.spool = Some(spool);
+        }
+
+        self.handles[&handle].spool.as_ref().ok_or(libc::EBADF)
+    }
+
+    /// A block of the file from the cache, missing blocks are downloaded with the ones after them
+    fn block(
+        &mut self,
+        path: &str,
+        entry: &Entry,
+        index: u64,
+        read_ahead: u64,
+    ) -> FsResult<Vec<u8>> {
+        if let Some(block) = self.cache.get(&entry.etag, index) {
+            return Ok(block);
+        }
+
+        let blocks = entry.size.div_ceil(BLOCK_SIZE);
+        let mut last = index + 1;
+        while last < blocks.min(index + read_ahead) && !self.cache.contains(&entry.etag, last) {
+            last += 1;
+        }
+        let range = index * BLOCK_SIZE..(last * BLOCK_SIZE).min(entry.size);
+
+        let data = self
+            .runtime
+            .block_on(
+                self.api
+                    .download(self.storage_id, path, Some(range.clone())),
+            )
+            .map_err(errno)?;
+        // a server without ranges sends the whole file
+        let data = match data.len() as u64 == entry.size && range.end - range.start != entry.size {
+            true => data.slice(range.start as usize..range.end as usize),
+            false => data,
+        };
+        if data.len() as u64 != range.end - range.start {
+            tracing::warn!("got {} bytes of {path} instead of {range:?}", data.len());
+            return Err(libc::EIO);
+        }
+
+        for (offset, block) in data.chunks(BLOCK_SIZE as usize).enumerate() {
+            self.cache.put(&entry.etag, index + offset as u64, block);
+        }
+        Ok(data[..data.len().min(BLOCK_SIZE as usize)].to_vec())
+    }
+
+    fn read_file(&mut self, handle: u64, offset: u64, size: u64) -> FsResult<Vec<u8>> {
+        let opened = self.handles.get_mut(&handle).ok_or(libc::EBADF)?;
+        if let Some(spool) = &opened.spool {
+            let mut data = vec![0; size as usize];
+            let len = spool.read_at(&mut data, offset).map_err(io_errno)?;
+            data.truncate(len);
+            return Ok(data);
+        }
+
+        opened.read_ahead = match offset == opened.next_offset {
+            true => (opened.read_ahead * 2).min(MAX_READ_AHEAD_BLOCKS),
+            false => 1,
+        };
+        opened.next_offset = offset + size;
+        let (path, entry, read_ahead) =
+            (opened.path.clone(), opened.entry.clone(), opened.read_ahead);
+
+        let end = (offset + size).min(entry.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let mut data = Vec::with_capacity((end - offset) as usize);
+        for index in offset / BLOCK_SIZE..=(end - 1) / BLOCK_SIZE {
+            let block = self.block(&path, &entry, index, read_ahead)?;
+            let start = index * BLOCK_SIZE;
+            let from = (offset.max(start) - start) as usize;
+            let to = ((end - start) as usize).min(block.len());
+            if from < to {
+                data.extend_from_slice(&block[from..to]);
+            }
+        }
+        Ok(data)
+    }
+
+    fn write_file(&mut self, handle: u64, offset: u64, data: &[u8]) -> FsResult<u32> {
+        self.spool(handle)?
+            .write_all_at(data, offset)
+            .map_err(io_errno)?;
+        self.handles.get_mut(&handle).ok_or(libc::EBADF)?.dirty = true;
+        Ok(data.len() as u32)
+    }
+
+    fn release_file(&mut self, handle: u64) -> FsResult<()> {
+        let result = self.upload(handle);
+        self.handles.remove(&handle);
+        result
+    }
+
+    /// Uploads the spool of a written file in chunks, the replaced file goes to the trash
+    /// unless the storage keeps versions
+    fn upload(&mut self, handle: u64) -> FsResult<()> {
+        let Some(opened) = self.handles.get(&handle).filter(|opened| opened.dirty) else {
+            return Ok(());
+        };
+        let (path, spool) = (
+            opened.path.clone(),
+            opened.spool.as_ref().ok_or(libc::EBADF)?,
+        );
+        let size = spool.metadata().map_err(io_errno)?.len();
+
+        let name = path.rsplit('/').next().unwrap_or_default();
+        let exists = self.list(parent(&path))?.contains_key(name);
+        if exists && !self.versioning {
+            self.runtime
+                .block_on(self.api.delete(self.storage_id, &path))
+                .map_err(errno)?;
+        }
+
+        let spool = self.handles[&handle].spool.as_ref().ok_or(libc::EBADF)?;
+        let total_chunks = (size.div_ceil(UPLOAD_CHUNK_SIZE as u64) as usize).max(1);
+        let mut file_id = None;
+        for chunk_index in 0..total_chunks {
+            let start = (chunk_index * UPLOAD_CHUNK_SIZE) as u64;
+            let mut chunk = vec![0; (size - start).min(UPLOAD_CHUNK_SIZE as u64) as usize];
+            spool.read_exact_at(&mut chunk, start).map_err(io_errno)?;
+
+            let uploaded = self.runtime.block_on(self.api.upload_chunk(
+                self.storage_id,
+                UploadChunk {
+                    path: path.clone(),
+                    size,
+                    file_id,
+                    chunk_index,
+                    total_chunks,
+                    chunk,
+                },
+            ));
+            file_id = Some(uploaded.map_err(errno)?);
+        }
+
+        self.invalidate(parent(&path));
+        let opened = self.handles.get_mut(&handle).ok_or(libc::EBADF)?;
+        opened.dirty = false;
+        opened.entry.size = size;
+        Ok(())
+    }
+}
+
+fn reply_empty(reply: ReplyEmpty, result: FsResult<()>) {
+    match result {
+        Ok(()) => reply.ok(),
+        Err(errno) => reply.error(errno),
+    }
+}
+
+fn reply_entry(reply: ReplyEntry, result: FsResult<FileAttr>) {
+    match result {
+        Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+        Err(errno) => reply.error(errno),
+    }
+}
+
+fn reply_attr(reply: ReplyAttr, result: FsResult<FileAttr>) {
+    match result {
+        Ok(attr) => reply.attr(&ATTR_TTL, &attr),
+        Err(errno) => reply.error(errno),
+    }
+}
+
+impl Filesystem for CloudFs {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
+        // `O_TRUNC` comes with OPEN instead of a separate SETATTR when the kernel is able to
+        let _ = config.add_capabilities(FUSE_ATOMIC_O_TRUNC);
+        Ok(())
+    }
+
+    fn destroy(&mut self) {
+        self.shutdown();
+    }
+
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        reply_entry(reply, self.lookup_child(parent, name));
+    }
+
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        self.inodes.forget(ino, nlookup);
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        reply_attr(reply, self.attr_of(ino));
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        reply_attr(reply, self.set_size(ino, fh, size));
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        reply_entry(reply, self.make_dir(parent, name));
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        reply_empty(reply, self.remove_file(parent, name));
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        reply_empty(reply, self.remove_dir(parent, name));
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply_empty(reply, self.move_entry((parent, name), (newparent, newname), flags));
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        match self.open_file(ino, flags) {
+            Ok(handle) => reply.opened(handle, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.read_file(fh, offset.max(0) as u64, u64::from(size)) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        match self.write_file(fh, offset.max(0) as u64, data) {
+            Ok(written) => reply.written(written),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    /// Written files are uploaded when they are closed, so the error gets to close(2)
+    fn flush(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        reply_empty(reply, self.upload(fh));
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        reply_empty(reply, self.release_file(fh));
+    }
+
+    fn fsync(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        reply_empty(reply, self.upload(fh));
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entries = match self.dir_entries(ino) {
+            Ok(entries) => entries,
+            Err(errno) => return reply.error(errno),
+        };
+        for (index, (name, child_id, kind)) in entries.iter().enumerate().skip(offset as usize) {
+            if reply.add(*child_id, index as i64 + 1, *kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let blocks = 1 << 32;
+        reply.statfs(blocks, blocks, blocks, 1 << 20, 1 << 20, 4096, 255, 4096);
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        match self.create_file(parent, name, flags) {
+            Ok((attr, handle)) => reply.created(&ATTR_TTL, &attr, 0, handle, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Mounts a storage via FUSE, see the README for the usage
+
+#[cfg(target_os = "linux")]
+mod cache;
+#[cfg(target_os = "linux")]
+mod fs;
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("cloud_boostclicks_mount works only on Linux");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use std::{
+        env,
+        os::unix::fs::MetadataExt,
+        path::{Path, PathBuf},
+        process::{self, Command},
+        str::FromStr,
+        time::Duration,
+    };
+
+    use cloud_boostclicks::client::api::ApiClient;
+    use fuser::{MountOption, Session};
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+    use uuid::Uuid;
+
+    fn env_or<T: FromStr>(name: &str, default: T) -> T {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn fail(message: impl std::fmt::Display) -> ! {
+        tracing::error!("{message}");
+        process::exit(1);
+    }
+
+    /// Unmounts lazily, so it works while files are still opened
+    fn unmount(mountpoint: &Path) -> std::io::Result<()> {
+        let status = ["fusermount3", "fusermount"]
+            .into_iter()
+            .find_map(|fusermount| {
+                Command::new(fusermount)
+                    .args(["-u", "-z", "--"])
+                    .arg(mountpoint)
+                    .status()
+                    .ok()
+            })
+            .ok_or_else(|| std::io::Error::other("fusermount isn't found"))?;
+
+        match status.success() {
+            true => Ok(()),
+            false => Err(std::io::Error::other(format!("fusermount failed: {status}"))),
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "cloud_boostclicks_mount=info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .init();
+
+    let args: Vec<String> = env::args().collect();
+    let [_, base_url, storage_id, mountpoint] = args.as_slice() else {
+        eprintln!("usage: cloud_boostclicks_mount <api url> <storage id> <mountpoint>");
+        process::exit(2);
+    };
+    let Ok(storage_id) = Uuid::parse_str(storage_id) else {
+        fail("storage id isn't a uuid");
+    };
+    let mountpoint = PathBuf::from(mountpoint);
+    let owner = std::fs::metadata(&mountpoint)
+        .map(|metadata| (metadata.uid(), metadata.gid()))
+        .unwrap_or_else(|e| fail(format!("can't open the mountpoint: {e}")));
+
+    let cache_dir = env::var_os("CLOUD_BOOSTCLICKS_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .map(|dir| dir.join("cloud_boostclicks"))
+        })
+        .unwrap_or_else(|| fail("set CLOUD_BOOSTCLICKS_CACHE_DIR"))
+        .join(storage_id.to_string());
+    let cache_size = env_or::<u64>("CLOUD_BOOSTCLICKS_CACHE_SIZE", 1024) * 1024 * 1024;
+    let dir_ttl = Duration::from_secs(env_or("CLOUD_BOOSTCLICKS_DIR_TTL", 10));
+
+    let runtime = tokio::runtime::Runtime::new().unwrap_or_else(|e| fail(e));
+
+    // 0. signing in
+    let api = match env::var("CLOUD_BOOSTCLICKS_TOKEN") {
+        Ok(token) => ApiClient::new(base_url, token),
+        Err(_) => {
+            let (Ok(email), Ok(password)) = (
+                env::var("CLOUD_BOOSTCLICKS_EMAIL"),
+                env::var("CLOUD_BOOSTCLICKS_PASSWORD"),
+            ) else {
+                fail("set CLOUD_BOOSTCLICKS_TOKEN or CLOUD_BOOSTCLICKS_EMAIL and CLOUD_BOOSTCLICKS_PASSWORD");
+            };
+            runtime
+                .block_on(ApiClient::login(base_url, email, password))
+                .unwrap_or_else(|e| fail(format!("can't sign in: {e}")))
+        }
+    };
+    let storage = runtime
+        .block_on(api.storage(storage_id))
+        .unwrap_or_else(|e| fail(format!("can't get the storage: {e}")));
+
+    // 1. mounting
+    let cache = cache::BlockCache::open(&cache_dir, cache_size)
+        .unwrap_or_else(|e| fail(format!("can't open the cache: {e}")));
+    let handle = runtime.handle().clone();
+    let fs = fs::CloudFs::new(
+        runtime,
+        api,
+        storage_id,
+        storage.settings.versioning,
+        owner,
+        cache_dir,
+        cache,
+        dir_ttl,
+    );
+    let options = [
+        MountOption::FSName("cloud_boostclicks".to_string()),
+        MountOption::Subtype("cloud_boostclicks".to_string()),
+        MountOption::DefaultPermissions,
+    ];
+    let mut session = Session::new(fs, &mountpoint, &options)
+        .unwrap_or_else(|e| fail(format!("can't mount: {e}")));
+    tracing::info!("{} is mounted at {}", storage.name, mountpoint.display());
+
+    // the session below ends once the file system is unmounted
+    let signal_mountpoint = mountpoint.clone();
+    handle.spawn(async move {
+        let Ok(mut terminate) =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        else {
+            return;
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+        if let Err(e) = unmount(&signal_mountpoint) {
+            tracing::error!("can't unmount: {e}");
+        }
+    });
+
+    // 2. serving the kernel, files left opened are uploaded when the session is dropped
+    if let Err(e) = session.run() {
+        tracing::error!("can't serve the file system: {e}");
+    }
+    drop(session);
+    tracing::info!("{} is unmounted", mountpoint.display());
+}
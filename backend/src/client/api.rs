@@ -0,0 +1,278 @@
+use std::ops::Range;
+
+use reqwest::{header, multipart, Client, RequestBuilder, Response, StatusCode};
+use tokio_util::bytes::Bytes;
+use uuid::Uuid;
+
+use crate::{
+    common::webdav::href,
+    models::{
+        files::FSElement,
+        storages::{Storage, StorageWithInfo},
+    },
+    schemas::{
+        auth::{LoginSchema, TokenSchema},
+        files::{BatchOperation, BatchSchema, BatchSummary, UploadParams},
+        shares::{CreateShareSchema, ShareCreatedSchema},
+        storages::StoragesListSchema,
+    },
+};
+
+/// Files are uploaded in chunks of this size, the same as the web interface does
+pub const UPLOAD_CHUNK_SIZE: usize = 20 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The server answered with an error, the message is the one it sent
+    #[error("{status}: {message}")]
+    Status { status: StatusCode, message: String },
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Status { status, .. } => Some(*status),
+            Self::Request(e) => e.status(),
+        }
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// A chunk of a file uploaded with `upload_chunked`, the first one comes without `file_id`
+pub struct UploadChunk {
+    pub path: String,
+    pub size: u64,
+    pub file_id: Option<Uuid>,
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub chunk: Vec<u8>,
+}
+
+/// Client of the HTTP API, `base_url` is the URL the API is nested at, e.g. `https://cloud.example.com/api`
+#[derive(Clone)]
+pub struct ApiClient {
+    http: Client,
+    base_url: String,
+    token: String,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str, token: String) -> Self {
+        Self {
+            http: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    pub async fn login(base_url: &str, email: String, password: String) -> ApiResult<Self> {
+        let client = Self::new(base_url, String::new());
+        let token: TokenSchema = Self::check(
+            client
+                .http
+                .post(client.url("/auth/login"))
+                .json(&LoginSchema { email, password })
+                .send()
+                .await?,
+        )
+        .await?
+        .json()
+        .await?;
+
+        Ok(Self {
+            token: token.access_token,
+            ..client
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// URL of a file action like `tree` or `download`, the path is encoded segment by segment
+    fn files_url(&self, storage_id: Uuid, action: &str, path: &str) -> String {
+        href(
+            &self.url(&format!("/storages/{storage_id}/files/{action}")),
+            path,
+            false,
+        )
+    }
+
+    async fn send(&self, request: RequestBuilder) -> ApiResult<Response> {
+        Self::check(request.bearer_auth(&self.token).send().await?).await
+    }
+
+    async fn check(response: Response) -> ApiResult<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let message = response.text().await.unwrap_or_default();
+        Err(ApiError::Status { status, message })
+    }
+
+    pub async fn storages(&self) -> ApiResult<Vec<StorageWithInfo>> {
+        let list: StoragesListSchema = self
+            .send(self.http.get(self.url("/storages")))
+            .await?
+            .json()
+            .await?;
+        Ok(list.storages)
+    }
+
+    pub async fn storage(&self, storage_id: Uuid) -> ApiResult<Storage> {
+        let url = self.url(&format!("/storages/{storage_id}"));
+        Ok(self.send(self.http.get(url)).await?.json().await?)
+    }
+
+    /// Files and folders of the folder, the empty path is the root of the storage
+    pub async fn list_dir(&self, storage_id: Uuid, path: &str) -> ApiResult<Vec<FSElement>> {
+        let url = self.files_url(storage_id, "tree", path);
+        Ok(self.send(self.http.get(url)).await?.json().await?)
+    }
+
+    /// Content of the file or of its part when the range is given
+    pub async fn download(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        range: Option<Range<u64>>,
+    ) -> ApiResult<Bytes> {
+        let mut request = self.http.get(self.files_url(storage_id, "download", path));
+        if let Some(range) = range.filter(|range| !range.is_empty()) {
+            request = request.header(
+                header::RANGE,
+                format!("bytes={}-{}", range.start, range.end - 1),
+            );
+        }
+
+        Ok(self.send(request).await?.bytes().await?)
+    }
+
+    /// The response streaming the file from the offset, its body is read with `Response::chunk`
+    pub async fn download_from(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        offset: u64,
+    ) -> ApiResult<Response> {
+        let mut request = self.http.get(self.files_url(storage_id, "download", path));
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={offset}-"));
+        }
+        self.send(request).await
+    }
+
+    /// Returns the file id which has to be sent with the following chunks
+    pub async fn upload_chunk(&self, storage_id: Uuid, chunk: UploadChunk) -> ApiResult<Uuid> {
+        #[derive(serde::Deserialize)]
+        struct Uploaded {
+            file_id: Uuid,
+        }
+
+        let mut form = multipart::Form::new()
+            .text("path", chunk.path)
+            .text("size", chunk.size.to_string())
+            .text("chunk_index", chunk.chunk_index.to_string())
+            .text("total_chunks", chunk.total_chunks.to_string())
+            .part(
+                "chunk",
+                multipart::Part::bytes(chunk.chunk).file_name("chunk"),
+            );
+        if let Some(file_id) = chunk.file_id {
+            form = form.text("file_id", file_id.to_string());
+        }
+
+        let url = self.url(&format!("/storages/{storage_id}/files/upload_chunked"));
+        let uploaded: Uploaded = self
+            .send(self.http.post(url).multipart(form))
+            .await?
+            .json()
+            .await?;
+        Ok(uploaded.file_id)
+    }
+
+    pub async fn create_folder(
+        &self,
+        storage_id: Uuid,
+        parent_path: &str,
+        folder_name: &str,
+    ) -> ApiResult<()> {
+        let params = UploadParams {
+            path: parent_path.to_string(),
+            folder_name: folder_name.to_string(),
+        };
+        let url = self.url(&format!("/storages/{storage_id}/files/create_folder"));
+        self.send(self.http.post(url).json(&params))
+            .await
+            .map(|_| ())
+    }
+
+    /// Moves the file or the folder into the trash
+    pub async fn delete(&self, storage_id: Uuid, path: &str) -> ApiResult<()> {
+        let url = href(
+            &self.url(&format!("/storages/{storage_id}/files")),
+            path,
+            false,
+        );
+        self.send(self.http.delete(url)).await.map(|_| ())
+    }
+
+    /// Returns the id of the share, the same one if the path is shared already
+    pub async fn create_share(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        is_folder: bool,
+    ) -> ApiResult<Uuid> {
+        let schema = CreateShareSchema {
+            path: path.to_string(),
+            is_folder,
+        };
+        let url = self.url(&format!("/storages/{storage_id}/files/share"));
+        let share: ShareCreatedSchema = self
+            .send(self.http.post(url).json(&schema))
+            .await?
+            .json()
+            .await?;
+        Ok(share.id)
+    }
+
+    /// Runs the operations in one transaction, the error of the first failed one is returned
+    pub async fn batch(
+        &self,
+        storage_id: Uuid,
+        operations: Vec<BatchOperation>,
+    ) -> ApiResult<BatchSummary> {
+        let schema = BatchSchema {
+            operations,
+            dry_run: false,
+        };
+        let url = self.url(&format!("/storages/{storage_id}/files/batch"));
+        let summary: BatchSummary = self
+            .send(self.http.post(url).json(&schema))
+            .await?
+            .json()
+            .await?;
+
+        match summary.items.iter().find_map(|item| item.error.clone()) {
+            Some(message) => Err(ApiError::Status {
+                status: StatusCode::CONFLICT,
+                message,
+            }),
+            None => Ok(summary),
+        }
+    }
+}
@@ -0,0 +1,3 @@
+//! Client side of the HTTP API for the binaries working with a remote server
+
+pub mod api;
@@ -19,12 +19,14 @@ pub enum ClientData {
 
 pub struct UploadFileData {
     pub file_id: Uuid,
+    pub version_id: Option<Uuid>,
     pub user_id: Uuid,
     pub file_data: Box<[u8]>,
 }
 
 pub struct DownloadFileData {
     pub file_id: Uuid,
+    pub version_id: Option<Uuid>,
     pub storage_id: Uuid,
     pub user_id: Uuid,
 }
@@ -0,0 +1,114 @@
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+
+/// The only codec so far, it's stored in `files.codec`
+pub const ZSTD: &str = "zstd";
+
+const LEVEL: i32 = 3;
+
+/// Size of a buffer decompressed data is read into by `Decompressor`
+const OUT_BUFFER_SIZE: usize = 128 * 1024;
+
+/// Files of these types are compressed already, compressing them again only wastes time
+const COMPRESSED_TYPES: [&str; 14] = [
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "application/zstd",
+    "application/pdf",
+    "application/epub+zip",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+];
+
+/// Guesses by the file name whether compressing the file makes sense
+pub fn is_compressible(path: &str) -> bool {
+    let Some(mime) = mime_guess::from_path(path).first() else {
+        return true;
+    };
+
+    match mime.type_().as_str() {
+        "video" | "audio" => false,
+        "image" => matches!(mime.subtype().as_str(), "svg" | "bmp" | "tiff"),
+        _ => !COMPRESSED_TYPES.contains(&mime.essence_str()),
+    }
+}
+
+/// Compresses the file, `None` is returned if it doesn't get smaller
+pub fn compress(data: &[u8]) -> CloudBoostclicksResult<Option<Vec<u8>>> {
+    let compressed = zstd::stream::encode_all(data, LEVEL).map_err(|e| {
+        tracing::error!("can't compress file: {e}");
+        CloudBoostclicksError::Unknown
+    })?;
+
+    Ok((compressed.len() < data.len()).then_some(compressed))
+}
+
+/// Compresses one chunk of a file uploaded by chunks, it's a frame of its own,
+/// so the frames of all chunks are decompressed as one stream
+pub fn compress_chunk(codec: &str, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+    if codec != ZSTD {
+        tracing::error!("unknown codec \"{codec}\"");
+        return Err(CloudBoostclicksError::Unknown);
+    }
+
+    zstd::stream::encode_all(data, LEVEL).map_err(|e| {
+        tracing::error!("can't compress chunk: {e}");
+        CloudBoostclicksError::Unknown
+    })
+}
+
+pub fn decompress(codec: &str, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+    Decompressor::new(codec)?.feed(data)
+}
+
+/// Decompresses a file chunk by chunk, so it doesn't have to be in memory at once
+pub struct Decompressor {
+    decoder: Decoder<'static>,
+}
+
+impl Decompressor {
+    pub fn new(codec: &str) -> CloudBoostclicksResult<Self> {
+        if codec != ZSTD {
+            tracing::error!("unknown codec \"{codec}\"");
+            return Err(CloudBoostclicksError::Unknown);
+        }
+
+        Decoder::new()
+            .map(|decoder| Self { decoder })
+            .map_err(|e| {
+                tracing::error!("can't create decoder: {e}");
+                CloudBoostclicksError::Unknown
+            })
+    }
+
+    /// Returns everything that can be decompressed from the given part of the file
+    pub fn feed(&mut self, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        let mut input = InBuffer::around(data);
+        let mut decompressed = Vec::new();
+        let mut buffer = vec![0; OUT_BUFFER_SIZE];
+
+        loop {
+            let mut output = OutBuffer::around(buffer.as_mut_slice());
+            self.decoder.run(&mut input, &mut output).map_err(|e| {
+                tracing::error!("can't decompress file: {e}");
+                CloudBoostclicksError::DecompressionFailed
+            })?;
+
+            let written = output.pos();
+            decompressed.extend_from_slice(&buffer[..written]);
+
+            // the decoder may still hold data if it filled the whole buffer
+            if input.pos == data.len() && written < buffer.len() {
+                return Ok(decompressed);
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const STORAGE_KEY_INFO: &[u8] = b"cloud-boostclicks storage key";
+
+/// How much longer a chunk gets after encryption
+pub const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// Every file of a client encrypted storage starts with it, see `docs/client-encryption.md`
+pub const CLIENT_MAGIC: &[u8] = b"CBZK\x01";
+
+/// Key encrypting chunks of a file.
+///
+/// It's stored wrapped by the key of the storage, which is derived from the server secret,
+/// and the wrapped form starts with the storage id so it stays readable wherever the chunk is referenced
+pub struct DataKey {
+    cipher: XChaCha20Poly1305,
+    pub wrapped: Vec<u8>,
+}
+
+impl DataKey {
+    pub fn generate(secret: &str, storage_id: Uuid) -> CloudBoostclicksResult<Self> {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let wrapped = wrap(secret, storage_id, &key)?;
+
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new(&key),
+            wrapped,
+        })
+    }
+
+    pub fn unwrap(secret: &str, wrapped: &[u8]) -> CloudBoostclicksResult<Self> {
+        let key = unwrap(secret, wrapped)?;
+
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new(&key),
+            wrapped: wrapped.to_vec(),
+        })
+    }
+
+    /// Returns the random nonce followed by the ciphertext
+    pub fn encrypt(&self, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        seal(&self.cipher, data, b"")
+    }
+
+    pub fn decrypt(&self, payload: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        open(&self.cipher, payload, b"")
+    }
+}
+
+/// Wraps the data key again by the storage key derived from the new secret.
+///
+/// Keys already wrapped by the new secret are returned as is, so a rotation can be run again
+pub fn rewrap(old_secret: &str, new_secret: &str, wrapped: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+    if unwrap(new_secret, wrapped).is_ok() {
+        return Ok(wrapped.to_vec());
+    }
+
+    let key = unwrap(old_secret, wrapped)?;
+    let storage_id = storage_id_of(wrapped)?;
+    wrap(new_secret, storage_id, &key)
+}
+
+fn wrap(secret: &str, storage_id: Uuid, key: &Key) -> CloudBoostclicksResult<Vec<u8>> {
+    let sealed = seal(&storage_key(secret, storage_id)?, key, storage_id.as_bytes())?;
+
+    let mut wrapped = storage_id.as_bytes().to_vec();
+    wrapped.extend(sealed);
+    Ok(wrapped)
+}
+
+fn unwrap(secret: &str, wrapped: &[u8]) -> CloudBoostclicksResult<Key> {
+    let storage_id = storage_id_of(wrapped)?;
+    let key = open(&storage_key(secret, storage_id)?, &wrapped[16..], storage_id.as_bytes())?;
+
+    Ok(*Key::from_slice(&key))
+}
+
+fn storage_id_of(wrapped: &[u8]) -> CloudBoostclicksResult<Uuid> {
+    wrapped
+        .get(..16)
+        .and_then(|bytes| Uuid::from_slice(bytes).ok())
+        .ok_or(CloudBoostclicksError::DecryptionFailed)
+}
+
+fn storage_key(secret: &str, storage_id: Uuid) -> CloudBoostclicksResult<XChaCha20Poly1305> {
+    if secret.is_empty() {
+        return Err(CloudBoostclicksError::EncryptionNotConfigured);
+    }
+
+    let mut info = STORAGE_KEY_INFO.to_vec();
+    info.extend_from_slice(storage_id.as_bytes());
+
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(None, secret.as_bytes())
+        .expand(&info, &mut key)
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+    Ok(XChaCha20Poly1305::new(&key))
+}
+
+fn seal(cipher: &XChaCha20Poly1305, data: &[u8], aad: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: data, aad })
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend(ciphertext);
+    Ok(payload)
+}
+
+fn open(cipher: &XChaCha20Poly1305, payload: &[u8], aad: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+    if payload.len() < OVERHEAD {
+        return Err(CloudBoostclicksError::DecryptionFailed);
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| CloudBoostclicksError::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    #[test]
+    fn encrypted_chunk_is_decrypted() {
+        let key = DataKey::generate(SECRET, Uuid::new_v4()).unwrap();
+        let data = b"content of the chunk";
+
+        let payload = key.encrypt(data).unwrap();
+        assert_eq!(payload.len(), data.len() + OVERHEAD);
+        assert_eq!(key.decrypt(&payload).unwrap(), data);
+
+        // the stored form of the key opens it as well
+        let unwrapped = DataKey::unwrap(SECRET, &key.wrapped).unwrap();
+        assert_eq!(unwrapped.decrypt(&payload).unwrap(), data);
+    }
+
+    #[test]
+    fn damaged_payload_and_wrong_secret_are_rejected() {
+        let key = DataKey::generate(SECRET, Uuid::new_v4()).unwrap();
+        let mut payload = key.encrypt(b"content").unwrap();
+        *payload.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(
+            key.decrypt(&payload),
+            Err(CloudBoostclicksError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            key.decrypt(&payload[..OVERHEAD - 1]),
+            Err(CloudBoostclicksError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            DataKey::unwrap("another secret", &key.wrapped),
+            Err(CloudBoostclicksError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            DataKey::generate("", Uuid::new_v4()),
+            Err(CloudBoostclicksError::EncryptionNotConfigured)
+        ));
+    }
+
+    #[test]
+    fn rewrapped_key_opens_old_chunks() {
+        let key = DataKey::generate(SECRET, Uuid::new_v4()).unwrap();
+        let payload = key.encrypt(b"content").unwrap();
+
+        let rewrapped = rewrap(SECRET, "new secret", &key.wrapped).unwrap();
+        assert_eq!(rewrapped[..16], key.wrapped[..16]);
+        let key = DataKey::unwrap("new secret", &rewrapped).unwrap();
+        assert_eq!(key.decrypt(&payload).unwrap(), b"content");
+
+        // a rotation run again keeps keys which are rewrapped already
+        assert_eq!(rewrap(SECRET, "new secret", &rewrapped).unwrap(), rewrapped);
+    }
+}
@@ -0,0 +1,96 @@
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+
+/// Computes parity chunks of a group of data chunks, shorter chunks are padded with zeros
+pub fn encode(data_chunks: &[&[u8]], parity_chunks: usize) -> CloudBoostclicksResult<Vec<Vec<u8>>> {
+    let shard_len = data_chunks.iter().map(|c| c.len()).max().unwrap_or(0);
+
+    let mut shards: Vec<Vec<u8>> = data_chunks
+        .iter()
+        .map(|c| padded(c.to_vec(), shard_len))
+        .collect();
+    shards.extend((0..parity_chunks).map(|_| vec![0; shard_len]));
+
+    ReedSolomon::new(data_chunks.len(), parity_chunks)
+        .and_then(|rs| rs.encode(&mut shards))
+        .map_err(|e| {
+            tracing::error!("can't compute parity chunks: {e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+    Ok(shards.split_off(data_chunks.len()))
+}
+
+/// Fills in missing shards of a group, data shards first and parity shards after.
+///
+/// Restored data shards keep the padding, it's up to the caller to cut them to the original size
+pub fn reconstruct(
+    shards: &mut [Option<Vec<u8>>],
+    data_chunks: usize,
+    shard_len: usize,
+) -> CloudBoostclicksResult<()> {
+    for shard in shards.iter_mut() {
+        if let Some(data) = shard.take() {
+            *shard = Some(padded(data, shard_len));
+        }
+    }
+
+    ReedSolomon::new(data_chunks, shards.len() - data_chunks)
+        .and_then(|rs| rs.reconstruct(shards))
+        .map_err(|e| {
+            tracing::error!("can't reconstruct chunks: {e}");
+            CloudBoostclicksError::ChunksUnrecoverable
+        })
+}
+
+fn padded(mut data: Vec<u8>, len: usize) -> Vec<u8> {
+    data.resize(len, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lost_chunks_are_reconstructed() {
+        let data_chunks: Vec<Vec<u8>> = [1000u32, 1000, 300]
+            .iter()
+            .enumerate()
+            .map(|(index, len)| (0..*len).map(|i| (i * 7 + index as u32) as u8).collect())
+            .collect();
+        let data_refs: Vec<&[u8]> = data_chunks.iter().map(Vec::as_slice).collect();
+        let parity_chunks = encode(&data_refs, 2).unwrap();
+        assert_eq!(parity_chunks.len(), 2);
+
+        // any two of the five chunks may be lost
+        for lost in [[0, 1], [1, 2], [2, 4], [0, 3]] {
+            let mut shards: Vec<Option<Vec<u8>>> = data_chunks
+                .iter()
+                .chain(&parity_chunks)
+                .cloned()
+                .map(Some)
+                .collect();
+            for index in lost {
+                shards[index] = None;
+            }
+
+            reconstruct(&mut shards, 3, 1000).unwrap();
+            for (shard, original) in shards.iter().zip(&data_chunks) {
+                assert_eq!(&shard.as_ref().unwrap()[..original.len()], original.as_slice());
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_lost_chunks_are_reported() {
+        let parity_chunks = encode(&[b"first", b"other"], 1).unwrap();
+        let mut shards = vec![None, None, Some(parity_chunks[0].clone())];
+
+        assert!(matches!(
+            reconstruct(&mut shards, 2, 5),
+            Err(CloudBoostclicksError::ChunksUnrecoverable)
+        ));
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::PathBuf,
+};
+
+use flate2::read::GzDecoder;
+use tar::EntryType;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use uuid::Uuid;
+
+use crate::{
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    schemas::files::ArchiveFormat,
+};
+
+/// Limits against archives which unpack into much more than they take (zip bombs)
+const MAX_ENTRIES: usize = 10_000;
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+const MAX_TOTAL_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+/// How many times the unpacked content may be larger than the archive
+const MAX_RATIO: u64 = 100;
+/// Small archives may compress better than `MAX_RATIO`, they can't harm anyway
+const RATIO_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const SYMLINK_MODE: u32 = 0o120000;
+const FILE_TYPE_MASK: u32 = 0o170000;
+
+/// Entries are given one by one, so only one of them is in memory at once
+pub enum ExtractedItem {
+    /// Path with the trailing slash
+    Folder(String),
+    File(String, Vec<u8>),
+    Skipped(String, &'static str),
+}
+
+/// Uploaded archive kept in a temporary file, so it's never held in memory as a whole.
+/// Zip archives can't be unpacked as they arrive, their directory is at the end.
+/// The file is removed when the archive is dropped
+pub struct ArchiveFile {
+    path: PathBuf,
+    file: tokio::fs::File,
+    size: u64,
+    max_size: u64,
+}
+
+impl ArchiveFile {
+    pub async fn create(max_size: u64) -> CloudBoostclicksResult<Self> {
+        let path =
+            std::env::temp_dir().join(format!("cloud_boostclicks-{}.archive", Uuid::new_v4()));
+        let file = tokio::fs::File::create(&path).await.map_err(|e| {
+            tracing::error!("can't create a temporary file for the archive: {e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        Ok(Self {
+            path,
+            file,
+            size: 0,
+            max_size,
+        })
+    }
+
+    /// Appends the next part of the uploaded archive
+    pub async fn write(&mut self, data: &[u8]) -> CloudBoostclicksResult<()> {
+        self.size += data.len() as u64;
+        if self.size > self.max_size {
+            return Err(CloudBoostclicksError::ArchiveTooLarge);
+        }
+
+        self.file.write_all(data).await.map_err(|e| {
+            tracing::error!("can't write the archive to a temporary file: {e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    fn open(&self) -> CloudBoostclicksResult<BufReader<File>> {
+        File::open(&self.path).map(BufReader::new).map_err(|e| {
+            tracing::error!("can't open the temporary file of the archive: {e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+}
+
+impl Drop for ArchiveFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("can't remove the temporary file of the archive: {e}");
+        }
+    }
+}
+
+/// Reads the archive in a blocking thread and sends its entries as they are unpacked
+pub fn extract(
+    format: ArchiveFormat,
+    mut archive: ArchiveFile,
+) -> mpsc::Receiver<CloudBoostclicksResult<ExtractedItem>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        // everything written has to reach the file before it's read in another thread
+        if let Err(e) = archive.file.flush().await {
+            tracing::error!("can't write the archive to a temporary file: {e}");
+            let _ = tx.send(Err(CloudBoostclicksError::Unknown)).await;
+            return;
+        }
+
+        let _ = tokio::task::spawn_blocking(move || {
+            let mut limits = Limits::new(archive.size);
+            let result = archive.open().and_then(|reader| match format {
+                ArchiveFormat::Zip => extract_zip(reader, &mut limits, &tx),
+                ArchiveFormat::Tar => extract_tar(reader, &mut limits, &tx),
+                ArchiveFormat::TarGz => extract_tar(GzDecoder::new(reader), &mut limits, &tx),
+                ArchiveFormat::TarZst => zstd::stream::read::Decoder::new(reader)
+                    .map_err(|_| CloudBoostclicksError::InvalidArchive)
+                    .and_then(|decoder| extract_tar(decoder, &mut limits, &tx)),
+            });
+
+            if let Err(e) = result {
+                let _ = tx.blocking_send(Err(e));
+            }
+        })
+        .await;
+    });
+
+    rx
+}
+
+type Sender = mpsc::Sender<CloudBoostclicksResult<ExtractedItem>>;
+
+fn extract_zip(
+    reader: BufReader<File>,
+    limits: &mut Limits,
+    tx: &Sender,
+) -> CloudBoostclicksResult<()> {
+    let mut zip = zip::ZipArchive::new(reader).map_err(|e| {
+        tracing::warn!("can't read zip: {e}");
+        CloudBoostclicksError::InvalidArchive
+    })?;
+
+    for index in 0..zip.len() {
+        limits.count_entry()?;
+
+        let mut entry = zip.by_index(index).map_err(|e| {
+            tracing::warn!("can't read zip entry: {e}");
+            CloudBoostclicksError::InvalidArchive
+        })?;
+        let name = entry.name().to_string();
+
+        let item = match sanitize_path(&name, entry.is_dir()) {
+            None => ExtractedItem::Skipped(name, "небезопасный путь"),
+            Some(path) if entry.is_dir() => ExtractedItem::Folder(path),
+            Some(_)
+                if entry
+                    .unix_mode()
+                    .is_some_and(|mode| mode & FILE_TYPE_MASK == SYMLINK_MODE) =>
+            {
+                ExtractedItem::Skipped(name, "ссылки не поддерживаются")
+            }
+            Some(path) => {
+                let size = entry.size();
+                let data = limits.read(&mut entry, size)?;
+                ExtractedItem::File(path, data)
+            }
+        };
+
+        if tx.blocking_send(Ok(item)).is_err() {
+            // nobody waits for the rest
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_tar<R: Read>(reader: R, limits: &mut Limits, tx: &Sender) -> CloudBoostclicksResult<()> {
+    let mut tar = tar::Archive::new(reader);
+    let entries = tar.entries().map_err(|e| {
+        tracing::warn!("can't read tar: {e}");
+        CloudBoostclicksError::InvalidArchive
+    })?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| {
+            tracing::warn!("can't read tar entry: {e}");
+            CloudBoostclicksError::InvalidArchive
+        })?;
+        let entry_type = entry.header().entry_type();
+        // extensions of the format aren't entries on their own
+        if matches!(entry_type, EntryType::XGlobalHeader | EntryType::XHeader) {
+            continue;
+        }
+        limits.count_entry()?;
+
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let is_folder = entry_type.is_dir();
+
+        let item = match sanitize_path(&name, is_folder) {
+            None => ExtractedItem::Skipped(name, "небезопасный путь"),
+            Some(path) if is_folder => ExtractedItem::Folder(path),
+            Some(path) if entry_type.is_file() => {
+                let size = entry.size();
+                let data = limits.read(&mut entry, size)?;
+                ExtractedItem::File(path, data)
+            }
+            Some(_) => ExtractedItem::Skipped(name, "поддерживаются только файлы и папки"),
+        };
+
+        if tx.blocking_send(Ok(item)).is_err() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes a relative path of an entry, the ones leading outside of the target folder (zip slip) are refused
+fn sanitize_path(name: &str, is_folder: bool) -> Option<String> {
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') || name.contains(':') {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+
+    let path = parts.join("/");
+    Some(if is_folder { format!("{path}/") } else { path })
+}
+
+struct Limits {
+    archive_size: u64,
+    entries: usize,
+    total_size: u64,
+}
+
+impl Limits {
+    fn new(archive_size: u64) -> Self {
+        Self {
+            archive_size,
+            entries: 0,
+            total_size: 0,
+        }
+    }
+
+    fn count_entry(&mut self) -> CloudBoostclicksResult<()> {
+        self.entries += 1;
+        if self.entries > MAX_ENTRIES {
+            return Err(CloudBoostclicksError::ArchiveTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Reads the entry, never trusting the size declared in the archive
+    fn read(&mut self, entry: &mut impl Read, declared_size: u64) -> CloudBoostclicksResult<Vec<u8>> {
+        let allowed = MAX_ENTRY_SIZE.min(self.max_total_size() - self.total_size);
+        if declared_size > allowed {
+            return Err(CloudBoostclicksError::ArchiveTooLarge);
+        }
+
+        let mut data = Vec::with_capacity(declared_size as usize);
+        entry
+            .take(allowed + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| {
+                tracing::warn!("can't unpack entry: {e}");
+                CloudBoostclicksError::InvalidArchive
+            })?;
+
+        if data.len() as u64 > allowed {
+            return Err(CloudBoostclicksError::ArchiveTooLarge);
+        }
+        self.total_size += data.len() as u64;
+
+        Ok(data)
+    }
+
+    fn max_total_size(&self) -> u64 {
+        let by_ratio = self.archive_size.saturating_mul(MAX_RATIO).max(RATIO_THRESHOLD);
+        MAX_TOTAL_SIZE.min(by_ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use crate::common::{
+        tar::{TarCompression, TarWriter},
+        zip::ZipWriter,
+    };
+
+    async fn archive_file(data: &[u8]) -> ArchiveFile {
+        let mut archive = ArchiveFile::create(1024 * 1024).await.unwrap();
+        // written in parts, the same way the upload arrives
+        for part in data.chunks(100) {
+            archive.write(part).await.unwrap();
+        }
+        archive
+    }
+
+    async fn collect(format: ArchiveFormat, archive: Vec<u8>) -> Vec<ExtractedItem> {
+        let mut items = extract(format, archive_file(&archive).await);
+        let mut collected = Vec::new();
+        while let Some(item) = items.recv().await {
+            collected.push(item.unwrap());
+        }
+        collected
+    }
+
+    fn describe(items: &[ExtractedItem]) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| match item {
+                ExtractedItem::Folder(path) => format!("folder {path}"),
+                ExtractedItem::File(path, data) => {
+                    format!("file {path} {}", String::from_utf8_lossy(data))
+                }
+                ExtractedItem::Skipped(name, _) => format!("skipped {name}"),
+            })
+            .collect()
+    }
+
+    const ENTRIES: [(&str, &str); 5] = [
+        ("docs/", ""),
+        ("docs/a.txt", "first"),
+        ("./docs//b.txt", "second"),
+        ("../evil.txt", "outside"),
+        ("/etc/passwd", "absolute"),
+    ];
+    const EXPECTED: [&str; 5] = [
+        "folder docs/",
+        "file docs/a.txt first",
+        "file docs/b.txt second",
+        "skipped ../evil.txt",
+        "skipped /etc/passwd",
+    ];
+
+    #[tokio::test]
+    async fn zip_entries_are_extracted() {
+        let mut writer = ZipWriter::new();
+        let mut archive = Vec::new();
+        for (name, content) in ENTRIES {
+            archive.extend(writer.start_file(name, NaiveDateTime::default()));
+            archive.extend(writer.write(content.as_bytes()).unwrap());
+            archive.extend(writer.finish_file().unwrap());
+        }
+        archive.extend(writer.finish());
+
+        assert_eq!(describe(&collect(ArchiveFormat::Zip, archive).await), EXPECTED);
+    }
+
+    #[tokio::test]
+    async fn tar_entries_are_extracted() {
+        for (format, compression) in [
+            (ArchiveFormat::Tar, TarCompression::None),
+            (ArchiveFormat::TarGz, TarCompression::Gzip),
+            (ArchiveFormat::TarZst, TarCompression::Zstd),
+        ] {
+            let mut writer = TarWriter::new(compression).unwrap();
+            let mut archive = Vec::new();
+            for (name, content) in ENTRIES {
+                let size = content.len() as u64;
+                archive.extend(writer.start_file(name, size, NaiveDateTime::default()).unwrap());
+                archive.extend(writer.write(content.as_bytes()).unwrap());
+                archive.extend(writer.finish_file().unwrap());
+            }
+            archive.extend(writer.finish().unwrap());
+
+            assert_eq!(describe(&collect(format, archive).await), EXPECTED);
+        }
+    }
+
+    #[tokio::test]
+    async fn broken_archive_is_reported() {
+        let mut items = extract(ArchiveFormat::Zip, archive_file(b"not a zip").await);
+
+        assert!(matches!(
+            items.recv().await,
+            Some(Err(CloudBoostclicksError::InvalidArchive))
+        ));
+    }
+
+    #[tokio::test]
+    async fn archive_larger_than_allowed_is_refused() {
+        let mut archive = ArchiveFile::create(150).await.unwrap();
+        archive.write(&[0; 100]).await.unwrap();
+
+        assert!(matches!(
+            archive.write(&[0; 100]).await,
+            Err(CloudBoostclicksError::ArchiveTooLarge)
+        ));
+    }
+}
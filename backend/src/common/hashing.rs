@@ -0,0 +1,179 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::{
+    compress256,
+    digest::generic_array::{typenum::U64, GenericArray},
+    Digest, Sha256,
+};
+
+/// Hex encoded SHA-256 of the data
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Value of the `Digest` header (RFC 3230) built from a hex encoded SHA-256
+pub fn digest_header(sha256: &str) -> String {
+    let raw = hex::decode(sha256).unwrap_or_default();
+    format!("sha-256={}", STANDARD.encode(raw))
+}
+
+/// SHA-256 which can be saved after any piece of data and continued later,
+/// so files uploaded chunk by chunk are hashed as the chunks arrive
+#[derive(Clone)]
+pub struct ResumableSha256 {
+    state: [u32; 8],
+    len: u64,
+    tail: Vec<u8>,
+}
+
+impl ResumableSha256 {
+    const BLOCK: usize = 64;
+    const INITIAL: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    pub fn new() -> Self {
+        Self {
+            state: Self::INITIAL,
+            len: 0,
+            tail: Vec::new(),
+        }
+    }
+
+    /// Restores the hasher saved by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 40 || bytes.len() >= 40 + Self::BLOCK {
+            return None;
+        }
+
+        let mut state = [0; 8];
+        for (word, bytes) in state.iter_mut().zip(bytes[..32].chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().ok()?);
+        }
+        let len = u64::from_be_bytes(bytes[32..40].try_into().ok()?);
+        let tail = bytes[40..].to_vec();
+        if len % Self::BLOCK as u64 != tail.len() as u64 {
+            return None;
+        }
+
+        Some(Self {
+            state,
+            len,
+            tail,
+        })
+    }
+
+    /// The state words, the amount of hashed bytes and the bytes not making up a whole block yet
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(40 + self.tail.len());
+        for word in self.state {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.len.to_be_bytes());
+        bytes.extend_from_slice(&self.tail);
+        bytes
+    }
+
+    /// Amount of bytes hashed so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        if !self.tail.is_empty() {
+            let taken = (Self::BLOCK - self.tail.len()).min(data.len());
+            self.tail.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.tail.len() < Self::BLOCK {
+                return;
+            }
+            let tail = std::mem::take(&mut self.tail);
+            self.compress(&tail);
+        }
+
+        let whole = data.len() - data.len() % Self::BLOCK;
+        self.compress(&data[..whole]);
+        self.tail.extend_from_slice(&data[whole..]);
+    }
+
+    /// Hex encoded SHA-256 of everything given to `update`
+    pub fn finalize(mut self) -> String {
+        let bits = self.len.wrapping_mul(8);
+
+        let mut padding = std::mem::take(&mut self.tail);
+        padding.push(0x80);
+        while padding.len() % Self::BLOCK != Self::BLOCK - 8 {
+            padding.push(0);
+        }
+        padding.extend_from_slice(&bits.to_be_bytes());
+        self.compress(&padding);
+
+        hex::encode(self.state.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<u8>>())
+    }
+
+    fn compress(&mut self, blocks: &[u8]) {
+        if blocks.is_empty() {
+            return;
+        }
+
+        let blocks: Vec<GenericArray<u8, U64>> = blocks
+            .chunks_exact(Self::BLOCK)
+            .map(GenericArray::clone_from_slice)
+            .collect();
+        compress256(&mut self.state, &blocks);
+    }
+}
+
+impl Default for ResumableSha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumable_sha256_matches_sha256() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 31 % 251) as u8).collect();
+
+        for split in [0, 1, 55, 56, 63, 64, 65, 128, 500, 1000] {
+            let mut hasher = ResumableSha256::new();
+            hasher.update(&data[..split]);
+            let mut hasher = ResumableSha256::from_bytes(&hasher.to_bytes()).unwrap();
+            hasher.update(&data[split..]);
+
+            assert_eq!(hasher.len(), data.len() as u64);
+            assert_eq!(hasher.finalize(), sha256_hex(&data));
+        }
+    }
+
+    #[test]
+    fn resumable_sha256_of_nothing() {
+        assert_eq!(
+            ResumableSha256::new().finalize(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn broken_state_is_rejected() {
+        assert!(ResumableSha256::from_bytes(&[0; 10]).is_none());
+        assert!(ResumableSha256::from_bytes(&[0; 41]).is_none());
+    }
+}
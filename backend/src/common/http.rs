@@ -0,0 +1,128 @@
+//! Bits of HTTP shared by the API and the protocols served besides it (WebDAV, S3)
+
+use std::ops::Range;
+
+use axum::http::{header, HeaderMap};
+use chrono::{NaiveDateTime, SubsecRound};
+
+use uuid::Uuid;
+
+use crate::common::hashing::sha256_hex;
+
+/// Parses a `Range` header of a single byte range.
+///
+/// Several ranges aren't supported and give the whole content, `None` means that the range
+/// can't be satisfied
+pub fn parse_range(value: &str, len: usize) -> Option<Range<usize>> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Some(0..len);
+    };
+    if spec.contains(',') {
+        return Some(0..len);
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len)
+        }
+        (start, "") => (start.parse().ok()?, len),
+        (start, end) => {
+            let end: usize = end.parse().ok()?;
+            (start.parse().ok()?, end.saturating_add(1).min(len))
+        }
+    };
+
+    (start < end).then_some(start..end)
+}
+
+/// Date of the `Last-Modified` header and of the WebDAV `getlastmodified` property
+pub fn http_date(date: &NaiveDateTime) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub fn parse_http_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%a, %d %b %Y %H:%M:%S GMT").ok()
+}
+
+/// ETag of a file (without quotes) which is the hash of its content.
+///
+/// Files uploaded before checksums were kept get one of their id and version,
+/// which change only with the content as well
+pub fn etag(sha256: Option<&str>, file_id: Uuid, version: i32) -> String {
+    match sha256 {
+        Some(sha256) => sha256.to_string(),
+        None => sha256_hex(format!("{file_id}:{version}").as_bytes()),
+    }
+}
+
+/// Whether the ETag is in the list of `If-Match` or `If-None-Match`, `*` matches any.
+///
+/// Tags may come with or without quotes, `weak` comparison of `If-None-Match` ignores `W/`
+/// while weak tags never match `If-Match`
+pub fn etag_listed(list: &str, etag: &str, weak: bool) -> bool {
+    list.split(',').map(str::trim).any(|tag| {
+        if tag == "*" {
+            return true;
+        }
+        match tag.strip_prefix("W/") {
+            Some(tag) => weak && tag.trim_matches('"') == etag,
+            None => tag.trim_matches('"') == etag,
+        }
+    })
+}
+
+/// Conditional headers of a download
+#[derive(Default)]
+pub struct Preconditions {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<NaiveDateTime>,
+}
+
+impl Preconditions {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        Self {
+            if_none_match: value(header::IF_NONE_MATCH),
+            if_modified_since: value(header::IF_MODIFIED_SINCE)
+                .and_then(|date| parse_http_date(&date)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.if_none_match.is_none() && self.if_modified_since.is_none()
+    }
+
+    /// The copy the client has is still fresh, `If-None-Match` wins over `If-Modified-Since`
+    pub fn not_modified(&self, etag: &str, last_modified: &NaiveDateTime) -> bool {
+        match (&self.if_none_match, &self.if_modified_since) {
+            (Some(list), _) => etag_listed(list, etag, true),
+            // HTTP dates have no fractions of a second
+            (None, Some(since)) => last_modified.trunc_subsecs(0) <= *since,
+            (None, None) => false,
+        }
+    }
+}
+
+/// Value of the `If-Match` header of a request changing a file
+pub fn if_match(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+pub fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
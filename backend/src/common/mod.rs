@@ -1,10 +1,24 @@
 ﻿pub mod access;
 pub mod channels;
+pub mod compression;
+pub mod crypto;
 pub mod db;
+pub mod erasure;
+pub mod extract;
+pub mod hashing;
+pub mod http;
 pub mod jwt_manager;
+pub mod notifier;
 pub mod password_manager;
 pub mod routing;
+pub mod s3;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod sigv4;
+pub mod ssh;
+pub mod tar;
 pub mod telegram_api;
 pub mod types;
+pub mod webdav;
 pub mod zip;
 
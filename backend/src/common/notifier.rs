@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::repositories::changes::CHANGES_CHANNEL;
+
+/// How many notifications a slow waiter may miss before it's told it lagged
+const CAPACITY: usize = 256;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Tells waiting requests that a storage has new changes.
+///
+/// `ChangesRepository::record` notifies the channel in the transaction of the change,
+/// so changes made by other processes (SFTP sessions, the S3 gateway) are seen as well
+#[derive(Debug, Clone)]
+pub struct ChangesNotifier {
+    tx: broadcast::Sender<Uuid>,
+}
+
+impl ChangesNotifier {
+    /// Listens to the database in the background
+    pub fn listen(db_uri: String) -> Self {
+        let (tx, _) = broadcast::channel(CAPACITY);
+
+        let sender = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = Self::forward(&db_uri, &sender).await {
+                    tracing::error!("can't listen to changes: {e}");
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+
+        Self { tx }
+    }
+
+    /// Ids of storages with new changes, only the ones after the subscription are given
+    pub fn subscribe(&self) -> broadcast::Receiver<Uuid> {
+        self.tx.subscribe()
+    }
+
+    async fn forward(db_uri: &str, tx: &broadcast::Sender<Uuid>) -> Result<(), sqlx::Error> {
+        let mut listener = PgListener::connect(db_uri).await?;
+        listener.listen(CHANGES_CHANNEL).await?;
+
+        loop {
+            let notification = listener.recv().await?;
+            if let Ok(storage_id) = notification.payload().parse() {
+                // nobody may be waiting
+                let _ = tx.send(storage_id);
+            }
+        }
+    }
+}
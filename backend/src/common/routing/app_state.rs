@@ -1,17 +1,31 @@
 ﻿use sqlx::{Pool, Postgres};
 
-use crate::{common::channels::ClientSender, config::Config};
+use crate::{
+    common::{channels::ClientSender, notifier::ChangesNotifier},
+    config::Config,
+};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db: Pool<Postgres>,
     pub config: Config,
     pub tx: ClientSender,
+    pub changes: ChangesNotifier,
 }
 
 impl AppState {
-    pub fn new(db: Pool<Postgres>, config: Config, tx: ClientSender) -> Self {
-        Self { db, config, tx }
+    pub fn new(
+        db: Pool<Postgres>,
+        config: Config,
+        tx: ClientSender,
+        changes: ChangesNotifier,
+    ) -> Self {
+        Self {
+            db,
+            config,
+            tx,
+            changes,
+        }
     }
 }
 
@@ -2,10 +2,13 @@
 
 use axum::{
     extract::State,
-    headers::{authorization::Bearer, Authorization, HeaderMapExt},
-    http::{HeaderMap, HeaderValue, Request},
+    headers::{
+        authorization::{Basic, Bearer},
+        Authorization, HeaderMapExt,
+    },
+    http::{header, HeaderMap, HeaderValue, Request},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
 use reqwest::StatusCode;
 
@@ -15,6 +18,7 @@ use crate::{
         routing::app_state::AppState,
     },
     errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    services::app_passwords::AppPasswordsService,
 };
 
 /// Middleware that requires to be loggen in
@@ -30,6 +34,46 @@ pub async fn logged_in_required<B>(
     Ok(next.run(req).await)
 }
 
+/// Middleware for clients like WebDAV ones which know only Basic authentication.
+///
+/// A bearer token works as well, Basic credentials are the email of a user and one of their
+/// application passwords, the password of the account isn't accepted
+pub async fn basic_or_bearer_required<B>(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let auth_user = match authenticate(req.headers(), &state.config.secret_key) {
+        Ok(auth_user) => Ok(auth_user),
+        Err(_) => authenticate_basic(req.headers(), &state).await,
+    };
+
+    match auth_user {
+        Ok(auth_user) => {
+            req.extensions_mut().insert(auth_user);
+            next.run(req).await
+        }
+        Err(e) => {
+            let (status, message) = <(StatusCode, String)>::from(e);
+            let challenge = [(header::WWW_AUTHENTICATE, r#"Basic realm="cloud_boostclicks""#)];
+            (status, challenge, message).into_response()
+        }
+    }
+}
+
+async fn authenticate_basic(
+    headers: &HeaderMap<HeaderValue>,
+    state: &AppState,
+) -> CloudBoostclicksResult<AuthUser> {
+    let auth_header = headers
+        .typed_get::<Authorization<Basic>>()
+        .ok_or(CloudBoostclicksError::NotAuthenticated)?;
+
+    AppPasswordsService::new(&state.db)
+        .authenticate(auth_header.username(), auth_header.password())
+        .await
+}
+
 #[inline]
 fn authenticate(headers: &HeaderMap<HeaderValue>, secret_key: &str) -> CloudBoostclicksResult<AuthUser> {
     let auth_header = headers
@@ -0,0 +1,337 @@
+//! Protocol pieces of the S3 API which know nothing about storages: XML bodies and error codes
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use uuid::Uuid;
+
+use crate::{
+    common::http::escape_xml as escape,
+    errors::CloudBoostclicksError,
+    models::upload_parts::UploadPart,
+    schemas::s3::{CompletedPart, ListObjectsParams, ListObjectsResult},
+};
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+const XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// Keys listed with `encoding-type=url` keep slashes
+const KEY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Timestamps of listings, e.g. `2009-10-12T17:50:30.000Z`
+pub fn timestamp(date: &NaiveDateTime) -> String {
+    date.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Continuation tokens of ListObjectsV2 are the last listed key
+pub fn continuation_token(key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(key)
+}
+
+pub fn parse_continuation_token(token: &str) -> Option<String> {
+    URL_SAFE_NO_PAD
+        .decode(token)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
+}
+
+pub fn error(code: &str, message: &str, resource: &str) -> String {
+    format!(
+        "{XML_HEADER}<Error><Code>{code}</Code><Message>{}</Message><Resource>{}</Resource><RequestId>{}</RequestId></Error>",
+        escape(message),
+        escape(resource),
+        Uuid::new_v4().simple(),
+    )
+}
+
+/// Status and S3 code of an error, `not_found` is the code of the missing entity
+/// (`NoSuchKey`, `NoSuchBucket` or `NoSuchUpload`)
+pub fn error_code(e: &CloudBoostclicksError, not_found: &'static str) -> (StatusCode, &'static str) {
+    match e {
+        CloudBoostclicksError::DoesNotExist(_) => (StatusCode::NOT_FOUND, not_found),
+        CloudBoostclicksError::NotAuthenticated => (StatusCode::FORBIDDEN, "InvalidAccessKeyId"),
+        CloudBoostclicksError::InvalidSignature => {
+            (StatusCode::FORBIDDEN, "SignatureDoesNotMatch")
+        }
+        CloudBoostclicksError::UserWasRemoved => (StatusCode::FORBIDDEN, "AccessDenied"),
+        CloudBoostclicksError::InvalidPath | CloudBoostclicksError::InvalidFolderName => {
+            (StatusCode::BAD_REQUEST, "InvalidArgument")
+        }
+        CloudBoostclicksError::InvalidUploadPart => (StatusCode::BAD_REQUEST, "InvalidPart"),
+        CloudBoostclicksError::RangeNotSatisfiable(_) => {
+            (StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange")
+        }
+        CloudBoostclicksError::AlreadyExists(_) => (StatusCode::CONFLICT, "OperationAborted"),
+        CloudBoostclicksError::HeaderMissed(_) | CloudBoostclicksError::HeaderIsInvalid(..) => {
+            (StatusCode::BAD_REQUEST, "InvalidRequest")
+        }
+        CloudBoostclicksError::ClientEncryptionRequired
+        | CloudBoostclicksError::UnavailableForClientEncryption
+        | CloudBoostclicksError::InvalidArchive => (StatusCode::BAD_REQUEST, "InvalidRequest"),
+        CloudBoostclicksError::StorageDoesNotHaveWorkers => {
+            (StatusCode::CONFLICT, "InvalidBucketState")
+        }
+        e => {
+            tracing::error!("{e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "InternalError")
+        }
+    }
+}
+
+pub fn list_buckets(owner_id: Uuid, owner_name: &str, buckets: &[Uuid]) -> String {
+    let buckets: String = buckets
+        .iter()
+        .map(|bucket| {
+            format!(
+                "<Bucket><Name>{bucket}</Name><CreationDate>{}</CreationDate></Bucket>",
+                timestamp(&NaiveDateTime::default())
+            )
+        })
+        .collect();
+
+    format!(
+        r#"{XML_HEADER}<ListAllMyBucketsResult xmlns="{XMLNS}"><Owner><ID>{owner_id}</ID><DisplayName>{}</DisplayName></Owner><Buckets>{buckets}</Buckets></ListAllMyBucketsResult>"#,
+        escape(owner_name)
+    )
+}
+
+/// Bodies of both ListObjects and ListObjectsV2, `v2` holds the continuation token of the latter
+pub fn list_objects(
+    bucket: Uuid,
+    params: &ListObjectsParams,
+    result: &ListObjectsResult,
+    v2: Option<Option<&str>>,
+    url_encoded: bool,
+) -> String {
+    let key = |key: &str| match url_encoded {
+        true => utf8_percent_encode(key, KEY).to_string(),
+        false => escape(key),
+    };
+
+    let mut body = format!(
+        r#"{XML_HEADER}<ListBucketResult xmlns="{XMLNS}"><Name>{bucket}</Name><Prefix>{}</Prefix><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>"#,
+        key(&params.prefix),
+        params.max_keys,
+        result.is_truncated,
+    );
+    if let Some(delimiter) = &params.delimiter {
+        body.push_str(&format!("<Delimiter>{}</Delimiter>", key(delimiter)));
+    }
+    if url_encoded {
+        body.push_str("<EncodingType>url</EncodingType>");
+    }
+
+    match v2 {
+        Some(token) => {
+            body.push_str(&format!(
+                "<KeyCount>{}</KeyCount>",
+                result.contents.len() + result.common_prefixes.len()
+            ));
+            if let Some(token) = token {
+                body.push_str(&format!(
+                    "<ContinuationToken>{}</ContinuationToken>",
+                    escape(token)
+                ));
+            }
+            if let Some(next) = &result.next_marker {
+                body.push_str(&format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    continuation_token(next)
+                ));
+            }
+        }
+        None => {
+            body.push_str(&format!(
+                "<Marker>{}</Marker>",
+                key(params.marker.as_deref().unwrap_or_default())
+            ));
+            if let Some(next) = &result.next_marker {
+                body.push_str(&format!("<NextMarker>{}</NextMarker>", key(next)));
+            }
+        }
+    }
+
+    for entry in &result.contents {
+        body.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            key(&entry.key),
+            timestamp(&entry.last_modified),
+            entry.etag,
+            entry.size,
+        ));
+    }
+    for prefix in &result.common_prefixes {
+        body.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            key(prefix)
+        ));
+    }
+
+    body.push_str("</ListBucketResult>");
+    body
+}
+
+pub fn location() -> String {
+    format!(r#"{XML_HEADER}<LocationConstraint xmlns="{XMLNS}"></LocationConstraint>"#)
+}
+
+pub fn copy_object(etag: &str, last_modified: &NaiveDateTime) -> String {
+    format!(
+        r#"{XML_HEADER}<CopyObjectResult xmlns="{XMLNS}"><LastModified>{}</LastModified><ETag>&quot;{etag}&quot;</ETag></CopyObjectResult>"#,
+        timestamp(last_modified)
+    )
+}
+
+/// Result of DeleteObjects, errors are pairs of a key and an S3 error code
+pub fn delete_result(deleted: &[String], errors: &[(String, &str)], quiet: bool) -> String {
+    let mut body = format!(r#"{XML_HEADER}<DeleteResult xmlns="{XMLNS}">"#);
+    if !quiet {
+        for key in deleted {
+            body.push_str(&format!("<Deleted><Key>{}</Key></Deleted>", escape(key)));
+        }
+    }
+    for (key, code) in errors {
+        body.push_str(&format!(
+            "<Error><Key>{}</Key><Code>{code}</Code></Error>",
+            escape(key)
+        ));
+    }
+    body.push_str("</DeleteResult>");
+    body
+}
+
+pub fn initiate_multipart_upload(bucket: Uuid, key: &str, upload_id: Uuid) -> String {
+    format!(
+        r#"{XML_HEADER}<InitiateMultipartUploadResult xmlns="{XMLNS}"><Bucket>{bucket}</Bucket><Key>{}</Key><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>"#,
+        escape(key)
+    )
+}
+
+pub fn complete_multipart_upload(bucket: Uuid, key: &str, etag: &str) -> String {
+    format!(
+        r#"{XML_HEADER}<CompleteMultipartUploadResult xmlns="{XMLNS}"><Bucket>{bucket}</Bucket><Key>{}</Key><ETag>&quot;{etag}&quot;</ETag></CompleteMultipartUploadResult>"#,
+        escape(key)
+    )
+}
+
+pub fn list_parts(bucket: Uuid, key: &str, upload_id: Uuid, parts: &[UploadPart]) -> String {
+    let parts: String = parts
+        .iter()
+        .map(|part| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag><Size>{}</Size></Part>",
+                part.part_number,
+                timestamp(&part.uploaded_at),
+                part.etag,
+                part.size,
+            )
+        })
+        .collect();
+
+    format!(
+        r#"{XML_HEADER}<ListPartsResult xmlns="{XMLNS}"><Bucket>{bucket}</Bucket><Key>{}</Key><UploadId>{upload_id}</UploadId><IsTruncated>false</IsTruncated>{parts}</ListPartsResult>"#,
+        escape(key)
+    )
+}
+
+/// Unfinished uploads aren't listed, clients abort uploads they know about
+pub fn list_multipart_uploads(bucket: Uuid) -> String {
+    format!(
+        r#"{XML_HEADER}<ListMultipartUploadsResult xmlns="{XMLNS}"><Bucket>{bucket}</Bucket><IsTruncated>false</IsTruncated></ListMultipartUploadsResult>"#
+    )
+}
+
+/// Keys of a `<Delete>` request and whether it's quiet
+pub fn parse_delete(body: &str) -> Option<(Vec<String>, bool)> {
+    let keys: Vec<String> = elements(body, "Object")
+        .iter()
+        .filter_map(|object| elements(object, "Key").first().map(|key| unescape(key)))
+        .collect();
+    if keys.is_empty() {
+        return None;
+    }
+
+    let quiet = elements(body, "Quiet")
+        .first()
+        .is_some_and(|quiet| quiet.trim().eq_ignore_ascii_case("true"));
+    Some((keys, quiet))
+}
+
+/// Parts of a `<CompleteMultipartUpload>` request, ETags are without quotes
+pub fn parse_complete(body: &str) -> Option<Vec<CompletedPart>> {
+    elements(body, "Part")
+        .iter()
+        .map(|part| {
+            let part_number = elements(part, "PartNumber").first()?.trim().parse().ok()?;
+            let etag = unescape(elements(part, "ETag").first()?)
+                .trim()
+                .trim_matches('"')
+                .to_string();
+            Some(CompletedPart { part_number, etag })
+        })
+        .collect()
+}
+
+/// Contents of the elements with the name, nested elements of the same name aren't expected
+fn elements<'b>(body: &'b str, name: &str) -> Vec<&'b str> {
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    let mut found = Vec::new();
+    let mut rest = body;
+
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(decoded) => {
+                result.push(decoded);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,300 @@
+//! Protocol pieces of SFTP version 3 (draft-ietf-secsh-filexfer-02) which know nothing about storages
+
+use chrono::NaiveDateTime;
+
+use crate::errors::CloudBoostclicksError;
+
+pub const VERSION: u32 = 3;
+
+pub const FXP_INIT: u8 = 1;
+pub const FXP_VERSION: u8 = 2;
+pub const FXP_OPEN: u8 = 3;
+pub const FXP_CLOSE: u8 = 4;
+pub const FXP_READ: u8 = 5;
+pub const FXP_WRITE: u8 = 6;
+pub const FXP_LSTAT: u8 = 7;
+pub const FXP_FSTAT: u8 = 8;
+pub const FXP_SETSTAT: u8 = 9;
+pub const FXP_FSETSTAT: u8 = 10;
+pub const FXP_OPENDIR: u8 = 11;
+pub const FXP_READDIR: u8 = 12;
+pub const FXP_REMOVE: u8 = 13;
+pub const FXP_MKDIR: u8 = 14;
+pub const FXP_RMDIR: u8 = 15;
+pub const FXP_REALPATH: u8 = 16;
+pub const FXP_STAT: u8 = 17;
+pub const FXP_RENAME: u8 = 18;
+pub const FXP_STATUS: u8 = 101;
+pub const FXP_HANDLE: u8 = 102;
+pub const FXP_DATA: u8 = 103;
+pub const FXP_NAME: u8 = 104;
+pub const FXP_ATTRS: u8 = 105;
+
+pub const FX_OK: u32 = 0;
+pub const FX_EOF: u32 = 1;
+pub const FX_NO_SUCH_FILE: u32 = 2;
+pub const FX_PERMISSION_DENIED: u32 = 3;
+pub const FX_FAILURE: u32 = 4;
+pub const FX_BAD_MESSAGE: u32 = 5;
+pub const FX_OP_UNSUPPORTED: u32 = 8;
+
+pub const FXF_WRITE: u32 = 0x02;
+pub const FXF_APPEND: u32 = 0x04;
+pub const FXF_CREAT: u32 = 0x08;
+pub const FXF_TRUNC: u32 = 0x10;
+pub const FXF_EXCL: u32 = 0x20;
+
+const ATTR_SIZE: u32 = 0x01;
+const ATTR_UIDGID: u32 = 0x02;
+const ATTR_PERMISSIONS: u32 = 0x04;
+const ATTR_ACMODTIME: u32 = 0x08;
+const ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const MODE_FILE: u32 = 0o100644;
+const MODE_DIR: u32 = 0o040755;
+
+/// Attributes of a file, only the ones the server knows are sent
+#[derive(Default)]
+pub struct Attrs {
+    pub size: Option<u64>,
+    pub permissions: Option<u32>,
+    pub mtime: Option<u32>,
+}
+
+impl Attrs {
+    pub fn file(size: u64, updated_at: &NaiveDateTime) -> Self {
+        Self {
+            size: Some(size),
+            permissions: Some(MODE_FILE),
+            mtime: Some(updated_at.and_utc().timestamp().max(0) as u32),
+        }
+    }
+
+    pub fn dir(updated_at: Option<&NaiveDateTime>) -> Self {
+        Self {
+            size: Some(0),
+            permissions: Some(MODE_DIR),
+            mtime: updated_at.map(|updated_at| updated_at.and_utc().timestamp().max(0) as u32),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.permissions.is_some_and(|permissions| permissions & 0o170000 == 0o040000)
+    }
+
+    /// The `ls -l` line clients show, e.g. `-rw-r--r-- 1 cloud cloud 1024 Jan 01 12:00 name`
+    pub fn long_name(&self, name: &str) -> String {
+        let kind = if self.is_dir() { "drwxr-xr-x" } else { "-rw-r--r--" };
+        let date = self
+            .mtime
+            .and_then(|mtime| chrono::DateTime::from_timestamp(mtime.into(), 0))
+            .map(|date| date.format("%b %d %H:%M").to_string())
+            .unwrap_or_else(|| "Jan 01  1970".to_string());
+        format!(
+            "{kind} 1 cloud cloud {:>10} {date} {name}",
+            self.size.unwrap_or_default()
+        )
+    }
+}
+
+/// Reads fields of a request packet, `None` means that the packet is malformed
+pub struct Reader<'p> {
+    data: &'p [u8],
+}
+
+impl<'p> Reader<'p> {
+    pub fn new(data: &'p [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'p [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Option<&'p [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        self.bytes()
+            .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+    }
+
+    /// Attributes sent by clients are read to get past them, the server doesn't store them
+    pub fn attrs(&mut self) -> Option<()> {
+        let flags = self.u32()?;
+        if flags & ATTR_SIZE != 0 {
+            self.u64()?;
+        }
+        if flags & ATTR_UIDGID != 0 {
+            self.take(8)?;
+        }
+        if flags & ATTR_PERMISSIONS != 0 {
+            self.u32()?;
+        }
+        if flags & ATTR_ACMODTIME != 0 {
+            self.take(8)?;
+        }
+        if flags & ATTR_EXTENDED != 0 {
+            for _ in 0..self.u32()? {
+                self.bytes()?;
+                self.bytes()?;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Builds a response packet, the length is filled in by `finish`
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new(kind: u8) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&[0; 4]);
+        buf.push(kind);
+        Self { buf }
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn bytes(mut self, value: &[u8]) -> Self {
+        self = self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn attrs(mut self, attrs: &Attrs) -> Self {
+        let mut flags = 0;
+        if attrs.size.is_some() {
+            flags |= ATTR_SIZE;
+        }
+        if attrs.permissions.is_some() {
+            flags |= ATTR_PERMISSIONS;
+        }
+        if attrs.mtime.is_some() {
+            flags |= ATTR_ACMODTIME;
+        }
+
+        self = self.u32(flags);
+        if let Some(size) = attrs.size {
+            self = self.u64(size);
+        }
+        if let Some(permissions) = attrs.permissions {
+            self = self.u32(permissions);
+        }
+        if let Some(mtime) = attrs.mtime {
+            self = self.u32(mtime).u32(mtime);
+        }
+        self
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let len = (self.buf.len() - 4) as u32;
+        self.buf[..4].copy_from_slice(&len.to_be_bytes());
+        self.buf
+    }
+}
+
+pub fn version() -> Vec<u8> {
+    Writer::new(FXP_VERSION).u32(VERSION).finish()
+}
+
+pub fn status(id: u32, code: u32, message: &str) -> Vec<u8> {
+    Writer::new(FXP_STATUS)
+        .u32(id)
+        .u32(code)
+        .bytes(message.as_bytes())
+        .bytes(b"en")
+        .finish()
+}
+
+pub fn handle(id: u32, handle: u32) -> Vec<u8> {
+    Writer::new(FXP_HANDLE)
+        .u32(id)
+        .bytes(&handle.to_be_bytes())
+        .finish()
+}
+
+pub fn data(id: u32, data: &[u8]) -> Vec<u8> {
+    Writer::new(FXP_DATA).u32(id).bytes(data).finish()
+}
+
+pub fn attrs(id: u32, attrs: &Attrs) -> Vec<u8> {
+    Writer::new(FXP_ATTRS).u32(id).attrs(attrs).finish()
+}
+
+pub fn name(id: u32, entries: &[(String, Attrs)]) -> Vec<u8> {
+    entries
+        .iter()
+        .fold(
+            Writer::new(FXP_NAME).u32(id).u32(entries.len() as u32),
+            |writer, (name, attrs)| {
+                writer
+                    .bytes(name.as_bytes())
+                    .bytes(attrs.long_name(name).as_bytes())
+                    .attrs(attrs)
+            },
+        )
+        .finish()
+}
+
+/// Status code and message of a failed operation
+pub fn error_status(id: u32, e: &CloudBoostclicksError) -> Vec<u8> {
+    let code = match e {
+        CloudBoostclicksError::DoesNotExist(_) => FX_NO_SUCH_FILE,
+        CloudBoostclicksError::NotAuthenticated => FX_PERMISSION_DENIED,
+        _ => FX_FAILURE,
+    };
+    status(id, code, &e.to_string())
+}
+
+/// Handles are sent as 4 bytes of the handle number
+pub fn parse_handle(handle: &[u8]) -> Option<u32> {
+    handle.try_into().ok().map(u32::from_be_bytes)
+}
+
+/// Resolves `.` and `..`, relative paths start at the root
+pub fn normalize(path: &str) -> Vec<String> {
+    let mut segments: Vec<String> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+    segments
+}
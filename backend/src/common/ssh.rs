@@ -0,0 +1,55 @@
+//! OpenSSH public keys as they are written in `authorized_keys`
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
+use sha2::{Digest, Sha256};
+
+const ALGORITHMS: [&str; 8] = [
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+    "ssh-dss",
+];
+
+pub struct PublicKey {
+    pub algorithm: String,
+    pub blob: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+impl PublicKey {
+    /// Parses `<algorithm> <base64 blob> [comment]`, the blob has to start with the same algorithm
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let algorithm = fields.next().filter(|algorithm| ALGORITHMS.contains(algorithm))?;
+        let blob = STANDARD.decode(fields.next()?).ok()?;
+        let comment = fields.collect::<Vec<_>>().join(" ");
+
+        let len = u32::from_be_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+        if blob.get(4..4 + len)? != algorithm.as_bytes() {
+            return None;
+        }
+
+        Some(Self {
+            algorithm: algorithm.to_string(),
+            blob,
+            comment: (!comment.is_empty()).then_some(comment),
+        })
+    }
+
+    /// The key without the comment
+    pub fn to_openssh(&self) -> String {
+        format!("{} {}", self.algorithm, STANDARD.encode(&self.blob))
+    }
+
+    /// `SHA256:<base64>`, the format of `ssh-keygen -l` and of `%f` in sshd config
+    pub fn fingerprint(&self) -> String {
+        format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(&self.blob)))
+    }
+}
@@ -0,0 +1,249 @@
+use std::io::Write;
+
+use chrono::NaiveDateTime;
+use flate2::{write::GzEncoder, Compression};
+use tar::{EntryType, Header};
+
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+
+const BLOCK_SIZE: u64 = 512;
+/// Longer names go to a GNU long name entry in front of the header
+const NAME_SIZE: usize = 100;
+const LONG_NAME_PATH: &[u8] = b"././@LongLink";
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy)]
+pub enum TarCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+enum Encoder {
+    None,
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(compression: TarCompression) -> CloudBoostclicksResult<Self> {
+        Ok(match compression {
+            TarCompression::None => Self::None,
+            TarCompression::Gzip => Self::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            TarCompression::Zstd => Self::Zstd(
+                zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL).map_err(map_io_error)?,
+            ),
+        })
+    }
+
+    /// Returns whatever the compressor is ready to give away
+    fn write(&mut self, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip(encoder) => {
+                encoder.write_all(data).map_err(map_io_error)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            Self::Zstd(encoder) => {
+                encoder.write_all(data).map_err(map_io_error)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> CloudBoostclicksResult<Vec<u8>> {
+        match self {
+            Self::None => Ok(Vec::new()),
+            Self::Gzip(encoder) => encoder.finish().map_err(map_io_error),
+            Self::Zstd(encoder) => encoder.finish().map_err(map_io_error),
+        }
+    }
+}
+
+/// Writes a tar archive entry by entry, optionally compressing it on the fly.
+///
+/// Sizes of files go in front of their content, so they have to be known in advance
+pub struct TarWriter {
+    encoder: Encoder,
+    /// Bytes of the current file which haven't been written yet
+    remaining: u64,
+    /// Padding which ends the current file
+    padding: u64,
+}
+
+impl TarWriter {
+    pub fn new(compression: TarCompression) -> CloudBoostclicksResult<Self> {
+        Ok(Self {
+            encoder: Encoder::new(compression)?,
+            remaining: 0,
+            padding: 0,
+        })
+    }
+
+    /// Starts a new entry, paths ending with a slash are written as folders
+    pub fn start_file(
+        &mut self,
+        path: &str,
+        size: u64,
+        modified_at: NaiveDateTime,
+    ) -> CloudBoostclicksResult<Vec<u8>> {
+        let is_folder = path.ends_with('/');
+        let size = if is_folder { 0 } else { size };
+        let mut output = Vec::new();
+
+        let name = path.as_bytes();
+        if name.len() > NAME_SIZE {
+            let mut header = Header::new_gnu();
+            header.as_old_mut().name[..LONG_NAME_PATH.len()].copy_from_slice(LONG_NAME_PATH);
+            header.set_entry_type(EntryType::GNULongName);
+            header.set_mode(0o644);
+            header.set_size(name.len() as u64 + 1);
+            header.set_cksum();
+
+            output.extend_from_slice(header.as_bytes());
+            output.extend_from_slice(name);
+            output.resize(output.len() + padding(name.len() as u64 + 1) as usize + 1, 0);
+        }
+
+        let mut header = Header::new_gnu();
+        let name_len = name.len().min(NAME_SIZE);
+        header.as_old_mut().name[..name_len].copy_from_slice(&name[..name_len]);
+        if is_folder {
+            header.set_entry_type(EntryType::Directory);
+            header.set_mode(0o755);
+        } else {
+            header.set_entry_type(EntryType::Regular);
+            header.set_mode(0o644);
+        }
+        header.set_size(size);
+        header.set_mtime(modified_at.and_utc().timestamp().max(0) as u64);
+        header.set_cksum();
+        output.extend_from_slice(header.as_bytes());
+
+        self.remaining = size;
+        self.padding = padding(size);
+
+        self.encoder.write(&output)
+    }
+
+    /// Adds the next part of the content of the current file
+    pub fn write(&mut self, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        let len = data.len() as u64;
+        if len > self.remaining {
+            tracing::error!("file is larger than its size in the tar header");
+            return Err(CloudBoostclicksError::ChecksumMismatch);
+        }
+        self.remaining -= len;
+
+        self.encoder.write(data)
+    }
+
+    pub fn finish_file(&mut self) -> CloudBoostclicksResult<Vec<u8>> {
+        if self.remaining != 0 {
+            tracing::error!("file is smaller than its size in the tar header");
+            return Err(CloudBoostclicksError::ChecksumMismatch);
+        }
+
+        let padding = vec![0; self.padding as usize];
+        self.encoder.write(&padding)
+    }
+
+    /// Ends the archive with two empty blocks
+    pub fn finish(mut self) -> CloudBoostclicksResult<Vec<u8>> {
+        let mut output = self.encoder.write(&[0; 2 * BLOCK_SIZE as usize])?;
+        output.extend(self.encoder.finish()?);
+        Ok(output)
+    }
+}
+
+/// Zeros needed to fill the last block of the content
+fn padding(size: u64) -> u64 {
+    (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+fn map_io_error(e: std::io::Error) -> CloudBoostclicksError {
+    tracing::error!("can't write tar: {e}");
+    CloudBoostclicksError::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::NaiveDate;
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn read_back(compression: TarCompression, archive: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+        let reader: Box<dyn Read> = match compression {
+            TarCompression::None => Box::new(Cursor::new(archive)),
+            TarCompression::Gzip => Box::new(GzDecoder::new(Cursor::new(archive))),
+            TarCompression::Zstd => {
+                Box::new(zstd::stream::read::Decoder::new(Cursor::new(archive)).unwrap())
+            }
+        };
+
+        tar::Archive::new(reader)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content).unwrap();
+                (path, content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn written_archive_is_read_back() {
+        let modified_at = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let long_name = format!("docs/{}.txt", "long".repeat(40));
+        let data: Vec<u8> = (0..3000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let files = [
+            ("docs/".to_string(), Vec::new()),
+            ("docs/data.bin".to_string(), data.clone()),
+            (long_name, b"content".to_vec()),
+            ("empty.txt".to_string(), Vec::new()),
+        ];
+
+        for compression in [TarCompression::None, TarCompression::Gzip, TarCompression::Zstd] {
+            let mut writer = TarWriter::new(compression).unwrap();
+            let mut archive = Vec::new();
+            for (path, content) in &files {
+                archive.extend(
+                    writer
+                        .start_file(path, content.len() as u64, modified_at)
+                        .unwrap(),
+                );
+                for part in content.chunks(1000) {
+                    archive.extend(writer.write(part).unwrap());
+                }
+                archive.extend(writer.finish_file().unwrap());
+            }
+            archive.extend(writer.finish().unwrap());
+
+            assert_eq!(read_back(compression, archive), files);
+        }
+    }
+
+    #[test]
+    fn content_has_to_match_the_declared_size() {
+        let modified_at = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut writer = TarWriter::new(TarCompression::None).unwrap();
+
+        writer.start_file("a.txt", 3, modified_at).unwrap();
+        assert!(writer.write(b"abcd").is_err());
+        writer.write(b"ab").unwrap();
+        assert!(writer.finish_file().is_err());
+    }
+}
@@ -1,10 +1,10 @@
-﻿use reqwest::multipart;
+﻿use reqwest::{multipart, StatusCode};
 use crate::{
     common::types::ChatId,
-    errors::CloudBoostclicksResult,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
 };
 
-use super::schemas::{DownloadBodySchema, UploadBodySchema, UploadSchema};
+use super::schemas::{DownloadBodySchema, DownloadSchema, UploadBodySchema, UploadResultSchema};
 
 pub struct TelegramBotApi<'t> {
     base_url: &'t str,
@@ -20,7 +20,7 @@ impl<'t> TelegramBotApi<'t> {
         file: &[u8],
         chat_id: ChatId,
         token: String,
-    ) -> CloudBoostclicksResult<UploadSchema> {
+    ) -> CloudBoostclicksResult<UploadResultSchema> {
         let chat_id = Self::normalize_chat_id(chat_id);
 
         let url = self.build_url("", "sendDocument", &token);
@@ -39,7 +39,7 @@ impl<'t> TelegramBotApi<'t> {
 
         match response.error_for_status() {
             // https://stackoverflow.com/a/32679930/12255756
-            Ok(r) => Ok(r.json::<UploadBodySchema>().await?.result.document),
+            Ok(r) => Ok(r.json::<UploadBodySchema>().await?.result),
             Err(e) => Err(e.into()),
         }
     }
@@ -50,18 +50,10 @@ impl<'t> TelegramBotApi<'t> {
         token: String,
     ) -> CloudBoostclicksResult<Vec<u8>> {
         // getting file path
-        let url = self.build_url("", "getFile", &token);
-        // TODO: add retries with their number taking from env
-        let body: DownloadBodySchema = reqwest::Client::new()
-            .get(url)
-            .query(&[("file_id", telegram_file_id)])
-            .send()
-            .await?
-            .json()
-            .await?;
+        let file_info = self.get_file(telegram_file_id, &token).await?;
 
         // downloading the file itself
-        let url = self.build_url("file/", &body.result.file_path, &token);
+        let url = self.build_url("file/", &file_info.file_path, &token);
         let file = reqwest::get(url)
             .await?
             .bytes()
@@ -71,6 +63,78 @@ impl<'t> TelegramBotApi<'t> {
         Ok(file)
     }
 
+    /// Gets info about the file, fails if telegram can't provide it anymore
+    pub async fn get_file(
+        &self,
+        telegram_file_id: &str,
+        token: &str,
+    ) -> CloudBoostclicksResult<DownloadSchema> {
+        let url = self.build_url("", "getFile", token);
+        // TODO: add retries with their number taking from env
+        let response = reqwest::Client::new()
+            .get(url)
+            .query(&[("file_id", telegram_file_id)])
+            .send()
+            .await?;
+        // telegram answers so to file ids it doesn't know, unlike limits and outages it's final
+        if response.status() == StatusCode::BAD_REQUEST {
+            return Err(CloudBoostclicksError::TelegramFileNotFound);
+        }
+        let body: DownloadBodySchema = response.error_for_status()?.json().await?;
+
+        Ok(body.result)
+    }
+
+    /// Forwards a message into another chat, the bot has to be a member of both chats
+    pub async fn forward_message(
+        &self,
+        chat_id: ChatId,
+        from_chat_id: ChatId,
+        message_id: i64,
+        token: String,
+    ) -> CloudBoostclicksResult<UploadResultSchema> {
+        let chat_id = Self::normalize_chat_id(chat_id);
+
+        let url = self.build_url("", "forwardMessage", &token);
+
+        let response = reqwest::Client::new()
+            .post(url)
+            .form(&[
+                ("chat_id", chat_id.to_string()),
+                ("from_chat_id", from_chat_id.to_string()),
+                ("message_id", message_id.to_string()),
+            ])
+            .send()
+            .await?;
+
+        match response.error_for_status() {
+            Ok(r) => Ok(r.json::<UploadBodySchema>().await?.result),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes a message sent by the bot, e.g. an uploaded chunk nobody refers to anymore
+    pub async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: i64,
+        token: String,
+    ) -> CloudBoostclicksResult<()> {
+        let url = self.build_url("", "deleteMessage", &token);
+
+        reqwest::Client::new()
+            .post(url)
+            .form(&[
+                ("chat_id", chat_id.to_string()),
+                ("message_id", message_id.to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
     /// Taking token by a value to force dropping it so it can be used only once
     #[inline]
     fn build_url(&self, pre: &str, relative: &str, token: &str) -> String {
@@ -1,5 +1,7 @@
 ﻿use serde::Deserialize;
 
+use crate::common::types::ChatId;
+
 #[derive(Deserialize)]
 pub struct UploadBodySchema {
     pub result: UploadResultSchema,
@@ -7,9 +9,16 @@ pub struct UploadBodySchema {
 
 #[derive(Deserialize)]
 pub struct UploadResultSchema {
+    pub message_id: i64,
+    pub chat: ChatSchema,
     pub document: UploadSchema,
 }
 
+#[derive(Deserialize)]
+pub struct ChatSchema {
+    pub id: ChatId,
+}
+
 #[derive(Deserialize)]
 pub struct UploadSchema {
     pub file_id: String,
@@ -23,5 +32,6 @@ pub struct DownloadBodySchema {
 #[derive(Deserialize)]
 pub struct DownloadSchema {
     pub file_path: String,
+    pub file_size: Option<i64>,
 }
 
@@ -0,0 +1,136 @@
+//! Protocol pieces of WebDAV (RFC 4918) which know nothing about storages
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use uuid::Uuid;
+
+use crate::{
+    common::http::{escape_xml as escape, http_date},
+    schemas::webdav::DavResource,
+};
+
+/// Characters escaped in a segment of an href, `/` is escaped since segments are encoded one by one
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Methods answered by the server, sent in the `Allow` header
+pub const ALLOWED_METHODS: &str =
+    "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, MKCOL, MOVE, COPY, LOCK, UNLOCK";
+
+/// Locks aren't kept, a lock is granted for this amount of seconds and simply forgotten
+pub const LOCK_TIMEOUT_SECS: u32 = 3600;
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="utf-8"?>"#;
+
+/// Builds the href of a path relative to the storage root
+pub fn href(base: &str, path: &str, is_collection: bool) -> String {
+    let mut href = base.trim_end_matches('/').to_string();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        href.push('/');
+        href.extend(utf8_percent_encode(segment, SEGMENT));
+    }
+    if is_collection {
+        href.push('/');
+    }
+    href
+}
+
+/// Turns the `Destination` header into a path relative to the storage root.
+///
+/// The header holds an absolute URL or an absolute path, it has to point into the same storage
+pub fn destination_path(destination: &str, base: &str) -> Option<String> {
+    let path = match destination.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => destination,
+    };
+    let path = path.strip_prefix(base.trim_end_matches('/'))?;
+    if !path.is_empty() && !path.starts_with('/') {
+        return None;
+    }
+
+    percent_decode_str(path)
+        .decode_utf8()
+        .ok()
+        .map(|path| path.trim_matches('/').to_string())
+}
+
+/// Body of a `207 Multi-Status` response to PROPFIND
+pub fn multistatus(base: &str, resources: &[DavResource]) -> String {
+    let mut body = format!(r#"{XML_HEADER}<D:multistatus xmlns:D="DAV:">"#);
+
+    for resource in resources {
+        let href = href(base, &resource.path, resource.is_collection);
+        let mut props = format!(
+            "<D:displayname>{}</D:displayname>",
+            escape(resource.name())
+        );
+
+        if resource.is_collection {
+            props.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            let content_type = mime_guess::from_path(&resource.path).first_or_octet_stream();
+            props.push_str(&format!(
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>{}</D:getcontenttype>",
+                resource.size,
+                escape(content_type.as_ref()),
+            ));
+        }
+        if let Some(etag) = &resource.etag {
+            props.push_str(&format!("<D:getetag>\"{}\"</D:getetag>", escape(etag)));
+        }
+        if let Some(updated_at) = &resource.updated_at {
+            props.push_str(&format!(
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                http_date(updated_at)
+            ));
+        }
+        if let Some(created_at) = &resource.created_at {
+            props.push_str(&format!(
+                "<D:creationdate>{}</D:creationdate>",
+                created_at.format("%Y-%m-%dT%H:%M:%SZ")
+            ));
+        }
+        props.push_str(
+            "<D:supportedlock><D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry></D:supportedlock>",
+        );
+
+        body.push_str(&format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop>{props}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+            escape(&href)
+        ));
+    }
+
+    body.push_str("</D:multistatus>");
+    body
+}
+
+/// Body of a `207 Multi-Status` response to PROPPATCH, properties aren't stored so nothing is reported back
+pub fn proppatch_status(href: &str) -> String {
+    format!(
+        r#"{XML_HEADER}<D:multistatus xmlns:D="DAV:"><D:response><D:href>{}</D:href><D:propstat><D:prop/><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response></D:multistatus>"#,
+        escape(href)
+    )
+}
+
+/// Creates a lock token and the body of the response to LOCK
+pub fn lock_discovery(href: &str) -> (String, String) {
+    let token = format!("opaquelocktoken:{}", Uuid::new_v4());
+    let body = format!(
+        r#"{XML_HEADER}<D:prop xmlns:D="DAV:"><D:lockdiscovery><D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:exclusive/></D:lockscope><D:depth>infinity</D:depth><D:timeout>Second-{LOCK_TIMEOUT_SECS}</D:timeout><D:locktoken><D:href>{token}</D:href></D:locktoken><D:lockroot><D:href>{}</D:href></D:lockroot></D:activelock></D:lockdiscovery></D:prop>"#,
+        escape(href)
+    );
+    (token, body)
+}
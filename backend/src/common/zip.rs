@@ -1,25 +1,303 @@
 use std::io::Write;
 
-use zip::write::FileOptions;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use crc32fast::Hasher;
+use flate2::{write::DeflateEncoder, Compression};
 
 use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
 
-pub fn build_zip(files: Vec<(String, Vec<u8>)>) -> CloudBoostclicksResult<Vec<u8>> {
-    let mut buffer = std::io::Cursor::new(Vec::new());
-    {
-        let mut zip = zip::ZipWriter::new(&mut buffer);
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-
-        for (path, data) in files {
-            zip.start_file(path, options)
-                .map_err(|_| CloudBoostclicksError::Unknown)?;
-            zip.write_all(&data)
-                .map_err(|_| CloudBoostclicksError::Unknown)?;
+use super::compression;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// ZIP64 is used for every entry, so sizes and offsets never have to be known in advance
+const VERSION_NEEDED: u16 = 45;
+/// Unix, so readers pick up permissions from external attributes
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_NEEDED;
+/// Sizes and CRC come in a data descriptor after the content, names are UTF-8
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+const FILE_ATTRIBUTES: u32 = 0o100644 << 16;
+/// Unix mode and the MS-DOS directory flag
+const FOLDER_ATTRIBUTES: u32 = (0o040755 << 16) | 0x10;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+struct CurrentEntry {
+    central: CentralEntry,
+    hasher: Hasher,
+    encoder: Option<DeflateEncoder<Vec<u8>>>,
+}
+
+/// Writes a ZIP64 archive entry by entry without seeking back.
+///
+/// Every method returns bytes which go right after the ones returned before,
+/// only the central directory is kept until the end
+#[derive(Default)]
+pub struct ZipWriter {
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    current: Option<CurrentEntry>,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new entry, files which are compressed already are stored as is.
+    ///
+    /// Names ending with a slash are folders and get no content
+    pub fn start_file(&mut self, name: &str, modified_at: NaiveDateTime) -> Vec<u8> {
+        let method = if !name.ends_with('/') && compression::is_compressible(name) {
+            DEFLATED
+        } else {
+            STORED
+        };
+        let (time, date) = dos_datetime(modified_at);
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, VERSION_NEEDED);
+        put_u16(&mut header, FLAGS);
+        put_u16(&mut header, method);
+        put_u16(&mut header, time);
+        put_u16(&mut header, date);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, u32::MAX);
+        put_u32(&mut header, u32::MAX);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, 20);
+        header.extend_from_slice(name.as_bytes());
+        put_u16(&mut header, ZIP64_EXTRA_ID);
+        put_u16(&mut header, 16);
+        put_u64(&mut header, 0);
+        put_u64(&mut header, 0);
+
+        let central = CentralEntry {
+            name: name.to_string(),
+            method,
+            time,
+            date,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            offset: self.offset,
+        };
+        self.current = Some(CurrentEntry {
+            central,
+            hasher: Hasher::new(),
+            encoder: (method == DEFLATED)
+                .then(|| DeflateEncoder::new(Vec::new(), Compression::default())),
+        });
+        self.offset += header.len() as u64;
+
+        header
+    }
+
+    /// Adds the next part of the content of the current entry
+    pub fn write(&mut self, data: &[u8]) -> CloudBoostclicksResult<Vec<u8>> {
+        let current = self.current.as_mut().ok_or(CloudBoostclicksError::Unknown)?;
+        current.hasher.update(data);
+        current.central.size += data.len() as u64;
+
+        let output = match current.encoder.as_mut() {
+            Some(encoder) => {
+                encoder.write_all(data).map_err(map_io_error)?;
+                std::mem::take(encoder.get_mut())
+            }
+            None => data.to_vec(),
+        };
+        current.central.compressed_size += output.len() as u64;
+        self.offset += output.len() as u64;
+
+        Ok(output)
+    }
+
+    /// Ends the current entry with the rest of compressed content and the data descriptor
+    pub fn finish_file(&mut self) -> CloudBoostclicksResult<Vec<u8>> {
+        let current = self.current.take().ok_or(CloudBoostclicksError::Unknown)?;
+        let mut central = current.central;
+
+        let mut output = match current.encoder {
+            Some(encoder) => encoder.finish().map_err(map_io_error)?,
+            None => Vec::new(),
+        };
+        central.compressed_size += output.len() as u64;
+        central.crc = current.hasher.finalize();
+
+        put_u32(&mut output, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut output, central.crc);
+        put_u64(&mut output, central.compressed_size);
+        put_u64(&mut output, central.size);
+
+        self.offset += output.len() as u64;
+        self.entries.push(central);
+
+        Ok(output)
+    }
+
+    /// Writes the central directory, nothing can be added after it
+    pub fn finish(self) -> Vec<u8> {
+        let mut output = Vec::new();
+        let central_offset = self.offset;
+
+        for entry in &self.entries {
+            put_u32(&mut output, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut output, VERSION_MADE_BY);
+            put_u16(&mut output, VERSION_NEEDED);
+            put_u16(&mut output, FLAGS);
+            put_u16(&mut output, entry.method);
+            put_u16(&mut output, entry.time);
+            put_u16(&mut output, entry.date);
+            put_u32(&mut output, entry.crc);
+            put_u32(&mut output, u32::MAX);
+            put_u32(&mut output, u32::MAX);
+            put_u16(&mut output, entry.name.len() as u16);
+            put_u16(&mut output, 28);
+            put_u16(&mut output, 0);
+            put_u16(&mut output, 0);
+            put_u16(&mut output, 0);
+            let attributes = if entry.name.ends_with('/') {
+                FOLDER_ATTRIBUTES
+            } else {
+                FILE_ATTRIBUTES
+            };
+            put_u32(&mut output, attributes);
+            put_u32(&mut output, u32::MAX);
+            output.extend_from_slice(entry.name.as_bytes());
+            put_u16(&mut output, ZIP64_EXTRA_ID);
+            put_u16(&mut output, 24);
+            put_u64(&mut output, entry.size);
+            put_u64(&mut output, entry.compressed_size);
+            put_u64(&mut output, entry.offset);
         }
 
-        zip.finish()
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
+        let central_size = output.len() as u64;
+        let zip64_end_offset = central_offset + central_size;
+        let entries = self.entries.len() as u64;
+
+        put_u32(&mut output, ZIP64_END_SIGNATURE);
+        put_u64(&mut output, 44);
+        put_u16(&mut output, VERSION_MADE_BY);
+        put_u16(&mut output, VERSION_NEEDED);
+        put_u32(&mut output, 0);
+        put_u32(&mut output, 0);
+        put_u64(&mut output, entries);
+        put_u64(&mut output, entries);
+        put_u64(&mut output, central_size);
+        put_u64(&mut output, central_offset);
+
+        put_u32(&mut output, ZIP64_LOCATOR_SIGNATURE);
+        put_u32(&mut output, 0);
+        put_u64(&mut output, zip64_end_offset);
+        put_u32(&mut output, 1);
+
+        put_u32(&mut output, END_SIGNATURE);
+        put_u16(&mut output, 0);
+        put_u16(&mut output, 0);
+        put_u16(&mut output, u16::MAX);
+        put_u16(&mut output, u16::MAX);
+        put_u32(&mut output, u32::MAX);
+        put_u32(&mut output, u32::MAX);
+        put_u16(&mut output, 0);
+
+        output
+    }
+}
+
+/// MS-DOS can't store dates before 1980
+fn dos_datetime(datetime: NaiveDateTime) -> (u16, u16) {
+    if datetime.year() < 1980 {
+        return (0, (1 << 5) | 1);
     }
 
-    Ok(buffer.into_inner())
+    let time = (datetime.hour() << 11) | (datetime.minute() << 5) | (datetime.second() / 2);
+    let date = (((datetime.year() - 1980) as u32) << 9) | (datetime.month() << 5) | datetime.day();
+    (time as u16, date as u16)
+}
+
+fn put_u16(output: &mut Vec<u8>, value: u16) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(output: &mut Vec<u8>, value: u64) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn map_io_error(e: std::io::Error) -> CloudBoostclicksError {
+    tracing::error!("can't write zip: {e}");
+    CloudBoostclicksError::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn written_archive_is_read_back() {
+        let modified_at = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(12, 30, 10)
+            .unwrap();
+        let text: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 7).to_string().into_bytes())
+            .collect();
+        let image: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 251) as u8).collect();
+
+        // the content is written in parts, the same way archives are streamed
+        let mut writer = ZipWriter::new();
+        let mut archive = writer.start_file("docs/", modified_at);
+        archive.extend(writer.finish_file().unwrap());
+        for (name, data) in [("docs/notes.txt", &text), ("photo.jpg", &image)] {
+            archive.extend(writer.start_file(name, modified_at));
+            for part in data.chunks(4096) {
+                archive.extend(writer.write(part).unwrap());
+            }
+            archive.extend(writer.finish_file().unwrap());
+        }
+        archive.extend(writer.finish());
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(zip.len(), 3);
+        assert!(zip.by_index(0).unwrap().is_dir());
+        for (index, name, data, method) in [
+            (1, "docs/notes.txt", &text, zip::CompressionMethod::Deflated),
+            (2, "photo.jpg", &image, zip::CompressionMethod::Stored),
+        ] {
+            let mut entry = zip.by_index(index).unwrap();
+            assert_eq!(entry.name(), name);
+            assert_eq!(entry.compression(), method);
+            assert_eq!(entry.last_modified().hour(), 12);
+
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            assert_eq!(&content, data);
+        }
+    }
 }
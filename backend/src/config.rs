@@ -8,6 +8,8 @@ pub struct Config {
     pub db_uri_without_dbname: String,
     pub db_name: String,
     pub port: u16,
+    /// Port of the S3 gateway, it isn't served when zero
+    pub s3_port: u16,
     pub workers: u16,
     pub channel_capacity: u16,
 
@@ -19,6 +21,20 @@ pub struct Config {
     pub telegram_rate_limit: u8,
     pub telegram_login_bot_token: String,
     pub telegram_login_max_age_secs: u64,
+
+    pub trash_retention_days: u32,
+    pub janitor_interval_secs: u64,
+
+    pub scrub_interval_secs: u64,
+    pub scrub_batch_size: u32,
+    pub scrub_recheck_days: u32,
+    pub scrub_download: bool,
+
+    /// Upper bound of archives uploaded for extraction, in bytes
+    pub extract_max_archive_size: u64,
+
+    pub encryption_secret: String,
+    pub encryption_secret_old: String,
 }
 
 impl Config {
@@ -33,6 +49,7 @@ impl Config {
         let db_uri_without_dbname =
             { format!("postgres://{db_user}:{db_password}@{db_host}:{db_port}") };
         let port = Self::get_env_var("PORT")?;
+        let s3_port = Self::get_env_var_with_default("S3_PORT", 0u16)?;
         let workers = Self::get_env_var("WORKERS")?;
         let channel_capacity = Self::get_env_var("CHANNEL_CAPACITY")?;
         let access_token_expire_in_secs = Self::get_env_var("ACCESS_TOKEN_EXPIRE_IN_SECS")?;
@@ -43,12 +60,25 @@ impl Config {
         let telegram_login_bot_token = Self::get_env_var("TELEGRAM_LOGIN_BOT_TOKEN")?;
         let telegram_login_max_age_secs =
             Self::get_env_var_with_default("TELEGRAM_LOGIN_MAX_AGE_SECS", 86400u64)?;
+        let trash_retention_days = Self::get_env_var_with_default("TRASH_RETENTION_DAYS", 30)?;
+        let janitor_interval_secs =
+            Self::get_env_var_with_default("JANITOR_INTERVAL_SECS", 3600u64)?;
+        let scrub_interval_secs = Self::get_env_var_with_default("SCRUB_INTERVAL_SECS", 60u64)?;
+        let scrub_batch_size = Self::get_env_var_with_default("SCRUB_BATCH_SIZE", 5)?;
+        let scrub_recheck_days = Self::get_env_var_with_default("SCRUB_RECHECK_DAYS", 7)?;
+        let scrub_download = Self::get_env_var_with_default("SCRUB_DOWNLOAD", false)?;
+        let extract_max_archive_size =
+            Self::get_env_var_with_default("EXTRACT_MAX_ARCHIVE_SIZE", 4 * 1024 * 1024 * 1024u64)?;
+        let encryption_secret = Self::get_env_var_with_default("ENCRYPTION_SECRET", String::new())?;
+        let encryption_secret_old =
+            Self::get_env_var_with_default("ENCRYPTION_SECRET_OLD", String::new())?;
 
         Ok(Self {
             db_uri,
             db_uri_without_dbname,
             db_name,
             port,
+            s3_port,
             workers,
             channel_capacity,
             access_token_expire_in_secs,
@@ -58,6 +88,15 @@ impl Config {
             telegram_rate_limit,
             telegram_login_bot_token,
             telegram_login_max_age_secs,
+            trash_retention_days,
+            janitor_interval_secs,
+            scrub_interval_secs,
+            scrub_batch_size,
+            scrub_recheck_days,
+            scrub_download,
+            extract_max_archive_size,
+            encryption_secret,
+            encryption_secret_old,
         })
     }
 
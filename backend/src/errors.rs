@@ -21,12 +21,14 @@ pub enum CloudBoostclicksError {
     StorageChatIdConflict,
     #[error("У пользователя уже есть бот с таким именем")]
     StorageWorkerNameConflict,
-    #[error("Токен должен быть уникальным")]
+    #[error("Этот бот уже добавлен в облако")]
     StorageWorkerTokenConflict,
     #[error("не авторизован")]
     NotAuthenticated,
     #[error("[Telegram API] {0}")]
     TelegramAPIError(String),
+    #[error("[Telegram API] файл не найден")]
+    TelegramFileNotFound,
     #[error("Добавьте хотя бы одного бота")]
     NoStorageWorkers,
     #[error("Неверный путь")]
@@ -37,6 +39,52 @@ pub enum CloudBoostclicksError {
     CannotManageAccessOfYourself,
     #[error("У облака нет ботов")]
     StorageDoesNotHaveWorkers,
+    #[error("У облаков нет общих ботов")]
+    StoragesDoNotShareWorkers,
+    #[error("Укажите сколько версий оставить или их максимальный возраст")]
+    PruneRuleMissed,
+    #[error("Реплика должна быть в другом чате")]
+    ReplicaInStorageChat,
+    #[error("Неверные параметры избыточного кодирования")]
+    InvalidErasureCoding,
+    #[error("Слишком много чанков файла утеряно")]
+    ChunksUnrecoverable,
+    #[error("Шифрование не настроено на сервере")]
+    EncryptionNotConfigured,
+    #[error("Не удалось расшифровать данные")]
+    DecryptionFailed,
+    #[error("Не удалось распаковать данные")]
+    DecompressionFailed,
+    #[error("Облако принимает только файлы, зашифрованные на клиенте")]
+    ClientEncryptionRequired,
+    #[error("Облако не использует шифрование на клиенте")]
+    NotClientEncrypted,
+    #[error("Недоступно для облака с шифрованием на клиенте")]
+    UnavailableForClientEncryption,
+    #[error("Содержимое файла повреждено")]
+    ChecksumMismatch,
+    #[error("Не удалось прочитать архив")]
+    InvalidArchive,
+    #[error("Архив слишком велик после распаковки")]
+    ArchiveTooLarge,
+    #[error("Подпись запроса не совпадает")]
+    InvalidSignature,
+    #[error("Части загрузки не совпадают с загруженными")]
+    InvalidUploadPart,
+    #[error("Неверный публичный ключ SSH")]
+    InvalidPublicKey,
+    #[error("Укажите имя приложения")]
+    InvalidAppPasswordName,
+    #[error("Курсор устарел, получите список файлов заново")]
+    CursorExpired,
+    #[error("Файл был изменён, получите его заново")]
+    PreconditionFailed,
+    #[error("Перенос прерван перезапуском сервера")]
+    TransferInterrupted,
+    #[error("Ожидается чанк {0}")]
+    UnexpectedChunk(usize),
+    #[error("Диапазон вне файла длиной {0}")]
+    RangeNotSatisfiable(u64),
     #[error("неизвестная ошибка")]
     Unknown,
     #[error("требуется заголовок {0}")]
@@ -54,13 +102,41 @@ impl From<CloudBoostclicksError> for (StatusCode, String) {
             | CloudBoostclicksError::StorageWorkerNameConflict
             | CloudBoostclicksError::StorageWorkerTokenConflict
             | CloudBoostclicksError::StorageDoesNotHaveWorkers
-            | CloudBoostclicksError::CannotManageAccessOfYourself => {
+            | CloudBoostclicksError::StoragesDoNotShareWorkers
+            | CloudBoostclicksError::CannotManageAccessOfYourself
+            | CloudBoostclicksError::ReplicaInStorageChat
+            | CloudBoostclicksError::UnexpectedChunk(_) => {
                 (StatusCode::CONFLICT, e.to_string())
             }
             CloudBoostclicksError::NotAuthenticated => (StatusCode::UNAUTHORIZED, e.to_string()),
+            CloudBoostclicksError::InvalidSignature => (StatusCode::FORBIDDEN, e.to_string()),
+            CloudBoostclicksError::ChecksumMismatch
+            | CloudBoostclicksError::ChunksUnrecoverable
+            | CloudBoostclicksError::DecryptionFailed
+            | CloudBoostclicksError::DecompressionFailed => {
+                (StatusCode::BAD_GATEWAY, e.to_string())
+            }
             CloudBoostclicksError::DoesNotExist(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            CloudBoostclicksError::CursorExpired => (StatusCode::GONE, e.to_string()),
+            CloudBoostclicksError::PreconditionFailed => {
+                (StatusCode::PRECONDITION_FAILED, e.to_string())
+            }
+            CloudBoostclicksError::ArchiveTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()),
+            CloudBoostclicksError::RangeNotSatisfiable(_) => {
+                (StatusCode::RANGE_NOT_SATISFIABLE, e.to_string())
+            }
             CloudBoostclicksError::HeaderMissed(_)
             | CloudBoostclicksError::HeaderIsInvalid(..)
+            | CloudBoostclicksError::PruneRuleMissed
+            | CloudBoostclicksError::InvalidErasureCoding
+            | CloudBoostclicksError::EncryptionNotConfigured
+            | CloudBoostclicksError::ClientEncryptionRequired
+            | CloudBoostclicksError::NotClientEncrypted
+            | CloudBoostclicksError::UnavailableForClientEncryption
+            | CloudBoostclicksError::InvalidArchive
+            | CloudBoostclicksError::InvalidUploadPart
+            | CloudBoostclicksError::InvalidPublicKey
+            | CloudBoostclicksError::InvalidAppPasswordName
             | CloudBoostclicksError::InvalidFolderName => (StatusCode::BAD_REQUEST, e.to_string()),
             _ => {
                 tracing::error!("{e}");
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::time;
+
+use crate::{
+    config::Config,
+    repositories::{changes::ChangesRepository, upload_parts::UploadPartsRepository},
+    services::{
+        files::REPLACING_PREFIX, s3::MULTIPART_PREFIX, storage_manager::StorageManagerService,
+        trash::TrashService,
+    },
+};
+
+/// Multipart uploads which are neither completed nor aborted, and replacements left
+/// by a restart, are dropped after this time
+const STALE_UPLOAD_DAYS: u32 = 7;
+/// Clients which didn't sync for longer list the whole tree again
+const CHANGES_KEEP_DAYS: u32 = 30;
+
+/// Background housekeeping: purges expired trash, stale multipart uploads and old changes
+pub struct Janitor {
+    db: PgPool,
+    config: Config,
+}
+
+impl Janitor {
+    pub fn new(db: PgPool, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = time::interval(Duration::from_secs(self.config.janitor_interval_secs));
+
+        loop {
+            interval.tick().await;
+            tracing::debug!("running janitor");
+
+            if let Err(e) = TrashService::new(&self.db, self.config.clone())
+                .purge_expired()
+                .await
+            {
+                tracing::error!("janitor failed to purge trash: {e}");
+            }
+
+            for prefix in [MULTIPART_PREFIX, REPLACING_PREFIX] {
+                match UploadPartsRepository::new(&self.db)
+                    .purge_stale(prefix, STALE_UPLOAD_DAYS)
+                    .await
+                {
+                    Ok((0, _)) => (),
+                    Ok((count, unreferenced_chunks)) => {
+                        tracing::info!("janitor dropped {count} stale uploads under {prefix}");
+                        StorageManagerService::new(
+                            &self.db,
+                            &self.config.telegram_api_base_url,
+                            &self.config.encryption_secret,
+                            self.config.telegram_rate_limit,
+                        )
+                        .delete_chunks(unreferenced_chunks)
+                        .await;
+                    }
+                    Err(e) => tracing::error!("janitor failed to drop stale uploads: {e}"),
+                }
+            }
+
+            match ChangesRepository::new(&self.db)
+                .prune(CHANGES_KEEP_DAYS)
+                .await
+            {
+                Ok(0) => (),
+                Ok(count) => tracing::info!("janitor pruned {count} old changes"),
+                Err(e) => tracing::error!("janitor failed to prune changes: {e}"),
+            }
+        }
+    }
+}
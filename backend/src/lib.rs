@@ -0,0 +1,16 @@
+pub mod client;
+pub mod common;
+pub mod config;
+pub mod errors;
+pub mod janitor;
+pub mod models;
+pub mod repositories;
+pub mod routers;
+pub mod schemas;
+pub mod scrubber;
+pub mod server;
+pub mod services;
+#[cfg(feature = "sftp")]
+pub mod sftp_server;
+pub mod startup;
+pub mod storage_manager;
@@ -1,4 +1,5 @@
 ﻿use std::{
+    env,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
 };
@@ -6,26 +7,20 @@
 use tokio::{sync::mpsc, time};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::{
-    common::{channels::ClientMessage, db::pool::get_pool, routing::app_state::AppState},
+use cloud_boostclicks::{
+    common::{
+        channels::ClientMessage, db::pool::get_pool, notifier::ChangesNotifier,
+        routing::app_state::AppState,
+    },
     config::Config,
+    janitor::Janitor,
+    scrubber::Scrubber,
     server::Server,
+    services::{encryption::EncryptionService, transfers::TransfersService},
     startup::{create_db, init_db},
     storage_manager::StorageManager,
 };
 
-mod common;
-mod config;
-mod errors;
-mod models;
-mod repositories;
-mod routers;
-mod schemas;
-mod server;
-mod services;
-mod startup;
-mod storage_manager;
-
 #[tokio::main]
 async fn main() {
     let config = Config::new().unwrap();
@@ -36,11 +31,42 @@ async fn main() {
                 "cloud_boostclicks=debug,tower_http=debug,axum::rejection=trace".into()
             }),
         )
-        .with(tracing_subscriber::fmt::layer())
+        // stdout carries the SFTP protocol when running as a subsystem
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     let (tx, rx) = mpsc::channel::<ClientMessage>(config.channel_capacity.into());
 
+    // sshd runs these for every connection, the server has already set up the schema
+    #[cfg(feature = "sftp")]
+    match env::args().nth(1).as_deref() {
+        // `authorized-keys %f` is sshd's AuthorizedKeysCommand
+        Some("authorized-keys") => {
+            let db = get_pool(&config.db_uri, 1, time::Duration::from_secs(30)).await;
+            if let Some(fingerprint) = env::args().nth(2) {
+                cloud_boostclicks::sftp_server::authorized_keys(&db, &fingerprint).await;
+            }
+            return;
+        }
+        // `sftp-server <fingerprint>` is the command sshd forces for account keys
+        Some("sftp-server") => {
+            let db = get_pool(&config.db_uri, config.workers.into(), time::Duration::from_secs(30))
+                .await;
+            run_manager(rx, config.clone());
+
+            let fingerprint = env::args().nth(2).unwrap_or_default();
+            match cloud_boostclicks::sftp_server::SftpServer::new(db, config, tx, &fingerprint).await {
+                Some(mut server) => server.run().await,
+                None => {
+                    tracing::error!("unknown ssh key {fingerprint}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => (),
+    }
+
     // creating db
     create_db(
         &config.db_uri_without_dbname,
@@ -61,30 +87,75 @@ async fn main() {
     // initing db
     init_db(&db).await;
 
+    // `rotate-keys` rewraps keys of encrypted chunks instead of serving
+    if env::args().nth(1).as_deref() == Some("rotate-keys") {
+        match EncryptionService::new(&db, config).rotate_keys().await {
+            Ok(count) => tracing::info!("rewrapped {count} data keys"),
+            Err(e) => {
+                tracing::error!("can't rotate keys: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // transfers run in the background of the process which started them
+    match TransfersService::new(&db, config.clone()).fail_interrupted().await {
+        Ok(0) => (),
+        Ok(count) => tracing::warn!("{count} transfers were interrupted by the restart"),
+        Err(e) => tracing::error!("can't fail interrupted transfers: {e}"),
+    }
+
     // running manager
-    let config_copy = config.clone();
+    run_manager(rx, config.clone());
+
+    // running janitor
+    let janitor = Janitor::new(db.clone(), config.clone());
+    tokio::spawn(async move {
+        tracing::debug!("running janitor");
+        janitor.run().await;
+    });
+
+    // running scrubber
+    let scrubber = Scrubber::new(db.clone(), config.clone());
+    tokio::spawn(async move {
+        tracing::debug!("running scrubber");
+        scrubber.run().await;
+    });
+
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), config.port);
+
+    let workers = config.workers;
+    let s3_port = config.s3_port;
+    let changes = ChangesNotifier::listen(config.db_uri.clone());
+    let shared_state = Arc::new(AppState::new(db, config, tx, changes));
+
+    // running S3 gateway
+    if s3_port != 0 {
+        let s3_server = Server::build_s3_server(workers.into(), shared_state.clone());
+        tokio::spawn(async move {
+            let s3_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), s3_port);
+            s3_server.run(&s3_addr).await
+        });
+    }
+
+    let server = Server::build_server(workers.into(), shared_state);
+
+    server.run(&addr).await
+}
+
+/// The manager uploads and downloads chunks for services, it has its own pool
+fn run_manager(rx: mpsc::Receiver<ClientMessage>, config: Config) {
     tokio::spawn(async move {
         let db = get_pool(
-            &config_copy.db_uri,
+            &config.db_uri,
             config.workers.into(),
             time::Duration::from_secs(30),
         )
         .await;
-        let mut manager = StorageManager::new(rx, db, config_copy);
+        let mut manager = StorageManager::new(rx, db, config);
 
         tracing::debug!("running manager");
         manager.run().await;
     });
-
-    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), config.port);
-
-    let server = {
-        let workers = config.workers;
-        let app_state = AppState::new(db, config, tx);
-        let shared_state = Arc::new(app_state);
-        Server::build_server(workers.into(), shared_state)
-    };
-
-    server.run(&addr).await
 }
-
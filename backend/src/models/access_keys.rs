@@ -0,0 +1,10 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Key id of S3 clients, the secret isn't stored but derived from it
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct AccessKey {
+    pub id: String,
+    pub user_id: uuid::Uuid,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,14 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Password of one application logging in with Basic authentication, only its hash is stored
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct AppPassword {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    /// Hex SHA-256 of the password, it's random, so a slow hash isn't needed
+    #[serde(skip)]
+    pub password_hash: String,
+    pub created_at: NaiveDateTime,
+}
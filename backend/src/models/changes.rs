@@ -0,0 +1,74 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(sqlx::Type, Debug, Serialize, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "change_kind", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    Move,
+    Delete,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::Move => "move",
+            ChangeKind::Delete => "delete",
+        }
+    }
+}
+
+/// A change of one row of `files`, the path is given like it's stored (folders end with a slash)
+pub struct InChange {
+    pub kind: ChangeKind,
+    pub path: String,
+    pub old_path: Option<String>,
+    pub sha256: Option<String>,
+    pub version: Option<i32>,
+}
+
+impl InChange {
+    pub fn new(kind: ChangeKind, path: String, sha256: Option<String>, version: Option<i32>) -> Self {
+        Self {
+            kind,
+            path,
+            old_path: None,
+            sha256,
+            version,
+        }
+    }
+
+    pub fn moved(old_path: String, path: String, sha256: Option<String>, version: Option<i32>) -> Self {
+        Self {
+            old_path: Some(old_path),
+            ..Self::new(ChangeKind::Move, path, sha256, version)
+        }
+    }
+
+    pub fn is_folder(&self) -> bool {
+        self.path.ends_with('/')
+    }
+}
+
+/// An entry of the change journal of a storage.
+///
+/// Paths have no trailing slash, `is_folder` tells folders apart
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Change {
+    /// Position in the journal, passing it as the cursor returns the changes after this one
+    #[serde(rename = "cursor")]
+    pub seq: i64,
+    pub kind: ChangeKind,
+    pub path: String,
+    /// Set only for moves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    pub is_folder: bool,
+    pub sha256: Option<String>,
+    pub version: Option<i32>,
+    pub changed_at: NaiveDateTime,
+}
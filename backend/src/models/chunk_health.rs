@@ -0,0 +1,37 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct HealthCounters {
+    pub checked_chunks: i64,
+    pub unchecked_chunks: i64,
+    pub damaged_chunks: i64,
+}
+
+/// A file (or one of its previous versions) with chunks telegram can't give back
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DamagedFile {
+    pub path: String,
+    pub version: Option<i32>,
+    pub in_trash: bool,
+    pub damaged_chunks: i64,
+    pub total_chunks: i64,
+    pub checked_at: Option<NaiveDateTime>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    #[serde(flatten)]
+    pub counters: HealthCounters,
+    pub damaged_files: Vec<DamagedFile>,
+}
+
+impl HealthReport {
+    pub fn new(counters: HealthCounters, damaged_files: Vec<DamagedFile>) -> Self {
+        Self {
+            counters,
+            damaged_files,
+        }
+    }
+}
@@ -1,12 +1,25 @@
-﻿use crate::common::types::Position;
+﻿use crate::common::types::{ChatId, Position};
 
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct FileChunk {
     pub id: uuid::Uuid,
     pub file_id: uuid::Uuid,
     pub telegram_file_id: String,
     pub storage_worker_id: Option<uuid::Uuid>,
     pub position: Position,
+    pub message_id: Option<i64>,
+    pub chat_id: Option<ChatId>,
+    pub version_id: Option<uuid::Uuid>,
+    pub sha256: Option<String>,
+    pub size: Option<i32>,
+    /// Index among parity chunks of the group, data chunks don't have it.
+    ///
+    /// Position of a parity chunk is the number of its group
+    pub parity_index: Option<i16>,
+    /// How many data chunks make a group
+    pub parity_group_size: Option<i16>,
+    /// Wrapped key the telegram file is encrypted with
+    pub data_key: Option<Vec<u8>>,
 }
 
 impl FileChunk {
@@ -16,6 +29,8 @@ impl FileChunk {
         telegram_file_id: String,
         storage_worker_id: Option<uuid::Uuid>,
         position: Position,
+        message_id: Option<i64>,
+        chat_id: Option<ChatId>,
     ) -> Self {
         Self {
             id,
@@ -23,7 +38,44 @@ impl FileChunk {
             telegram_file_id,
             storage_worker_id,
             position,
+            message_id,
+            chat_id,
+            version_id: None,
+            sha256: None,
+            size: None,
+            parity_index: None,
+            parity_group_size: None,
+            data_key: None,
         }
     }
+
+    /// Marks the chunk as a part of a not current version of the file
+    pub fn with_version(mut self, version_id: Option<uuid::Uuid>) -> Self {
+        self.version_id = version_id;
+        self
+    }
+
+    /// Sets the checksum and the size of the chunk's content
+    pub fn with_content(mut self, sha256: Option<String>, size: Option<i32>) -> Self {
+        self.sha256 = sha256;
+        self.size = size;
+        self
+    }
+
+    pub fn with_data_key(mut self, data_key: Option<Vec<u8>>) -> Self {
+        self.data_key = data_key;
+        self
+    }
+
+    /// Marks the chunk as a parity chunk of the group
+    pub fn with_parity(mut self, parity_index: i16, group_size: i16) -> Self {
+        self.parity_index = Some(parity_index);
+        self.parity_group_size = Some(group_size);
+        self
+    }
+
+    pub fn is_parity(&self) -> bool {
+        self.parity_index.is_some()
+    }
 }
 
@@ -0,0 +1,72 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::files::File;
+
+/// Not current content of a file.
+///
+/// `version` is empty while the version is being uploaded
+#[derive(Debug, sqlx::FromRow)]
+pub struct FileVersion {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub version: Option<i32>,
+    pub size: i64,
+    pub archived_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub uploaded_by: Option<Uuid>,
+    pub sha256: Option<String>,
+    pub encrypted_key: Option<String>,
+    pub client_metadata: Option<String>,
+    pub codec: Option<String>,
+    /// `If-Match` of the upload, the file has to still match it when the version is promoted
+    pub if_match: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileVersionInfo {
+    pub version: i32,
+    pub size: i64,
+    pub is_current: bool,
+    pub created_at: NaiveDateTime,
+    pub uploaded_by: Option<Uuid>,
+    pub sha256: Option<String>,
+    pub archived_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_metadata: Option<String>,
+}
+
+impl From<File> for FileVersionInfo {
+    fn from(file: File) -> Self {
+        Self {
+            version: file.version,
+            size: file.size,
+            is_current: true,
+            created_at: file.updated_at,
+            uploaded_by: file.uploaded_by,
+            sha256: file.sha256,
+            archived_at: None,
+            encrypted_key: file.encrypted_key,
+            client_metadata: file.client_metadata,
+        }
+    }
+}
+
+impl From<FileVersion> for FileVersionInfo {
+    fn from(v: FileVersion) -> Self {
+        Self {
+            version: v.version.unwrap_or_default(),
+            size: v.size,
+            is_current: false,
+            created_at: v.created_at,
+            uploaded_by: v.uploaded_by,
+            sha256: v.sha256,
+            archived_at: Some(v.archived_at),
+            encrypted_key: v.encrypted_key,
+            client_metadata: v.client_metadata,
+        }
+    }
+}
@@ -1,17 +1,22 @@
-﻿use serde::Serialize;
+﻿use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::common::http::etag;
 
 pub struct InFile {
     pub path: String,
     pub size: i64,
     pub storage_id: uuid::Uuid,
+    pub uploaded_by: uuid::Uuid,
 }
 
 impl InFile {
-    pub fn new(path: String, size: i64, storage_id: uuid::Uuid) -> Self {
+    pub fn new(path: String, size: i64, storage_id: uuid::Uuid, uploaded_by: uuid::Uuid) -> Self {
         Self {
             path,
             size,
             storage_id,
+            uploaded_by,
         }
     }
 }
@@ -23,23 +28,20 @@ pub struct File {
     pub size: i64,
     pub storage_id: uuid::Uuid,
     pub is_uploaded: bool,
+    pub version: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub uploaded_by: Option<uuid::Uuid>,
+    pub sha256: Option<String>,
+    pub encrypted_key: Option<String>,
+    pub client_metadata: Option<String>,
+    /// Chunks keep the file compressed with it, `size` is the size before compression
+    pub codec: Option<String>,
 }
 
 impl File {
-    pub fn new(
-        id: uuid::Uuid,
-        path: String,
-        size: i64,
-        storage_id: uuid::Uuid,
-        is_uploaded: bool,
-    ) -> Self {
-        Self {
-            id,
-            path,
-            size,
-            storage_id,
-            is_uploaded,
-        }
+    pub fn etag(&self) -> String {
+        etag(self.sha256.as_deref(), self.id, self.version)
     }
 }
 
@@ -48,19 +50,60 @@ pub struct DBFSElement {
     pub name: String,
     pub size: i64,
     pub is_file: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub uploaded_by: Option<uuid::Uuid>,
+    pub uploaded_by_identifier: Option<String>,
+    pub sha256: Option<String>,
+    pub encrypted_key: Option<String>,
+    pub client_metadata: Option<String>,
+    /// Id and version of a file, folders don't have them
+    pub file_id: Option<uuid::Uuid>,
+    pub version: Option<i32>,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
 pub struct FSElement {
     pub path: String,
     pub name: String,
     pub size: i64,
     pub is_file: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub uploaded_by: Option<uuid::Uuid>,
+    pub uploaded_by_identifier: Option<String>,
+    pub sha256: Option<String>,
+    /// Sent back in `If-Match` to change the file only if nobody changed it meanwhile.
+    ///
+    /// Empty for folders
+    #[serde(default)]
+    #[sqlx(default)]
+    pub etag: Option<String>,
+    /// Set only in client encrypted storages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_metadata: Option<String>,
+}
+
+impl FSElement {
+    pub fn without_uploader(self) -> Self {
+        Self {
+            uploaded_by: None,
+            uploaded_by_identifier: None,
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, sqlx::FromRow, Serialize)]
 pub struct SearchFSElement {
     pub path: String,
     pub is_file: bool,
+    pub size: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub uploaded_by: Option<uuid::Uuid>,
+    pub uploaded_by_identifier: Option<String>,
 }
 
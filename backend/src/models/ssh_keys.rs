@@ -0,0 +1,15 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// OpenSSH public key the user logs in to SFTP with
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct SshKey {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    /// `<algorithm> <base64 blob>` without the comment
+    pub public_key: String,
+    /// `SHA256:<base64>` like `ssh-keygen -l` prints it
+    pub fingerprint: String,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,25 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::common::types::ChatId;
+
+/// Additional chat every chunk of the storage is copied to
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StorageReplica {
+    pub id: Uuid,
+    pub chat_id: ChatId,
+    pub created_at: NaiveDateTime,
+}
+
+/// Copy of a telegram file of chunks in a replica chat
+#[derive(Debug, sqlx::FromRow)]
+pub struct ChunkCopy {
+    pub id: Uuid,
+    pub replica_id: Uuid,
+    pub source_telegram_file_id: String,
+    pub telegram_file_id: String,
+    pub storage_worker_id: Option<Uuid>,
+    pub message_id: Option<i64>,
+    pub chat_id: Option<ChatId>,
+}
@@ -1,36 +1,84 @@
-﻿use serde::Serialize;
+﻿use serde::{Deserialize, Serialize};
 
 use crate::common::types::ChatId;
 
 pub struct InStorage {
     pub name: String,
     pub chat_id: ChatId,
+    pub client_encrypted: bool,
 }
 
 impl InStorage {
-    pub fn new(name: String, chat_id: ChatId) -> Self {
-        Self { name, chat_id }
+    pub fn new(name: String, chat_id: ChatId, client_encrypted: bool) -> Self {
+        Self {
+            name,
+            chat_id,
+            client_encrypted,
+        }
     }
 }
 
+/// How much of the storage content is shared between files.
+///
+/// Sizes of chunks uploaded before their sizes were tracked are unknown and not counted
 #[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DedupStats {
+    pub total_chunks: i64,
+    pub stored_chunks: i64,
+    pub logical_size: i64,
+    pub stored_size: i64,
+    pub saved_size: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StorageSettings {
+    pub versioning: bool,
+    /// How many data chunks make a group protected by parity chunks
+    pub erasure_data_chunks: i16,
+    /// Parity chunks uploaded for every group, 0 disables erasure coding
+    pub erasure_parity_chunks: i16,
+    /// Chunks are encrypted before they are sent to telegram
+    pub encryption: bool,
+    /// Files are compressed before they are divided into chunks
+    pub compression: bool,
+    /// Files are encrypted by clients, the server never sees their content, names or keys.
+    ///
+    /// It's chosen once when the storage is created
+    pub client_encrypted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Storage {
     pub id: uuid::Uuid,
     pub name: String,
     pub chat_id: ChatId,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub settings: StorageSettings,
 }
 
 impl Storage {
-    pub fn new(id: uuid::Uuid, name: String, chat_id: ChatId) -> Self {
-        Self { id, name, chat_id }
+    pub fn new(id: uuid::Uuid, name: String, chat_id: ChatId, client_encrypted: bool) -> Self {
+        Self {
+            id,
+            name,
+            chat_id,
+            settings: StorageSettings {
+                client_encrypted,
+                ..Default::default()
+            },
+        }
     }
 }
 
-#[derive(Debug, Serialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct StorageWithInfo {
     pub id: uuid::Uuid,
     pub name: String,
     pub chat_id: ChatId,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub settings: StorageSettings,
     pub files_amount: i64,
     pub size: i64,
 }
@@ -0,0 +1,53 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(sqlx::Type, Debug, Serialize, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "transfer_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+pub struct InTransfer {
+    pub user_id: Uuid,
+    pub source_storage_id: Uuid,
+    pub source_path: String,
+    pub destination_storage_id: Uuid,
+    pub destination_path: String,
+}
+
+impl InTransfer {
+    pub fn new(
+        user_id: Uuid,
+        source_storage_id: Uuid,
+        source_path: String,
+        destination_storage_id: Uuid,
+        destination_path: String,
+    ) -> Self {
+        Self {
+            user_id,
+            source_storage_id,
+            source_path,
+            destination_storage_id,
+            destination_path,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Transfer {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub source_storage_id: Uuid,
+    pub source_path: String,
+    pub destination_storage_id: Uuid,
+    pub destination_path: String,
+    pub status: TransferStatus,
+    pub total_chunks: i32,
+    pub transferred_chunks: i32,
+    pub error: Option<String>,
+}
@@ -0,0 +1,15 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TrashItem {
+    pub id: Uuid,
+    pub path: String,
+    pub is_folder: bool,
+    pub deleted_by: Option<Uuid>,
+    pub deleted_by_identifier: Option<String>,
+    pub deleted_at: NaiveDateTime,
+    pub files_amount: i64,
+    pub size: i64,
+}
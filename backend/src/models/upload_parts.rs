@@ -0,0 +1,11 @@
+use chrono::NaiveDateTime;
+
+/// A part of an S3 multipart upload, its chunks are already in Telegram
+#[derive(Debug, sqlx::FromRow)]
+pub struct UploadPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+    pub chunk_ids: Vec<uuid::Uuid>,
+    pub uploaded_at: NaiveDateTime,
+}
@@ -0,0 +1,70 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::access_keys::AccessKey;
+
+pub const ACCESS_KEYS_TABLE: &str = "access_keys";
+
+pub struct AccessKeysRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> AccessKeysRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(&self, id: &str, user_id: Uuid) -> CloudBoostclicksResult<AccessKey> {
+        sqlx::query_as(&format!(
+            "
+            INSERT INTO {ACCESS_KEYS_TABLE} (id, user_id)
+            VALUES ($1, $2)
+            RETURNING *;
+            "
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    pub async fn get_by_id(&self, id: &str) -> CloudBoostclicksResult<AccessKey> {
+        sqlx::query_as(&format!("SELECT * FROM {ACCESS_KEYS_TABLE} WHERE id = $1"))
+            .bind(id)
+            .fetch_one(self.db)
+            .await
+            .map_err(|e| map_not_found(e, "access key"))
+    }
+
+    pub async fn list_by_user_id(&self, user_id: Uuid) -> CloudBoostclicksResult<Vec<AccessKey>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {ACCESS_KEYS_TABLE} WHERE user_id = $1 ORDER BY created_at"
+        ))
+        .bind(user_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "access keys"))
+    }
+
+    pub async fn delete(&self, id: &str, user_id: Uuid) -> CloudBoostclicksResult<()> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {ACCESS_KEYS_TABLE} WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if result.rows_affected() == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("ключ доступа".to_string()));
+        }
+        Ok(())
+    }
+}
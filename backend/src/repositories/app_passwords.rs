@@ -0,0 +1,82 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::app_passwords::AppPassword;
+
+pub const APP_PASSWORDS_TABLE: &str = "app_passwords";
+
+pub struct AppPasswordsRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> AppPasswordsRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        password_hash: &str,
+    ) -> CloudBoostclicksResult<AppPassword> {
+        sqlx::query_as(&format!(
+            "
+            INSERT INTO {APP_PASSWORDS_TABLE} (id, user_id, name, password_hash)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *;
+            "
+        ))
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(name)
+        .bind(password_hash)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    pub async fn get_by_password_hash(
+        &self,
+        password_hash: &str,
+    ) -> CloudBoostclicksResult<AppPassword> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {APP_PASSWORDS_TABLE} WHERE password_hash = $1"
+        ))
+        .bind(password_hash)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "app password"))
+    }
+
+    pub async fn list_by_user_id(&self, user_id: Uuid) -> CloudBoostclicksResult<Vec<AppPassword>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {APP_PASSWORDS_TABLE} WHERE user_id = $1 ORDER BY created_at"
+        ))
+        .bind(user_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "app passwords"))
+    }
+
+    pub async fn delete(&self, id: Uuid, user_id: Uuid) -> CloudBoostclicksResult<()> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {APP_PASSWORDS_TABLE} WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if result.rows_affected() == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("пароль приложения".to_string()));
+        }
+        Ok(())
+    }
+}
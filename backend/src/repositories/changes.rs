@@ -0,0 +1,158 @@
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::changes::{Change, InChange};
+use crate::repositories::storages::TABLE as STORAGES_TABLE;
+
+pub const CHANGES_TABLE: &str = "changes";
+/// Postgres channel notified with the id of the storage on every change
+pub const CHANGES_CHANNEL: &str = "storage_changes";
+
+/// Journal of changes of files, every storage numbers its changes on its own.
+///
+/// `storages.last_change` holds the number of the last change, updating it locks the storage row,
+/// so concurrent transactions get their numbers in the order they commit
+pub struct ChangesRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> ChangesRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Changes after the cursor in the order they were made
+    pub async fn list_since(
+        &self,
+        storage_id: Uuid,
+        cursor: i64,
+        limit: i64,
+    ) -> CloudBoostclicksResult<Vec<Change>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT seq, kind, path, old_path, is_folder, sha256, version, changed_at
+            FROM {CHANGES_TABLE}
+            WHERE storage_id = $1 AND seq > $2
+            ORDER BY seq ASC
+            LIMIT $3;
+            "
+        ))
+        .bind(storage_id)
+        .bind(cursor)
+        .bind(limit)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    /// The oldest change which is still kept and the number of the last change
+    pub async fn bounds(&self, storage_id: Uuid) -> CloudBoostclicksResult<(Option<i64>, i64)> {
+        sqlx::query_as(&format!(
+            "
+            SELECT (SELECT MIN(seq) FROM {CHANGES_TABLE} WHERE storage_id = s.id), s.last_change
+            FROM {STORAGES_TABLE} s
+            WHERE s.id = $1;
+            "
+        ))
+        .bind(storage_id)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => CloudBoostclicksError::DoesNotExist("облако".to_string()),
+            _ => CloudBoostclicksError::Unknown,
+        })
+    }
+
+    /// Deletes changes made more than `older_than_days` days ago, cursors pointing before them expire
+    pub async fn prune(&self, older_than_days: u32) -> CloudBoostclicksResult<u64> {
+        sqlx::query(&format!(
+            "DELETE FROM {CHANGES_TABLE} WHERE changed_at < NOW() - make_interval(days => $1)"
+        ))
+        .bind(older_than_days as i32)
+        .execute(self.db)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    /// Appends changes to the journal of the storage, has to run in the transaction making them
+    pub async fn record(
+        conn: &mut PgConnection,
+        storage_id: Uuid,
+        changes: Vec<InChange>,
+    ) -> CloudBoostclicksResult<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let amount = changes.len() as i64;
+        let mut kinds = Vec::with_capacity(changes.len());
+        let mut paths = Vec::with_capacity(changes.len());
+        let mut old_paths = Vec::with_capacity(changes.len());
+        let mut are_folders = Vec::with_capacity(changes.len());
+        let mut sha256s = Vec::with_capacity(changes.len());
+        let mut versions = Vec::with_capacity(changes.len());
+        for change in changes {
+            // folders have neither content nor versions
+            let is_folder = change.is_folder();
+            kinds.push(change.kind.as_str());
+            are_folders.push(is_folder);
+            paths.push(change.path.trim_end_matches('/').to_string());
+            old_paths.push(
+                change
+                    .old_path
+                    .map(|old_path| old_path.trim_end_matches('/').to_string()),
+            );
+            sha256s.push(change.sha256.filter(|_| !is_folder));
+            versions.push(change.version.filter(|_| !is_folder));
+        }
+
+        sqlx::query(&format!(
+            "
+            WITH s AS (
+                UPDATE {STORAGES_TABLE}
+                SET last_change = last_change + $2
+                WHERE id = $1
+                RETURNING last_change - $2 AS base
+            )
+            INSERT INTO {CHANGES_TABLE} (storage_id, seq, kind, path, old_path, is_folder, sha256, version)
+            SELECT $1, s.base + c.n, c.kind::change_kind, c.path, c.old_path, c.is_folder, c.sha256, c.version
+            FROM s, UNNEST($3::text[], $4::text[], $5::text[], $6::bool[], $7::text[], $8::int4[])
+                WITH ORDINALITY AS c(kind, path, old_path, is_folder, sha256, version, n);
+            "
+        ))
+        .bind(storage_id)
+        .bind(amount)
+        .bind(&kinds)
+        .bind(&paths)
+        .bind(&old_paths)
+        .bind(&are_folders)
+        .bind(&sha256s)
+        .bind(&versions)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        // sent once the transaction commits, so waiters never miss the changes
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CHANGES_CHANNEL)
+            .bind(storage_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            })
+            .map(|_| ())
+    }
+}
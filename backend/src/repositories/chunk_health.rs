@@ -0,0 +1,187 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::{
+    chunk_health::{DamagedFile, HealthCounters},
+    file_chunks::FileChunk,
+};
+use crate::repositories::{
+    file_versions::FILE_VERSIONS_TABLE,
+    files::{CHUNKS_TABLE, FILES_TABLE},
+    storage_replicas::CHUNK_COPIES_TABLE,
+};
+
+/// Results of checking whether telegram still gives chunks back.
+///
+/// Health is a property of a telegram file, so it's recorded for every chunk referencing it
+pub struct ChunkHealthRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> ChunkHealthRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Lists chunks which were never checked or were checked more than `recheck_days` days ago
+    pub async fn list_due(
+        &self,
+        limit: u32,
+        recheck_days: u32,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT *
+            FROM {CHUNKS_TABLE}
+            WHERE checked_at IS NULL OR checked_at < NOW() - MAKE_INTERVAL(days => $2)
+            ORDER BY checked_at NULLS FIRST
+            LIMIT $1;
+            "
+        ))
+        .bind(limit as i64)
+        .bind(recheck_days as i32)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    /// Lists damaged chunks of files which have parity chunks to restore them from
+    pub async fn list_repairable(&self, limit: u32) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT fc.*
+            FROM {CHUNKS_TABLE} fc
+            WHERE fc.is_healthy = false AND EXISTS (
+                SELECT 1
+                FROM {CHUNKS_TABLE} p
+                WHERE p.file_id = fc.file_id
+                    AND p.version_id IS NOT DISTINCT FROM fc.version_id
+                    AND p.parity_index IS NOT NULL
+            )
+            ORDER BY fc.checked_at
+            LIMIT $1;
+            "
+        ))
+        .bind(limit as i64)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    /// Points every chunk referencing the lost telegram file to the re-uploaded one
+    pub async fn replace_telegram_file(
+        &self,
+        lost_telegram_file_id: &str,
+        chunk: &FileChunk,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        sqlx::query(&format!(
+            "
+            UPDATE {CHUNKS_TABLE}
+            SET telegram_file_id = $2,
+                storage_worker_id = $3,
+                message_id = $4,
+                chat_id = $5,
+                data_key = $6,
+                checked_at = NOW(),
+                is_healthy = true,
+                check_error = NULL
+            WHERE telegram_file_id = $1;
+            "
+        ))
+        .bind(lost_telegram_file_id)
+        .bind(&chunk.telegram_file_id)
+        .bind(chunk.storage_worker_id)
+        .bind(chunk.message_id)
+        .bind(chunk.chat_id)
+        .bind(&chunk.data_key)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        sqlx::query(&format!(
+            "
+            UPDATE {CHUNK_COPIES_TABLE}
+            SET source_telegram_file_id = $2
+            WHERE source_telegram_file_id = $1;
+            "
+        ))
+        .bind(lost_telegram_file_id)
+        .bind(&chunk.telegram_file_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    pub async fn record(
+        &self,
+        telegram_file_id: &str,
+        error: Option<&str>,
+    ) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "
+            UPDATE {CHUNKS_TABLE}
+            SET checked_at = NOW(), is_healthy = $2::varchar IS NULL, check_error = $2
+            WHERE telegram_file_id = $1;
+            "
+        ))
+        .bind(telegram_file_id)
+        .bind(error)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    pub async fn counters(&self, storage_id: Uuid) -> CloudBoostclicksResult<HealthCounters> {
+        sqlx::query_as(&format!(
+            "
+            SELECT
+                COUNT(*) FILTER (WHERE fc.checked_at IS NOT NULL) AS checked_chunks,
+                COUNT(*) FILTER (WHERE fc.checked_at IS NULL) AS unchecked_chunks,
+                COUNT(*) FILTER (WHERE fc.is_healthy = false) AS damaged_chunks
+            FROM {CHUNKS_TABLE} fc
+            JOIN {FILES_TABLE} f ON f.id = fc.file_id
+            WHERE f.storage_id = $1;
+            "
+        ))
+        .bind(storage_id)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage"))
+    }
+
+    pub async fn list_damaged_files(&self, storage_id: Uuid) -> CloudBoostclicksResult<Vec<DamagedFile>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT
+                f.path,
+                v.version,
+                f.trash_id IS NOT NULL AS in_trash,
+                COUNT(*) FILTER (WHERE fc.is_healthy = false) AS damaged_chunks,
+                COUNT(*) AS total_chunks,
+                MAX(fc.checked_at) AS checked_at,
+                MAX(fc.check_error) AS error
+            FROM {CHUNKS_TABLE} fc
+            JOIN {FILES_TABLE} f ON f.id = fc.file_id
+            LEFT JOIN {FILE_VERSIONS_TABLE} v ON v.id = fc.version_id
+            WHERE f.storage_id = $1
+            GROUP BY f.id, v.id
+            HAVING COUNT(*) FILTER (WHERE fc.is_healthy = false) > 0
+            ORDER BY f.path, v.version DESC NULLS FIRST;
+            "
+        ))
+        .bind(storage_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "files"))
+    }
+}
@@ -0,0 +1,55 @@
+use sqlx::PgPool;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::repositories::files::CHUNKS_TABLE;
+
+/// Wrapped keys of encrypted chunks
+pub struct DataKeysRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> DataKeysRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Replaces every wrapped key by the result of `rewrap` in a single transaction,
+    /// returns how many distinct keys there were
+    pub async fn rewrap_all<F>(&self, rewrap: F) -> CloudBoostclicksResult<usize>
+    where
+        F: Fn(&[u8]) -> CloudBoostclicksResult<Vec<u8>>,
+    {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let data_keys: Vec<Vec<u8>> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT data_key FROM {CHUNKS_TABLE} WHERE data_key IS NOT NULL"
+        ))
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "data keys"))?;
+
+        for data_key in &data_keys {
+            let rewrapped = rewrap(data_key)?;
+            if rewrapped == *data_key {
+                continue;
+            }
+
+            sqlx::query(&format!(
+                "UPDATE {CHUNKS_TABLE} SET data_key = $2 WHERE data_key = $1"
+            ))
+            .bind(data_key)
+            .bind(rewrapped)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok(data_keys.len())
+    }
+}
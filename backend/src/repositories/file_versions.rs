@@ -0,0 +1,404 @@
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::{
+    changes::{ChangeKind, InChange},
+    file_chunks::FileChunk,
+    file_versions::FileVersion,
+};
+use crate::repositories::changes::ChangesRepository;
+use crate::repositories::files::{FilesRepository, CHUNKS_TABLE, FILES_TABLE};
+use crate::schemas::files::ClientEnvelope;
+
+pub const FILE_VERSIONS_TABLE: &str = "file_versions";
+
+/// Stores previous contents of files.
+///
+/// Chunks of the current content have an empty `version_id`, chunks of the others point to their version
+pub struct FileVersionsRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> FileVersionsRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Creates a version which is being uploaded and is going to become the current one
+    pub async fn create_pending(
+        &self,
+        file_id: Uuid,
+        size: i64,
+        uploaded_by: Uuid,
+        if_match: Option<&str>,
+    ) -> CloudBoostclicksResult<FileVersion> {
+        sqlx::query_as(&format!(
+            "
+            INSERT INTO {FILE_VERSIONS_TABLE} (id, file_id, size, uploaded_by, if_match)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *;
+            "
+        ))
+        .bind(Uuid::new_v4())
+        .bind(file_id)
+        .bind(size)
+        .bind(uploaded_by)
+        .bind(if_match)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    pub async fn get_pending(
+        &self,
+        id: Uuid,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<Option<FileVersion>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT v.*
+            FROM {FILE_VERSIONS_TABLE} v
+            JOIN {FILES_TABLE} f ON f.id = v.file_id
+            WHERE v.id = $1 AND v.version IS NULL AND f.storage_id = $2;
+            "
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .fetch_optional(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+    }
+
+    pub async fn get_by_id(&self, id: Uuid) -> CloudBoostclicksResult<FileVersion> {
+        sqlx::query_as(&format!("SELECT * FROM {FILE_VERSIONS_TABLE} WHERE id = $1"))
+            .bind(id)
+            .fetch_one(self.db)
+            .await
+            .map_err(|e| map_not_found(e, "версия файла"))
+    }
+
+    pub async fn get_by_number(&self, file_id: Uuid, version: i32) -> CloudBoostclicksResult<FileVersion> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {FILE_VERSIONS_TABLE} WHERE file_id = $1 AND version = $2"
+        ))
+        .bind(file_id)
+        .bind(version)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "версия файла"))
+    }
+
+    /// Lists previous versions of the file, the newest first
+    pub async fn list(&self, file_id: Uuid) -> CloudBoostclicksResult<Vec<FileVersion>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT *
+            FROM {FILE_VERSIONS_TABLE}
+            WHERE file_id = $1 AND version IS NOT NULL
+            ORDER BY version DESC;
+            "
+        ))
+        .bind(file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file versions"))
+    }
+
+    /// Lists data chunks of the version
+    pub async fn list_chunks(&self, version_id: Uuid) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {CHUNKS_TABLE} WHERE version_id = $1 AND parity_index IS NULL"
+        ))
+        .bind(version_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    pub async fn list_parity_chunks(&self, version_id: Uuid) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {CHUNKS_TABLE} WHERE version_id = $1 AND parity_index IS NOT NULL"
+        ))
+        .bind(version_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    pub async fn set_sha256(&self, id: Uuid, sha256: &str) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!("UPDATE {FILE_VERSIONS_TABLE} SET sha256 = $2 WHERE id = $1"))
+            .bind(id)
+            .bind(sha256)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
+    }
+
+    pub async fn set_codec(&self, id: Uuid, codec: Option<&str>) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!("UPDATE {FILE_VERSIONS_TABLE} SET codec = $2 WHERE id = $1"))
+            .bind(id)
+            .bind(codec)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
+    }
+
+    pub async fn set_envelope(&self, id: Uuid, envelope: &ClientEnvelope) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "UPDATE {FILE_VERSIONS_TABLE} SET encrypted_key = $2, client_metadata = $3 WHERE id = $1"
+        ))
+        .bind(id)
+        .bind(&envelope.encrypted_key)
+        .bind(&envelope.metadata)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    pub async fn delete(&self, id: Uuid) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!("DELETE FROM {FILE_VERSIONS_TABLE} WHERE id = $1"))
+            .bind(id)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
+    }
+
+    /// Makes an uploaded pending version current, the previous content becomes a version.
+    ///
+    /// Versions uploaded by chunks get their checksum here. The file is compared with
+    /// `If-Match` of the upload here as well, so of two uploads expecting the same content
+    /// only the first one replaces it
+    pub async fn promote(
+        &self,
+        pending_id: Uuid,
+        sha256: Option<&str>,
+        size: Option<i64>,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let mut pending: FileVersion = sqlx::query_as(&format!(
+            "SELECT * FROM {FILE_VERSIONS_TABLE} WHERE id = $1 AND version IS NULL"
+        ))
+        .bind(pending_id)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "версия файла"))?;
+        if let Some(sha256) = sha256 {
+            pending.sha256 = Some(sha256.to_string());
+        }
+        if let Some(size) = size {
+            pending.size = size;
+        }
+
+        if let Some(if_match) = &pending.if_match {
+            let (path, storage_id): (String, Uuid) = sqlx::query_as(&format!(
+                "SELECT path, storage_id FROM {FILES_TABLE} WHERE id = $1"
+            ))
+            .bind(pending.file_id)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| map_not_found(e, "файл"))?;
+
+            FilesRepository::match_element(&mut transaction, &path, storage_id, if_match).await?;
+        }
+
+        Self::archive_current(&mut transaction, pending.file_id).await?;
+
+        sqlx::query(&format!(
+            "UPDATE {CHUNKS_TABLE} SET version_id = NULL WHERE version_id = $1"
+        ))
+        .bind(pending.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        Self::bump_version(&mut transaction, &pending, pending.uploaded_by).await?;
+
+        sqlx::query(&format!("DELETE FROM {FILE_VERSIONS_TABLE} WHERE id = $1"))
+            .bind(pending.id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    /// Makes a copy of the version the current content, so the history stays linear
+    pub async fn restore(&self, version: &FileVersion, restored_by: Uuid) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        Self::archive_current(&mut transaction, version.file_id).await?;
+
+        sqlx::query(&format!(
+            "
+            INSERT INTO {CHUNKS_TABLE} (id, file_id, telegram_file_id, storage_worker_id, position, message_id, chat_id, sha256, size, parity_index, parity_group_size, data_key)
+            SELECT gen_random_uuid(), file_id, telegram_file_id, storage_worker_id, position, message_id, chat_id, sha256, size, parity_index, parity_group_size, data_key
+            FROM {CHUNKS_TABLE}
+            WHERE version_id = $1;
+            "
+        ))
+        .bind(version.id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        Self::bump_version(&mut transaction, version, Some(restored_by)).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    /// Deletes versions beyond the `keep` newest ones or archived more than `older_than_days` days ago.
+    ///
+    /// Returns the amount of deleted versions and the chunks whose telegram messages aren't needed anymore
+    pub async fn prune(
+        &self,
+        storage_id: Uuid,
+        path: Option<&str>,
+        keep: Option<i32>,
+        older_than_days: Option<i32>,
+    ) -> CloudBoostclicksResult<(i64, Vec<FileChunk>)> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let (path, is_folder) = match path {
+            Some(path) => FilesRepository::resolve_element(&mut transaction, path, storage_id).await?,
+            None => (String::new(), true),
+        };
+        let path_filter = FilesRepository::element_filter("f", is_folder);
+
+        let (version_ids,): (Vec<Uuid>,) = sqlx::query_as(&format!(
+            "
+            WITH ranked AS (
+                SELECT
+                    v.id,
+                    v.archived_at,
+                    ROW_NUMBER() OVER (PARTITION BY v.file_id ORDER BY v.version DESC) AS n
+                FROM {FILE_VERSIONS_TABLE} v
+                JOIN {FILES_TABLE} f ON f.id = v.file_id
+                WHERE f.storage_id = $1 AND f.trash_id IS NULL AND v.version IS NOT NULL AND {path_filter}
+            )
+            SELECT COALESCE(ARRAY_AGG(id), '{{}}')
+            FROM ranked
+            WHERE n > $3 OR archived_at < NOW() - MAKE_INTERVAL(days => $4);
+            "
+        ))
+        .bind(storage_id)
+        .bind(&path)
+        .bind(keep)
+        .bind(older_than_days)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        let chunks: Vec<FileChunk> = sqlx::query_as(&format!(
+            "DELETE FROM {CHUNKS_TABLE} WHERE version_id = ANY($1) RETURNING *"
+        ))
+        .bind(&version_ids)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        sqlx::query(&format!("DELETE FROM {FILE_VERSIONS_TABLE} WHERE id = ANY($1)"))
+            .bind(&version_ids)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        let unreferenced_chunks =
+            FilesRepository::filter_unreferenced(&mut transaction, chunks).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok((version_ids.len() as i64, unreferenced_chunks))
+    }
+
+    /////////////////////////////////////////////////////////////////////
+    ////    Helpers
+    /////////////////////////////////////////////////////////////////////
+
+    /// Saves the current content of the file as a version under its current number
+    async fn archive_current(conn: &mut PgConnection, file_id: Uuid) -> CloudBoostclicksResult<()> {
+        let version_id = Uuid::new_v4();
+
+        sqlx::query(&format!(
+            "
+            INSERT INTO {FILE_VERSIONS_TABLE} (id, file_id, version, size, created_at, uploaded_by, sha256, encrypted_key, client_metadata, codec)
+            SELECT $1, id, version, size, updated_at, uploaded_by, sha256, encrypted_key, client_metadata, codec
+            FROM {FILES_TABLE}
+            WHERE id = $2;
+            "
+        ))
+        .bind(version_id)
+        .bind(file_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        sqlx::query(&format!(
+            "UPDATE {CHUNKS_TABLE} SET version_id = $1 WHERE file_id = $2 AND version_id IS NULL"
+        ))
+        .bind(version_id)
+        .bind(file_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    /// Sets the content of the version as the current content of its file
+    async fn bump_version(
+        conn: &mut PgConnection,
+        version: &FileVersion,
+        uploaded_by: Option<Uuid>,
+    ) -> CloudBoostclicksResult<()> {
+        let (storage_id, path, sha256, new_version): (Uuid, String, Option<String>, i32) =
+            sqlx::query_as(&format!(
+                "
+                UPDATE {FILES_TABLE}
+                SET version = version + 1, size = $2, updated_at = NOW(), uploaded_by = $3, sha256 = $4,
+                    encrypted_key = $5, client_metadata = $6, codec = $7
+                WHERE id = $1
+                RETURNING storage_id, path, sha256, version;
+                "
+            ))
+            .bind(version.file_id)
+            .bind(version.size)
+            .bind(uploaded_by)
+            .bind(&version.sha256)
+            .bind(&version.encrypted_key)
+            .bind(&version.client_metadata)
+            .bind(&version.codec)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| map_not_found(e, "файл"))?;
+
+        let change = InChange::new(ChangeKind::Update, path, sha256, Some(new_version));
+        ChangesRepository::record(conn, storage_id, vec![change]).await
+    }
+}
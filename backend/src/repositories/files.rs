@@ -1,17 +1,30 @@
-﻿use std::path::Path;
+﻿use std::collections::HashSet;
+use std::path::Path;
 
-use sqlx::{PgPool, QueryBuilder};
+use sqlx::{Connection, PgConnection, PgPool, QueryBuilder};
 use uuid::Uuid;
 
 use crate::common::db::errors::map_not_found;
+use crate::common::http::{etag, etag_listed};
 use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::changes::{ChangeKind, InChange};
 use crate::models::file_chunks::FileChunk;
 use crate::models::files::{DBFSElement, FSElement, File, InFile, SearchFSElement};
-use crate::schemas::files::DeleteSummary;
+use crate::repositories::{
+    changes::ChangesRepository, file_versions::FILE_VERSIONS_TABLE,
+    storage_workers::STORAGE_WORKERS_TABLE, trash::TRASH_TABLE, users::identifier_expr,
+};
+use crate::schemas::files::{
+    BatchOperation, BatchSummary, ClientEnvelope, CopySummary, DeleteSummary, ListQuery, SortBy,
+    SortOrder,
+};
 
 pub const FILES_TABLE: &str = "files";
 pub const CHUNKS_TABLE: &str = "file_chunks";
 
+/// Path, checksum, version and whether it's uploaded of a changed row, for the change journal
+type ChangedRow = (String, Option<String>, i32, bool);
+
 /// General repo for files and chunks since they share common logic
 pub struct FilesRepository<'d> {
     db: &'d PgPool,
@@ -32,12 +45,14 @@ impl<'d> FilesRepository<'d> {
 
     async fn _create_file(&self, in_obj: InFile, is_uploaded: bool) -> CloudBoostclicksResult<File> {
         let id = Uuid::new_v4();
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
 
-        sqlx::query(
+        let file: File = sqlx::query_as(
             format!(
                 "
-                INSERT INTO {FILES_TABLE} (id, path, size, storage_id, is_uploaded)
-                VALUES ($1, $2, $3, $4, $5);
+                INSERT INTO {FILES_TABLE} (id, path, size, storage_id, is_uploaded, uploaded_by)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING *;
             "
             )
             .as_str(),
@@ -47,7 +62,8 @@ impl<'d> FilesRepository<'d> {
         .bind(in_obj.size)
         .bind(in_obj.storage_id)
         .bind(is_uploaded)
-        .execute(self.db)
+        .bind(in_obj.uploaded_by)
+        .fetch_one(&mut *transaction)
         .await
         .map_err(|e| match e {
             sqlx::Error::Database(dbe) if dbe.is_foreign_key_violation() => {
@@ -62,8 +78,18 @@ impl<'d> FilesRepository<'d> {
             }
         })?;
 
-        let storage = File::new(id, in_obj.path, in_obj.size, in_obj.storage_id, false);
-        Ok(storage)
+        // files appear in the journal once they are uploaded, folders right away
+        if is_uploaded {
+            let change = InChange::new(ChangeKind::Create, file.path.clone(), None, None);
+            ChangesRepository::record(&mut transaction, file.storage_id, vec![change]).await?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok(file)
     }
 
     /// Creates a file even if the given path already exists
@@ -82,7 +108,7 @@ impl<'d> FilesRepository<'d> {
             (splited_path.join("/"), suffix)
         };
 
-        println!("{path_with_stem} {suffix}");
+        tracing::debug!("{path_with_stem} {suffix}");
 
         let chars_to_skip = path_with_stem.len() + 3; // if the name is `kek` then it's gonna be a len of `kek (` + 1
         let skip_chars_from_back = chars_to_skip + suffix.len();
@@ -91,11 +117,11 @@ impl<'d> FilesRepository<'d> {
         sqlx::query_as(
             format!(
                 r#"
-                INSERT INTO files (path, storage_id, id, size, is_uploaded)
+                INSERT INTO files (path, storage_id, id, size, is_uploaded, uploaded_by)
                 WITH f AS (
                     SELECT path
                     FROM {FILES_TABLE}
-                    WHERE storage_id = $3 AND path ~ ('^(' || regexp_quote($1) || regexp_quote($2) || '|' || regexp_quote($1) || ' \(\d+\)' || regexp_quote($2) || ')$')
+                    WHERE storage_id = $3 AND trash_id IS NULL AND path ~ ('^(' || regexp_quote($1) || regexp_quote($2) || '|' || regexp_quote($1) || ' \(\d+\)' || regexp_quote($2) || ')$')
                     ORDER BY path DESC
                 )
                 SELECT
@@ -143,7 +169,8 @@ impl<'d> FilesRepository<'d> {
                     $3,
                     $4,
                     $5,
-                    false
+                    false,
+                    $6
                 FROM f
                 RETURNING *;
             "#
@@ -155,6 +182,7 @@ impl<'d> FilesRepository<'d> {
         .bind(in_obj.storage_id)
         .bind(id)
         .bind(in_obj.size)
+        .bind(in_obj.uploaded_by)
         .fetch_one(self.db)
         .await
         .map_err(|e| match e {
@@ -169,25 +197,121 @@ impl<'d> FilesRepository<'d> {
     }
 
     pub async fn create_chunks_batch(&self, chunks: Vec<FileChunk>) -> CloudBoostclicksResult<()> {
+        let mut conn = self.db.acquire().await.map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        Self::insert_chunks(&mut conn, chunks).await
+    }
+
+    /// How many data chunks of the file, or of its pending version, are saved and
+    /// the state of the checksum computed over them
+    pub async fn upload_progress(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+    ) -> CloudBoostclicksResult<(i64, Option<Vec<u8>>)> {
+        let mut conn = self.db.acquire().await.map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        Self::lock_upload(&mut conn, file_id, version_id, false).await
+    }
+
+    /// Saves the next data chunk of a file uploaded by chunks together with the state of
+    /// the checksum including it, chunks have to come in order
+    /// Saves the next chunk of a file uploaded by chunks together with parity chunks of the group
+    /// it closes, if any
+    pub async fn push_chunk(
+        &self,
+        chunk: FileChunk,
+        parity_chunks: Vec<FileChunk>,
+        hash_state: &[u8],
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let (file_id, version_id) = (chunk.file_id, chunk.version_id);
+        let (uploaded, _) = Self::lock_upload(&mut transaction, file_id, version_id, true).await?;
+        if uploaded != chunk.position as i64 {
+            return Err(CloudBoostclicksError::UnexpectedChunk(uploaded as usize));
+        }
+
+        let mut chunks = vec![chunk];
+        chunks.extend(parity_chunks);
+        Self::insert_chunks(&mut transaction, chunks).await?;
+
+        let table = match version_id {
+            Some(_) => FILE_VERSIONS_TABLE,
+            None => FILES_TABLE,
+        };
+        sqlx::query(&format!("UPDATE {table} SET hash_state = $2 WHERE id = $1"))
+            .bind(version_id.unwrap_or(file_id))
+            .bind(hash_state)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    pub async fn insert_chunks(conn: &mut PgConnection, chunks: Vec<FileChunk>) -> CloudBoostclicksResult<()> {
         QueryBuilder::new(
-            format!("INSERT INTO {CHUNKS_TABLE} (id, file_id, telegram_file_id, storage_worker_id, position)")
-                .as_str(),
+            format!(
+                "INSERT INTO {CHUNKS_TABLE} (id, file_id, telegram_file_id, storage_worker_id, position, message_id, chat_id, version_id, sha256, size, parity_index, parity_group_size, data_key)"
+            )
+            .as_str(),
         )
         .push_values(chunks, |mut q, chunk| {
             q.push_bind(chunk.id)
                 .push_bind(chunk.file_id)
                 .push_bind(chunk.telegram_file_id)
                 .push_bind(chunk.storage_worker_id)
-                .push_bind(chunk.position);
+                .push_bind(chunk.position)
+                .push_bind(chunk.message_id)
+                .push_bind(chunk.chat_id)
+                .push_bind(chunk.version_id)
+                .push_bind(chunk.sha256)
+                .push_bind(chunk.size)
+                .push_bind(chunk.parity_index)
+                .push_bind(chunk.parity_group_size)
+                .push_bind(chunk.data_key);
         })
         .build()
-        .execute(self.db)
+        .execute(conn)
         .await
         .map_err(|_| CloudBoostclicksError::Unknown)?;
 
         Ok(())
     }
 
+    /// Amount of saved data chunks and the checksum state of a pending upload
+    async fn lock_upload(
+        conn: &mut PgConnection,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        for_update: bool,
+    ) -> CloudBoostclicksResult<(i64, Option<Vec<u8>>)> {
+        let (table, chunks_filter) = match version_id {
+            Some(_) => (FILE_VERSIONS_TABLE, "c.version_id = u.id"),
+            None => (FILES_TABLE, "c.file_id = u.id AND c.version_id IS NULL"),
+        };
+        let lock = if for_update { "FOR UPDATE OF u" } else { "" };
+
+        sqlx::query_as(&format!(
+            "
+            SELECT
+                (SELECT COUNT(*) FROM {CHUNKS_TABLE} c WHERE {chunks_filter} AND c.parity_index IS NULL),
+                u.hash_state
+            FROM {table} u
+            WHERE u.id = $1
+            {lock};
+            "
+        ))
+        .bind(version_id.unwrap_or(file_id))
+        .fetch_one(conn)
+        .await
+        .map_err(|e| map_not_found(e, "загрузка"))
+    }
+
     /// NOTE:
     ///
     /// `prefix` must be without leading and trailing slashes
@@ -195,6 +319,7 @@ impl<'d> FilesRepository<'d> {
         &self,
         storage_id: Uuid,
         prefix: &str,
+        list_query: &ListQuery,
     ) -> CloudBoostclicksResult<Vec<FSElement>> {
         let query = {
             let adding_to_position = !prefix.is_empty() as usize + 1;
@@ -206,17 +331,32 @@ impl<'d> FilesRepository<'d> {
                 "AND path LIKE $1 || '%'"
             };
 
+            let identifier = identifier_expr("u");
+            let filter_and_order = Self::list_filter_and_order(list_query, 3, "name");
+
+            // folders get the size, the dates and the uploader of their content
             format!(
                 "
-                SELECT
-                    DISTINCT {split_part} AS name,
-                    $1 || {split_part} = path AS is_file,
-                    CASE
-                        WHEN $1 || {split_part} = path THEN size
-                        ELSE (SELECT SUM(size) FROM {FILES_TABLE} WHERE path LIKE $1 || {split_part} || '/' || '%')::BigInt
-                    END AS size
-                FROM {FILES_TABLE}
-                WHERE storage_id = $2 {path_filter} AND is_uploaded AND {split_part} <> '';
+                SELECT e.*, {identifier} AS uploaded_by_identifier
+                FROM (
+                    SELECT
+                        {split_part} AS name,
+                        $1 || {split_part} = path AS is_file,
+                        COALESCE(SUM(size), 0)::BigInt AS size,
+                        MIN(created_at) AS created_at,
+                        MAX(updated_at) AS updated_at,
+                        (ARRAY_AGG(uploaded_by ORDER BY created_at))[1] AS uploaded_by,
+                        MAX(sha256) FILTER (WHERE $1 || {split_part} = path) AS sha256,
+                        MAX(encrypted_key) FILTER (WHERE $1 || {split_part} = path) AS encrypted_key,
+                        MAX(client_metadata) FILTER (WHERE $1 || {split_part} = path) AS client_metadata,
+                        (ARRAY_AGG(id) FILTER (WHERE $1 || {split_part} = path))[1] AS file_id,
+                        MAX(version) FILTER (WHERE $1 || {split_part} = path) AS version
+                    FROM {FILES_TABLE}
+                    WHERE storage_id = $2 {path_filter} AND is_uploaded AND trash_id IS NULL AND {split_part} <> ''
+                    GROUP BY 1, 2
+                ) e
+                LEFT JOIN users u ON e.uploaded_by = u.id
+                {filter_and_order};
             "
             )
         };
@@ -230,6 +370,9 @@ impl<'d> FilesRepository<'d> {
         let fs_layer = sqlx::query_as::<_, DBFSElement>(&query)
             .bind(&prefix)
             .bind(storage_id)
+            .bind(list_query.uploaded_by)
+            .bind(list_query.updated_after)
+            .bind(list_query.updated_before)
             .fetch_all(self.db)
             .await
             .map_err(|e| {
@@ -240,11 +383,25 @@ impl<'d> FilesRepository<'d> {
             .into_iter()
             .map(|el| {
                 let path = format!("{prefix}{}", el.name);
+                let etag = match (el.file_id, el.version) {
+                    (Some(file_id), Some(version)) if el.is_file => {
+                        Some(etag(el.sha256.as_deref(), file_id, version))
+                    }
+                    _ => None,
+                };
                 FSElement {
+                    etag,
                     path,
                     name: el.name,
                     is_file: el.is_file,
                     size: el.size,
+                    created_at: el.created_at,
+                    updated_at: el.updated_at,
+                    uploaded_by: el.uploaded_by,
+                    uploaded_by_identifier: el.uploaded_by_identifier,
+                    sha256: el.sha256,
+                    encrypted_key: el.encrypted_key,
+                    client_metadata: el.client_metadata,
                 }
             })
             .collect();
@@ -257,14 +414,28 @@ impl<'d> FilesRepository<'d> {
         search_path: &str,
         path: &str,
         storage_id: Uuid,
+        list_query: &ListQuery,
     ) -> CloudBoostclicksResult<Vec<SearchFSElement>> {
+        let identifier = identifier_expr("u");
+        let filter_and_order = Self::list_filter_and_order(list_query, 4, "path");
+
         sqlx::query_as(
             format!(
-                "SELECT
-                    path,
-                    path LIKE '%/' AS is_file
-                FROM {FILES_TABLE}
-                WHERE storage_id = $1 AND path ILIKE $2 || '%' || $3 || '%'
+                "
+                SELECT e.*, {identifier} AS uploaded_by_identifier
+                FROM (
+                    SELECT
+                        path,
+                        path LIKE '%/' AS is_file,
+                        size,
+                        created_at,
+                        updated_at,
+                        uploaded_by
+                    FROM {FILES_TABLE}
+                    WHERE storage_id = $1 AND trash_id IS NULL AND path ILIKE $2 || '%' || $3 || '%'
+                ) e
+                LEFT JOIN users u ON e.uploaded_by = u.id
+                {filter_and_order};
             "
             )
             .as_str(),
@@ -272,6 +443,9 @@ impl<'d> FilesRepository<'d> {
         .bind(storage_id)
         .bind(path)
         .bind(search_path)
+        .bind(list_query.uploaded_by)
+        .bind(list_query.updated_after)
+        .bind(list_query.updated_before)
         .fetch_all(self.db)
         .await
         .map_err(|e| {
@@ -280,9 +454,20 @@ impl<'d> FilesRepository<'d> {
         })
     }
 
+    pub async fn get_by_id(&self, id: Uuid) -> CloudBoostclicksResult<File> {
+        sqlx::query_as(format!("SELECT * FROM {FILES_TABLE} WHERE id = $1").as_str())
+            .bind(id)
+            .fetch_one(self.db)
+            .await
+            .map_err(|e| map_not_found(e, "file"))
+    }
+
     pub async fn get_file_by_path(&self, path: &str, storage_id: Uuid) -> CloudBoostclicksResult<File> {
         sqlx::query_as(
-            format!("SELECT * FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2").as_str(),
+            format!(
+                "SELECT * FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2 AND trash_id IS NULL"
+            )
+            .as_str(),
         )
         .bind(storage_id)
         .bind(path)
@@ -298,7 +483,7 @@ impl<'d> FilesRepository<'d> {
     ) -> CloudBoostclicksResult<File> {
         sqlx::query_as(
             format!(
-                "SELECT * FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2 AND is_uploaded = true"
+                "SELECT * FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2 AND is_uploaded = true AND trash_id IS NULL"
             )
             .as_str(),
         )
@@ -309,12 +494,97 @@ impl<'d> FilesRepository<'d> {
         .map_err(|e| map_not_found(e, "file"))
     }
 
+    /// Lists data chunks of the current version of the file
     pub async fn list_chunks_of_file(&self, file_id: Uuid) -> CloudBoostclicksResult<Vec<FileChunk>> {
-        sqlx::query_as(format!("SELECT * FROM {CHUNKS_TABLE} WHERE file_id = $1").as_str())
-            .bind(file_id)
-            .fetch_all(self.db)
-            .await
-            .map_err(|e| map_not_found(e, "file chunks"))
+        sqlx::query_as(
+            format!("SELECT * FROM {CHUNKS_TABLE} WHERE file_id = $1 AND version_id IS NULL AND parity_index IS NULL").as_str(),
+        )
+        .bind(file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    /// Saves the key blob and the metadata a client encrypted the file with
+    pub async fn set_envelope(&self, id: Uuid, envelope: &ClientEnvelope) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "UPDATE {FILES_TABLE} SET encrypted_key = $2, client_metadata = $3 WHERE id = $1"
+        ))
+        .bind(id)
+        .bind(&envelope.encrypted_key)
+        .bind(&envelope.metadata)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    /// Finds the key chunks of the file or its pending version are already encrypted with
+    pub async fn find_data_key(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+    ) -> CloudBoostclicksResult<Option<Vec<u8>>> {
+        sqlx::query_scalar(&format!(
+            "
+            SELECT data_key
+            FROM {CHUNKS_TABLE}
+            WHERE file_id = $1 AND version_id IS NOT DISTINCT FROM $2 AND data_key IS NOT NULL
+            LIMIT 1;
+            "
+        ))
+        .bind(file_id)
+        .bind(version_id)
+        .fetch_optional(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    /// Lists parity chunks of the current version of the file
+    pub async fn list_parity_chunks_of_file(&self, file_id: Uuid) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        sqlx::query_as(
+            format!("SELECT * FROM {CHUNKS_TABLE} WHERE file_id = $1 AND version_id IS NULL AND parity_index IS NOT NULL").as_str(),
+        )
+        .bind(file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))
+    }
+
+    /// Finds a chunk of the storage with the same content whose telegram file can be referenced again.
+    ///
+    /// With `encrypted_only` plaintext telegram files are skipped
+    pub async fn find_chunk_by_sha256(
+        &self,
+        storage_id: Uuid,
+        sha256: &str,
+        encrypted_only: bool,
+    ) -> CloudBoostclicksResult<Option<FileChunk>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT fc.*
+            FROM {CHUNKS_TABLE} fc
+            JOIN {FILES_TABLE} f ON f.id = fc.file_id
+            WHERE f.storage_id = $1
+                AND fc.sha256 = $2
+                AND fc.storage_worker_id IS NOT NULL
+                AND fc.is_healthy IS NOT FALSE
+                AND (NOT $3 OR fc.data_key IS NOT NULL)
+            LIMIT 1;
+            "
+        ))
+        .bind(storage_id)
+        .bind(sha256)
+        .bind(encrypted_only)
+        .fetch_optional(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
     }
 
     pub async fn folder_exists(
@@ -328,7 +598,7 @@ impl<'d> FilesRepository<'d> {
                 SELECT EXISTS(
                     SELECT 1
                     FROM {FILES_TABLE}
-                    WHERE storage_id = $1 AND is_uploaded AND path LIKE $2 || '%'
+                    WHERE storage_id = $1 AND is_uploaded AND trash_id IS NULL AND path LIKE $2 || '%'
                 );
             "
             )
@@ -343,7 +613,8 @@ impl<'d> FilesRepository<'d> {
         Ok(exists)
     }
 
-    pub async fn list_files_in_folder(
+    /// Uploaded files and folders inside the folder, the folder itself isn't included
+    pub async fn list_folder_content(
         &self,
         storage_id: Uuid,
         prefix: &str,
@@ -355,7 +626,8 @@ impl<'d> FilesRepository<'d> {
                 FROM {FILES_TABLE}
                 WHERE storage_id = $1
                     AND is_uploaded
-                    AND path NOT LIKE '%/'
+                    AND trash_id IS NULL
+                    AND path <> $2
                     AND path LIKE $2 || '%'
                 ORDER BY path ASC;
             "
@@ -369,40 +641,127 @@ impl<'d> FilesRepository<'d> {
         .map_err(|_| CloudBoostclicksError::Unknown)
     }
 
-    pub async fn set_as_uploaded(&self, file_id: Uuid) -> CloudBoostclicksResult<()> {
-        sqlx::query(format!("UPDATE {FILES_TABLE} SET is_uploaded = true WHERE id = $1").as_str())
+    /// Marks the file as uploaded, files uploaded by chunks get their checksum and size here
+    pub async fn set_as_uploaded(
+        &self,
+        file_id: Uuid,
+        sha256: Option<&str>,
+        size: Option<i64>,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let (storage_id, path, sha256, version): (Uuid, String, Option<String>, i32) =
+            sqlx::query_as(
+                format!(
+                    "
+                    UPDATE {FILES_TABLE}
+                    SET is_uploaded = true, updated_at = NOW(), sha256 = COALESCE($2, sha256),
+                        size = COALESCE($3, size), hash_state = NULL
+                    WHERE id = $1
+                    RETURNING storage_id, path, sha256, version;
+                "
+                )
+                .as_str(),
+            )
             .bind(file_id)
-            .execute(self.db)
+            .bind(sha256)
+            .bind(size)
+            .fetch_one(&mut *transaction)
             .await
-            .map_err(|_| CloudBoostclicksError::Unknown)
-            .map(|_| ())
+            .map_err(|e| map_not_found(e, "файл"))?;
+
+        let change = InChange::new(ChangeKind::Create, path, sha256, Some(version));
+        ChangesRepository::record(&mut transaction, storage_id, vec![change]).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
     }
 
-    pub async fn update_path(
+    /// Marks the pending file as uploaded at the path. The file it replaces goes to the trash
+    /// in the same transaction, so it stays when the new one can't take its place
+    pub async fn put_in_place(
         &self,
-        old_path: &str,
-        new_path: &str,
-        storage_id: Uuid,
+        file_id: Uuid,
+        path: &str,
+        replaced_by: Uuid,
+        if_match: Option<&str>,
     ) -> CloudBoostclicksResult<()> {
-        let chars_skip = old_path.len();
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
 
-        sqlx::query(
-            format!(
-                "
-                UPDATE {FILES_TABLE}
-                SET path = $1 || SUBSTRING(path, {chars_skip})
-                WHERE storage_id = $2 AND path LIKE $3 || '%'
+        let (storage_id, replaced): (Uuid, bool) = sqlx::query_as(&format!(
             "
+            SELECT storage_id, EXISTS (
+                SELECT 1 FROM {FILES_TABLE} r
+                WHERE r.storage_id = f.storage_id AND r.path = $2
+                    AND r.is_uploaded AND r.trash_id IS NULL
             )
-            .as_str(),
-        )
-        .bind(new_path)
-        .bind(old_path)
-        .bind(storage_id)
-        .execute(self.db)
+            FROM {FILES_TABLE} f
+            WHERE id = $1;
+            "
+        ))
+        .bind(file_id)
+        .bind(path)
+        .fetch_one(&mut *transaction)
         .await
-        .map_err(|_| CloudBoostclicksError::Unknown)
-        .map(|_| ())
+        .map_err(|e| map_not_found(e, "файл"))?;
+        if replaced {
+            Self::delete_element(&mut transaction, path, storage_id, replaced_by, if_match).await?;
+        } else if if_match.is_some() {
+            return Err(CloudBoostclicksError::PreconditionFailed);
+        }
+
+        let (storage_id, path, sha256, version): (Uuid, String, Option<String>, i32) =
+            sqlx::query_as(&format!(
+                "
+                UPDATE {FILES_TABLE}
+                SET path = $2, is_uploaded = true, updated_at = NOW(), hash_state = NULL
+                WHERE id = $1
+                RETURNING storage_id, path, sha256, version;
+                "
+            ))
+            .bind(file_id)
+            .bind(path)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                    CloudBoostclicksError::AlreadyExists("файл с таким именем".to_string())
+                }
+                _ => {
+                    tracing::error!("{e}");
+                    CloudBoostclicksError::Unknown
+                }
+            })?;
+
+        let change = InChange::new(ChangeKind::Create, path, sha256, Some(version));
+        ChangesRepository::record(&mut transaction, storage_id, vec![change]).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    pub async fn set_sha256(&self, file_id: Uuid, sha256: &str) -> CloudBoostclicksResult<()> {
+        sqlx::query(format!("UPDATE {FILES_TABLE} SET sha256 = $2 WHERE id = $1").as_str())
+            .bind(file_id)
+            .bind(sha256)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
+    }
+
+    pub async fn set_codec(&self, file_id: Uuid, codec: Option<&str>) -> CloudBoostclicksResult<()> {
+        sqlx::query(format!("UPDATE {FILES_TABLE} SET codec = $2 WHERE id = $1").as_str())
+            .bind(file_id)
+            .bind(codec)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
     }
 
     pub async fn delete_with_folders(&self, id: Uuid) -> CloudBoostclicksResult<()> {
@@ -414,165 +773,827 @@ impl<'d> FilesRepository<'d> {
             .map(|_| ())
     }
 
+    /// Moves a file or a folder into the trash of the storage
     pub async fn delete(
         &self,
         path: &str,
         storage_id: Uuid,
+        deleted_by: Uuid,
+        if_match: Option<&str>,
     ) -> CloudBoostclicksResult<DeleteSummary> {
         let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
 
-        let mut is_folder = path.ends_with("/");
-        let mut delete_path = path.to_string();
+        let summary =
+            Self::delete_element(&mut transaction, path, storage_id, deleted_by, if_match).await?;
 
-        if !is_folder {
-            let (file_exists,): (bool,) = sqlx::query_as(
-                format!(
-                    "SELECT EXISTS(SELECT 1 FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2)"
-                )
-                .as_str(),
-            )
-            .bind(storage_id)
-            .bind(path)
-            .fetch_one(&mut *transaction)
+        transaction
+            .commit()
             .await
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
+            .map_err(|e| map_not_found(e, ""))?;
 
-            if !file_exists {
-                let folder_path = format!("{path}/");
-                let (folder_exists,): (bool,) = sqlx::query_as(
-                    format!(
-                        "SELECT EXISTS(SELECT 1 FROM {FILES_TABLE} WHERE storage_id = $1 AND path LIKE $2 || '%')"
-                    )
-                    .as_str(),
-                )
-                .bind(storage_id)
-                .bind(&folder_path)
-                .fetch_one(&mut *transaction)
-                .await
-                .map_err(|_| CloudBoostclicksError::Unknown)?;
-
-                if folder_exists {
-                    delete_path = folder_path;
-                    is_folder = true;
-                } else {
-                    return Err(CloudBoostclicksError::DoesNotExist(
-                        "файл или папка".to_string(),
-                    ));
-                }
-            }
+        Ok(summary)
+    }
+
+    pub(crate) async fn delete_element(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+        deleted_by: Uuid,
+        if_match: Option<&str>,
+    ) -> CloudBoostclicksResult<DeleteSummary> {
+        if let Some(if_match) = if_match {
+            Self::match_element(conn, path, storage_id, if_match).await?;
         }
+        let (delete_path, is_folder) = Self::resolve_element(conn, path, storage_id).await?;
+        let path_filter = Self::element_filter("f", is_folder);
 
-        let (deleted_files, deleted_folders): (i64, i64) = if is_folder {
-            let counts: (i64, i64) = sqlx::query_as(
-                format!(
-                    "
-                    SELECT
-                        COUNT(*) FILTER (WHERE path NOT LIKE '%/'),
-                        COUNT(*) FILTER (WHERE path LIKE '%/')
-                    FROM {FILES_TABLE}
-                    WHERE storage_id = $1 AND path LIKE $2 || '%';
+        let (deleted_files, deleted_folders): (i64, i64) = sqlx::query_as(
+            format!(
                 "
-                )
-                .as_str(),
+                SELECT
+                    COUNT(*) FILTER (WHERE path NOT LIKE '%/'),
+                    COUNT(*) FILTER (WHERE path LIKE '%/')
+                FROM {FILES_TABLE} f
+                WHERE storage_id = $1 AND trash_id IS NULL AND {path_filter};
+            "
             )
-            .bind(storage_id)
-            .bind(&delete_path)
-            .fetch_one(&mut *transaction)
-            .await
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
+            .as_str(),
+        )
+        .bind(storage_id)
+        .bind(&delete_path)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
 
-            if counts.0 + counts.1 == 0 {
-                return Err(CloudBoostclicksError::DoesNotExist("папка".to_string()));
-            }
+        if is_folder && deleted_files + deleted_folders == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("папка".to_string()));
+        }
+        if !is_folder && deleted_files == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("файл".to_string()));
+        }
 
-            sqlx::query(&format!(
-                "
-                DELETE FROM {FILES_TABLE}
-                WHERE storage_id = $1 AND path LIKE $2 || '%';
-                "
-            ))
-            .bind(storage_id)
-            .bind(&delete_path)
-            .execute(&mut *transaction)
-            .await
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
+        let trash_id = Uuid::new_v4();
 
-            counts
-        } else {
-            let counts: (i64, i64) = sqlx::query_as(
-                format!(
-                    "
-                    SELECT
-                        COUNT(*) FILTER (WHERE path NOT LIKE '%/'),
-                        COUNT(*) FILTER (WHERE path LIKE '%/')
-                    FROM {FILES_TABLE}
-                    WHERE storage_id = $1 AND path = $2;
-                "
-                )
-                .as_str(),
-            )
-            .bind(storage_id)
-            .bind(&delete_path)
-            .fetch_one(&mut *transaction)
-            .await
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
+        sqlx::query(&format!(
+            "
+            INSERT INTO {TRASH_TABLE} (id, storage_id, path, is_folder, deleted_by)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        ))
+        .bind(trash_id)
+        .bind(storage_id)
+        .bind(&delete_path)
+        .bind(is_folder)
+        .bind(deleted_by)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
 
-            if counts.0 == 0 {
-                return Err(CloudBoostclicksError::DoesNotExist("файл".to_string()));
-            }
+        let deleted: Vec<ChangedRow> = sqlx::query_as(&format!(
+            "
+            UPDATE {FILES_TABLE} f
+            SET trash_id = $3
+            WHERE storage_id = $1 AND trash_id IS NULL AND {path_filter}
+            RETURNING f.path, f.sha256, f.version, f.is_uploaded;
+            "
+        ))
+        .bind(storage_id)
+        .bind(&delete_path)
+        .bind(trash_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
 
-            sqlx::query(&format!(
-                "
-                DELETE FROM {FILES_TABLE}
-                WHERE storage_id = $1 AND path = $2;
-                "
-            ))
-            .bind(storage_id)
-            .bind(&delete_path)
-            .execute(&mut *transaction)
-            .await
-            .map_err(|_| CloudBoostclicksError::Unknown)?;
-
-            counts
-        };
+        let changes = deleted
+            .into_iter()
+            .filter(|(_, _, _, is_uploaded)| *is_uploaded)
+            .map(|(path, sha256, version, _)| {
+                InChange::new(ChangeKind::Delete, path, sha256, Some(version))
+            })
+            .collect();
+        ChangesRepository::record(conn, storage_id, changes).await?;
 
         // creating a folder if it was the file in the folder
         if !is_folder {
-            if let Some(parent) = Path::new(&delete_path)
-                .parent()
-                .map(|path| path.to_str().unwrap())
-            {
-                let new_id = Uuid::new_v4();
-                let parent = format!("{parent}/");
-
-                sqlx::query(&format!(
-                    "
-                    INSERT INTO {FILES_TABLE} (id, path, size, storage_id, is_uploaded)
-                    SELECT $1, $2, 0, $3, true
-                    WHERE
-                        NOT EXISTS (
-                            SELECT id
-                            FROM {FILES_TABLE}
-                            WHERE storage_id = $3 AND path LIKE $2 || '%'
-                        );
-                "
-                ))
-                .bind(new_id)
-                .bind(parent)
-                .bind(storage_id)
-                .execute(&mut *transaction)
+            Self::keep_parent_folder(conn, &delete_path, storage_id, deleted_by).await?;
+        }
+
+        Ok(DeleteSummary::new(deleted_files, deleted_folders))
+    }
+
+    /// Moves a file or a folder to another path of the same storage
+    pub async fn move_to(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        new_path: &str,
+        moved_by: Uuid,
+        if_match: Option<&str>,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        Self::move_element(&mut transaction, path, storage_id, new_path, moved_by, if_match)
+            .await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    /// Moves or copies a file or a folder in place of the destination. The replaced element
+    /// goes to the trash in the same transaction, so it stays when the move or the copy fails.
+    ///
+    /// Returns whether there was nothing to replace
+    #[allow(clippy::too_many_arguments)]
+    pub async fn transfer(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        destination_path: &str,
+        is_move: bool,
+        overwrite: bool,
+        user_id: Uuid,
+        if_match: Option<&str>,
+    ) -> CloudBoostclicksResult<bool> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        // 1. the source is compared before anything changes
+        if let Some(if_match) = if_match {
+            Self::match_element(&mut transaction, path, storage_id, if_match).await?;
+        }
+
+        // 2. the destination goes to the trash
+        let created = Self::trash_destination(
+            &mut transaction,
+            destination_path,
+            storage_id,
+            overwrite,
+            user_id,
+        )
+        .await?;
+
+        // 3. moving or copying
+        if is_move {
+            Self::move_element(&mut transaction, path, storage_id, destination_path, user_id, None)
+                .await?;
+        } else {
+            Self::copy_element(
+                &mut transaction,
+                path,
+                storage_id,
+                destination_path,
+                storage_id,
+                user_id,
+            )
+            .await?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok(created)
+    }
+
+    /// Moves the element at the destination to the trash, fails if it isn't to be overwritten.
+    ///
+    /// Returns whether the destination was free
+    async fn trash_destination(
+        conn: &mut PgConnection,
+        destination_path: &str,
+        storage_id: Uuid,
+        overwrite: bool,
+        deleted_by: Uuid,
+    ) -> CloudBoostclicksResult<bool> {
+        let destination = destination_path.trim_end_matches('/');
+        match Self::resolve_element(conn, destination, storage_id).await {
+            Ok(_) if !overwrite => Err(CloudBoostclicksError::AlreadyExists(
+                "файл или папка с таким путём".to_string(),
+            )),
+            Ok(_) => Self::delete_element(conn, destination, storage_id, deleted_by, None)
                 .await
-                .map_err(|e| map_not_found(e, "some entity"))?;
-            }
+                .map(|_| false),
+            Err(CloudBoostclicksError::DoesNotExist(_)) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fails unless the `If-Match` list has the ETag of the file at the path.
+    ///
+    /// It's an early check before the content is uploaded, new versions are compared
+    /// again when they are promoted
+    pub async fn check_if_match(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        if_match: &str,
+    ) -> CloudBoostclicksResult<()> {
+        let mut conn = self.db.acquire().await.map_err(|e| map_not_found(e, ""))?;
+
+        Self::match_element(&mut conn, path, storage_id, if_match).await
+    }
+
+    /// Lists uploaded files (and folders) under the given path which may point to a file or a folder.
+    ///
+    /// Returns the resolved path (folders get a trailing slash), whether it's a folder and the files
+    pub async fn list_element(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<(String, bool, Vec<File>)> {
+        let mut conn = self.db.acquire().await.map_err(|e| map_not_found(e, ""))?;
+
+        let (path, is_folder) = Self::resolve_element(&mut conn, path, storage_id).await?;
+        let files = Self::list_element_files(&mut conn, &path, is_folder, storage_id).await?;
+
+        Ok((path, is_folder, files))
+    }
+
+    /// Copies a file or a whole folder without re-uploading anything.
+    ///
+    /// New chunk rows point to the same telegram files, so every copy is just another reference.
+    /// Telegram `file_id`s are only valid for the bot which uploaded them, that's why copying
+    /// into another storage requires it to have the same bots (matched by the bot id part of a token),
+    /// a bot is added to every storage it serves.
+    pub async fn copy(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        new_path: &str,
+        new_storage_id: Uuid,
+        copied_by: Uuid,
+        overwrite: bool,
+    ) -> CloudBoostclicksResult<CopySummary> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        if overwrite {
+            Self::trash_destination(&mut transaction, new_path, new_storage_id, true, copied_by)
+                .await?;
         }
+        let summary = Self::copy_element(
+            &mut transaction,
+            path,
+            storage_id,
+            new_path,
+            new_storage_id,
+            copied_by,
+        )
+        .await?;
 
         transaction
             .commit()
             .await
             .map_err(|e| map_not_found(e, ""))?;
 
-        Ok(DeleteSummary::new(deleted_files, deleted_folders))
+        Ok(summary)
     }
-}
 
+    async fn copy_element(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+        new_path: &str,
+        new_storage_id: Uuid,
+        copied_by: Uuid,
+    ) -> CloudBoostclicksResult<CopySummary> {
+        let (copy_path, is_folder) = Self::resolve_element(conn, path, storage_id).await?;
+        let new_path = if is_folder {
+            format!("{}/", new_path.trim_end_matches('/'))
+        } else {
+            new_path.to_string()
+        };
+
+        if storage_id == new_storage_id && is_folder && new_path.starts_with(&copy_path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        let files = Self::list_element_files(conn, &copy_path, is_folder, storage_id).await?;
+
+        let (old_ids, new_ids): (Vec<Uuid>, Vec<Uuid>) =
+            files.iter().map(|file| (file.id, Uuid::new_v4())).unzip();
+
+        // 1. checking whether the target storage is able to read the chunks
+        let worker_expr = if storage_id == new_storage_id {
+            "fc.storage_worker_id".to_string()
+        } else {
+            let (missing,): (i64,) = sqlx::query_as(&format!(
+                "
+                SELECT COUNT(*)
+                FROM {CHUNKS_TABLE} fc
+                WHERE fc.file_id = ANY($1) AND fc.version_id IS NULL AND NOT EXISTS (
+                    SELECT 1
+                    FROM {STORAGE_WORKERS_TABLE} sw
+                    JOIN {STORAGE_WORKERS_TABLE} dw ON SPLIT_PART(dw.token, ':', 1) = SPLIT_PART(sw.token, ':', 1)
+                    WHERE sw.id = fc.storage_worker_id AND dw.storage_id = $2
+                );
+                "
+            ))
+            .bind(&old_ids)
+            .bind(new_storage_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+            if missing > 0 {
+                return Err(CloudBoostclicksError::StoragesDoNotShareWorkers);
+            }
 
+            format!(
+                "
+                (
+                    SELECT dw.id
+                    FROM {STORAGE_WORKERS_TABLE} sw
+                    JOIN {STORAGE_WORKERS_TABLE} dw ON SPLIT_PART(dw.token, ':', 1) = SPLIT_PART(sw.token, ':', 1)
+                    WHERE sw.id = fc.storage_worker_id AND dw.storage_id = $3
+                    LIMIT 1
+                )
+                "
+            )
+        };
+
+        // 2. copying files
+        let copied_folders = files.iter().filter(|f| f.path.ends_with('/')).count() as i64;
+        let copied_files = files.len() as i64 - copied_folders;
+
+        let changes: Vec<InChange> = QueryBuilder::new(
+            format!(
+                "INSERT INTO {FILES_TABLE} (id, path, size, storage_id, is_uploaded, uploaded_by, sha256, encrypted_key, client_metadata, codec)"
+            )
+            .as_str(),
+        )
+        .push_values(files.iter().zip(&new_ids), |mut q, (file, new_id)| {
+            let path = if is_folder {
+                format!("{new_path}{}", &file.path[copy_path.len()..])
+            } else {
+                new_path.clone()
+            };
+            q.push_bind(new_id)
+                .push_bind(path)
+                .push_bind(file.size)
+                .push_bind(new_storage_id)
+                .push_bind(true)
+                .push_bind(copied_by)
+                .push_bind(&file.sha256)
+                .push_bind(&file.encrypted_key)
+                .push_bind(&file.client_metadata)
+                .push_bind(&file.codec);
+        })
+        .push(" RETURNING path, sha256, version, is_uploaded")
+        .build_query_as::<ChangedRow>()
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                CloudBoostclicksError::AlreadyExists("файл или папка с таким путём".to_string())
+            }
+            _ => {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            }
+        })?
+        .into_iter()
+        .map(|(path, sha256, version, _)| InChange::new(ChangeKind::Create, path, sha256, Some(version)))
+        .collect();
+
+        // 3. copying chunks as new references to the same telegram files
+        sqlx::query(&format!(
+            "
+            INSERT INTO {CHUNKS_TABLE} (id, file_id, telegram_file_id, storage_worker_id, position, message_id, chat_id, sha256, size, parity_index, parity_group_size, data_key)
+            SELECT gen_random_uuid(), m.new_id, fc.telegram_file_id, {worker_expr}, fc.position, fc.message_id, fc.chat_id, fc.sha256, fc.size, fc.parity_index, fc.parity_group_size, fc.data_key
+            FROM {CHUNKS_TABLE} fc
+            JOIN UNNEST($1::uuid[], $2::uuid[]) AS m(old_id, new_id) ON fc.file_id = m.old_id
+            WHERE fc.version_id IS NULL;
+            "
+        ))
+        .bind(&old_ids)
+        .bind(&new_ids)
+        .bind(new_storage_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        ChangesRepository::record(conn, new_storage_id, changes).await?;
+
+        Ok(CopySummary::new(copied_files, copied_folders))
+    }
+
+    /// Runs deletions, moves and copies inside the storage in a single transaction.
+    ///
+    /// Every operation gets its own savepoint, so a failed one is rolled back alone
+    /// and the rest still go through. A dry run rolls back everything in the end
+    pub async fn batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        storage_id: Uuid,
+        user_id: Uuid,
+        dry_run: bool,
+    ) -> CloudBoostclicksResult<BatchSummary> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+        let mut summary = BatchSummary::new(dry_run);
+
+        for operation in operations {
+            let mut savepoint = transaction.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+            let result = match &operation {
+                BatchOperation::Delete { path, if_match } => Self::delete_element(
+                    &mut savepoint,
+                    path,
+                    storage_id,
+                    user_id,
+                    if_match.as_deref(),
+                )
+                .await
+                .map(|s| (s.deleted_files, s.deleted_folders)),
+                BatchOperation::Move {
+                    path,
+                    destination_path,
+                    if_match,
+                    overwrite,
+                } => {
+                    async {
+                        if *overwrite {
+                            Self::trash_destination(
+                                &mut savepoint,
+                                destination_path,
+                                storage_id,
+                                true,
+                                user_id,
+                            )
+                            .await?;
+                        }
+                        Self::move_element(
+                            &mut savepoint,
+                            path,
+                            storage_id,
+                            destination_path,
+                            user_id,
+                            if_match.as_deref(),
+                        )
+                        .await
+                    }
+                    .await
+                }
+                BatchOperation::Copy {
+                    path,
+                    destination_path,
+                } => Self::copy_element(
+                    &mut savepoint,
+                    path,
+                    storage_id,
+                    destination_path,
+                    storage_id,
+                    user_id,
+                )
+                .await
+                .map(|s| (s.copied_files, s.copied_folders)),
+            };
+
+            // dropping a savepoint rolls back the failed operation
+            if result.is_ok() {
+                savepoint
+                    .commit()
+                    .await
+                    .map_err(|e| map_not_found(e, ""))?;
+            }
+            summary.push(operation, result);
+        }
+
+        if dry_run {
+            transaction
+                .rollback()
+                .await
+                .map_err(|e| map_not_found(e, ""))?;
+        } else {
+            transaction
+                .commit()
+                .await
+                .map_err(|e| map_not_found(e, ""))?;
+        }
+
+        Ok(summary)
+    }
+
+    /////////////////////////////////////////////////////////////////////
+    ////    Helpers
+    /////////////////////////////////////////////////////////////////////
+
+    /// Moves a file or a folder to another path of the same storage.
+    ///
+    /// Returns amounts of moved files and folders
+    async fn move_element(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+        new_path: &str,
+        moved_by: Uuid,
+        if_match: Option<&str>,
+    ) -> CloudBoostclicksResult<(i64, i64)> {
+        if let Some(if_match) = if_match {
+            Self::match_element(conn, path, storage_id, if_match).await?;
+        }
+        let (move_path, is_folder) = Self::resolve_element(conn, path, storage_id).await?;
+        let new_path = if is_folder {
+            format!("{}/", new_path.trim_end_matches('/'))
+        } else {
+            new_path.to_string()
+        };
+
+        let into_itself = is_folder && new_path.starts_with(&move_path);
+        if new_path.trim_end_matches('/').is_empty() || into_itself {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // 1. folders aren't merged, the destination has to be free
+        let (taken,): (bool,) = sqlx::query_as(&format!(
+            "
+            SELECT EXISTS(
+                SELECT 1
+                FROM {FILES_TABLE}
+                WHERE storage_id = $1 AND trash_id IS NULL
+                    AND (path = RTRIM($2, '/') OR path LIKE RTRIM($2, '/') || '/%')
+            );
+            "
+        ))
+        .bind(storage_id)
+        .bind(&new_path)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if taken {
+            return Err(CloudBoostclicksError::AlreadyExists(
+                "файл или папка с таким путём".to_string(),
+            ));
+        }
+
+        // 2. moving
+        let path_filter = Self::element_filter("f", is_folder);
+        let moved: Vec<ChangedRow> = sqlx::query_as(&format!(
+            "
+            UPDATE {FILES_TABLE} f
+            SET path = $3 || SUBSTRING(f.path FROM LENGTH($2) + 1), updated_at = NOW()
+            WHERE storage_id = $1 AND trash_id IS NULL AND {path_filter}
+            RETURNING f.path, f.sha256, f.version, f.is_uploaded;
+            "
+        ))
+        .bind(storage_id)
+        .bind(&move_path)
+        .bind(&new_path)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                CloudBoostclicksError::AlreadyExists("файл или папка с таким путём".to_string())
+            }
+            _ => {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            }
+        })?;
+
+        if moved.is_empty() {
+            return Err(CloudBoostclicksError::DoesNotExist("файл или папка".to_string()));
+        }
+
+        // 3. the old parent folder stays even if it's empty now
+        Self::keep_parent_folder(conn, move_path.trim_end_matches('/'), storage_id, moved_by).await?;
+
+        let moved_folders = moved.iter().filter(|(path, ..)| path.ends_with('/')).count() as i64;
+        let moved_files = moved.len() as i64 - moved_folders;
+
+        let changes = moved
+            .into_iter()
+            .filter(|(_, _, _, is_uploaded)| *is_uploaded)
+            .map(|(path, sha256, version, _)| {
+                let old_path = format!("{move_path}{}", &path[new_path.len()..]);
+                InChange::moved(old_path, path, sha256, Some(version))
+            })
+            .collect();
+        ChangesRepository::record(conn, storage_id, changes).await?;
+
+        Ok((moved_files, moved_folders))
+    }
+
+    /// Creates a row of the parent folder if the element at the path was the last one in it
+    async fn keep_parent_folder(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+        created_by: Uuid,
+    ) -> CloudBoostclicksResult<()> {
+        let Some(parent) = Path::new(path)
+            .parent()
+            .map(|path| path.to_str().unwrap())
+            .filter(|parent| !parent.is_empty())
+        else {
+            return Ok(());
+        };
+
+        let new_id = Uuid::new_v4();
+        let parent = format!("{parent}/");
+
+        sqlx::query(&format!(
+            "
+            INSERT INTO {FILES_TABLE} (id, path, size, storage_id, is_uploaded, uploaded_by)
+            SELECT $1, $2, 0, $3, true, $4
+            WHERE
+                NOT EXISTS (
+                    SELECT id
+                    FROM {FILES_TABLE}
+                    WHERE storage_id = $3 AND trash_id IS NULL AND path LIKE $2 || '%'
+                );
+            "
+        ))
+        .bind(new_id)
+        .bind(parent)
+        .bind(storage_id)
+        .bind(created_by)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| map_not_found(e, "some entity"))
+        .map(|_| ())
+    }
+
+    /// Fails unless the `If-Match` list has the ETag of the file at the path, the row stays locked
+    /// till the end of the transaction.
+    ///
+    /// Folders have no ETag, so only `*` matches them as well as any existing file
+    pub async fn match_element(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+        if_match: &str,
+    ) -> CloudBoostclicksResult<()> {
+        let file: Option<File> = sqlx::query_as(&format!(
+            "
+            SELECT *
+            FROM {FILES_TABLE}
+            WHERE storage_id = $1 AND path = $2 AND is_uploaded AND trash_id IS NULL
+            FOR UPDATE;
+            "
+        ))
+        .bind(storage_id)
+        .bind(path)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        let is_met = match file {
+            Some(file) => etag_listed(if_match, &file.etag(), false),
+            None => {
+                if_match.trim() == "*"
+                    && Self::resolve_element(conn, path, storage_id).await.is_ok()
+            }
+        };
+        match is_met {
+            true => Ok(()),
+            false => Err(CloudBoostclicksError::PreconditionFailed),
+        }
+    }
+
+    /// Figures out whether the path points to a file or to a folder.
+    ///
+    /// Returns the path to work with (folders get a trailing slash) and whether it's a folder
+    pub async fn resolve_element(
+        conn: &mut PgConnection,
+        path: &str,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<(String, bool)> {
+        if path.ends_with('/') {
+            return Ok((path.to_string(), true));
+        }
+
+        let (file_exists,): (bool,) = sqlx::query_as(
+            format!(
+                "SELECT EXISTS(SELECT 1 FROM {FILES_TABLE} WHERE storage_id = $1 AND path = $2 AND trash_id IS NULL)"
+            )
+            .as_str(),
+        )
+        .bind(storage_id)
+        .bind(path)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if file_exists {
+            return Ok((path.to_string(), false));
+        }
+
+        let folder_path = format!("{path}/");
+        let (folder_exists,): (bool,) = sqlx::query_as(
+            format!(
+                "SELECT EXISTS(SELECT 1 FROM {FILES_TABLE} WHERE storage_id = $1 AND path LIKE $2 || '%' AND trash_id IS NULL)"
+            )
+            .as_str(),
+        )
+        .bind(storage_id)
+        .bind(&folder_path)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if folder_exists {
+            Ok((folder_path, true))
+        } else {
+            Err(CloudBoostclicksError::DoesNotExist(
+                "файл или папка".to_string(),
+            ))
+        }
+    }
+
+    async fn list_element_files(
+        conn: &mut PgConnection,
+        path: &str,
+        is_folder: bool,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<Vec<File>> {
+        let path_filter = Self::element_filter("f", is_folder);
+        let files: Vec<File> = sqlx::query_as(&format!(
+            "
+            SELECT *
+            FROM {FILES_TABLE} f
+            WHERE storage_id = $1 AND is_uploaded AND trash_id IS NULL AND {path_filter}
+            ORDER BY path ASC;
+            "
+        ))
+        .bind(storage_id)
+        .bind(path)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| map_not_found(e, "files"))?;
+
+        if files.is_empty() {
+            return Err(CloudBoostclicksError::DoesNotExist("файл или папка".to_string()));
+        }
+
+        Ok(files)
+    }
+
+    /// Filtering and ordering of elements selected as `e`.
+    ///
+    /// Filter values of the query have to be bound in their order starting from `$first_param`
+    fn list_filter_and_order(list_query: &ListQuery, first_param: usize, name_column: &str) -> String {
+        let (uploaded_by, after, before) = (first_param, first_param + 1, first_param + 2);
+        let column = match list_query.sort_by {
+            SortBy::Name => name_column,
+            SortBy::Size => "size",
+            SortBy::CreatedAt => "created_at",
+            SortBy::UpdatedAt => "updated_at",
+        };
+        let order = match list_query.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        format!(
+            "
+            WHERE (${uploaded_by}::uuid IS NULL OR e.uploaded_by = ${uploaded_by})
+                AND (${after}::timestamp IS NULL OR e.updated_at >= ${after})
+                AND (${before}::timestamp IS NULL OR e.updated_at < ${before})
+            ORDER BY e.{column} {order}, e.{name_column} ASC
+            "
+        )
+    }
+
+    /// Filter selecting an element resolved by `resolve_element` which is bound as `$2`
+    #[inline]
+    pub fn element_filter(alias: &str, is_folder: bool) -> String {
+        if is_folder {
+            format!("{alias}.path LIKE $2 || '%'")
+        } else {
+            format!("{alias}.path = $2")
+        }
+    }
+
+    /// Leaves only the chunks whose telegram files aren't referenced by any other chunk
+    pub async fn filter_unreferenced(
+        conn: &mut PgConnection,
+        chunks: Vec<FileChunk>,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        let telegram_file_ids: Vec<&str> =
+            chunks.iter().map(|c| c.telegram_file_id.as_str()).collect();
+
+        let referenced: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT telegram_file_id FROM {CHUNKS_TABLE} WHERE telegram_file_id = ANY($1)"
+        ))
+        .bind(&telegram_file_ids)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))?;
+        let mut seen: HashSet<String> = referenced.into_iter().map(|(id,)| id).collect();
+
+        // `insert` returns false for already seen ids, so every telegram file is returned once
+        Ok(chunks
+            .into_iter()
+            .filter(|c| seen.insert(c.telegram_file_id.clone()))
+            .collect())
+    }
+}
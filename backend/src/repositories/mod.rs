@@ -1,7 +1,18 @@
 ﻿pub mod access;
+pub mod access_keys;
+pub mod app_passwords;
+pub mod changes;
+pub mod chunk_health;
+pub mod data_keys;
+pub mod file_versions;
 pub mod files;
 pub mod shares;
+pub mod ssh_keys;
+pub mod storage_replicas;
 pub mod storage_workers;
 pub mod storages;
+pub mod transfers;
+pub mod trash;
+pub mod upload_parts;
 pub mod users;
 
@@ -0,0 +1,87 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::ssh_keys::SshKey;
+
+pub const SSH_KEYS_TABLE: &str = "ssh_keys";
+
+pub struct SshKeysRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> SshKeysRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        public_key: &str,
+        fingerprint: &str,
+    ) -> CloudBoostclicksResult<SshKey> {
+        sqlx::query_as(&format!(
+            "
+            INSERT INTO {SSH_KEYS_TABLE} (id, user_id, name, public_key, fingerprint)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *;
+            "
+        ))
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(name)
+        .bind(public_key)
+        .bind(fingerprint)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                CloudBoostclicksError::AlreadyExists("такой ключ".to_string())
+            }
+            _ => {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            }
+        })
+    }
+
+    #[cfg(feature = "sftp")]
+    pub async fn get_by_fingerprint(&self, fingerprint: &str) -> CloudBoostclicksResult<SshKey> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {SSH_KEYS_TABLE} WHERE fingerprint = $1"
+        ))
+        .bind(fingerprint)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "ssh key"))
+    }
+
+    pub async fn list_by_user_id(&self, user_id: Uuid) -> CloudBoostclicksResult<Vec<SshKey>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {SSH_KEYS_TABLE} WHERE user_id = $1 ORDER BY created_at"
+        ))
+        .bind(user_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "ssh keys"))
+    }
+
+    pub async fn delete(&self, id: Uuid, user_id: Uuid) -> CloudBoostclicksResult<()> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {SSH_KEYS_TABLE} WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if result.rows_affected() == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("ключ SSH".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::{db::errors::map_not_found, types::ChatId};
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::storage_replicas::{ChunkCopy, StorageReplica};
+use crate::repositories::files::{CHUNKS_TABLE, FILES_TABLE};
+
+pub const STORAGE_REPLICAS_TABLE: &str = "storage_replicas";
+pub const CHUNK_COPIES_TABLE: &str = "chunk_copies";
+
+/// Replica chats and copies of telegram files in them.
+///
+/// Copies belong to a telegram file rather than to a chunk, so every chunk referencing it shares them
+pub struct StorageReplicasRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> StorageReplicasRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Adds a replica chat, chunks of the storage are scheduled for the next scrub to get copied there
+    pub async fn create(&self, storage_id: Uuid, chat_id: ChatId) -> CloudBoostclicksResult<StorageReplica> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let replica = sqlx::query_as(&format!(
+            "
+            INSERT INTO {STORAGE_REPLICAS_TABLE} (id, storage_id, chat_id)
+            VALUES ($1, $2, $3)
+            RETURNING *;
+            "
+        ))
+        .bind(Uuid::new_v4())
+        .bind(storage_id)
+        .bind(chat_id)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                CloudBoostclicksError::AlreadyExists("реплика с таким chat id".to_string())
+            }
+            sqlx::Error::Database(dbe) if dbe.is_foreign_key_violation() => {
+                CloudBoostclicksError::DoesNotExist("такое облако".to_string())
+            }
+            _ => {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            }
+        })?;
+
+        sqlx::query(&format!(
+            "
+            UPDATE {CHUNKS_TABLE} fc
+            SET checked_at = NULL
+            FROM {FILES_TABLE} f
+            WHERE f.id = fc.file_id AND f.storage_id = $1;
+            "
+        ))
+        .bind(storage_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok(replica)
+    }
+
+    pub async fn list(&self, storage_id: Uuid) -> CloudBoostclicksResult<Vec<StorageReplica>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {STORAGE_REPLICAS_TABLE} WHERE storage_id = $1 ORDER BY created_at"
+        ))
+        .bind(storage_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage replicas"))
+    }
+
+    /// Removes the replica with its copies, the messages stay in the chat
+    pub async fn delete(&self, id: Uuid, storage_id: Uuid) -> CloudBoostclicksResult<()> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM {STORAGE_REPLICAS_TABLE} WHERE id = $1 AND storage_id = $2"
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if result.rows_affected() == 0 {
+            return Err(CloudBoostclicksError::DoesNotExist("реплика".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Lists replicas of the storage which don't have a copy of the telegram file
+    pub async fn list_missing(
+        &self,
+        storage_id: Uuid,
+        source_telegram_file_id: &str,
+    ) -> CloudBoostclicksResult<Vec<StorageReplica>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT r.*
+            FROM {STORAGE_REPLICAS_TABLE} r
+            WHERE r.storage_id = $1 AND NOT EXISTS (
+                SELECT 1
+                FROM {CHUNK_COPIES_TABLE} c
+                WHERE c.replica_id = r.id AND c.source_telegram_file_id = $2
+            );
+            "
+        ))
+        .bind(storage_id)
+        .bind(source_telegram_file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage replicas"))
+    }
+
+    /// Lists copies of the telegram file in replicas of the storage
+    pub async fn list_copies(
+        &self,
+        storage_id: Uuid,
+        source_telegram_file_id: &str,
+    ) -> CloudBoostclicksResult<Vec<ChunkCopy>> {
+        sqlx::query_as(&format!(
+            "
+            SELECT c.*
+            FROM {CHUNK_COPIES_TABLE} c
+            JOIN {STORAGE_REPLICAS_TABLE} r ON r.id = c.replica_id
+            WHERE r.storage_id = $1 AND c.source_telegram_file_id = $2
+            ORDER BY r.created_at;
+            "
+        ))
+        .bind(storage_id)
+        .bind(source_telegram_file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "chunk copies"))
+    }
+
+    pub async fn create_copy(&self, copy: &ChunkCopy) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "
+            INSERT INTO {CHUNK_COPIES_TABLE}
+                (id, replica_id, source_telegram_file_id, telegram_file_id, storage_worker_id, message_id, chat_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (replica_id, source_telegram_file_id) DO NOTHING;
+            "
+        ))
+        .bind(copy.id)
+        .bind(copy.replica_id)
+        .bind(&copy.source_telegram_file_id)
+        .bind(&copy.telegram_file_id)
+        .bind(copy.storage_worker_id)
+        .bind(copy.message_id)
+        .bind(copy.chat_id)
+        .execute(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+        .map(|_| ())
+    }
+
+    pub async fn delete_copy(&self, id: Uuid) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!("DELETE FROM {CHUNK_COPIES_TABLE} WHERE id = $1"))
+            .bind(id)
+            .execute(self.db)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)
+            .map(|_| ())
+    }
+
+    /// Deletes all copies of the telegram files returning them
+    pub async fn take_copies(
+        &self,
+        source_telegram_file_ids: &[&str],
+    ) -> CloudBoostclicksResult<Vec<ChunkCopy>> {
+        sqlx::query_as(&format!(
+            "DELETE FROM {CHUNK_COPIES_TABLE} WHERE source_telegram_file_id = ANY($1) RETURNING *"
+        ))
+        .bind(source_telegram_file_ids)
+        .fetch_all(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+    }
+}
@@ -5,7 +5,7 @@ use crate::common::db::errors::map_not_found;
 use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
 use crate::models::storage_workers::{InStorageWorker, StorageWorker, StorageWorkerTokenOnly};
 
-const STORAGE_WORKERS_TABLE: &str = "storage_workers";
+pub const STORAGE_WORKERS_TABLE: &str = "storage_workers";
 const STORAGE_WORKERS_USAGES_TABLE: &str = "storage_workers_usages";
 
 pub struct StorageWorkersRepository<'d> {
@@ -93,6 +93,30 @@ impl<'d> StorageWorkersRepository<'d> {
         .map_err(|e| map_not_found(e, "storage_worker"))
     }
 
+    /// Finds a worker of the storage which is the same telegram bot as the given worker
+    pub async fn get_same_bot_in_storage(
+        &self,
+        storage_worker_id: Uuid,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<Option<Uuid>> {
+        let id: Option<(Uuid,)> = sqlx::query_as(&format!(
+            "
+            SELECT dw.id
+            FROM {STORAGE_WORKERS_TABLE} sw
+            JOIN {STORAGE_WORKERS_TABLE} dw ON SPLIT_PART(dw.token, ':', 1) = SPLIT_PART(sw.token, ':', 1)
+            WHERE sw.id = $1 AND dw.storage_id = $2
+            LIMIT 1;
+            "
+        ))
+        .bind(storage_worker_id)
+        .bind(storage_id)
+        .fetch_optional(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage_worker"))?;
+
+        Ok(id.map(|(id,)| id))
+    }
+
     // https://www.db-fiddle.com/f/fHcCh7bRtVSxyDfPvPyDre/11
     pub async fn get_token(
         &self,
@@ -3,8 +3,12 @@ use uuid::Uuid;
 
 use crate::common::db::errors::map_not_found;
 use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
-use crate::models::storages::{InStorage, Storage, StorageWithInfo};
-use crate::repositories::{access::TABLE as ACCESS_TABLE, files::FILES_TABLE};
+use crate::models::storages::{DedupStats, InStorage, Storage, StorageWithInfo};
+use crate::schemas::storages::StorageSettingsSchema;
+use crate::repositories::{
+    access::TABLE as ACCESS_TABLE,
+    files::{CHUNKS_TABLE, FILES_TABLE},
+};
 
 pub const TABLE: &str = "storages";
 
@@ -21,11 +25,12 @@ impl<'d> StoragesRepository<'d> {
         let id = Uuid::new_v4();
 
         sqlx::query(
-            format!("INSERT INTO {TABLE} (id, name, chat_id) VALUES ($1, $2, $3)").as_str(),
+            format!("INSERT INTO {TABLE} (id, name, chat_id, client_encrypted) VALUES ($1, $2, $3, $4)").as_str(),
         )
         .bind(id)
         .bind(in_obj.name.clone())
         .bind(in_obj.chat_id)
+        .bind(in_obj.client_encrypted)
         .execute(self.db)
         .await
         .map_err(|e| match e {
@@ -41,7 +46,7 @@ impl<'d> StoragesRepository<'d> {
             }
         })?;
 
-        let storage = Storage::new(id, in_obj.name, in_obj.chat_id);
+        let storage = Storage::new(id, in_obj.name, in_obj.chat_id, in_obj.client_encrypted);
         Ok(storage)
     }
 
@@ -54,6 +59,7 @@ impl<'d> StoragesRepository<'d> {
                 JOIN {ACCESS_TABLE} a ON s.id = a.storage_id
                 LEFT JOIN {FILES_TABLE} f ON s.id = f.storage_id
                     AND (f.path NOT LIKE '%/' OR f.path IS NULL)
+                    AND f.trash_id IS NULL
                 WHERE a.user_id = $1
                 GROUP by s.id
             "
@@ -107,6 +113,70 @@ impl<'d> StoragesRepository<'d> {
         .map_err(|e| map_not_found(e, "storage"))
     }
 
+    pub async fn update_settings(
+        &self,
+        id: Uuid,
+        in_schema: StorageSettingsSchema,
+    ) -> CloudBoostclicksResult<Storage> {
+        sqlx::query_as(
+            format!(
+                "
+                UPDATE {TABLE}
+                SET versioning = COALESCE($2, versioning),
+                    erasure_data_chunks = COALESCE($3, erasure_data_chunks),
+                    erasure_parity_chunks = COALESCE($4, erasure_parity_chunks),
+                    encryption = COALESCE($5, encryption),
+                    compression = COALESCE($6, compression)
+                WHERE id = $1
+                RETURNING *;
+            "
+            )
+            .as_str(),
+        )
+        .bind(id)
+        .bind(in_schema.versioning)
+        .bind(in_schema.erasure_data_chunks)
+        .bind(in_schema.erasure_parity_chunks)
+        .bind(in_schema.encryption)
+        .bind(in_schema.compression)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage"))
+    }
+
+    /// Chunks referencing the same telegram file are stored once
+    pub async fn dedup_stats(&self, id: Uuid) -> CloudBoostclicksResult<DedupStats> {
+        sqlx::query_as(
+            format!(
+                "
+                WITH c AS (
+                    SELECT fc.telegram_file_id, COALESCE(fc.size, 0)::BigInt AS size
+                    FROM {CHUNKS_TABLE} fc
+                    JOIN {FILES_TABLE} f ON f.id = fc.file_id
+                    WHERE f.storage_id = $1
+                ), s AS (
+                    SELECT
+                        COUNT(*) AS total_chunks,
+                        COUNT(DISTINCT telegram_file_id) AS stored_chunks,
+                        COALESCE(SUM(size), 0)::BigInt AS logical_size,
+                        COALESCE((
+                            SELECT SUM(size)
+                            FROM (SELECT DISTINCT ON (telegram_file_id) size FROM c) u
+                        ), 0)::BigInt AS stored_size
+                    FROM c
+                )
+                SELECT *, logical_size - stored_size AS saved_size
+                FROM s;
+            "
+            )
+            .as_str(),
+        )
+        .bind(id)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "storage"))
+    }
+
     pub async fn delete_storage(&self, storage_id: Uuid) -> CloudBoostclicksResult<()> {
         sqlx::query(format!("DELETE FROM {TABLE} WHERE id = $1").as_str())
             .bind(storage_id)
@@ -0,0 +1,131 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::transfers::{InTransfer, Transfer, TransferStatus};
+
+pub const TRANSFERS_TABLE: &str = "transfers";
+
+pub struct TransfersRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> TransfersRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(&self, in_obj: InTransfer) -> CloudBoostclicksResult<Transfer> {
+        let id = Uuid::new_v4();
+
+        sqlx::query_as(&format!(
+            "
+            INSERT INTO {TRANSFERS_TABLE} (
+                id, user_id, source_storage_id, source_path,
+                destination_storage_id, destination_path, status
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *;
+            "
+        ))
+        .bind(id)
+        .bind(in_obj.user_id)
+        .bind(in_obj.source_storage_id)
+        .bind(in_obj.source_path)
+        .bind(in_obj.destination_storage_id)
+        .bind(in_obj.destination_path)
+        .bind(TransferStatus::Pending)
+        .fetch_one(self.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })
+    }
+
+    pub async fn get_by_id(&self, id: Uuid) -> CloudBoostclicksResult<Transfer> {
+        sqlx::query_as(&format!("SELECT * FROM {TRANSFERS_TABLE} WHERE id = $1"))
+            .bind(id)
+            .fetch_one(self.db)
+            .await
+            .map_err(|e| map_not_found(e, "transfer"))
+    }
+
+    pub async fn list_by_user_id(&self, user_id: Uuid) -> CloudBoostclicksResult<Vec<Transfer>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {TRANSFERS_TABLE} WHERE user_id = $1 ORDER BY created_at DESC"
+        ))
+        .bind(user_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "transfers"))
+    }
+
+    pub async fn set_running(&self, id: Uuid, total_chunks: i32) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "UPDATE {TRANSFERS_TABLE} SET status = $2, total_chunks = $3 WHERE id = $1"
+        ))
+        .bind(id)
+        .bind(TransferStatus::Running)
+        .bind(total_chunks)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    pub async fn add_progress(&self, id: Uuid, chunks: i32) -> CloudBoostclicksResult<()> {
+        sqlx::query(&format!(
+            "UPDATE {TRANSFERS_TABLE} SET transferred_chunks = transferred_chunks + $2 WHERE id = $1"
+        ))
+        .bind(id)
+        .bind(chunks)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+
+    /// Fails every transfer which is pending or running, returns how many there were
+    pub async fn fail_unfinished(&self, error: &str) -> CloudBoostclicksResult<u64> {
+        sqlx::query(&format!(
+            "
+            UPDATE {TRANSFERS_TABLE}
+            SET status = $3, error = $4, finished_at = NOW()
+            WHERE status IN ($1, $2)
+            "
+        ))
+        .bind(TransferStatus::Pending)
+        .bind(TransferStatus::Running)
+        .bind(TransferStatus::Failed)
+        .bind(error)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|result| result.rows_affected())
+    }
+
+    pub async fn finish(&self, id: Uuid, error: Option<String>) -> CloudBoostclicksResult<()> {
+        let status = if error.is_some() {
+            TransferStatus::Failed
+        } else {
+            TransferStatus::Done
+        };
+
+        sqlx::query(&format!(
+            "
+            UPDATE {TRANSFERS_TABLE}
+            SET status = $2, error = $3, finished_at = NOW()
+            WHERE id = $1
+            "
+        ))
+        .bind(id)
+        .bind(status)
+        .bind(error)
+        .execute(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)
+        .map(|_| ())
+    }
+}
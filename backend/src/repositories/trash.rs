@@ -0,0 +1,228 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::{
+    changes::{ChangeKind, InChange},
+    file_chunks::FileChunk,
+    trash::TrashItem,
+};
+use crate::repositories::changes::ChangesRepository;
+use crate::repositories::files::{FilesRepository, CHUNKS_TABLE, FILES_TABLE};
+use crate::repositories::users::identifier_expr;
+
+pub const TRASH_TABLE: &str = "trash";
+
+pub struct TrashRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> TrashRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    pub async fn list(&self, storage_id: Uuid) -> CloudBoostclicksResult<Vec<TrashItem>> {
+        let identifier = identifier_expr("u");
+        sqlx::query_as(&format!(
+            "
+            SELECT
+                t.id,
+                t.path,
+                t.is_folder,
+                t.deleted_by,
+                {identifier} AS deleted_by_identifier,
+                t.deleted_at,
+                COUNT(f.id) FILTER (WHERE f.path NOT LIKE '%/') AS files_amount,
+                COALESCE(SUM(f.size), 0)::BigInt AS size
+            FROM {TRASH_TABLE} t
+            LEFT JOIN users u ON t.deleted_by = u.id
+            LEFT JOIN {FILES_TABLE} f ON f.trash_id = t.id
+            WHERE t.storage_id = $1
+            GROUP BY t.id, u.id
+            ORDER BY t.deleted_at DESC;
+            "
+        ))
+        .bind(storage_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "trash"))
+    }
+
+    pub async fn exists(&self, id: Uuid, storage_id: Uuid) -> CloudBoostclicksResult<bool> {
+        let (exists,): (bool,) = sqlx::query_as(&format!(
+            "SELECT EXISTS(SELECT 1 FROM {TRASH_TABLE} WHERE id = $1 AND storage_id = $2)"
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .fetch_one(self.db)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        Ok(exists)
+    }
+
+    /// Puts trashed files back, `new_path` replaces the original path of the trashed item.
+    ///
+    /// Folders which exist already are merged, while any other existing file is a conflict
+    pub async fn restore(
+        &self,
+        id: Uuid,
+        storage_id: Uuid,
+        new_path: Option<&str>,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let (path, is_folder): (String, bool) = sqlx::query_as(&format!(
+            "SELECT path, is_folder FROM {TRASH_TABLE} WHERE id = $1 AND storage_id = $2 FOR UPDATE"
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "trash item"))?;
+
+        let new_path = match new_path {
+            Some(new_path) if is_folder => format!("{}/", new_path.trim_end_matches('/')),
+            Some(new_path) => new_path.to_string(),
+            None => path.clone(),
+        };
+        // SUBSTRING counts characters, not bytes
+        let chars_skip = path.chars().count() as i32 + 1;
+        let new_path_expr = "$3 || SUBSTRING(t.path, $4)";
+
+        // 1. merging folders
+        sqlx::query(&format!(
+            "
+            DELETE FROM {FILES_TABLE} t
+            WHERE t.trash_id = $1 AND t.path LIKE '%/' AND EXISTS (
+                SELECT 1
+                FROM {FILES_TABLE} a
+                WHERE a.storage_id = $2 AND a.trash_id IS NULL AND a.path = {new_path_expr}
+            );
+            "
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .bind(&new_path)
+        .bind(chars_skip)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        // 2. looking for conflicts
+        let (has_conflicts,): (bool,) = sqlx::query_as(&format!(
+            "
+            SELECT EXISTS(
+                SELECT 1
+                FROM {FILES_TABLE} t
+                JOIN {FILES_TABLE} a ON a.path = {new_path_expr}
+                WHERE t.trash_id = $1 AND a.storage_id = $2 AND a.trash_id IS NULL
+            );
+            "
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .bind(&new_path)
+        .bind(chars_skip)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if has_conflicts {
+            return Err(CloudBoostclicksError::AlreadyExists(
+                "файл с таким путём".to_string(),
+            ));
+        }
+
+        // 3. restoring
+        let restored: Vec<(String, Option<String>, i32, bool)> = sqlx::query_as(&format!(
+            "
+            UPDATE {FILES_TABLE} t
+            SET path = {new_path_expr}, trash_id = NULL
+            WHERE t.trash_id = $1 AND t.storage_id = $2
+            RETURNING t.path, t.sha256, t.version, t.is_uploaded;
+            "
+        ))
+        .bind(id)
+        .bind(storage_id)
+        .bind(&new_path)
+        .bind(chars_skip)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        let changes = restored
+            .into_iter()
+            .filter(|(_, _, _, is_uploaded)| *is_uploaded)
+            .map(|(path, sha256, version, _)| {
+                InChange::new(ChangeKind::Create, path, sha256, Some(version))
+            })
+            .collect();
+        ChangesRepository::record(&mut transaction, storage_id, changes).await?;
+
+        sqlx::query(&format!("DELETE FROM {TRASH_TABLE} WHERE id = $1"))
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    /// Deletes a trashed item for good.
+    ///
+    /// Returns the chunks whose telegram messages aren't needed anymore
+    pub async fn purge(&self, id: Uuid) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        // chunks are going to be removed by the cascade so remembering them beforehand
+        let chunks: Vec<FileChunk> = sqlx::query_as(&format!(
+            "
+            SELECT fc.*
+            FROM {CHUNKS_TABLE} fc
+            JOIN {FILES_TABLE} f ON fc.file_id = f.id
+            WHERE f.trash_id = $1;
+            "
+        ))
+        .bind(id)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))?;
+
+        sqlx::query(&format!("DELETE FROM {TRASH_TABLE} WHERE id = $1"))
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        let unreferenced_chunks =
+            FilesRepository::filter_unreferenced(&mut transaction, chunks).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok(unreferenced_chunks)
+    }
+
+    pub async fn list_expired(&self, retention_days: u32) -> CloudBoostclicksResult<Vec<Uuid>> {
+        let ids: Vec<(Uuid,)> = sqlx::query_as(&format!(
+            "SELECT id FROM {TRASH_TABLE} WHERE deleted_at < NOW() - make_interval(days => $1)"
+        ))
+        .bind(retention_days as i32)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "trash"))?;
+
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+}
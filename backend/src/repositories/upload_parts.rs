@@ -0,0 +1,251 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::db::errors::map_not_found;
+use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
+use crate::models::{
+    changes::{ChangeKind, InChange},
+    file_chunks::FileChunk,
+    upload_parts::UploadPart,
+};
+use crate::repositories::changes::ChangesRepository;
+use crate::repositories::files::{FilesRepository, CHUNKS_TABLE, FILES_TABLE};
+
+pub const UPLOAD_PARTS_TABLE: &str = "upload_parts";
+
+pub struct UploadPartsRepository<'d> {
+    db: &'d PgPool,
+}
+
+impl<'d> UploadPartsRepository<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Saves the part, chunks of the same part uploaded before are dropped
+    pub async fn upsert(
+        &self,
+        file_id: Uuid,
+        part_number: i32,
+        etag: &str,
+        size: i64,
+        chunk_ids: &[Uuid],
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let previous: Option<(Vec<Uuid>,)> = sqlx::query_as(&format!(
+            "SELECT chunk_ids FROM {UPLOAD_PARTS_TABLE} WHERE file_id = $1 AND part_number = $2 FOR UPDATE"
+        ))
+        .bind(file_id)
+        .bind(part_number)
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        if let Some((previous_ids,)) = previous {
+            sqlx::query(&format!("DELETE FROM {CHUNKS_TABLE} WHERE id = ANY($1)"))
+                .bind(&previous_ids)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|_| CloudBoostclicksError::Unknown)?;
+        }
+
+        sqlx::query(&format!(
+            "
+            INSERT INTO {UPLOAD_PARTS_TABLE} (file_id, part_number, etag, size, chunk_ids)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (file_id, part_number)
+            DO UPDATE
+                SET etag = EXCLUDED.etag,
+                    size = EXCLUDED.size,
+                    chunk_ids = EXCLUDED.chunk_ids,
+                    uploaded_at = NOW();
+            "
+        ))
+        .bind(file_id)
+        .bind(part_number)
+        .bind(etag)
+        .bind(size)
+        .bind(chunk_ids)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("{e}");
+            CloudBoostclicksError::Unknown
+        })?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    pub async fn list(&self, file_id: Uuid) -> CloudBoostclicksResult<Vec<UploadPart>> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM {UPLOAD_PARTS_TABLE} WHERE file_id = $1 ORDER BY part_number"
+        ))
+        .bind(file_id)
+        .fetch_all(self.db)
+        .await
+        .map_err(|e| map_not_found(e, "upload parts"))
+    }
+
+    /// Puts chunks of the chosen parts in order and turns the pending file into the uploaded one.
+    ///
+    /// Chunks of parts which weren't chosen are dropped, parity chunks of the joined content
+    /// are saved. The object it replaces goes to the trash in the same transaction,
+    /// so it's kept if the completion fails
+    #[allow(clippy::too_many_arguments)]
+    pub async fn complete(
+        &self,
+        file_id: Uuid,
+        chunk_ids: &[Uuid],
+        parity_chunks: Vec<FileChunk>,
+        path: &str,
+        size: i64,
+        sha256: &str,
+        completed_by: Uuid,
+    ) -> CloudBoostclicksResult<()> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+        let positions: Vec<i16> = (0..chunk_ids.len() as i16).collect();
+
+        let (storage_id, replaced): (Uuid, bool) = sqlx::query_as(&format!(
+            "
+            SELECT storage_id, EXISTS (
+                SELECT 1 FROM {FILES_TABLE} r
+                WHERE r.storage_id = f.storage_id AND r.path = $2
+                    AND r.is_uploaded AND r.trash_id IS NULL
+            )
+            FROM {FILES_TABLE} f
+            WHERE id = $1;
+            "
+        ))
+        .bind(file_id)
+        .bind(path)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "загрузка"))?;
+        if replaced {
+            FilesRepository::delete_element(&mut transaction, path, storage_id, completed_by, None)
+                .await?;
+        }
+
+        sqlx::query(&format!(
+            "
+            UPDATE {CHUNKS_TABLE} fc
+            SET position = o.position
+            FROM UNNEST($1::uuid[], $2::int2[]) AS o(id, position)
+            WHERE fc.id = o.id;
+            "
+        ))
+        .bind(chunk_ids)
+        .bind(&positions)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        sqlx::query(&format!(
+            "DELETE FROM {CHUNKS_TABLE} WHERE file_id = $1 AND version_id IS NULL AND id <> ALL($2)"
+        ))
+        .bind(file_id)
+        .bind(chunk_ids)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| CloudBoostclicksError::Unknown)?;
+        if !parity_chunks.is_empty() {
+            FilesRepository::insert_chunks(&mut transaction, parity_chunks).await?;
+        }
+
+        sqlx::query(&format!("DELETE FROM {UPLOAD_PARTS_TABLE} WHERE file_id = $1"))
+            .bind(file_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| CloudBoostclicksError::Unknown)?;
+
+        let (storage_id, path, sha256, version): (Uuid, String, Option<String>, i32) =
+            sqlx::query_as(&format!(
+                "
+                UPDATE {FILES_TABLE}
+                SET path = $2, size = $3, sha256 = $4, is_uploaded = true, updated_at = NOW()
+                WHERE id = $1
+                RETURNING storage_id, path, sha256, version;
+                "
+            ))
+            .bind(file_id)
+            .bind(path)
+            .bind(size)
+            .bind(sha256)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(dbe) if dbe.is_unique_violation() => {
+                    CloudBoostclicksError::AlreadyExists("файл с таким именем".to_string())
+                }
+                _ => {
+                    tracing::error!("{e}");
+                    CloudBoostclicksError::Unknown
+                }
+            })?;
+
+        let change = InChange::new(ChangeKind::Create, path, sha256, Some(version));
+        ChangesRepository::record(&mut transaction, storage_id, vec![change]).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))
+    }
+
+    /// Deletes pending files of uploads which were neither completed nor aborted.
+    ///
+    /// Returns the number of dropped uploads and their chunks which aren't referenced anymore
+    pub async fn purge_stale(
+        &self,
+        prefix: &str,
+        older_than_days: u32,
+    ) -> CloudBoostclicksResult<(u64, Vec<FileChunk>)> {
+        let mut transaction = self.db.begin().await.map_err(|e| map_not_found(e, ""))?;
+
+        let file_ids: Vec<(Uuid,)> = sqlx::query_as(&format!(
+            "
+            SELECT id FROM {FILES_TABLE}
+            WHERE NOT is_uploaded AND path LIKE $1 || '%' AND created_at < NOW() - make_interval(days => $2)
+            FOR UPDATE;
+            "
+        ))
+        .bind(prefix)
+        .bind(older_than_days as i32)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "files"))?;
+        let file_ids: Vec<Uuid> = file_ids.into_iter().map(|(id,)| id).collect();
+
+        // chunks are going to be removed by the cascade so remembering them beforehand
+        let chunks: Vec<FileChunk> = sqlx::query_as(&format!(
+            "SELECT * FROM {CHUNKS_TABLE} WHERE file_id = ANY($1)"
+        ))
+        .bind(&file_ids)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| map_not_found(e, "file chunks"))?;
+
+        sqlx::query(&format!("DELETE FROM {FILES_TABLE} WHERE id = ANY($1)"))
+            .bind(&file_ids)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| {
+                tracing::error!("{e}");
+                CloudBoostclicksError::Unknown
+            })?;
+
+        let unreferenced_chunks =
+            FilesRepository::filter_unreferenced(&mut transaction, chunks).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| map_not_found(e, ""))?;
+
+        Ok((file_ids.len() as u64, unreferenced_chunks))
+    }
+}
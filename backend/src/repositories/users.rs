@@ -5,6 +5,19 @@ use crate::common::db::errors::map_not_found;
 use crate::errors::{CloudBoostclicksError, CloudBoostclicksResult};
 use crate::models::users::{InDBUser, User};
 
+/// SQL expression with a human readable identifier of the user joined as `alias`
+pub fn identifier_expr(alias: &str) -> String {
+    format!(
+        "
+        COALESCE(
+            NULLIF(CONCAT('@', {alias}.telegram_username), '@'),
+            {alias}.email,
+            NULLIF(CONCAT('tg:', {alias}.telegram_id), 'tg:')
+        )
+        "
+    )
+}
+
 pub struct UsersRepository<'d> {
     db: &'d PgPool,
 }
@@ -48,6 +61,14 @@ impl<'d> UsersRepository<'d> {
         Ok(user)
     }
 
+    pub async fn get_by_id(&self, id: Uuid) -> CloudBoostclicksResult<User> {
+        sqlx::query_as("SELECT * FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_one(self.db)
+            .await
+            .map_err(|e| map_not_found(e, "user"))
+    }
+
     pub async fn get_by_email(&self, email: &str) -> CloudBoostclicksResult<User> {
         sqlx::query_as("SELECT * FROM users WHERE email = $1")
             .bind(email)
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+
+use crate::{
+    common::{
+        jwt_manager::AuthUser,
+        routing::{app_state::AppState, middlewares::auth::logged_in_required},
+    },
+    services::access_keys::AccessKeysService,
+};
+
+pub struct AccessKeysRouter;
+
+impl AccessKeysRouter {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/", get(Self::list).post(Self::create))
+            .route("/:access_key_id", delete(Self::delete))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                logged_in_required,
+            ))
+            .with_state(state)
+    }
+
+    async fn create(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+    ) -> impl IntoResponse {
+        let key = AccessKeysService::new(&state.db)
+            .create(&user, &state.config.secret_key)
+            .await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::CREATED, Json(key)))
+    }
+
+    async fn list(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+    ) -> impl IntoResponse {
+        let keys = AccessKeysService::new(&state.db).list(&user).await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::OK, Json(keys)))
+    }
+
+    async fn delete(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(access_key_id): Path<String>,
+    ) -> impl IntoResponse {
+        AccessKeysService::new(&state.db)
+            .delete(&access_key_id, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>(StatusCode::NO_CONTENT)
+    }
+}
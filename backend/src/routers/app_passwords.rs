@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        jwt_manager::AuthUser,
+        routing::{app_state::AppState, middlewares::auth::logged_in_required},
+    },
+    schemas::app_passwords::InAppPasswordSchema,
+    services::app_passwords::AppPasswordsService,
+};
+
+pub struct AppPasswordsRouter;
+
+impl AppPasswordsRouter {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/", get(Self::list).post(Self::create))
+            .route("/:password_id", delete(Self::delete))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                logged_in_required,
+            ))
+            .with_state(state)
+    }
+
+    async fn create(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Json(in_schema): Json<InAppPasswordSchema>,
+    ) -> impl IntoResponse {
+        let password = AppPasswordsService::new(&state.db)
+            .create(in_schema, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::CREATED, Json(password)))
+    }
+
+    async fn list(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+    ) -> impl IntoResponse {
+        let passwords = AppPasswordsService::new(&state.db).list(&user).await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::OK, Json(passwords)))
+    }
+
+    async fn delete(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(password_id): Path<Uuid>,
+    ) -> impl IntoResponse {
+        AppPasswordsService::new(&state.db)
+            .delete(password_id, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>(StatusCode::NO_CONTENT)
+    }
+}
@@ -1,35 +1,48 @@
 ﻿use std::{collections::HashMap, path::Path, sync::Arc};
 
 use axum::{
-    body::Full,
+    body::{Full, StreamBody},
     extract::{DefaultBodyLimit, Multipart, Path as RoutePath, Query, State},
     http::StatusCode,
     middleware,
     response::{AppendHeaders, IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post},
     Extension, Json, Router,
 };
-use reqwest::header;
+use reqwest::header::{self, HeaderMap, HeaderName};
 use serde_json::json;
 use tokio_util::bytes::Bytes;
 use uuid::Uuid;
 
 use crate::{
     common::{
+        extract::ArchiveFile,
+        hashing::digest_header,
+        http::{http_date, if_match, Preconditions},
         jwt_manager::AuthUser,
         routing::{app_state::AppState, middlewares::auth::logged_in_required},
     },
     errors::{CloudBoostclicksError, CloudBoostclicksResult},
-    models::files::InFile,
+    models::{file_versions::FileVersionInfo, files::InFile, trash::TrashItem},
     schemas::files::{
-        DeleteSummary, InFileSchema, InFolderSchema, SearchQuery, UploadParams,
+        ArchiveFormat, ArchiveQuery, BatchSchema, BatchSummary, ClientEnvelope, CopySchema, CopySummary, DeleteSummary,
+        DownloadArchiveSchema, ExtractSchema, ExtractSummary, InChunkSchema, InFileSchema,
+        InFolderSchema, ListQuery, SearchQuery, UploadParams,
         IN_FILE_SCHEMA_FIELDS_AMOUNT,
     },
+    schemas::file_versions::{PruneSummary, PruneVersionsSchema, RestoreVersionSchema, VersionQuery},
     schemas::shares::{CreateShareSchema, ShareCreatedSchema, ShareInfoSchema, ShareQuery},
+    schemas::trash::RestoreSchema,
+    services::{archives::ArchivesService, content::ContentService},
+    services::file_versions::FileVersionsService,
     services::files::FilesService,
     services::shares::SharesService,
+    services::trash::TrashService,
 };
 
+/// Room for the form fields of `extract` besides the archive itself
+const EXTRACT_FORM_OVERHEAD: usize = 64 * 1024;
+
 pub struct FilesRouter;
 
 impl FilesRouter {
@@ -45,6 +58,21 @@ impl FilesRouter {
             .route("/upload", post(Self::upload))
             .route("/upload_to", post(Self::upload_to))
             .route("/upload_chunked", post(Self::upload_chunked))
+            .route(
+                "/extract",
+                // the archive is bounded on its own, the rest of the form is small
+                post(Self::extract).layer(DefaultBodyLimit::max(
+                    state.config.extract_max_archive_size as usize + EXTRACT_FORM_OVERHEAD,
+                )),
+            )
+            .route("/download_archive", post(Self::download_archive))
+            .route("/copy", post(Self::copy))
+            .route("/batch", post(Self::batch))
+            .route("/trash", get(Self::list_trash))
+            .route("/trash/:trash_id", delete(Self::purge_trash_item))
+            .route("/trash/:trash_id/restore", post(Self::restore_trash_item))
+            .route("/versions/restore", post(Self::restore_version))
+            .route("/versions/prune", post(Self::prune_versions))
             .route("/*path", get(Self::dynamic_get).delete(Self::delete))
             .layer(DefaultBodyLimit::disable())
             .route_layer(middleware::from_fn_with_state(
@@ -54,20 +82,30 @@ impl FilesRouter {
             .with_state(state)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn dynamic_get(
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
         RoutePath((storage_id, path)): RoutePath<(Uuid, String)>,
         query: Query<SearchQuery>,
+        Query(version_query): Query<VersionQuery>,
+        Query(list_query): Query<ListQuery>,
+        Query(archive_query): Query<ArchiveQuery>,
+        headers: HeaderMap,
     ) -> impl IntoResponse {
         let (root_path, path) = path.split_once("/").unwrap_or((&path, ""));
         match root_path {
-            "tree" => Self::tree(state, user, storage_id, path).await,
-            "download" => Self::download(state, user, storage_id, path).await,
-            "download_folder" => Self::download_folder(state, user, storage_id, path).await,
+            "tree" => Self::tree(state, user, storage_id, path, list_query).await,
+            "download" => {
+                Self::download(state, user, storage_id, path, version_query.version, &headers).await
+            }
+            "versions" => Self::list_versions(state, user, storage_id, path).await,
+            "download_folder" => {
+                Self::download_folder(state, user, storage_id, path, archive_query.format).await
+            }
             "search" => {
                 if let Some(search_path) = query.0.search_path {
-                    Self::search(state, user, storage_id, path, &search_path).await
+                    Self::search(state, user, storage_id, path, &search_path, list_query).await
                 } else {
                     Err((
                         StatusCode::UNPROCESSABLE_ENTITY,
@@ -84,9 +122,10 @@ impl FilesRouter {
         user: AuthUser,
         storage_id: Uuid,
         path: &str,
+        list_query: ListQuery,
     ) -> Result<Response, (StatusCode, String)> {
         let fs_layer = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
-            .list_dir(storage_id, path, &user)
+            .list_dir(storage_id, path, &list_query, &user)
             .await?;
         Ok(Json(fs_layer).into_response())
     }
@@ -95,11 +134,13 @@ impl FilesRouter {
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
         RoutePath(storage_id): RoutePath<Uuid>,
+        headers: HeaderMap,
         mut multipart: Multipart,
     ) -> Result<StatusCode, (StatusCode, String)> {
         // parsing
-        let (file, path) = {
+        let (file, path, envelope) = {
             let (mut file, mut filename, mut path) = (None, None, None);
+            let (mut encrypted_key, mut metadata) = (None, None);
 
             // parsing
             while let Some(field) = multipart.next_field().await.unwrap() {
@@ -113,6 +154,8 @@ impl FilesRouter {
                         filename = Some(field_filename);
                     }
                     "path" => path = Some(String::from_utf8(data.to_vec()).unwrap()),
+                    "encrypted_key" => encrypted_key = Some(data),
+                    "metadata" => metadata = Some(data),
                     // don't give a fuck about other fields
                     _ => (),
                 }
@@ -122,13 +165,14 @@ impl FilesRouter {
             let path = path
                 .ok_or((StatusCode::BAD_REQUEST, "путь обязателен".to_owned()))
                 .map(|path| Self::construct_path(&path, &filename.unwrap()))??;
-            (file, path)
+            let envelope = Self::parse_envelope(encrypted_key.as_ref(), metadata.as_ref())?;
+            (file, path, envelope)
         };
         let size = file.len() as i64;
-        let in_file = InFile::new(path, size, storage_id);
+        let in_file = InFile::new(path, size, storage_id, user.id);
 
         FilesService::new(&state.db, state.config.clone(), state.tx.clone())
-            .upload_anyway(in_file, file, &user)
+            .upload_anyway(in_file, file, envelope, if_match(&headers).as_deref(), &user)
             .await?;
         Ok(StatusCode::CREATED)
     }
@@ -137,6 +181,7 @@ impl FilesRouter {
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
         RoutePath(storage_id): RoutePath<Uuid>,
+        headers: HeaderMap,
         mut multipart: Multipart,
     ) -> Result<StatusCode, (StatusCode, String)> {
         // parsing and validating schema
@@ -163,7 +208,13 @@ impl FilesRouter {
                 .get("file")
                 .ok_or((StatusCode::BAD_REQUEST, "файл обязателен".to_owned()))?;
 
-            InFileSchema::new(storage_id, path, file.clone())
+            let envelope = Self::parse_envelope(
+                body_parts.get("encrypted_key"),
+                body_parts.get("metadata"),
+            )?;
+
+            InFileSchema::new(storage_id, path, file.clone(), envelope)
+                .with_if_match(if_match(&headers))
         };
 
         // do all other stuff
@@ -178,6 +229,7 @@ impl FilesRouter {
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
         RoutePath(storage_id): RoutePath<Uuid>,
+        headers: HeaderMap,
         mut multipart: Multipart,
     ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
         let mut path: Option<String> = None;
@@ -186,6 +238,7 @@ impl FilesRouter {
         let mut chunk_index: Option<usize> = None;
         let mut total_chunks: Option<usize> = None;
         let mut chunk: Option<Bytes> = None;
+        let (mut encrypted_key, mut metadata) = (None, None);
 
         while let Some(field) = multipart.next_field().await.unwrap() {
             let name = field.name().unwrap().to_string();
@@ -212,6 +265,8 @@ impl FilesRouter {
                         .and_then(|s| s.parse::<usize>().ok())
                 }
                 "chunk" | "file" => chunk = Some(data),
+                "encrypted_key" => encrypted_key = Some(data),
+                "metadata" => metadata = Some(data),
                 _ => {}
             }
         }
@@ -222,6 +277,7 @@ impl FilesRouter {
         let total_chunks =
             total_chunks.ok_or((StatusCode::BAD_REQUEST, "total_chunks обязателен".to_owned()))?;
         let chunk = chunk.ok_or((StatusCode::BAD_REQUEST, "chunk обязателен".to_owned()))?;
+        let envelope = Self::parse_envelope(encrypted_key.as_ref(), metadata.as_ref())?;
 
         if chunk_index >= total_chunks {
             return Err((
@@ -232,13 +288,17 @@ impl FilesRouter {
 
         let file_id = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
             .upload_chunked(
-                storage_id,
-                path,
-                size,
-                file_id,
-                chunk_index,
-                total_chunks,
-                chunk,
+                InChunkSchema {
+                    storage_id,
+                    path,
+                    size,
+                    file_id,
+                    chunk_index,
+                    total_chunks,
+                    chunk,
+                    envelope,
+                    if_match: if_match(&headers),
+                },
                 &user,
             )
             .await
@@ -247,6 +307,60 @@ impl FilesRouter {
         Ok(Json(json!({ "file_id": file_id })))
     }
 
+    async fn extract(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        mut multipart: Multipart,
+    ) -> Result<Json<ExtractSummary>, (StatusCode, String)> {
+        let (mut archive, mut filename, mut path, mut format) = (None, None, None, None);
+
+        while let Some(mut field) = multipart.next_field().await.unwrap() {
+            let name = field.name().unwrap().to_string();
+
+            match name.as_str() {
+                // the archive goes to a temporary file part by part as it arrives
+                "archive" | "file" => {
+                    filename = field.file_name().map(|f| f.to_string());
+                    let max_size = state.config.extract_max_archive_size;
+                    let mut file = ArchiveFile::create(max_size).await?;
+                    while let Some(data) = field
+                        .chunk()
+                        .await
+                        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+                    {
+                        file.write(&data).await?;
+                    }
+                    archive = Some(file);
+                }
+                "path" => path = Some(field.text().await.unwrap_or_default()),
+                "format" => format = Some(field.text().await.unwrap_or_default()),
+                _ => {}
+            }
+        }
+
+        let archive = archive.ok_or((StatusCode::BAD_REQUEST, "архив обязателен".to_owned()))?;
+        let format = match format {
+            Some(format) => ArchiveFormat::from_extension(&format),
+            None => filename.as_deref().and_then(ArchiveFormat::from_filename),
+        }
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "поддерживаются zip, tar, tar.gz и tar.zst".to_owned(),
+        ))?;
+        let in_schema = ExtractSchema {
+            storage_id,
+            path: path.unwrap_or_default(),
+            format,
+            archive,
+        };
+
+        let summary = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
+            .extract(in_schema, &user)
+            .await?;
+        Ok(Json(summary))
+    }
+
     async fn create_folder(
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
@@ -261,6 +375,27 @@ impl FilesRouter {
         Ok(StatusCode::CREATED)
     }
 
+    /// Envelope of a file encrypted by the client, given with `encrypted_key` and `metadata` fields
+    fn parse_envelope(
+        encrypted_key: Option<&Bytes>,
+        metadata: Option<&Bytes>,
+    ) -> Result<Option<ClientEnvelope>, (StatusCode, String)> {
+        let read = |data: Option<&Bytes>| {
+            data.map(|data| String::from_utf8(data.to_vec()))
+                .transpose()
+                .map_err(|_| (StatusCode::BAD_REQUEST, "не удалось прочитать ключ".to_owned()))
+        };
+        let envelope = ClientEnvelope::from_fields(read(encrypted_key)?, read(metadata)?);
+
+        match envelope {
+            Some(envelope) if !envelope.is_header_safe() => Err((
+                StatusCode::BAD_REQUEST,
+                "ключ и метаданные должны быть в base64".to_owned(),
+            )),
+            envelope => Ok(envelope),
+        }
+    }
+
     #[inline]
     fn construct_path(path: &str, filename: &str) -> CloudBoostclicksResult<String> {
         Path::new(path)
@@ -275,30 +410,84 @@ impl FilesRouter {
         user: AuthUser,
         storage_id: Uuid,
         path: &str,
+        version: Option<i32>,
+        headers: &HeaderMap,
     ) -> Result<Response, (StatusCode, String)> {
-        FilesService::new(&state.db, state.config.clone(), state.tx.clone())
-            .download(path, storage_id, &user)
-            .await
-            .map(|data| {
-                let filename = Path::new(&path)
-                    .file_name()
-                    .map(|name| name.to_str().unwrap_or_default())
-                    .unwrap_or("unnamed.bin");
-                let content_type = mime_guess::from_path(filename)
-                    .first_or_octet_stream()
-                    .to_string();
-                let bytes = Bytes::from(data);
-                let body = Full::new(bytes);
+        let service = FilesService::new(&state.db, state.config.clone(), state.tx.clone());
 
+        // a client with a fresh copy gets only the validators back
+        let preconditions = Preconditions::from_headers(headers);
+        if !preconditions.is_empty() {
+            let (etag, last_modified) = service.validators(path, storage_id, version, &user).await?;
+            if preconditions.not_modified(&etag, &last_modified) {
                 let headers = AppendHeaders([
-                    (header::CONTENT_TYPE, content_type),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{filename}\""),
-                    ),
+                    (header::ETAG, format!("\"{etag}\"")),
+                    (header::LAST_MODIFIED, http_date(&last_modified)),
                 ]);
+                return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+            }
+        }
 
-                (headers, body).into_response()
+        let filename = Path::new(&path)
+            .file_name()
+            .map(|name| name.to_str().unwrap_or_default())
+            .unwrap_or("unnamed.bin");
+        let content_type = mime_guess::from_path(filename)
+            .first_or_octet_stream()
+            .to_string();
+        let disposition = format!("attachment; filename=\"{filename}\"");
+
+        // a single range is enough for clients reading files in parts,
+        // only chunks covering it are downloaded
+        if let Some(range) = headers.get(header::RANGE).and_then(|range| range.to_str().ok()) {
+            match service.download_range(path, storage_id, version, range, &user).await {
+                Ok(Some(part)) => {
+                    let headers = AppendHeaders([
+                        (header::CONTENT_TYPE, content_type),
+                        (header::ACCEPT_RANGES, "bytes".to_string()),
+                        (header::CONTENT_DISPOSITION, disposition),
+                        (header::ETAG, format!("\"{}\"", part.etag)),
+                        (header::LAST_MODIFIED, http_date(&part.last_modified)),
+                        (header::CONTENT_RANGE, part.content_range()),
+                    ]);
+                    let envelope_headers =
+                        AppendHeaders(part.envelope.as_ref().map(|e| e.headers()).unwrap_or_default());
+                    let body = ContentService::new(state.db.clone(), state.config.clone())
+                        .range(storage_id, &part);
+
+                    let headers = (headers, envelope_headers);
+                    return Ok((StatusCode::PARTIAL_CONTENT, headers, StreamBody::new(body))
+                        .into_response());
+                }
+                Ok(None) => (),
+                Err(CloudBoostclicksError::RangeNotSatisfiable(len)) => {
+                    let headers = AppendHeaders([(header::CONTENT_RANGE, format!("bytes */{len}"))]);
+                    return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        service
+            .download(path, storage_id, version, &user)
+            .await
+            .map(|file| {
+                let headers = AppendHeaders([
+                    (header::CONTENT_TYPE, content_type),
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                    (header::ETAG, format!("\"{}\"", file.etag)),
+                    (HeaderName::from_static("digest"), digest_header(&file.sha256)),
+                ]);
+                let last_modified = AppendHeaders(
+                    file.last_modified
+                        .map(|date| (header::LAST_MODIFIED, http_date(&date))),
+                );
+                let envelope_headers =
+                    AppendHeaders(file.envelope.as_ref().map(|e| e.headers()).unwrap_or_default());
+
+                let headers = (headers, last_modified, envelope_headers);
+                (headers, Full::new(Bytes::from(file.data))).into_response()
             })
             .map_err(|e| <(StatusCode, String)>::from(e))
     }
@@ -308,11 +497,12 @@ impl FilesRouter {
         user: AuthUser,
         storage_id: Uuid,
         path: &str,
+        format: ArchiveFormat,
     ) -> Result<Response, (StatusCode, String)> {
         FilesService::new(&state.db, state.config.clone(), state.tx.clone())
             .download_folder(path, storage_id, &user)
             .await
-            .map(|data| {
+            .map(|entries| {
                 let folder_name = if path.is_empty() {
                     "cloud".to_string()
                 } else {
@@ -323,13 +513,17 @@ impl FilesRouter {
                         .unwrap_or_else(|| "folder".to_string())
                 };
 
-                let bytes = Bytes::from(data);
-                let body = Full::new(bytes);
+                let archive = ArchivesService::new(state.db.clone(), state.config.clone())
+                    .build(format, storage_id, entries);
+                let body = StreamBody::new(archive);
                 let headers = AppendHeaders([
-                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (header::CONTENT_TYPE, format.content_type().to_string()),
                     (
                         header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{folder_name}.zip\""),
+                        format!(
+                            "attachment; filename=\"{folder_name}.{}\"",
+                            format.extension()
+                        ),
                     ),
                 ]);
 
@@ -338,6 +532,30 @@ impl FilesRouter {
             .map_err(|e| <(StatusCode, String)>::from(e))
     }
 
+    async fn download_archive(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        Json(in_schema): Json<DownloadArchiveSchema>,
+    ) -> Result<Response, (StatusCode, String)> {
+        let entries = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
+            .download_archive(&in_schema, storage_id, &user)
+            .await?;
+
+        let format = in_schema.format;
+        let archive = ArchivesService::new(state.db.clone(), state.config.clone())
+            .build(format, storage_id, entries);
+        let headers = AppendHeaders([
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"archive.{}\"", format.extension()),
+            ),
+        ]);
+
+        Ok((headers, StreamBody::new(archive)).into_response())
+    }
+
     ///
     /// Need path with trailing slash
     ///
@@ -347,9 +565,10 @@ impl FilesRouter {
         storage_id: Uuid,
         path: &str,
         search_path: &str,
+        list_query: ListQuery,
     ) -> Result<Response, (StatusCode, String)> {
         FilesService::new(&state.db, state.config.clone(), state.tx.clone())
-            .search(storage_id, path, search_path, &user)
+            .search(storage_id, path, search_path, &list_query, &user)
             .await
             .map(|files| Json(files).into_response())
             .map_err(|e| <(StatusCode, String)>::from(e))
@@ -359,15 +578,118 @@ impl FilesRouter {
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
         RoutePath((storage_id, path)): RoutePath<(Uuid, String)>,
+        headers: HeaderMap,
     ) -> Result<Json<DeleteSummary>, (StatusCode, String)> {
         let result = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
-            .delete(&path, storage_id, &user)
+            .delete(&path, storage_id, if_match(&headers).as_deref(), &user)
             .await
             .map_err(|e| <(StatusCode, String)>::from(e))?;
 
         Ok(Json(result))
     }
 
+    async fn copy(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        Json(in_schema): Json<CopySchema>,
+    ) -> Result<(StatusCode, Json<CopySummary>), (StatusCode, String)> {
+        let result = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
+            .copy(in_schema, storage_id, &user)
+            .await?;
+
+        Ok((StatusCode::CREATED, Json(result)))
+    }
+
+    async fn batch(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        Json(in_schema): Json<BatchSchema>,
+    ) -> Result<Json<BatchSummary>, (StatusCode, String)> {
+        let result = FilesService::new(&state.db, state.config.clone(), state.tx.clone())
+            .batch(in_schema, storage_id, &user)
+            .await?;
+
+        Ok(Json(result))
+    }
+
+    async fn list_trash(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+    ) -> Result<Json<Vec<TrashItem>>, (StatusCode, String)> {
+        let items = TrashService::new(&state.db, state.config.clone())
+            .list(storage_id, &user)
+            .await?;
+
+        Ok(Json(items))
+    }
+
+    async fn restore_trash_item(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath((storage_id, trash_id)): RoutePath<(Uuid, Uuid)>,
+        Json(in_schema): Json<RestoreSchema>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        TrashService::new(&state.db, state.config.clone())
+            .restore(storage_id, trash_id, in_schema, &user)
+            .await?;
+
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    async fn purge_trash_item(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath((storage_id, trash_id)): RoutePath<(Uuid, Uuid)>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        TrashService::new(&state.db, state.config.clone())
+            .purge(storage_id, trash_id, &user)
+            .await?;
+
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    async fn list_versions(
+        state: Arc<AppState>,
+        user: AuthUser,
+        storage_id: Uuid,
+        path: &str,
+    ) -> Result<Response, (StatusCode, String)> {
+        let versions: Vec<FileVersionInfo> = FileVersionsService::new(&state.db, state.config.clone())
+            .list(path, storage_id, &user)
+            .await?;
+
+        Ok(Json(versions).into_response())
+    }
+
+    async fn restore_version(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        Json(in_schema): Json<RestoreVersionSchema>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        FileVersionsService::new(&state.db, state.config.clone())
+            .restore(in_schema, storage_id, &user)
+            .await?;
+
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    async fn prune_versions(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        RoutePath(storage_id): RoutePath<Uuid>,
+        Json(in_schema): Json<PruneVersionsSchema>,
+    ) -> Result<Json<PruneSummary>, (StatusCode, String)> {
+        let result = FileVersionsService::new(&state.db, state.config.clone())
+            .prune(in_schema, storage_id, &user)
+            .await?;
+
+        Ok(Json(result))
+    }
+
     async fn create_share(
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
@@ -1,6 +1,12 @@
+pub mod access_keys;
+pub mod app_passwords;
 pub mod auth;
 pub mod files;
+pub mod s3;
 pub mod shares;
+pub mod ssh_keys;
 pub mod storage_workers;
 pub mod storages;
+pub mod transfers;
 pub mod users;
+pub mod webdav;
@@ -0,0 +1,480 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::{Bytes, Full, StreamBody},
+    extract::{OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{AppendHeaders, IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use percent_encoding::percent_decode_str;
+use reqwest::header;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        http::http_date,
+        jwt_manager::AuthUser,
+        routing::app_state::AppState,
+        s3,
+        sigv4::{query_pairs, SignedRequest},
+    },
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    schemas::s3::{ListObjectsParams, ObjectEntry},
+    services::{access_keys::AccessKeysService, content::ContentService, s3::S3Service},
+};
+
+type S3Result = Result<Response, Response>;
+
+const XML_CONTENT_TYPE: &str = "application/xml";
+const MAX_KEYS: usize = 1000;
+
+/// S3-compatible API served on its own port, buckets are storages addressed by their ids in the path
+pub struct S3Router;
+
+impl S3Router {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/", any(Self::handle))
+            .route("/:bucket", any(Self::handle))
+            .route("/:bucket/", any(Self::handle))
+            .route("/:bucket/*key", any(Self::handle))
+            .with_state(state)
+    }
+
+    /// Operations are told apart by the method and the query, so every request comes here
+    async fn handle(
+        State(state): State<Arc<AppState>>,
+        Path(params): Path<HashMap<String, String>>,
+        OriginalUri(uri): OriginalUri,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let resource = uri.path();
+
+        // 0. authenticating by the signature
+        let (user, body) = match Self::authenticate(&state, &uri, &method, &headers, body).await {
+            Ok(authenticated) => authenticated,
+            Err(e) => return Self::error(&e, "AccessDenied", resource),
+        };
+
+        let service = S3Service::new(&state.db, state.config.clone(), state.tx.clone());
+        let query: HashMap<String, String> = query_pairs(uri.query()).into_iter().collect();
+
+        let Some(bucket) = params.get("bucket") else {
+            return match method {
+                Method::GET => Self::list_buckets(&service, &user).await,
+                _ => Err(Self::error_response(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", resource)),
+            }
+            .unwrap_or_else(|response| response);
+        };
+        let Ok(bucket) = Uuid::parse_str(bucket) else {
+            return Self::error_response(StatusCode::NOT_FOUND, "NoSuchBucket", resource);
+        };
+        let key = params
+            .get("key")
+            .map(|key| key.trim_start_matches('/'))
+            .unwrap_or_default();
+
+        let request = S3Request {
+            state: &state,
+            service: &service,
+            bucket,
+            key,
+            query: &query,
+            headers: &headers,
+            user: &user,
+            resource,
+        };
+        let result = if key.is_empty() {
+            request.bucket_operation(&method, body).await
+        } else {
+            request.object_operation(&method, body).await
+        };
+
+        result.unwrap_or_else(|response| response)
+    }
+
+    async fn authenticate(
+        state: &AppState,
+        uri: &Uri,
+        method: &Method,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> CloudBoostclicksResult<(AuthUser, Bytes)> {
+        let signed = SignedRequest::parse(uri, headers)?;
+        let (user, secret) = AccessKeysService::new(&state.db)
+            .authenticate(&signed.access_key_id, &state.config.secret_key)
+            .await?;
+        let body = signed.verify(&secret, method, uri, headers, body)?;
+
+        Ok((user, body))
+    }
+
+    async fn list_buckets(service: &S3Service<'_>, user: &AuthUser) -> S3Result {
+        let buckets = service
+            .list_buckets(user)
+            .await
+            .map_err(|e| Self::error(&e, "NoSuchBucket", "/"))?;
+
+        Ok(Self::xml(
+            StatusCode::OK,
+            s3::list_buckets(user.id, &user.identifier, &buckets),
+        ))
+    }
+
+    fn xml(status: StatusCode, body: String) -> Response {
+        (status, [(header::CONTENT_TYPE, XML_CONTENT_TYPE)], body).into_response()
+    }
+
+    fn error(e: &CloudBoostclicksError, not_found: &'static str, resource: &str) -> Response {
+        let (status, code) = s3::error_code(e, not_found);
+        (
+            status,
+            [(header::CONTENT_TYPE, XML_CONTENT_TYPE)],
+            s3::error(code, &e.to_string(), resource),
+        )
+            .into_response()
+    }
+
+    fn error_response(status: StatusCode, code: &str, resource: &str) -> Response {
+        Self::xml(status, s3::error(code, code, resource))
+    }
+
+    /// Headers describing an object in responses to GET and HEAD
+    fn object_headers(entry: &ObjectEntry) -> Vec<(header::HeaderName, String)> {
+        vec![
+            (
+                header::CONTENT_TYPE,
+                mime_guess::from_path(&entry.key)
+                    .first_or_octet_stream()
+                    .to_string(),
+            ),
+            (header::ETAG, format!("\"{}\"", entry.etag)),
+            (header::LAST_MODIFIED, http_date(&entry.last_modified)),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ]
+    }
+}
+
+/// A request to a bucket or to a key of it
+struct S3Request<'r> {
+    state: &'r AppState,
+    service: &'r S3Service<'r>,
+    bucket: Uuid,
+    key: &'r str,
+    query: &'r HashMap<String, String>,
+    headers: &'r HeaderMap,
+    user: &'r AuthUser,
+    resource: &'r str,
+}
+
+impl S3Request<'_> {
+    async fn bucket_operation(&self, method: &Method, body: Bytes) -> S3Result {
+        match *method {
+            Method::GET if self.query.contains_key("location") => {
+                self.head_bucket().await?;
+                Ok(S3Router::xml(StatusCode::OK, s3::location()))
+            }
+            Method::GET if self.query.contains_key("uploads") => {
+                self.head_bucket().await?;
+                Ok(S3Router::xml(StatusCode::OK, s3::list_multipart_uploads(self.bucket)))
+            }
+            Method::GET => self.list_objects().await,
+            // buckets are created as storages, so creating an existing one is fine
+            Method::HEAD | Method::PUT => {
+                self.head_bucket().await?;
+                Ok(StatusCode::OK.into_response())
+            }
+            Method::POST if self.query.contains_key("delete") => self.delete_objects(body).await,
+            _ => Err(S3Router::error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                self.resource,
+            )),
+        }
+    }
+
+    async fn object_operation(&self, method: &Method, body: Bytes) -> S3Result {
+        let upload_id = match self.query.get("uploadId") {
+            Some(upload_id) => Some(Uuid::parse_str(upload_id).map_err(|_| {
+                S3Router::error_response(StatusCode::NOT_FOUND, "NoSuchUpload", self.resource)
+            })?),
+            None => None,
+        };
+
+        match (method.clone(), upload_id) {
+            (Method::GET, Some(upload_id)) => self.list_parts(upload_id).await,
+            (Method::GET | Method::HEAD, None) => self.get_object(method).await,
+            (Method::PUT, Some(upload_id)) => self.upload_part(upload_id, body).await,
+            (Method::PUT, None) if self.headers.contains_key("x-amz-copy-source") => {
+                self.copy_object().await
+            }
+            (Method::PUT, None) => self.put_object(body).await,
+            (Method::POST, None) if self.query.contains_key("uploads") => {
+                self.create_multipart_upload().await
+            }
+            (Method::POST, Some(upload_id)) => {
+                self.complete_multipart_upload(upload_id, body).await
+            }
+            (Method::DELETE, Some(upload_id)) => {
+                self.service
+                    .abort_multipart_upload(self.bucket, self.key, upload_id, self.user)
+                    .await
+                    .map_err(|e| self.error(&e, "NoSuchUpload"))?;
+                Ok(StatusCode::NO_CONTENT.into_response())
+            }
+            (Method::DELETE, None) => {
+                self.service
+                    .delete_object(self.bucket, self.key, self.user)
+                    .await
+                    .map_err(|e| self.error(&e, "NoSuchBucket"))?;
+                Ok(StatusCode::NO_CONTENT.into_response())
+            }
+            _ => Err(S3Router::error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                self.resource,
+            )),
+        }
+    }
+
+    async fn head_bucket(&self) -> Result<(), Response> {
+        self.service
+            .head_bucket(self.bucket, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchBucket"))
+    }
+
+    /// ListObjectsV2 is asked with `list-type=2`, the original ListObjects otherwise
+    async fn list_objects(&self) -> S3Result {
+        let is_v2 = self.query.get("list-type").is_some_and(|list_type| list_type == "2");
+        let continuation_token = self.query.get("continuation-token");
+
+        let marker = match is_v2 {
+            true => match continuation_token {
+                Some(token) => Some(s3::parse_continuation_token(token).ok_or_else(|| {
+                    S3Router::error_response(StatusCode::BAD_REQUEST, "InvalidArgument", self.resource)
+                })?),
+                None => self.query.get("start-after").cloned(),
+            },
+            false => self.query.get("marker").cloned(),
+        };
+        let params = ListObjectsParams {
+            prefix: self.query.get("prefix").cloned().unwrap_or_default(),
+            delimiter: self
+                .query
+                .get("delimiter")
+                .filter(|delimiter| !delimiter.is_empty())
+                .cloned(),
+            marker: marker.filter(|marker| !marker.is_empty()),
+            max_keys: self
+                .query
+                .get("max-keys")
+                .and_then(|max_keys| max_keys.parse().ok())
+                .map_or(MAX_KEYS, |max_keys: usize| max_keys.min(MAX_KEYS)),
+        };
+
+        let result = self
+            .service
+            .list_objects(self.bucket, &params, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchBucket"))?;
+
+        let url_encoded = self
+            .query
+            .get("encoding-type")
+            .is_some_and(|encoding| encoding == "url");
+        let v2 = is_v2.then_some(continuation_token.map(String::as_str));
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::list_objects(self.bucket, &params, &result, v2, url_encoded),
+        ))
+    }
+
+    async fn get_object(&self, method: &Method) -> S3Result {
+        if *method == Method::HEAD {
+            let entry = self
+                .service
+                .head_object(self.bucket, self.key, self.user)
+                .await
+                .map_err(|e| self.error(&e, "NoSuchKey"))?;
+
+            let mut headers = S3Router::object_headers(&entry);
+            headers.push((header::CONTENT_LENGTH, entry.size.to_string()));
+            return Ok((AppendHeaders(headers), ()).into_response());
+        }
+
+        // only chunks covering the range are downloaded
+        if let Some(range) = self.headers.get(header::RANGE).and_then(|range| range.to_str().ok()) {
+            let (entry, part) = self
+                .service
+                .get_object_range(self.bucket, self.key, range, self.user)
+                .await
+                .map_err(|e| self.error(&e, "NoSuchKey"))?;
+            if let Some(part) = part {
+                let mut headers = S3Router::object_headers(&entry);
+                headers.push((header::CONTENT_RANGE, part.content_range()));
+                let body = ContentService::new(self.state.db.clone(), self.state.config.clone())
+                    .range(self.bucket, &part);
+                return Ok((StatusCode::PARTIAL_CONTENT, AppendHeaders(headers), StreamBody::new(body))
+                    .into_response());
+            }
+        }
+
+        let (entry, data) = self
+            .service
+            .get_object(self.bucket, self.key, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchKey"))?;
+        let headers = S3Router::object_headers(&entry);
+
+        Ok((AppendHeaders(headers), Full::new(Bytes::from(data))).into_response())
+    }
+
+    async fn put_object(&self, body: Bytes) -> S3Result {
+        let entry = self
+            .service
+            .put_object(self.bucket, self.key, body, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchBucket"))?;
+
+        Ok((AppendHeaders([(header::ETAG, format!("\"{}\"", entry.etag))]), ()).into_response())
+    }
+
+    /// The source is `<bucket>/<key>` with an optional leading slash and URL encoded
+    async fn copy_object(&self) -> S3Result {
+        let invalid_source = || {
+            S3Router::error_response(StatusCode::BAD_REQUEST, "InvalidArgument", self.resource)
+        };
+
+        let source = self
+            .headers
+            .get("x-amz-copy-source")
+            .and_then(|source| source.to_str().ok())
+            .ok_or_else(invalid_source)?;
+        let source = source.split_once('?').map_or(source, |(source, _)| source);
+        let source = percent_decode_str(source)
+            .decode_utf8()
+            .map_err(|_| invalid_source())?;
+        let (source_bucket, source_key) = source
+            .trim_start_matches('/')
+            .split_once('/')
+            .ok_or_else(invalid_source)?;
+        let source_bucket = Uuid::parse_str(source_bucket).map_err(|_| {
+            S3Router::error_response(StatusCode::NOT_FOUND, "NoSuchBucket", self.resource)
+        })?;
+
+        let entry = self
+            .service
+            .copy_object(self.bucket, self.key, source_bucket, source_key, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchKey"))?;
+
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::copy_object(&entry.etag, &entry.last_modified),
+        ))
+    }
+
+    async fn delete_objects(&self, body: Bytes) -> S3Result {
+        let (keys, quiet) = std::str::from_utf8(&body)
+            .ok()
+            .and_then(s3::parse_delete)
+            .ok_or_else(|| {
+                S3Router::error_response(StatusCode::BAD_REQUEST, "MalformedXML", self.resource)
+            })?;
+
+        let mut deleted = Vec::new();
+        let mut errors = Vec::new();
+        for (key, result) in self.service.delete_objects(self.bucket, keys, self.user).await {
+            match result {
+                Ok(()) => deleted.push(key),
+                Err(e) => errors.push((key, s3::error_code(&e, "NoSuchBucket").1)),
+            }
+        }
+
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::delete_result(&deleted, &errors, quiet),
+        ))
+    }
+
+    async fn create_multipart_upload(&self) -> S3Result {
+        let upload_id = self
+            .service
+            .create_multipart_upload(self.bucket, self.key, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchBucket"))?;
+
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::initiate_multipart_upload(self.bucket, self.key, upload_id),
+        ))
+    }
+
+    async fn upload_part(&self, upload_id: Uuid, body: Bytes) -> S3Result {
+        // parts copied from other objects aren't supported
+        if self.headers.contains_key("x-amz-copy-source") {
+            return Err(S3Router::error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                self.resource,
+            ));
+        }
+        let part_number = self
+            .query
+            .get("partNumber")
+            .and_then(|part_number| part_number.parse().ok())
+            .ok_or_else(|| {
+                S3Router::error_response(StatusCode::BAD_REQUEST, "InvalidArgument", self.resource)
+            })?;
+
+        let etag = self
+            .service
+            .upload_part(self.bucket, self.key, upload_id, part_number, body, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchUpload"))?;
+
+        Ok((AppendHeaders([(header::ETAG, format!("\"{etag}\""))]), ()).into_response())
+    }
+
+    async fn complete_multipart_upload(&self, upload_id: Uuid, body: Bytes) -> S3Result {
+        let parts = std::str::from_utf8(&body)
+            .ok()
+            .and_then(s3::parse_complete)
+            .ok_or_else(|| {
+                S3Router::error_response(StatusCode::BAD_REQUEST, "MalformedXML", self.resource)
+            })?;
+
+        let entry = self
+            .service
+            .complete_multipart_upload(self.bucket, self.key, upload_id, parts, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchUpload"))?;
+
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::complete_multipart_upload(self.bucket, self.key, &entry.etag),
+        ))
+    }
+
+    async fn list_parts(&self, upload_id: Uuid) -> S3Result {
+        let parts = self
+            .service
+            .list_parts(self.bucket, self.key, upload_id, self.user)
+            .await
+            .map_err(|e| self.error(&e, "NoSuchUpload"))?;
+
+        Ok(S3Router::xml(
+            StatusCode::OK,
+            s3::list_parts(self.bucket, self.key, upload_id, &parts),
+        ))
+    }
+
+    fn error(&self, e: &CloudBoostclicksError, not_found: &'static str) -> Response {
+        S3Router::error(e, not_found, self.resource)
+    }
+}
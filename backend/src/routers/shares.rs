@@ -1,21 +1,21 @@
 use std::sync::Arc;
 
 use axum::{
-    body::Full,
-    extract::{Path, State},
+    body::{Full, StreamBody},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{AppendHeaders, IntoResponse, Response},
     routing::get,
     Json, Router,
 };
-use reqwest::header;
+use reqwest::header::{self, HeaderName};
 use tokio_util::bytes::Bytes;
 use uuid::Uuid;
 
 use crate::{
-    common::routing::app_state::AppState,
-    schemas::shares::ShareInfoSchema,
-    services::shares::SharesService,
+    common::{hashing::digest_header, http::http_date, routing::app_state::AppState},
+    schemas::{files::ArchiveQuery, shares::ShareInfoSchema},
+    services::{archives::ArchivesService, shares::SharesService},
 };
 
 pub struct SharesRouter;
@@ -71,15 +71,14 @@ impl SharesRouter {
         service
             .download_file(share_id)
             .await
-            .map(|data| {
+            .map(|file| {
                 let name = share
                     .path
                     .trim_end_matches('/')
                     .split('/')
                     .last()
                     .unwrap_or("shared_file");
-                let bytes = Bytes::from(data);
-                let body = Full::new(bytes);
+                let body = Full::new(Bytes::from(file.data));
                 let content_type = mime_guess::from_path(name)
                     .first_or_octet_stream()
                     .to_string();
@@ -89,9 +88,17 @@ impl SharesRouter {
                         header::CONTENT_DISPOSITION,
                         format!("attachment; filename=\"{name}\""),
                     ),
+                    (header::ETAG, format!("\"{}\"", file.etag)),
+                    (HeaderName::from_static("digest"), digest_header(&file.sha256)),
                 ]);
+                let last_modified = AppendHeaders(
+                    file.last_modified
+                        .map(|date| (header::LAST_MODIFIED, http_date(&date))),
+                );
+                let envelope_headers =
+                    AppendHeaders(file.envelope.as_ref().map(|e| e.headers()).unwrap_or_default());
 
-                (headers, body).into_response()
+                (headers, last_modified, envelope_headers, body).into_response()
             })
             .map_err(|e| <(StatusCode, String)>::from(e))
     }
@@ -99,7 +106,9 @@ impl SharesRouter {
     async fn download_folder(
         State(state): State<Arc<AppState>>,
         Path(share_id): Path<Uuid>,
+        Query(archive_query): Query<ArchiveQuery>,
     ) -> Result<Response, (StatusCode, String)> {
+        let format = archive_query.format;
         let service = SharesService::new(&state.db, state.tx.clone());
         let share = service
             .get(share_id)
@@ -109,20 +118,21 @@ impl SharesRouter {
         service
             .download_folder(share_id)
             .await
-            .map(|data| {
+            .map(|entries| {
                 let name = share
                     .path
                     .trim_end_matches('/')
                     .split('/')
                     .last()
                     .unwrap_or("shared_folder");
-                let bytes = Bytes::from(data);
-                let body = Full::new(bytes);
+                let archive = ArchivesService::new(state.db.clone(), state.config.clone())
+                    .build(format, share.storage_id, entries);
+                let body = StreamBody::new(archive);
                 let headers = AppendHeaders([
-                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (header::CONTENT_TYPE, format.content_type().to_string()),
                     (
                         header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{name}.zip\""),
+                        format!("attachment; filename=\"{name}.{}\"", format.extension()),
                     ),
                 ]);
 
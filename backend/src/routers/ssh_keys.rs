@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        jwt_manager::AuthUser,
+        routing::{app_state::AppState, middlewares::auth::logged_in_required},
+    },
+    schemas::ssh_keys::InSshKeySchema,
+    services::ssh_keys::SshKeysService,
+};
+
+pub struct SshKeysRouter;
+
+impl SshKeysRouter {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/", get(Self::list).post(Self::create))
+            .route("/:key_id", delete(Self::delete))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                logged_in_required,
+            ))
+            .with_state(state)
+    }
+
+    async fn create(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Json(in_schema): Json<InSshKeySchema>,
+    ) -> impl IntoResponse {
+        let key = SshKeysService::new(&state.db)
+            .create(in_schema, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::CREATED, Json(key)))
+    }
+
+    async fn list(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+    ) -> impl IntoResponse {
+        let keys = SshKeysService::new(&state.db).list(&user).await?;
+        Ok::<_, (StatusCode, String)>((StatusCode::OK, Json(keys)))
+    }
+
+    async fn delete(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(key_id): Path<Uuid>,
+    ) -> impl IntoResponse {
+        SshKeysService::new(&state.db)
+            .delete(key_id, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>(StatusCode::NO_CONTENT)
+    }
+}
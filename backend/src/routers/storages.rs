@@ -1,11 +1,11 @@
 ﻿use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::get,
+    routing::{delete, get, put},
     Extension, Json, Router,
 };
 use uuid::Uuid;
@@ -15,12 +15,17 @@ use crate::{
         jwt_manager::AuthUser,
         routing::{app_state::AppState, middlewares::auth::logged_in_required},
     },
-    models::storages::Storage,
+    models::{
+        chunk_health::HealthReport,
+        storage_replicas::StorageReplica,
+        storages::{DedupStats, Storage},
+    },
     schemas::{
         access::{GrantAccess, RestrictAccess},
-        storages::{InStorageSchema, StoragesListSchema},
+        changes::{ChangesQuery, ChangesSchema},
+        storages::{InReplicaSchema, InStorageSchema, StorageSettingsSchema, StoragesListSchema},
     },
-    services::storages::StoragesService,
+    services::{changes::ChangesService, scrub::ScrubService, storages::StoragesService},
 };
 
 use super::files::FilesRouter;
@@ -33,6 +38,17 @@ impl StoragesRouter {
         Router::new()
             .route("/", get(Self::list).post(Self::create))
             .route("/:storage_id", get(Self::get).delete(Self::delete))
+            .route("/:storage_id/settings", put(Self::update_settings))
+            .route("/:storage_id/dedup", get(Self::dedup_stats))
+            .route("/:storage_id/health", get(Self::health_report))
+            .route(
+                "/:storage_id/replicas",
+                get(Self::list_replicas).post(Self::add_replica),
+            )
+            .route(
+                "/:storage_id/replicas/:replica_id",
+                delete(Self::remove_replica),
+            )
             .route(
                 "/:storage_id/access",
                 get(Self::list_users_with_access)
@@ -47,6 +63,18 @@ impl StoragesRouter {
             .with_state(state)
     }
 
+    /// Long polling of changes, it's served apart so waiting clients don't take
+    /// the slots of the concurrency limit
+    pub fn get_changes_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/:storage_id/changes", get(Self::list_changes))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                logged_in_required,
+            ))
+            .with_state(state)
+    }
+
     async fn create(
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
@@ -78,6 +106,84 @@ impl StoragesRouter {
         Ok(Json(storage))
     }
 
+    async fn dedup_stats(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<DedupStats>, (StatusCode, String)> {
+        let stats = StoragesService::new(&state.db).dedup_stats(id, &user).await?;
+        Ok(Json(stats))
+    }
+
+    async fn health_report(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<HealthReport>, (StatusCode, String)> {
+        let report = ScrubService::new(&state.db, state.config.clone())
+            .report(id, &user)
+            .await?;
+        Ok(Json(report))
+    }
+
+    async fn list_changes(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+        Query(query): Query<ChangesQuery>,
+    ) -> Result<Json<ChangesSchema>, (StatusCode, String)> {
+        let changes = ChangesService::new(&state.db, &state.changes)
+            .list(id, query, &user)
+            .await?;
+        Ok(Json(changes))
+    }
+
+    async fn list_replicas(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<Vec<StorageReplica>>, (StatusCode, String)> {
+        let replicas = StoragesService::new(&state.db)
+            .list_replicas(id, &user)
+            .await?;
+        Ok(Json(replicas))
+    }
+
+    async fn add_replica(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+        Json(in_schema): Json<InReplicaSchema>,
+    ) -> Result<(StatusCode, Json<StorageReplica>), (StatusCode, String)> {
+        let replica = StoragesService::new(&state.db)
+            .add_replica(id, in_schema, &user)
+            .await?;
+        Ok((StatusCode::CREATED, Json(replica)))
+    }
+
+    async fn remove_replica(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path((id, replica_id)): Path<(Uuid, Uuid)>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        StoragesService::new(&state.db)
+            .remove_replica(id, replica_id, &user)
+            .await?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    async fn update_settings(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+        Json(in_schema): Json<StorageSettingsSchema>,
+    ) -> Result<Json<Storage>, (StatusCode, String)> {
+        let storage = StoragesService::new(&state.db)
+            .update_settings(id, in_schema, &user)
+            .await?;
+        Ok(Json(storage))
+    }
+
     async fn delete(
         State(state): State<Arc<AppState>>,
         Extension(user): Extension<AuthUser>,
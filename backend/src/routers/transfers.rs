@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::get,
+    Extension, Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        jwt_manager::AuthUser,
+        routing::{app_state::AppState, middlewares::auth::logged_in_required},
+    },
+    models::transfers::Transfer,
+    schemas::transfers::{InTransferSchema, TransferCreatedSchema},
+    services::transfers::TransfersService,
+};
+
+pub struct TransfersRouter;
+
+impl TransfersRouter {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/", get(Self::list).post(Self::create))
+            .route("/:transfer_id", get(Self::get))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                logged_in_required,
+            ))
+            .with_state(state)
+    }
+
+    async fn create(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Json(in_schema): Json<InTransferSchema>,
+    ) -> impl IntoResponse {
+        let transfer = TransfersService::new(&state.db, state.config.clone())
+            .create(in_schema, &user)
+            .await?;
+        Ok::<_, (StatusCode, String)>((
+            StatusCode::ACCEPTED,
+            Json(TransferCreatedSchema::new(transfer.id)),
+        ))
+    }
+
+    async fn list(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+    ) -> impl IntoResponse {
+        let transfers = TransfersService::new(&state.db, state.config.clone())
+            .list(&user)
+            .await?;
+        Ok::<_, (StatusCode, String)>(Json(transfers))
+    }
+
+    async fn get(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<Transfer>, (StatusCode, String)> {
+        let transfer = TransfersService::new(&state.db, state.config.clone())
+            .get(id, &user)
+            .await?;
+        Ok(Json(transfer))
+    }
+}
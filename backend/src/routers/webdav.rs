@@ -0,0 +1,298 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::{Bytes, Full, StreamBody},
+    extract::{OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware,
+    response::{AppendHeaders, IntoResponse, Response},
+    routing::any,
+    Extension, Router,
+};
+use reqwest::header::{self, HeaderName};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        jwt_manager::AuthUser,
+        routing::{app_state::AppState, middlewares::auth::basic_or_bearer_required},
+        http::{http_date, if_match},
+        webdav::{
+            destination_path, href, lock_discovery, multistatus, proppatch_status,
+            ALLOWED_METHODS,
+        },
+    },
+    errors::CloudBoostclicksError,
+    services::{content::ContentService, webdav::WebDavService},
+};
+
+type DavResult = Result<Response, (StatusCode, String)>;
+
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Storages mounted as network drives, every storage is a separate WebDAV root
+pub struct WebDavRouter;
+
+impl WebDavRouter {
+    pub fn get_router(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/:storage_id", any(Self::handle))
+            .route("/:storage_id/", any(Self::handle))
+            .route("/:storage_id/*path", any(Self::handle))
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                basic_or_bearer_required,
+            ))
+            .with_state(state)
+    }
+
+    /// WebDAV methods aren't known by the router, so every request comes here
+    async fn handle(
+        State(state): State<Arc<AppState>>,
+        Extension(user): Extension<AuthUser>,
+        Path(params): Path<HashMap<String, String>>,
+        OriginalUri(uri): OriginalUri,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let Some(storage_id) = params
+            .get("storage_id")
+            .and_then(|id| Uuid::parse_str(id).ok())
+        else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let path = params
+            .get("path")
+            .map(|path| path.trim_matches('/'))
+            .unwrap_or_default();
+
+        // hrefs are built from the prefix the storage is mounted at
+        let storage_segment = format!("/{storage_id}");
+        let base = match uri.path().find(&storage_segment) {
+            Some(position) => &uri.path()[..position + storage_segment.len()],
+            None => return StatusCode::NOT_FOUND.into_response(),
+        };
+
+        let service = WebDavService::new(&state.db, state.config.clone(), state.tx.clone());
+        let result = match method.as_str() {
+            "OPTIONS" => Ok(Self::options()),
+            "PROPFIND" => Self::propfind(&service, storage_id, path, base, &headers, &user).await,
+            "PROPPATCH" => Ok(Self::xml(
+                StatusCode::MULTI_STATUS,
+                proppatch_status(&href(base, path, false)),
+            )),
+            "GET" | "HEAD" => Self::get(&state, &service, storage_id, path, &method, &headers, &user).await,
+            "PUT" => Self::put(&service, storage_id, path, body, &headers, &user).await,
+            "DELETE" => service
+                .delete(storage_id, path, if_match(&headers).as_deref(), &user)
+                .await
+                .map(|_| StatusCode::NO_CONTENT.into_response())
+                .map_err(|e| Self::dav_error(e, StatusCode::CONFLICT)),
+            "MKCOL" => service
+                .mkcol(storage_id, path, &user)
+                .await
+                .map(|_| StatusCode::CREATED.into_response())
+                .map_err(|e| Self::dav_error(e, StatusCode::METHOD_NOT_ALLOWED)),
+            "MOVE" | "COPY" => {
+                Self::transfer(&service, storage_id, path, base, &method, &headers, &user).await
+            }
+            "LOCK" => Ok(Self::lock(&href(base, path, false))),
+            "UNLOCK" => Ok(StatusCode::NO_CONTENT.into_response()),
+            _ => Err((
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Метод не поддерживается".to_owned(),
+            )),
+        };
+
+        result.unwrap_or_else(IntoResponse::into_response)
+    }
+
+    fn options() -> Response {
+        let headers = AppendHeaders([
+            (HeaderName::from_static("dav"), "1, 2"),
+            (header::ALLOW, ALLOWED_METHODS),
+            (HeaderName::from_static("ms-author-via"), "DAV"),
+        ]);
+        (StatusCode::OK, headers).into_response()
+    }
+
+    async fn propfind(
+        service: &WebDavService<'_>,
+        storage_id: Uuid,
+        path: &str,
+        base: &str,
+        headers: &HeaderMap,
+        user: &AuthUser,
+    ) -> DavResult {
+        // an infinite depth is served as 1, clients walk the tree by themselves
+        let depth = match headers.get("depth").and_then(|depth| depth.to_str().ok()) {
+            Some("0") => 0,
+            _ => 1,
+        };
+
+        let resources = service
+            .propfind(storage_id, path, depth, user)
+            .await
+            .map_err(|e| Self::dav_error(e, StatusCode::CONFLICT))?;
+
+        Ok(Self::xml(StatusCode::MULTI_STATUS, multistatus(base, &resources)))
+    }
+
+    async fn get(
+        state: &AppState,
+        service: &WebDavService<'_>,
+        storage_id: Uuid,
+        path: &str,
+        method: &Method,
+        headers: &HeaderMap,
+        user: &AuthUser,
+    ) -> DavResult {
+        let resource = service
+            .resource(storage_id, path, user)
+            .await
+            .map_err(|e| Self::dav_error(e, StatusCode::CONFLICT))?;
+
+        let content_type = if resource.is_collection {
+            "httpd/unix-directory".to_string()
+        } else {
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string()
+        };
+        let mut response_headers = vec![
+            (header::CONTENT_TYPE, content_type),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ];
+        if let Some(etag) = &resource.etag {
+            response_headers.push((header::ETAG, format!("\"{etag}\"")));
+        }
+        if let Some(updated_at) = &resource.updated_at {
+            response_headers.push((header::LAST_MODIFIED, http_date(updated_at)));
+        }
+
+        if *method == Method::HEAD || resource.is_collection {
+            response_headers.push((header::CONTENT_LENGTH, resource.size.to_string()));
+            return Ok((AppendHeaders(response_headers), ()).into_response());
+        }
+
+        // only chunks covering the range are downloaded
+        if let Some(range) = headers.get(header::RANGE).and_then(|range| range.to_str().ok()) {
+            match service.range(storage_id, path, range, user).await {
+                Ok(Some(part)) => {
+                    response_headers.push((header::CONTENT_RANGE, part.content_range()));
+                    let body = ContentService::new(state.db.clone(), state.config.clone())
+                        .range(storage_id, &part);
+                    let headers = AppendHeaders(response_headers);
+                    return Ok((StatusCode::PARTIAL_CONTENT, headers, StreamBody::new(body))
+                        .into_response());
+                }
+                Ok(None) => (),
+                Err(CloudBoostclicksError::RangeNotSatisfiable(len)) => {
+                    let headers = AppendHeaders([(header::CONTENT_RANGE, format!("bytes */{len}"))]);
+                    return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+                }
+                Err(e) => return Err(Self::dav_error(e, StatusCode::CONFLICT)),
+            }
+        }
+
+        let data = service
+            .get(storage_id, path, user)
+            .await
+            .map_err(|e| Self::dav_error(e, StatusCode::CONFLICT))?
+            .data;
+
+        Ok((AppendHeaders(response_headers), Full::new(Bytes::from(data))).into_response())
+    }
+
+    async fn put(
+        service: &WebDavService<'_>,
+        storage_id: Uuid,
+        path: &str,
+        body: Bytes,
+        headers: &HeaderMap,
+        user: &AuthUser,
+    ) -> DavResult {
+        if path.is_empty() {
+            return Err((StatusCode::METHOD_NOT_ALLOWED, "Неверный путь".to_owned()));
+        }
+
+        let created = service
+            .put(storage_id, path, body, if_match(headers).as_deref(), user)
+            .await
+            .map_err(|e| Self::dav_error(e, StatusCode::CONFLICT))?;
+
+        Ok(if created {
+            StatusCode::CREATED
+        } else {
+            StatusCode::NO_CONTENT
+        }
+        .into_response())
+    }
+
+    async fn transfer(
+        service: &WebDavService<'_>,
+        storage_id: Uuid,
+        path: &str,
+        base: &str,
+        method: &Method,
+        headers: &HeaderMap,
+        user: &AuthUser,
+    ) -> DavResult {
+        let destination = headers
+            .get("destination")
+            .and_then(|destination| destination.to_str().ok())
+            .ok_or_else(|| {
+                <(StatusCode, String)>::from(CloudBoostclicksError::HeaderMissed(
+                    "Destination".to_owned(),
+                ))
+            })?;
+        let destination = destination_path(destination, base)
+            .ok_or((StatusCode::BAD_GATEWAY, "Назначение в другом облаке".to_owned()))?;
+        let overwrite = headers
+            .get("overwrite")
+            .is_none_or(|overwrite| overwrite.as_bytes() != b"F");
+
+        let created = service
+            .transfer(
+                storage_id,
+                path,
+                &destination,
+                method.as_str() == "MOVE",
+                overwrite,
+                if_match(headers).as_deref(),
+                user,
+            )
+            .await
+            .map_err(|e| Self::dav_error(e, StatusCode::PRECONDITION_FAILED))?;
+
+        Ok(if created {
+            StatusCode::CREATED
+        } else {
+            StatusCode::NO_CONTENT
+        }
+        .into_response())
+    }
+
+    /// Locks are only pretended, clients like Finder and Windows Explorer don't write without them
+    fn lock(href: &str) -> Response {
+        let (token, body) = lock_discovery(href);
+        let headers = AppendHeaders([(HeaderName::from_static("lock-token"), format!("<{token}>"))]);
+        (headers, Self::xml(StatusCode::OK, body)).into_response()
+    }
+
+    fn xml(status: StatusCode, body: String) -> Response {
+        (status, [(header::CONTENT_TYPE, XML_CONTENT_TYPE)], body).into_response()
+    }
+
+    /// WebDAV has its own statuses for conflicts, e.g. MKCOL over an existing folder is 405
+    fn dav_error(e: CloudBoostclicksError, conflict: StatusCode) -> (StatusCode, String) {
+        match e {
+            CloudBoostclicksError::AlreadyExists(_) => (conflict, e.to_string()),
+            CloudBoostclicksError::InvalidPath | CloudBoostclicksError::InvalidFolderName => {
+                (StatusCode::BAD_REQUEST, e.to_string())
+            }
+            e => e.into(),
+        }
+    }
+}
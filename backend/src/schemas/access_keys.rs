@@ -0,0 +1,10 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// The only place the secret is shown, it can always be derived again from the key id
+#[derive(Serialize)]
+pub struct AccessKeyCreatedSchema {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,17 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct InAppPasswordSchema {
+    pub name: String,
+}
+
+/// The only place the password is shown, it can't be restored from its hash
+#[derive(Serialize)]
+pub struct AppPasswordCreatedSchema {
+    pub id: Uuid,
+    pub name: String,
+    pub password: String,
+    pub created_at: NaiveDateTime,
+}
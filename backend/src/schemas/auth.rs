@@ -1,6 +1,6 @@
 ﻿use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct LoginSchema {
     pub email: String,
     pub password: String,
@@ -17,9 +17,9 @@ pub struct TelegramLoginSchema {
     pub hash: String,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct TokenSchema {
-    access_token: String,
+    pub access_token: String,
 }
 
 impl TokenSchema {
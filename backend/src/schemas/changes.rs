@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::changes::Change;
+
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    /// Cursor of the last seen change, without it only the current cursor is returned
+    pub cursor: Option<i64>,
+    /// Seconds to wait for a change when there are none yet
+    pub timeout: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ChangesSchema {
+    pub changes: Vec<Change>,
+    /// Cursor to pass next time
+    pub cursor: i64,
+    /// Changes didn't fit into the response, the next request returns the rest right away
+    pub has_more: bool,
+}
+
+impl ChangesSchema {
+    pub fn new(changes: Vec<Change>, cursor: i64, has_more: bool) -> Self {
+        Self {
+            changes,
+            cursor,
+            has_more,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreVersionSchema {
+    pub path: String,
+    pub version: i32,
+}
+
+#[derive(Deserialize)]
+pub struct PruneVersionsSchema {
+    pub path: Option<String>,
+    pub keep: Option<u16>,
+    pub older_than_days: Option<u16>,
+}
+
+#[derive(Serialize)]
+pub struct PruneSummary {
+    pub pruned_versions: i64,
+}
+
+impl PruneSummary {
+    pub fn new(pruned_versions: i64) -> Self {
+        Self { pruned_versions }
+    }
+}
@@ -1,32 +1,97 @@
-﻿use axum::body::Bytes;
+﻿use std::ops::Range;
+
+use axum::body::Bytes;
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::common::types::Position;
+use crate::{
+    common::{extract::ArchiveFile, hashing::sha256_hex, types::Position},
+    errors::CloudBoostclicksResult,
+    models::files::File,
+};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct UploadParams {
     pub path: String,
     pub folder_name: String,
 }
 
+/// Key blob and metadata of a file encrypted by a client, both are opaque for the server
+#[derive(Clone)]
+pub struct ClientEnvelope {
+    pub encrypted_key: String,
+    pub metadata: Option<String>,
+}
+
+impl ClientEnvelope {
+    /// Envelope is given with the `encrypted_key` field, `metadata` is optional
+    pub fn from_fields(encrypted_key: Option<String>, metadata: Option<String>) -> Option<Self> {
+        encrypted_key.map(|encrypted_key| Self {
+            encrypted_key,
+            metadata,
+        })
+    }
+
+    /// Both values are sent back in headers, so only printable ASCII (e.g. base64) is accepted
+    pub fn is_header_safe(&self) -> bool {
+        std::iter::once(&self.encrypted_key)
+            .chain(&self.metadata)
+            .all(|value| value.bytes().all(|b| b.is_ascii_graphic()))
+    }
+
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("x-encrypted-key", self.encrypted_key.clone())];
+        if let Some(metadata) = &self.metadata {
+            headers.push(("x-client-metadata", metadata.clone()));
+        }
+        headers
+    }
+}
+
 pub struct InFileSchema {
     pub storage_id: Uuid,
     pub path: String,
     pub size: i64,
     pub file: Bytes,
+    pub envelope: Option<ClientEnvelope>,
+    /// ETag the replaced file must still have
+    pub if_match: Option<String>,
 }
 
 impl InFileSchema {
-    pub fn new(storage_id: Uuid, path: String, file: Bytes) -> Self {
+    pub fn new(storage_id: Uuid, path: String, file: Bytes, envelope: Option<ClientEnvelope>) -> Self {
         let size = file.len() as i64;
         Self {
             storage_id,
             path,
             size,
             file,
+            envelope,
+            if_match: None,
         }
     }
+
+    pub fn with_if_match(mut self, if_match: Option<String>) -> Self {
+        self.if_match = if_match;
+        self
+    }
+}
+
+/// A part of a file uploaded by chunks
+pub struct InChunkSchema {
+    pub storage_id: Uuid,
+    pub path: String,
+    pub size: Option<i64>,
+    /// Id returned for the first chunk
+    pub file_id: Option<Uuid>,
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub chunk: Bytes,
+    /// Required with the first chunk in client encrypted storages
+    pub envelope: Option<ClientEnvelope>,
+    /// ETag the replaced file must still have, checked with the first chunk
+    pub if_match: Option<String>,
 }
 
 pub const IN_FILE_SCHEMA_FIELDS_AMOUNT: usize = 2;
@@ -58,6 +123,134 @@ impl DownloadedChunkSchema {
     }
 }
 
+pub struct DownloadedFileSchema {
+    pub data: Vec<u8>,
+    pub sha256: String,
+    /// The same ETag listings give, the checksum of the data unless validators are set
+    pub etag: String,
+    pub last_modified: Option<NaiveDateTime>,
+    pub envelope: Option<ClientEnvelope>,
+}
+
+impl DownloadedFileSchema {
+    pub fn new(data: Vec<u8>, sha256: Option<String>) -> Self {
+        let sha256 = sha256.unwrap_or_else(|| sha256_hex(&data));
+        Self {
+            data,
+            etag: sha256.clone(),
+            sha256,
+            last_modified: None,
+            envelope: None,
+        }
+    }
+
+    pub fn with_validators(mut self, etag: String, last_modified: NaiveDateTime) -> Self {
+        self.etag = etag;
+        self.last_modified = Some(last_modified);
+        self
+    }
+
+    pub fn with_envelope(mut self, encrypted_key: Option<String>, metadata: Option<String>) -> Self {
+        self.envelope = ClientEnvelope::from_fields(encrypted_key, metadata);
+        self
+    }
+}
+
+/// A byte range of a file or of its version, its content is streamed as chunks are downloaded
+#[derive(Clone)]
+pub struct FileRangeSchema {
+    pub file_id: Uuid,
+    pub version_id: Option<Uuid>,
+    pub codec: Option<String>,
+    pub range: Range<u64>,
+    /// Length of the whole content
+    pub len: u64,
+    pub etag: String,
+    pub last_modified: NaiveDateTime,
+    pub envelope: Option<ClientEnvelope>,
+}
+
+impl FileRangeSchema {
+    /// Value of the `Content-Range` header
+    pub fn content_range(&self) -> String {
+        format!("bytes {}-{}/{}", self.range.start, self.range.end - 1, self.len)
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+    #[serde(rename = "tar.zst", alias = "tzst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+        }
+    }
+
+    /// Guesses the format by the extension of the archive name
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let filename = filename.to_lowercase();
+        [".tar.gz", ".tgz", ".tar.zst", ".tzst", ".tar", ".zip"]
+            .into_iter()
+            .find(|extension| filename.ends_with(extension))
+            .and_then(|extension| Self::from_extension(&extension[1..]))
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "zip" => Some(Self::Zip),
+            "tar" => Some(Self::Tar),
+            "tar.gz" | "tgz" => Some(Self::TarGz),
+            "tar.zst" | "tzst" => Some(Self::TarZst),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Tar => "application/x-tar",
+            Self::TarGz => "application/gzip",
+            Self::TarZst => "application/zstd",
+        }
+    }
+}
+
+/// Files and folders of one storage downloaded as a single archive
+#[derive(Deserialize)]
+pub struct DownloadArchiveSchema {
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ArchiveQuery {
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+/// A file put into an archive under the path relative to the archived folder
+///
+/// Folders keep the trailing slash, so empty ones are archived too
+pub struct ArchiveEntry {
+    pub path: String,
+    pub file: File,
+}
+
 #[derive(Serialize)]
 pub struct DeleteSummary {
     pub deleted_files: i64,
@@ -73,8 +266,235 @@ impl DeleteSummary {
     }
 }
 
+#[derive(Deserialize)]
+pub struct CopySchema {
+    pub path: String,
+    pub destination_path: String,
+    pub destination_storage_id: Option<Uuid>,
+    /// The element at the destination goes to the trash with the copy instead of failing it
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Serialize)]
+pub struct CopySummary {
+    pub copied_files: i64,
+    pub copied_folders: i64,
+}
+
+impl CopySummary {
+    pub fn new(copied_files: i64, copied_folders: i64) -> Self {
+        Self {
+            copied_files,
+            copied_folders,
+        }
+    }
+}
+
+/// One operation of a batch, all paths belong to the same storage
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Delete {
+        path: String,
+        /// Like the `If-Match` header, the operation fails unless the file still has this ETag
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        if_match: Option<String>,
+    },
+    Move {
+        path: String,
+        destination_path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        if_match: Option<String>,
+        /// The element at the destination goes to the trash with the move instead of failing it
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        overwrite: bool,
+    },
+    Copy {
+        path: String,
+        destination_path: String,
+    },
+}
+
+impl BatchOperation {
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            Self::Delete { path, .. } => vec![path],
+            Self::Move {
+                path,
+                destination_path,
+                ..
+            }
+            | Self::Copy {
+                path,
+                destination_path,
+            } => vec![path, destination_path],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BatchSchema {
+    pub operations: Vec<BatchOperation>,
+    /// Runs everything and rolls it back, so only the report is returned
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BatchItemResult {
+    #[serde(flatten)]
+    pub operation: BatchOperation,
+    pub files: i64,
+    pub folders: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BatchSummary {
+    pub dry_run: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub deleted_files: i64,
+    pub deleted_folders: i64,
+    pub moved_files: i64,
+    pub moved_folders: i64,
+    pub copied_files: i64,
+    pub copied_folders: i64,
+    /// Results in the order of the operations
+    pub items: Vec<BatchItemResult>,
+}
+
+impl BatchSummary {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            succeeded: 0,
+            failed: 0,
+            deleted_files: 0,
+            deleted_folders: 0,
+            moved_files: 0,
+            moved_folders: 0,
+            copied_files: 0,
+            copied_folders: 0,
+            items: Vec::new(),
+        }
+    }
+
+    /// Adds the result of an operation, `Ok` holds amounts of affected files and folders
+    pub fn push(&mut self, operation: BatchOperation, result: CloudBoostclicksResult<(i64, i64)>) {
+        let (files, folders, error) = match result {
+            Ok((files, folders)) => {
+                self.succeeded += 1;
+                let (total_files, total_folders) = match operation {
+                    BatchOperation::Delete { .. } => (&mut self.deleted_files, &mut self.deleted_folders),
+                    BatchOperation::Move { .. } => (&mut self.moved_files, &mut self.moved_folders),
+                    BatchOperation::Copy { .. } => (&mut self.copied_files, &mut self.copied_folders),
+                };
+                *total_files += files;
+                *total_folders += folders;
+                (files, folders, None)
+            }
+            Err(e) => {
+                self.failed += 1;
+                (0, 0, Some(e.to_string()))
+            }
+        };
+
+        self.items.push(BatchItemResult {
+            operation,
+            files,
+            folders,
+            error,
+        });
+    }
+}
+
+/// An archive to unpack into the folder
+pub struct ExtractSchema {
+    pub storage_id: Uuid,
+    /// The target folder, empty for the root
+    pub path: String,
+    pub format: ArchiveFormat,
+    pub archive: ArchiveFile,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractStatus {
+    Extracted,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct ExtractedEntry {
+    /// The path inside the archive
+    pub path: String,
+    pub status: ExtractStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ExtractSummary {
+    pub extracted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub entries: Vec<ExtractedEntry>,
+    /// Why the archive wasn't unpacked to the end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ExtractSummary {
+    pub fn push(&mut self, path: String, status: ExtractStatus, error: Option<String>) {
+        match status {
+            ExtractStatus::Extracted => self.extracted += 1,
+            ExtractStatus::Skipped => self.skipped += 1,
+            ExtractStatus::Failed => self.failed += 1,
+        }
+        self.entries.push(ExtractedEntry {
+            path,
+            status,
+            error,
+        });
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SearchQuery {
     pub search_path: Option<String>,
 }
 
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Sorting and filtering of tree and search results
+#[derive(Deserialize, Default)]
+pub struct ListQuery {
+    #[serde(default)]
+    pub sort_by: SortBy,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub uploaded_by: Option<Uuid>,
+    pub updated_after: Option<NaiveDateTime>,
+    pub updated_before: Option<NaiveDateTime>,
+}
+
@@ -1,8 +1,17 @@
 ﻿pub mod access;
+pub mod access_keys;
+pub mod app_passwords;
 pub mod auth;
+pub mod changes;
+pub mod file_versions;
 pub mod files;
+pub mod s3;
 pub mod shares;
+pub mod ssh_keys;
 pub mod storage_workers;
 pub mod storages;
+pub mod transfers;
+pub mod trash;
 pub mod users;
+pub mod webdav;
 
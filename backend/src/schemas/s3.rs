@@ -0,0 +1,55 @@
+use chrono::NaiveDateTime;
+
+use crate::models::files::{FSElement, File};
+
+/// Listing of a bucket, `marker` is the key the listing starts after
+pub struct ListObjectsParams {
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub marker: Option<String>,
+    pub max_keys: usize,
+}
+
+/// A key of a bucket, folders kept without files are keys ending with `/`
+pub struct ObjectEntry {
+    pub key: String,
+    pub size: i64,
+    pub etag: String,
+    pub last_modified: NaiveDateTime,
+}
+
+impl From<File> for ObjectEntry {
+    fn from(file: File) -> Self {
+        Self {
+            etag: file.etag(),
+            key: file.path,
+            size: file.size,
+            last_modified: file.updated_at,
+        }
+    }
+}
+
+impl From<FSElement> for ObjectEntry {
+    fn from(element: FSElement) -> Self {
+        Self {
+            etag: element.etag.unwrap_or_default(),
+            key: element.path,
+            size: element.size,
+            last_modified: element.updated_at,
+        }
+    }
+}
+
+pub struct ListObjectsResult {
+    pub contents: Vec<ObjectEntry>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    /// The last key or common prefix returned, set only when the listing is truncated
+    pub next_marker: Option<String>,
+}
+
+/// A part named in the request completing a multipart upload
+pub struct CompletedPart {
+    pub part_number: i32,
+    pub etag: String,
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct InSshKeySchema {
+    /// The comment of the key is used when it's missing
+    pub name: Option<String>,
+    pub public_key: String,
+}
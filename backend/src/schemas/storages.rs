@@ -6,9 +6,26 @@ use crate::{common::types::ChatId, models::storages::StorageWithInfo};
 pub struct InStorageSchema {
     pub name: String,
     pub chat_id: ChatId,
+    #[serde(default)]
+    pub client_encrypted: bool,
 }
 
-#[derive(Serialize)]
+/// Only given settings are changed
+#[derive(Deserialize)]
+pub struct StorageSettingsSchema {
+    pub versioning: Option<bool>,
+    pub erasure_data_chunks: Option<i16>,
+    pub erasure_parity_chunks: Option<i16>,
+    pub encryption: Option<bool>,
+    pub compression: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct InReplicaSchema {
+    pub chat_id: ChatId,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct StoragesListSchema {
     pub storages: Vec<StorageWithInfo>,
 }
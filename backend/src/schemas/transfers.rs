@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct InTransferSchema {
+    pub source_storage_id: Uuid,
+    pub source_path: String,
+    pub destination_storage_id: Uuid,
+    pub destination_path: String,
+}
+
+#[derive(Serialize)]
+pub struct TransferCreatedSchema {
+    pub id: Uuid,
+}
+
+impl TransferCreatedSchema {
+    pub fn new(id: Uuid) -> Self {
+        Self { id }
+    }
+}
@@ -0,0 +1,7 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct RestoreSchema {
+    /// Where to restore the item to, its original path is used if not given
+    pub path: Option<String>,
+}
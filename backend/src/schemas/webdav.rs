@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+
+use crate::models::files::{FSElement, File};
+
+/// A file or a folder as seen by WebDAV clients
+pub struct DavResource {
+    /// Path relative to the storage root without the trailing slash, empty for the root
+    pub path: String,
+    pub is_collection: bool,
+    pub size: i64,
+    pub etag: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl DavResource {
+    pub fn collection(path: &str) -> Self {
+        Self {
+            path: path.trim_end_matches('/').to_string(),
+            is_collection: true,
+            size: 0,
+            etag: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+}
+
+impl From<File> for DavResource {
+    fn from(file: File) -> Self {
+        Self {
+            etag: Some(file.etag()),
+            path: file.path,
+            is_collection: false,
+            size: file.size,
+            created_at: Some(file.created_at),
+            updated_at: Some(file.updated_at),
+        }
+    }
+}
+
+impl From<FSElement> for DavResource {
+    fn from(element: FSElement) -> Self {
+        Self {
+            path: element.path,
+            is_collection: !element.is_file,
+            size: element.size,
+            etag: element.etag,
+            created_at: Some(element.created_at),
+            updated_at: Some(element.updated_at),
+        }
+    }
+}
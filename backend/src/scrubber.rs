@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::time;
+
+use crate::{config::Config, services::scrub::ScrubService};
+
+/// Background verification that telegram still gives every chunk back
+pub struct Scrubber {
+    db: PgPool,
+    config: Config,
+}
+
+impl Scrubber {
+    pub fn new(db: PgPool, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    pub async fn run(&self) {
+        if self.config.scrub_batch_size == 0 {
+            tracing::debug!("scrubber is disabled");
+            return;
+        }
+
+        let mut interval = time::interval(Duration::from_secs(self.config.scrub_interval_secs));
+
+        loop {
+            interval.tick().await;
+
+            match ScrubService::new(&self.db, self.config.clone())
+                .scrub_batch()
+                .await
+            {
+                Ok(checked) => tracing::debug!("scrubber checked {checked} telegram files"),
+                Err(e) => tracing::error!("scrubber failed: {e}"),
+            }
+
+            match ScrubService::new(&self.db, self.config.clone())
+                .repair_batch()
+                .await
+            {
+                Ok(0) => (),
+                Ok(repaired) => tracing::info!("scrubber repaired {repaired} telegram files"),
+                Err(e) => tracing::error!("scrubber failed to repair chunks: {e}"),
+            }
+        }
+    }
+}
@@ -10,8 +10,10 @@ use tower_http::{
 use crate::{
     common::routing::app_state::AppState,
     routers::{
-        auth::AuthRouter, shares::SharesRouter, storage_workers::StorageWorkersRouter,
-        storages::StoragesRouter, users::UsersRouter,
+        access_keys::AccessKeysRouter, app_passwords::AppPasswordsRouter, auth::AuthRouter,
+        s3::S3Router, shares::SharesRouter, ssh_keys::SshKeysRouter,
+        storage_workers::StorageWorkersRouter, storages::StoragesRouter, transfers::TransfersRouter,
+        users::UsersRouter, webdav::WebDavRouter,
     },
 };
 
@@ -34,6 +36,15 @@ impl Server {
         Self { router }
     }
 
+    /// The S3 gateway is a separate listener, S3 clients expect buckets right at the root
+    pub fn build_s3_server(workers: usize, app_state: Arc<AppState>) -> Self {
+        let router = S3Router::get_router(app_state)
+            .layer(DefaultBodyLimit::disable())
+            .layer(ConcurrencyLimitLayer::new(workers));
+
+        Self { router }
+    }
+
     #[inline]
     fn build_api_router(workers: usize, app_state: Arc<AppState>) -> Router {
         let app_cors = cors::CorsLayer::new()
@@ -50,9 +61,16 @@ impl Server {
                 "/storage_workers",
                 StorageWorkersRouter::get_router(app_state.clone()),
             )
+            .nest("/transfers", TransfersRouter::get_router(app_state.clone()))
+            .nest("/access_keys", AccessKeysRouter::get_router(app_state.clone()))
+            .nest("/ssh_keys", SshKeysRouter::get_router(app_state.clone()))
+            .nest("/app_passwords", AppPasswordsRouter::get_router(app_state.clone()))
+            .nest("/dav", WebDavRouter::get_router(app_state.clone()))
             // allow very large uploads (disable Axum body limit; rely on infra limits)
             .layer(DefaultBodyLimit::disable())
             .layer(ConcurrencyLimitLayer::new(workers.into()))
+            // requests waiting for changes mostly sleep, they are out of the limit
+            .nest("/storages", StoragesRouter::get_changes_router(app_state))
             .layer(app_cors)
     }
 
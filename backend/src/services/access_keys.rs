@@ -0,0 +1,81 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{hashing::hmac_sha256, jwt_manager::AuthUser},
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::access_keys::AccessKey,
+    repositories::{access_keys::AccessKeysRepository, users::UsersRepository},
+    schemas::access_keys::AccessKeyCreatedSchema,
+};
+
+/// Prefix of key ids, the rest is random upper hex like in AWS
+const KEY_ID_PREFIX: &str = "CB";
+const KEY_ID_RANDOM_LEN: usize = 18;
+
+/// Access keys of S3 clients.
+///
+/// Secrets are HMACs of key ids under the server secret, so they are never stored
+/// and all of them are revoked when `SECRET_KEY` changes
+pub struct AccessKeysService<'d> {
+    repo: AccessKeysRepository<'d>,
+    users_repo: UsersRepository<'d>,
+}
+
+impl<'d> AccessKeysService<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        let repo = AccessKeysRepository::new(db);
+        let users_repo = UsersRepository::new(db);
+        Self { repo, users_repo }
+    }
+
+    pub async fn create(
+        &self,
+        user: &AuthUser,
+        secret_key: &str,
+    ) -> CloudBoostclicksResult<AccessKeyCreatedSchema> {
+        let random = Uuid::new_v4().simple().to_string().to_uppercase();
+        let id = format!("{KEY_ID_PREFIX}{}", &random[..KEY_ID_RANDOM_LEN]);
+
+        let key = self.repo.create(&id, user.id).await?;
+        Ok(AccessKeyCreatedSchema {
+            secret_access_key: Self::secret_of(&key.id, secret_key),
+            access_key_id: key.id,
+            created_at: key.created_at,
+        })
+    }
+
+    pub async fn list(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<AccessKey>> {
+        self.repo.list_by_user_id(user.id).await
+    }
+
+    pub async fn delete(&self, access_key_id: &str, user: &AuthUser) -> CloudBoostclicksResult<()> {
+        self.repo.delete(access_key_id, user.id).await
+    }
+
+    /// Finds the owner of the key and the secret requests are signed with
+    pub async fn authenticate(
+        &self,
+        access_key_id: &str,
+        secret_key: &str,
+    ) -> CloudBoostclicksResult<(AuthUser, String)> {
+        let key = self
+            .repo
+            .get_by_id(access_key_id)
+            .await
+            .map_err(|_| CloudBoostclicksError::NotAuthenticated)?;
+        let user = self.users_repo.get_by_id(key.user_id).await?;
+
+        Ok((
+            AuthUser::new(user.id, user.identifier()),
+            Self::secret_of(&key.id, secret_key),
+        ))
+    }
+
+    fn secret_of(access_key_id: &str, secret_key: &str) -> String {
+        hex::encode(hmac_sha256(
+            secret_key.as_bytes(),
+            format!("s3:{access_key_id}").as_bytes(),
+        ))
+    }
+}
@@ -0,0 +1,78 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{hashing::sha256_hex, jwt_manager::AuthUser},
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::app_passwords::AppPassword,
+    repositories::{app_passwords::AppPasswordsRepository, users::UsersRepository},
+    schemas::app_passwords::{AppPasswordCreatedSchema, InAppPasswordSchema},
+};
+
+/// Passwords WebDAV clients and other applications knowing only Basic authentication log in with.
+///
+/// Every application gets its own password, so it can be revoked without changing the account one
+pub struct AppPasswordsService<'d> {
+    repo: AppPasswordsRepository<'d>,
+    users_repo: UsersRepository<'d>,
+}
+
+impl<'d> AppPasswordsService<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        let repo = AppPasswordsRepository::new(db);
+        let users_repo = UsersRepository::new(db);
+        Self { repo, users_repo }
+    }
+
+    pub async fn create(
+        &self,
+        in_schema: InAppPasswordSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<AppPasswordCreatedSchema> {
+        let name = in_schema.name.trim();
+        if name.is_empty() {
+            return Err(CloudBoostclicksError::InvalidAppPasswordName);
+        }
+
+        // 256 random bits from two v4 uuids
+        let password = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let app_password = self
+            .repo
+            .create(user.id, name, &sha256_hex(password.as_bytes()))
+            .await?;
+
+        Ok(AppPasswordCreatedSchema {
+            id: app_password.id,
+            name: app_password.name,
+            password,
+            created_at: app_password.created_at,
+        })
+    }
+
+    pub async fn list(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<AppPassword>> {
+        self.repo.list_by_user_id(user.id).await
+    }
+
+    pub async fn delete(&self, id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<()> {
+        self.repo.delete(id, user.id).await
+    }
+
+    /// Checks Basic credentials: the email of the user and one of their application passwords
+    pub async fn authenticate(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> CloudBoostclicksResult<AuthUser> {
+        let app_password = self
+            .repo
+            .get_by_password_hash(&sha256_hex(password.as_bytes()))
+            .await
+            .map_err(|_| CloudBoostclicksError::NotAuthenticated)?;
+        let user = self.users_repo.get_by_id(app_password.user_id).await?;
+        if user.email.as_deref() != Some(email) {
+            return Err(CloudBoostclicksError::NotAuthenticated);
+        }
+
+        Ok(AuthUser::new(user.id, user.identifier()))
+    }
+}
@@ -0,0 +1,115 @@
+use std::pin::Pin;
+
+use axum::body::Bytes;
+use futures::{pin_mut, Stream, StreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        tar::{TarCompression, TarWriter},
+        zip::ZipWriter,
+    },
+    config::Config,
+    errors::CloudBoostclicksResult,
+    schemas::files::{ArchiveEntry, ArchiveFormat},
+};
+
+use super::storage_manager::StorageManagerService;
+
+pub type ArchiveStream = Pin<Box<dyn Stream<Item = CloudBoostclicksResult<Bytes>> + Send>>;
+
+/// Builds archives of folders while their files are being downloaded.
+///
+/// Owns everything it needs, so the archive can be streamed as a response body
+pub struct ArchivesService {
+    db: PgPool,
+    config: Config,
+}
+
+impl ArchivesService {
+    pub fn new(db: PgPool, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    pub fn build(
+        self,
+        format: ArchiveFormat,
+        storage_id: Uuid,
+        entries: Vec<ArchiveEntry>,
+    ) -> ArchiveStream {
+        match format {
+            ArchiveFormat::Zip => Box::pin(self.zip(storage_id, entries)),
+            ArchiveFormat::Tar => Box::pin(self.tar(TarCompression::None, storage_id, entries)),
+            ArchiveFormat::TarGz => Box::pin(self.tar(TarCompression::Gzip, storage_id, entries)),
+            ArchiveFormat::TarZst => Box::pin(self.tar(TarCompression::Zstd, storage_id, entries)),
+        }
+    }
+
+    /// Every chunk is written to the archive as soon as it's downloaded
+    pub fn zip(
+        self,
+        storage_id: Uuid,
+        entries: Vec<ArchiveEntry>,
+    ) -> impl Stream<Item = CloudBoostclicksResult<Bytes>> + Send {
+        async_stream::try_stream! {
+            let storage_manager = self.storage_manager();
+            let mut zip = ZipWriter::new();
+
+            for entry in entries {
+                yield Bytes::from(zip.start_file(&entry.path, entry.file.updated_at));
+
+                if !entry.path.ends_with('/') {
+                    let content = storage_manager.stream_file(storage_id, &entry.file);
+                    pin_mut!(content);
+                    while let Some(data) = content.next().await {
+                        yield Bytes::from(zip.write(&data?)?);
+                    }
+                }
+
+                yield Bytes::from(zip.finish_file()?);
+            }
+
+            yield Bytes::from(zip.finish());
+        }
+    }
+
+    /// The same as `zip`, sizes of files in headers are taken from db
+    pub fn tar(
+        self,
+        compression: TarCompression,
+        storage_id: Uuid,
+        entries: Vec<ArchiveEntry>,
+    ) -> impl Stream<Item = CloudBoostclicksResult<Bytes>> + Send {
+        async_stream::try_stream! {
+            let storage_manager = self.storage_manager();
+            let mut tar = TarWriter::new(compression)?;
+
+            for entry in entries {
+                let size = entry.file.size.max(0) as u64;
+                yield Bytes::from(tar.start_file(&entry.path, size, entry.file.updated_at)?);
+
+                if !entry.path.ends_with('/') {
+                    let content = storage_manager.stream_file(storage_id, &entry.file);
+                    pin_mut!(content);
+                    while let Some(data) = content.next().await {
+                        yield Bytes::from(tar.write(&data?)?);
+                    }
+                }
+
+                yield Bytes::from(tar.finish_file()?);
+            }
+
+            yield Bytes::from(tar.finish()?);
+        }
+    }
+
+    fn storage_manager(&self) -> StorageManagerService<'_> {
+        StorageManagerService::new(
+            &self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        )
+    }
+}
@@ -30,10 +30,28 @@ impl<'d> AuthService<'d> {
         login_data: LoginSchema,
         config: &Config,
     ) -> CloudBoostclicksResult<(String, Duration)> {
+        let user = self
+            .verify_credentials(&login_data.email, &login_data.password)
+            .await?;
+
+        // generating access token
+        let expire_in = Duration::from_secs(config.access_token_expire_in_secs.into());
+        let token = JWTManager::generate(user, expire_in, &config.secret_key);
+        Ok((token, expire_in))
+
+        // TODO: add generating refresh token
+    }
+
+    /// Checks an email and a password of the account
+    pub async fn verify_credentials(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> CloudBoostclicksResult<AuthUser> {
         // trying to find a user with a given email
         let user = self
             .repo
-            .get_by_email(&login_data.email)
+            .get_by_email(email)
             .await
             .map_err(|_| CloudBoostclicksError::NotAuthenticated)?;
 
@@ -42,15 +60,9 @@ impl<'d> AuthService<'d> {
             .password_hash
             .as_ref()
             .ok_or(CloudBoostclicksError::NotAuthenticated)?;
-        PasswordManager::verify(&login_data.password, password_hash)?;
+        PasswordManager::verify(password, password_hash)?;
 
-        // generating access token
-        let user = AuthUser::new(user.id, user.identifier());
-        let expire_in = Duration::from_secs(config.access_token_expire_in_secs.into());
-        let token = JWTManager::generate(user, expire_in, &config.secret_key);
-        Ok((token, expire_in))
-
-        // TODO: add generating refresh token
+        Ok(AuthUser::new(user.id, user.identifier()))
     }
 
     pub async fn telegram_login(
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{timeout_at, Instant},
+};
+use uuid::Uuid;
+
+use crate::{
+    common::{access::check_access, jwt_manager::AuthUser, notifier::ChangesNotifier},
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::access::AccessType,
+    repositories::{access::AccessRepository, changes::ChangesRepository},
+    schemas::changes::{ChangesQuery, ChangesSchema},
+};
+
+/// Changes returned at once, the rest are fetched with the next request
+const CHANGES_LIMIT: i64 = 1000;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_TIMEOUT_SECS: u64 = 120;
+/// Notifications are lost while the listener reconnects, the journal is checked this often anyway
+const RECHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+pub struct ChangesService<'d> {
+    repo: ChangesRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    notifier: &'d ChangesNotifier,
+}
+
+impl<'d> ChangesService<'d> {
+    pub fn new(db: &'d PgPool, notifier: &'d ChangesNotifier) -> Self {
+        let repo = ChangesRepository::new(db);
+        let access_repo = AccessRepository::new(db);
+        Self {
+            repo,
+            access_repo,
+            notifier,
+        }
+    }
+
+    /// Changes after the cursor, waits up to the timeout for the first one when there are none yet.
+    ///
+    /// A cursor older than the kept journal is expired, the client has to list the whole tree again
+    pub async fn list(
+        &self,
+        storage_id: Uuid,
+        query: ChangesQuery,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ChangesSchema> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        // 1. a new client starts from the current state
+        let (oldest, last_change) = self.repo.bounds(storage_id).await?;
+        let Some(cursor) = query.cursor else {
+            return Ok(ChangesSchema::new(vec![], last_change, false));
+        };
+
+        // 2. checking the cursor isn't expired
+        let missed = cursor < last_change && oldest.is_none_or(|oldest| cursor + 1 < oldest);
+        if cursor < 0 || cursor > last_change || missed {
+            return Err(CloudBoostclicksError::CursorExpired);
+        }
+
+        // 3. waiting for a notification about the storage, subscribing before the first look
+        // so a change made in between isn't missed
+        let timeout = query
+            .timeout
+            .unwrap_or(DEFAULT_TIMEOUT_SECS)
+            .min(MAX_TIMEOUT_SECS);
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        let mut notifications = self.notifier.subscribe();
+        let mut changes = self.repo.list_since(storage_id, cursor, CHANGES_LIMIT + 1).await?;
+        while changes.is_empty() && Instant::now() < deadline {
+            let wake_at = deadline.min(Instant::now() + RECHECK_INTERVAL);
+            match timeout_at(wake_at, notifications.recv()).await {
+                Ok(Ok(changed)) if changed != storage_id => continue,
+                // the storage has changed, missed notifications may have been about it too
+                Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) | Err(_) => (),
+                Ok(Err(RecvError::Closed)) => break,
+            }
+            changes = self.repo.list_since(storage_id, cursor, CHANGES_LIMIT + 1).await?;
+        }
+
+        // 4. one extra change tells whether there are more
+        let has_more = changes.len() as i64 > CHANGES_LIMIT;
+        changes.truncate(CHANGES_LIMIT as usize);
+        let cursor = changes.last().map_or(cursor, |change| change.seq);
+
+        Ok(ChangesSchema::new(changes, cursor, has_more))
+    }
+}
@@ -0,0 +1,43 @@
+use std::pin::Pin;
+
+use axum::body::Bytes;
+use futures::{pin_mut, Stream, StreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{config::Config, errors::CloudBoostclicksResult, schemas::files::FileRangeSchema};
+
+use super::storage_manager::StorageManagerService;
+
+pub type ContentStream = Pin<Box<dyn Stream<Item = CloudBoostclicksResult<Bytes>> + Send>>;
+
+/// Streams byte ranges of files, owns everything it needs like `ArchivesService`
+pub struct ContentService {
+    db: PgPool,
+    config: Config,
+}
+
+impl ContentService {
+    pub fn new(db: PgPool, config: Config) -> Self {
+        Self { db, config }
+    }
+
+    pub fn range(self, storage_id: Uuid, range: &FileRangeSchema) -> ContentStream {
+        let (file_id, version_id) = (range.file_id, range.version_id);
+        let (codec, range) = (range.codec.clone(), range.range.clone());
+
+        Box::pin(async_stream::try_stream! {
+            let storage_manager = StorageManagerService::new(
+                &self.db,
+                &self.config.telegram_api_base_url,
+                &self.config.encryption_secret,
+                self.config.telegram_rate_limit,
+            );
+            let content = storage_manager.stream_content(storage_id, file_id, version_id, codec, range);
+            pin_mut!(content);
+            while let Some(data) = content.next().await {
+                yield Bytes::from(data?);
+            }
+        })
+    }
+}
@@ -0,0 +1,42 @@
+use sqlx::PgPool;
+
+use crate::{
+    common::crypto,
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    repositories::data_keys::DataKeysRepository,
+};
+
+pub struct EncryptionService<'d> {
+    repo: DataKeysRepository<'d>,
+    config: Config,
+}
+
+impl<'d> EncryptionService<'d> {
+    pub fn new(db: &'d PgPool, config: Config) -> Self {
+        Self {
+            repo: DataKeysRepository::new(db),
+            config,
+        }
+    }
+
+    /// Wraps data keys of all chunks by storage keys derived from `ENCRYPTION_SECRET`
+    /// instead of `ENCRYPTION_SECRET_OLD`, chunks themselves stay untouched
+    pub async fn rotate_keys(&self) -> CloudBoostclicksResult<usize> {
+        if self.config.encryption_secret_old.is_empty() {
+            return Err(CloudBoostclicksError::EnvConfigLoadingError(
+                "ENCRYPTION_SECRET_OLD".to_string(),
+            ));
+        }
+
+        self.repo
+            .rewrap_all(|data_key| {
+                crypto::rewrap(
+                    &self.config.encryption_secret_old,
+                    &self.config.encryption_secret,
+                    data_key,
+                )
+            })
+            .await
+    }
+}
@@ -0,0 +1,119 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{access::check_access, jwt_manager::AuthUser},
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{access::AccessType, file_versions::FileVersionInfo},
+    repositories::{
+        access::AccessRepository, file_versions::FileVersionsRepository, files::FilesRepository,
+    },
+    schemas::file_versions::{PruneSummary, PruneVersionsSchema, RestoreVersionSchema},
+};
+
+use super::storage_manager::StorageManagerService;
+
+pub struct FileVersionsService<'d> {
+    db: &'d PgPool,
+    repo: FileVersionsRepository<'d>,
+    files_repo: FilesRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    config: Config,
+}
+
+impl<'d> FileVersionsService<'d> {
+    pub fn new(db: &'d PgPool, config: Config) -> Self {
+        Self {
+            db,
+            repo: FileVersionsRepository::new(db),
+            files_repo: FilesRepository::new(db),
+            access_repo: AccessRepository::new(db),
+            config,
+        }
+    }
+
+    /// Lists all versions of the file, the current one goes first
+    pub async fn list(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<FileVersionInfo>> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        let file = self.files_repo.get_uploaded_file_by_path(path, storage_id).await?;
+        let versions = self.repo.list(file.id).await?;
+
+        let mut result = Vec::with_capacity(versions.len() + 1);
+        result.push(FileVersionInfo::from(file));
+        result.extend(versions.into_iter().map(FileVersionInfo::from));
+        Ok(result)
+    }
+
+    pub async fn restore(
+        &self,
+        in_schema: RestoreVersionSchema,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        // 1. getting the version
+        let file = self
+            .files_repo
+            .get_uploaded_file_by_path(&in_schema.path, storage_id)
+            .await?;
+        if file.version == in_schema.version {
+            return Ok(());
+        }
+        let version = self.repo.get_by_number(file.id, in_schema.version).await?;
+
+        // 2. restoring
+        self.repo.restore(&version, user.id).await
+    }
+
+    pub async fn prune(
+        &self,
+        in_schema: PruneVersionsSchema,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<PruneSummary> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        // 1. validation
+        if in_schema.keep.is_none() && in_schema.older_than_days.is_none() {
+            return Err(CloudBoostclicksError::PruneRuleMissed);
+        }
+        if let Some(path) = &in_schema.path {
+            if path.starts_with('/') || path.contains("//") {
+                return Err(CloudBoostclicksError::InvalidPath);
+            }
+        }
+
+        // 2. deleting versions
+        let (pruned_versions, unreferenced_chunks) = self
+            .repo
+            .prune(
+                storage_id,
+                in_schema.path.as_deref(),
+                in_schema.keep.map(i32::from),
+                in_schema.older_than_days.map(i32::from),
+            )
+            .await?;
+
+        // 3. cleaning up telegram
+        StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        )
+        .delete_chunks(unreferenced_chunks)
+        .await;
+
+        Ok(PruneSummary::new(pruned_versions))
+    }
+}
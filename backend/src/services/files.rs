@@ -1,6 +1,7 @@
-﻿use std::pin::Pin;
+﻿use std::{collections::HashSet, pin::Pin};
 
 use axum::body::Bytes;
+use chrono::NaiveDateTime;
 use sqlx::PgPool;
 use tokio::sync::oneshot;
 use tokio_stream::Stream;
@@ -14,8 +15,12 @@ use crate::{
             ClientData, ClientMessage, ClientSender, DownloadFileData, StorageManagerData,
             UploadFileData,
         },
+        compression::{self, Decompressor},
+        extract::{extract, ExtractedItem},
+        crypto::CLIENT_MAGIC,
+        hashing::ResumableSha256,
+        http::{etag, parse_range},
         jwt_manager::AuthUser,
-        zip::build_zip,
     },
     errors::{CloudBoostclicksError, CloudBoostclicksResult},
     models::{
@@ -23,17 +28,25 @@ use crate::{
         files::{FSElement, File, InFile, SearchFSElement},
     },
     repositories::{
-        access::AccessRepository, files::FilesRepository, storage_workers::StorageWorkersRepository,
-        storages::StoragesRepository,
+        access::AccessRepository, file_versions::FileVersionsRepository, files::FilesRepository,
+        storage_workers::StorageWorkersRepository, storages::StoragesRepository,
+    },
+    schemas::files::{
+        ArchiveEntry, BatchSchema, BatchSummary, ClientEnvelope, CopySchema, CopySummary, DownloadArchiveSchema,
+        DownloadedFileSchema, ExtractSchema, FileRangeSchema,
+        ExtractStatus, ExtractSummary, InChunkSchema, InFileSchema, InFolderSchema, ListQuery,
     },
-    schemas::files::{InFileSchema, InFolderSchema},
 };
 use crate::schemas::files::DeleteSummary;
 use crate::services::storage_manager::StorageManagerService;
 
+/// Pending files replacing others are kept under this prefix until they are uploaded
+pub const REPLACING_PREFIX: &str = ".replacing/";
+
 pub struct FilesService<'d> {
     db: &'d PgPool,
     repo: FilesRepository<'d>,
+    versions_repo: FileVersionsRepository<'d>,
     storage_workers_repo: StorageWorkersRepository<'d>,
     access_repo: AccessRepository<'d>,
     config: Config,
@@ -43,11 +56,13 @@ pub struct FilesService<'d> {
 impl<'d> FilesService<'d> {
     pub fn new(db: &'d PgPool, config: crate::config::Config, tx: ClientSender) -> Self {
         let repo = FilesRepository::new(db);
+        let versions_repo = FileVersionsRepository::new(db);
         let storage_workers_repo = StorageWorkersRepository::new(db);
         let access_repo = AccessRepository::new(db);
         Self {
             db,
             repo,
+            versions_repo,
             access_repo,
             storage_workers_repo,
             config,
@@ -83,7 +98,7 @@ impl<'d> FilesService<'d> {
         } else {
             format!("{}/", in_schema.folder_name)
         };
-        let in_file = InFile::new(path, 0, in_schema.storage_id);
+        let in_file = InFile::new(path, 0, in_schema.storage_id, user.id);
 
         // 3. saving to db
         self.repo.create_folder(in_file).await.map(|_| ())
@@ -106,19 +121,101 @@ impl<'d> FilesService<'d> {
         if !Self::validate_filepath(&in_schema.path) {
             return Err(CloudBoostclicksError::InvalidPath);
         }
+        self.check_client_encryption(
+            in_schema.storage_id,
+            in_schema.envelope.as_ref(),
+            Some(&in_schema.file),
+        )
+        .await?;
+        if let Some(if_match) = &in_schema.if_match {
+            self.repo
+                .check_if_match(&in_schema.path, in_schema.storage_id, if_match)
+                .await?;
+        }
+
+        // 3. uploading a new version if the file exists and the storage keeps history
+        if let Some(file) = self
+            .get_versioned_file(&in_schema.path, in_schema.storage_id)
+            .await?
+        {
+            let version = self
+                .versions_repo
+                .create_pending(file.id, in_schema.size, user.id, in_schema.if_match.as_deref())
+                .await?;
+            if let Some(envelope) = &in_schema.envelope {
+                self.versions_repo.set_envelope(version.id, envelope).await?;
+            }
+            return self
+                ._upload(file.id, Some(version.id), in_schema.file, user)
+                .await;
+        }
+
+        let in_file = InFile::new(
+            in_schema.path,
+            in_schema.size,
+            in_schema.storage_id,
+            user.id,
+        );
+
+        // 4. saving file to db
+        let file = self.repo.create_file(in_file).await?;
+        if let Some(envelope) = &in_schema.envelope {
+            self.repo.set_envelope(file.id, envelope).await?;
+        }
 
-        let in_file = InFile::new(in_schema.path, in_schema.size, in_schema.storage_id);
+        self._upload(file.id, None, in_schema.file, user).await
+    }
+
+    /// Creates or replaces the file, returns whether it was created.
+    ///
+    /// With `If-Match` the file is replaced only if it's still the same
+    pub async fn replace(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        data: Bytes,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<bool> {
+        let existed = self
+            .repo
+            .get_uploaded_file_by_path(path, storage_id)
+            .await
+            .is_ok();
+
+        // storages with versioning store a new version of an existing file
+        let in_schema = InFileSchema::new(storage_id, path.to_string(), data.clone(), None)
+            .with_if_match(if_match.map(str::to_string));
+        match self.upload_to(in_schema, user).await {
+            Err(CloudBoostclicksError::AlreadyExists(_)) => (),
+            result => return result.map(|_| !existed),
+        }
 
-        // 3. saving file to db
+        // storages without versioning keep the replaced file in the trash, the new content
+        // is uploaded aside and takes its place only once it's in telegram
+        let pending_path = format!("{REPLACING_PREFIX}{}/{path}", Uuid::new_v4());
+        let in_file = InFile::new(pending_path, data.len() as i64, storage_id, user.id);
         let file = self.repo.create_file(in_file).await?;
 
-        self._upload(file, in_schema.file, user).await
+        let result = match self.send_to_manager(file.id, None, data, user).await {
+            Ok(()) => self.repo.put_in_place(file.id, path, user.id, if_match).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("{e}");
+            let _ = self.repo.delete_with_folders(file.id).await;
+            return Err(e);
+        }
+
+        Ok(false)
     }
 
     pub async fn upload_anyway(
         &self,
         in_file: InFile,
         file_data: Bytes,
+        envelope: Option<ClientEnvelope>,
+        if_match: Option<&str>,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<()> {
         // 0. checking access
@@ -132,20 +229,100 @@ impl<'d> FilesService<'d> {
 
         // 1. check whether storage got workers
         Self::check_storage_workers(&self, in_file.storage_id).await?;
+        self.check_client_encryption(in_file.storage_id, envelope.as_ref(), Some(&file_data))
+            .await?;
+
+        // 2. the client may replace only the content it has seen
+        if let Some(if_match) = if_match {
+            self.repo
+                .check_if_match(&in_file.path, in_file.storage_id, if_match)
+                .await?;
+        }
+
+        self.store_anyway(in_file, file_data, envelope, if_match, user).await
+    }
+
+    /// Uploads a new version if the file exists and the storage keeps history,
+    /// otherwise the file is saved under a free name
+    async fn store_anyway(
+        &self,
+        in_file: InFile,
+        file_data: Bytes,
+        envelope: Option<ClientEnvelope>,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        // 1. uploading a new version
+        if let Some(file) = self
+            .get_versioned_file(&in_file.path, in_file.storage_id)
+            .await?
+        {
+            let version = self
+                .versions_repo
+                .create_pending(file.id, in_file.size, user.id, if_match)
+                .await?;
+            if let Some(envelope) = &envelope {
+                self.versions_repo.set_envelope(version.id, envelope).await?;
+            }
+            return self._upload(file.id, Some(version.id), file_data, user).await;
+        }
 
         // 2. saving file in db
         let file = self.repo.create_file_anyway(in_file).await?;
+        if let Some(envelope) = &envelope {
+            self.repo.set_envelope(file.id, envelope).await?;
+        }
+
+        self._upload(file.id, None, file_data, user).await
+    }
+
+    /// Uploads the content of a new file or of a pending version of an existing one
+    async fn _upload(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        file_data: Bytes,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        let result = match self.send_to_manager(file_id, version_id, file_data, user).await {
+            Ok(()) => {
+                tracing::debug!("file loaded successfully");
+
+                // 4. setting file as uploaded
+                self.finish_upload(file_id, version_id).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("{e}");
+
+            // fallback logic: deleting file or its unfinished version
+            let _ = match version_id {
+                Some(version_id) => self.versions_repo.delete(version_id).await,
+                None => self.repo.delete_with_folders(file_id).await,
+            };
 
-        self._upload(file, file_data, user).await
+            return Err(e);
+        };
+
+        Ok(())
     }
 
-    async fn _upload(&self, file: File, file_data: Bytes, user: &AuthUser) -> CloudBoostclicksResult<()> {
+    /// Hands the content to the storage manager and waits until it's in telegram
+    async fn send_to_manager(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        file_data: Bytes,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
         // 2. sending file to storage manager
         let (resp_tx, resp_rx) = oneshot::channel();
 
         let message = {
             let upload_file_data = UploadFileData {
-                file_id: file.id,
+                file_id,
+                version_id,
                 user_id: user.id,
                 file_data: file_data.as_ref().into(),
             };
@@ -159,38 +336,122 @@ impl<'d> FilesService<'d> {
         let _ = self.tx.send(message).await;
 
         // 3. waiting for a storage manager result
-        let message_back = match resp_rx.await.unwrap().data {
+        match resp_rx.await.unwrap().data {
             StorageManagerData::UploadFile(r) => r,
             _ => unimplemented!(),
+        }
+    }
+
+    /// Unpacks the archive into the folder entry by entry, failed entries don't stop the rest
+    pub async fn extract(
+        &self,
+        in_schema: ExtractSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ExtractSummary> {
+        // 0. checking access
+        check_access(
+            &self.access_repo,
+            user.id,
+            in_schema.storage_id,
+            &AccessType::W,
+        )
+        .await?;
+
+        // 1. checks of the storage and the target folder
+        self.check_storage_workers(in_schema.storage_id).await?;
+        self.check_plaintext(in_schema.storage_id).await?;
+        if !Self::validate_path(&in_schema.path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+        let target = in_schema.path.trim_end_matches('/');
+        let prefix = if target.is_empty() {
+            "".to_string()
+        } else {
+            format!("{target}/")
         };
-        if let Err(e) = message_back.and({
-            tracing::debug!("file loaded successfully");
 
-            // 4. setting file as uploaded
-            self.repo.set_as_uploaded(file.id).await
-        }) {
-            tracing::error!("{e}");
+        // 2. saving entries while the archive is being unpacked
+        let mut created_folders = HashSet::new();
+        self.create_folders(in_schema.storage_id, &prefix, &mut created_folders, user)
+            .await?;
 
-            // fallback logic: deleting file
-            let _ = self.repo.delete_with_folders(file.id).await;
+        let mut summary = ExtractSummary::default();
+        let mut items = extract(in_schema.format, in_schema.archive);
+        while let Some(item) = items.recv().await {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    summary.error = Some(e.to_string());
+                    break;
+                }
+            };
 
-            return Err(e);
-        };
+            let (entry_path, result) = match item {
+                ExtractedItem::Skipped(entry_path, reason) => {
+                    summary.push(entry_path, ExtractStatus::Skipped, Some(reason.to_string()));
+                    continue;
+                }
+                ExtractedItem::Folder(entry_path) => {
+                    let path = format!("{prefix}{entry_path}");
+                    let result = self
+                        .create_folders(in_schema.storage_id, &path, &mut created_folders, user)
+                        .await;
+                    (entry_path, result)
+                }
+                ExtractedItem::File(entry_path, data) => {
+                    let path = format!("{prefix}{entry_path}");
+                    let parent = path.rsplit_once('/').map(|(parent, _)| format!("{parent}/"));
+                    let result = match parent {
+                        Some(parent) => {
+                            self.create_folders(
+                                in_schema.storage_id,
+                                &parent,
+                                &mut created_folders,
+                                user,
+                            )
+                            .await
+                        }
+                        None => Ok(()),
+                    };
+                    let result = match result {
+                        Ok(()) => {
+                            let size = data.len() as i64;
+                            let in_file = InFile::new(path, size, in_schema.storage_id, user.id);
+                            self.store_anyway(in_file, Bytes::from(data), None, None, user)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    (entry_path, result)
+                }
+            };
 
-        Ok(())
+            match result {
+                Ok(()) => summary.push(entry_path, ExtractStatus::Extracted, None),
+                Err(e) => summary.push(entry_path, ExtractStatus::Failed, Some(e.to_string())),
+            }
+        }
+
+        Ok(summary)
     }
 
     pub async fn upload_chunked(
         &self,
-        storage_id: Uuid,
-        path: String,
-        size: Option<i64>,
-        file_id: Option<Uuid>,
-        chunk_index: usize,
-        total_chunks: usize,
-        chunk_data: Bytes,
+        in_schema: InChunkSchema,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<Uuid> {
+        let InChunkSchema {
+            storage_id,
+            path,
+            size,
+            file_id,
+            chunk_index,
+            total_chunks,
+            chunk: chunk_data,
+            envelope,
+            if_match,
+        } = in_schema;
+
         // check access
         check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
         // workers check
@@ -199,32 +460,132 @@ impl<'d> FilesService<'d> {
         if !Self::validate_filepath(&path) {
             return Err(CloudBoostclicksError::InvalidPath);
         }
+        // chunks are hashed as they arrive, so they have to come in order
+        if file_id.is_none() && chunk_index != 0 {
+            return Err(CloudBoostclicksError::UnexpectedChunk(0));
+        }
+        // the envelope and `If-Match` come with the first request only,
+        // the stream header is in chunk 0
+        if file_id.is_none() {
+            let head = (chunk_index == 0).then_some(chunk_data.as_ref());
+            self.check_client_encryption(storage_id, envelope.as_ref(), head)
+                .await?;
+            if let Some(if_match) = &if_match {
+                self.repo.check_if_match(&path, storage_id, if_match).await?;
+            }
+        }
 
-        // create file once
-        let file_id = match file_id {
-            Some(id) => id,
+        // create file (or a new version of the existing one) once,
+        // then the returned id of the version is used as the file id
+        let storage = StoragesRepository::new(self.db).get_by_id(storage_id).await?;
+        let (file_id, version_id, codec) = match file_id {
+            Some(id) => match self.versions_repo.get_pending(id, storage_id).await? {
+                Some(version) => (version.file_id, Some(version.id), version.codec),
+                None => (id, None, self.repo.get_by_id(id).await?.codec),
+            },
             None => {
                 let file_size = size.unwrap_or(chunk_data.len() as i64);
-                let in_file = InFile::new(path.clone(), file_size, storage_id);
-                self.repo.create_file_anyway(in_file).await?.id
+                // every chunk is compressed on its own, whether it gets smaller or not
+                let codec = (storage.settings.compression
+                    && !storage.settings.client_encrypted
+                    && compression::is_compressible(&path))
+                .then_some(compression::ZSTD);
+                match self.get_versioned_file(&path, storage_id).await? {
+                    Some(file) => {
+                        let version = self
+                            .versions_repo
+                            .create_pending(file.id, file_size, user.id, if_match.as_deref())
+                            .await?;
+                        if let Some(envelope) = &envelope {
+                            self.versions_repo.set_envelope(version.id, envelope).await?;
+                        }
+                        self.versions_repo.set_codec(version.id, codec).await?;
+                        (file.id, Some(version.id), codec.map(str::to_string))
+                    }
+                    None => {
+                        let in_file = InFile::new(path.clone(), file_size, storage_id, user.id);
+                        let file = self.repo.create_file_anyway(in_file).await?;
+                        if let Some(envelope) = &envelope {
+                            self.repo.set_envelope(file.id, envelope).await?;
+                        }
+                        self.repo.set_codec(file.id, codec).await?;
+                        (file.id, None, codec.map(str::to_string))
+                    }
+                }
             }
         };
 
+        // a chunk sent again because its response was lost is saved already
+        let (uploaded, hash_state) = self.repo.upload_progress(file_id, version_id).await?;
+        if (chunk_index as i64) < uploaded {
+            return Ok(version_id.unwrap_or(file_id));
+        }
+        if chunk_index as i64 > uploaded {
+            return Err(CloudBoostclicksError::UnexpectedChunk(uploaded as usize));
+        }
+        let mut hasher = match hash_state {
+            Some(state) => {
+                ResumableSha256::from_bytes(&state).ok_or(CloudBoostclicksError::Unknown)?
+            }
+            None => ResumableSha256::new(),
+        };
+        hasher.update(&chunk_data);
+
         // upload chunk directly to Telegram via StorageManagerService
-        let storage = StoragesRepository::new(self.db).get_by_id(storage_id).await?;
-        let storage_manager =
-            StorageManagerService::new(self.db, &self.config.telegram_api_base_url, self.config.telegram_rate_limit);
+        let compressed = codec
+            .as_deref()
+            .map(|codec| compression::compress_chunk(codec, &chunk_data))
+            .transpose()?;
+        let content = compressed.as_deref().unwrap_or(&chunk_data);
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let data_key = storage_manager
+            .data_key_for(&storage, file_id, version_id)
+            .await?;
         let chunk = storage_manager
-            .upload_chunk(storage.id, storage.chat_id, file_id, chunk_index, &chunk_data)
+            .upload_chunk(&storage, file_id, chunk_index, content, data_key.as_ref())
             .await?;
 
-        self.repo.create_chunks_batch(vec![chunk]).await?;
+        // parity of a group is computed as soon as its last chunk comes,
+        // the last group of the file may be shorter
+        let group_size = storage.settings.erasure_data_chunks as usize;
+        let parity_chunks = if storage.settings.erasure_parity_chunks > 0
+            && ((chunk_index + 1) % group_size == 0 || chunk_index + 1 == total_chunks)
+        {
+            storage_manager
+                .upload_closed_group_parity(
+                    &storage,
+                    file_id,
+                    version_id,
+                    chunk_index,
+                    content,
+                    data_key.as_ref(),
+                )
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        self.repo
+            .push_chunk(chunk.with_version(version_id), parity_chunks, &hasher.to_bytes())
+            .await?;
 
         if chunk_index + 1 == total_chunks {
-            self.repo.set_as_uploaded(file_id).await?;
+            if let Err(e) = self.finish_upload(file_id, version_id).await {
+                // the upload can't be finished anymore, e.g. the file was changed meanwhile
+                let _ = match version_id {
+                    Some(version_id) => self.versions_repo.delete(version_id).await,
+                    None => self.repo.delete_with_folders(file_id).await,
+                };
+                return Err(e);
+            }
         }
 
-        Ok(file_id)
+        Ok(version_id.unwrap_or(file_id))
     }
 
     async fn check_storage_workers(&self, storage_id: Uuid) -> CloudBoostclicksResult<()> {
@@ -243,8 +604,9 @@ impl<'d> FilesService<'d> {
         &self,
         path: &str,
         storage_id: Uuid,
+        version: Option<i32>,
         user: &AuthUser,
-    ) -> CloudBoostclicksResult<Vec<u8>> {
+    ) -> CloudBoostclicksResult<DownloadedFileSchema> {
         // 0. checking access
         check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
 
@@ -256,7 +618,157 @@ impl<'d> FilesService<'d> {
         // 2. getting file by path
         let file = self.repo.get_file_by_path(path, storage_id).await?;
 
-        self.download_file_by_id(file.id, storage_id, user.id).await
+        // 3. picking a previous version if asked
+        let (version_id, number, sha256, encrypted_key, client_metadata, last_modified) = match version {
+            Some(version) if version != file.version => {
+                let version = self.versions_repo.get_by_number(file.id, version).await?;
+                (
+                    Some(version.id),
+                    version.version.unwrap_or_default(),
+                    version.sha256,
+                    version.encrypted_key,
+                    version.client_metadata,
+                    version.created_at,
+                )
+            }
+            _ => (
+                None,
+                file.version,
+                file.sha256,
+                file.encrypted_key,
+                file.client_metadata,
+                file.updated_at,
+            ),
+        };
+        let etag = etag(sha256.as_deref(), file.id, number);
+
+        let data = self
+            .download_file_by_id(file.id, version_id, storage_id, user.id)
+            .await?;
+        Ok(DownloadedFileSchema::new(data, sha256)
+            .with_validators(etag, last_modified)
+            .with_envelope(encrypted_key, client_metadata))
+    }
+
+    /// A byte range of the file or of its version given by the `Range` header.
+    ///
+    /// `None` means the whole content, which is rather downloaded and verified as a whole
+    pub async fn download_range(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        version: Option<i32>,
+        range: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Option<FileRangeSchema>> {
+        let content = self.content(path, storage_id, version, user).await?;
+        let len = content.len;
+
+        let range = parse_range(range, len as usize)
+            .ok_or(CloudBoostclicksError::RangeNotSatisfiable(len))?;
+        if range.len() as u64 == len {
+            return Ok(None);
+        }
+
+        Ok(Some(FileRangeSchema {
+            range: range.start as u64..range.end as u64,
+            ..content
+        }))
+    }
+
+    /// The whole content of the file or of its version, to be streamed by `ContentService`
+    pub async fn content(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        version: Option<i32>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<FileRangeSchema> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        // 1. path validation
+        if !Self::validate_path(path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // 2. getting file by path and picking a previous version if asked
+        let file = self.repo.get_file_by_path(path, storage_id).await?;
+        let (version_id, number, size, codec, sha256, envelope, last_modified) = match version {
+            Some(version) if version != file.version => {
+                let version = self.versions_repo.get_by_number(file.id, version).await?;
+                (
+                    Some(version.id),
+                    version.version.unwrap_or_default(),
+                    version.size,
+                    version.codec,
+                    version.sha256,
+                    ClientEnvelope::from_fields(version.encrypted_key, version.client_metadata),
+                    version.created_at,
+                )
+            }
+            _ => (
+                None,
+                file.version,
+                file.size,
+                file.codec,
+                file.sha256,
+                ClientEnvelope::from_fields(file.encrypted_key, file.client_metadata),
+                file.updated_at,
+            ),
+        };
+
+        // 3. the length is the sum of chunk sizes unless chunks are compressed
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let chunks = storage_manager.list_chunks(file.id, version_id).await?;
+        let chunk_sizes: Option<Vec<i32>> = chunks.iter().map(|chunk| chunk.size).collect();
+        let len = match (&codec, chunk_sizes) {
+            (None, Some(sizes)) => sizes.into_iter().map(|size| size as u64).sum(),
+            _ => size.max(0) as u64,
+        };
+
+        Ok(FileRangeSchema {
+            file_id: file.id,
+            version_id,
+            codec,
+            range: 0..len,
+            len,
+            etag: etag(sha256.as_deref(), file.id, number),
+            last_modified,
+            envelope,
+        })
+    }
+
+    /// ETag and modification date of the file or of its version, so a client having
+    /// a fresh copy doesn't download it again
+    pub async fn validators(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+        version: Option<i32>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<(String, NaiveDateTime)> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        if !Self::validate_path(path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        let file = self.repo.get_file_by_path(path, storage_id).await?;
+        match version {
+            Some(version) if version != file.version => {
+                let version = self.versions_repo.get_by_number(file.id, version).await?;
+                let number = version.version.unwrap_or_default();
+                let etag = etag(version.sha256.as_deref(), file.id, number);
+                Ok((etag, version.created_at))
+            }
+            _ => Ok((file.etag(), file.updated_at)),
+        }
     }
 
     pub async fn download_stream(
@@ -274,9 +786,14 @@ impl<'d> FilesService<'d> {
         let file = self.repo.get_file_by_path(path, storage_id).await?;
         let mut chunks = self.repo.list_chunks_of_file(file.id).await?;
         chunks.sort_by_key(|c| c.position);
+        let mut decompressor = file.codec.as_deref().map(Decompressor::new).transpose()?;
 
-        let storage_manager =
-            StorageManagerService::new(self.db, &self.config.telegram_api_base_url, self.config.telegram_rate_limit);
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
 
         let stream = async_stream::try_stream! {
             for chunk in chunks {
@@ -284,6 +801,10 @@ impl<'d> FilesService<'d> {
                     .download_chunk(storage_id, chunk)
                     .await?
                     .data;
+                let data = match decompressor.as_mut() {
+                    Some(decompressor) => decompressor.feed(&data)?,
+                    None => data,
+                };
                 yield Bytes::from(data);
             }
         };
@@ -295,11 +816,12 @@ impl<'d> FilesService<'d> {
         self,
         storage_id: Uuid,
         path: &str,
+        list_query: &ListQuery,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<Vec<FSElement>> {
         check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
 
-        self.repo.list_dir(storage_id, path).await
+        self.repo.list_dir(storage_id, path, list_query).await
     }
 
     pub async fn download_folder(
@@ -307,8 +829,9 @@ impl<'d> FilesService<'d> {
         path: &str,
         storage_id: Uuid,
         user: &AuthUser,
-    ) -> CloudBoostclicksResult<Vec<u8>> {
+    ) -> CloudBoostclicksResult<Vec<ArchiveEntry>> {
         check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+        self.check_plaintext(storage_id).await?;
 
         if !Self::validate_path(path) {
             return Err(CloudBoostclicksError::InvalidPath);
@@ -323,29 +846,60 @@ impl<'d> FilesService<'d> {
 
         let files = self
             .repo
-            .list_files_in_folder(storage_id, &prefix)
+            .list_folder_content(storage_id, &prefix)
             .await?;
 
-        if files.is_empty() {
+        if files.is_empty() && !self.repo.folder_exists(storage_id, &prefix).await? {
             return Err(CloudBoostclicksError::DoesNotExist("папка".to_string()));
         }
 
-        let mut zipped_files = Vec::with_capacity(files.len());
-        for file in files {
-            let data = self
-                .download_file_by_id(file.id, storage_id, user.id)
-                .await?;
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                let path = if prefix.is_empty() {
+                    file.path.clone()
+                } else {
+                    file.path.strip_prefix(&prefix).unwrap_or(&file.path).to_string()
+                };
+                ArchiveEntry { path, file }
+            })
+            .collect())
+    }
 
-            let rel_path = if prefix.is_empty() {
-                file.path.clone()
-            } else {
-                file.path.strip_prefix(&prefix).unwrap_or(&file.path).to_string()
-            };
+    /// Files and folders picked in one folder, their paths in the archive are relative to it.
+    ///
+    /// Items from different folders are put relative to the closest common one
+    pub async fn download_archive(
+        &self,
+        in_schema: &DownloadArchiveSchema,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<ArchiveEntry>> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+        self.check_plaintext(storage_id).await?;
 
-            zipped_files.push((rel_path, data));
+        // 1. path validation
+        if in_schema.paths.is_empty() || !in_schema.paths.iter().all(|p| Self::validate_path(p)) {
+            return Err(CloudBoostclicksError::InvalidPath);
         }
+        let base = Self::common_folder(&in_schema.paths);
+
+        // 2. collecting files of every item, items may overlap
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        for path in &in_schema.paths {
+            let path = path.trim_end_matches('/');
+            for file in self.collect_item(storage_id, path).await? {
+                if seen.insert(file.id) {
+                    let path = file.path.strip_prefix(&base).unwrap_or(&file.path).to_string();
+                    entries.push(ArchiveEntry { path, file });
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
 
-        build_zip(zipped_files)
+        Ok(entries)
     }
 
     pub async fn search(
@@ -353,11 +907,15 @@ impl<'d> FilesService<'d> {
         storage_id: Uuid,
         path: &str,
         search_path: &str,
+        list_query: &ListQuery,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<Vec<SearchFSElement>> {
         check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+        self.check_plaintext(storage_id).await?;
 
-        self.repo.search(search_path, path, storage_id).await
+        self.repo
+            .search(search_path, path, storage_id, list_query)
+            .await
     }
 
     pub async fn rename(
@@ -365,6 +923,7 @@ impl<'d> FilesService<'d> {
         old_path: &str,
         new_path: &str,
         storage_id: Uuid,
+        if_match: Option<&str>,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<()> {
         // 0. checking access
@@ -375,14 +934,56 @@ impl<'d> FilesService<'d> {
             return Err(CloudBoostclicksError::InvalidPath);
         }
 
-        // 2. renaming file
-        self.repo.update_path(old_path, new_path, storage_id).await
+        // 2. moving a file or a folder
+        self.repo
+            .move_to(old_path, storage_id, new_path, user.id, if_match)
+            .await
+    }
+
+    /// Moves or copies a file or a folder over the destination, the replaced one goes to the trash.
+    ///
+    /// Returns whether there was nothing to replace
+    #[allow(clippy::too_many_arguments)]
+    pub async fn transfer(
+        &self,
+        path: &str,
+        destination_path: &str,
+        storage_id: Uuid,
+        is_move: bool,
+        overwrite: bool,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<bool> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        // 1. path validation
+        if !Self::validate_path(path)
+            || !Self::validate_path(destination_path)
+            || destination_path.trim_end_matches('/').is_empty()
+        {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+        let is_file = match self.repo.get_file_by_path(path, storage_id).await {
+            Ok(_) => true,
+            Err(CloudBoostclicksError::DoesNotExist(_)) => false,
+            Err(e) => return Err(e),
+        };
+        if is_file && !Self::validate_filepath(destination_path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // 2. replacing in one transaction
+        self.repo
+            .transfer(path, storage_id, destination_path, is_move, overwrite, user.id, if_match)
+            .await
     }
 
     pub async fn delete(
         &self,
         path: &str,
         storage_id: Uuid,
+        if_match: Option<&str>,
         user: &AuthUser,
     ) -> CloudBoostclicksResult<DeleteSummary> {
         // 0. checking access
@@ -393,15 +994,99 @@ impl<'d> FilesService<'d> {
             return Err(CloudBoostclicksError::InvalidPath);
         }
 
-        // 2. deleting file
-        self.repo.delete(path, storage_id).await
+        // 2. moving file to the trash
+        self.repo.delete(path, storage_id, user.id, if_match).await
+    }
+
+    pub async fn copy(
+        &self,
+        in_schema: CopySchema,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<CopySummary> {
+        let destination_storage_id = in_schema.destination_storage_id.unwrap_or(storage_id);
+
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+        check_access(
+            &self.access_repo,
+            user.id,
+            destination_storage_id,
+            &AccessType::W,
+        )
+        .await?;
+
+        // 1. path validation
+        if !Self::validate_path(&in_schema.path)
+            || !Self::validate_path(&in_schema.destination_path)
+            || in_schema.destination_path.trim_end_matches('/').is_empty()
+        {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // a single file needs a file path, a folder one would hide it
+        let is_file = match self.repo.get_file_by_path(&in_schema.path, storage_id).await {
+            Ok(_) => true,
+            Err(CloudBoostclicksError::DoesNotExist(_)) => false,
+            Err(e) => return Err(e),
+        };
+        if is_file && !Self::validate_filepath(&in_schema.destination_path) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // 2. encrypted files can't be mixed with plain ones
+        if destination_storage_id != storage_id {
+            let storages_repo = StoragesRepository::new(self.db);
+            let source = storages_repo.get_by_id(storage_id).await?;
+            let destination = storages_repo.get_by_id(destination_storage_id).await?;
+            if source.settings.client_encrypted != destination.settings.client_encrypted {
+                return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+            }
+        }
+
+        // 3. copying
+        self.repo
+            .copy(
+                &in_schema.path,
+                storage_id,
+                &in_schema.destination_path,
+                destination_storage_id,
+                user.id,
+                in_schema.overwrite,
+            )
+            .await
+    }
+
+    pub async fn batch(
+        &self,
+        in_schema: BatchSchema,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<BatchSummary> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        // 1. path validation
+        let is_valid = in_schema
+            .operations
+            .iter()
+            .flat_map(|operation| operation.paths())
+            .all(Self::validate_path);
+        if !is_valid {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        // 2. running operations
+        self.repo
+            .batch(in_schema.operations, storage_id, user.id, in_schema.dry_run)
+            .await
     }
 
     /////////////////////////////////////////////////////////////////////
     ////    Helpers
     /////////////////////////////////////////////////////////////////////
 
-    fn validate_filepath(path: &str) -> bool {
+    pub fn validate_filepath(path: &str) -> bool {
         Self::validate_path(path) && !path.ends_with(r"/")
     }
 
@@ -409,9 +1094,159 @@ impl<'d> FilesService<'d> {
         !path.starts_with(r"/") && !path.contains(r"//")
     }
 
+    /// Returns the existing uploaded file if the storage keeps versions of files
+    async fn get_versioned_file(
+        &self,
+        path: &str,
+        storage_id: Uuid,
+    ) -> CloudBoostclicksResult<Option<File>> {
+        let storage = StoragesRepository::new(self.db).get_by_id(storage_id).await?;
+        if !storage.settings.versioning {
+            return Ok(None);
+        }
+
+        match self.repo.get_uploaded_file_by_path(path, storage_id).await {
+            Ok(file) => Ok(Some(file)),
+            Err(CloudBoostclicksError::DoesNotExist(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The file itself or everything inside the folder, paths are without the trailing slash
+    async fn collect_item(&self, storage_id: Uuid, path: &str) -> CloudBoostclicksResult<Vec<File>> {
+        if !path.is_empty() {
+            match self.repo.get_uploaded_file_by_path(path, storage_id).await {
+                Ok(file) => return Ok(vec![file]),
+                Err(CloudBoostclicksError::DoesNotExist(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let prefix = if path.is_empty() {
+            "".to_string()
+        } else {
+            format!("{path}/")
+        };
+        let mut files = self.repo.list_folder_content(storage_id, &prefix).await?;
+        if files.is_empty() {
+            // an empty folder still gets into the archive if it has its own row
+            match self.repo.get_uploaded_file_by_path(&prefix, storage_id).await {
+                Ok(folder) => files.push(folder),
+                Err(CloudBoostclicksError::DoesNotExist(_)) => {
+                    return Err(CloudBoostclicksError::DoesNotExist(format!("\"{path}\"")))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// The deepest folder containing every path, with the trailing slash unless it's the root
+    fn common_folder(paths: &[String]) -> String {
+        let parents: Vec<Vec<&str>> = paths
+            .iter()
+            .map(|path| {
+                let mut parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+                parts.pop();
+                parts
+            })
+            .collect();
+
+        let mut common = parents.first().cloned().unwrap_or_default();
+        for parent in &parents[1..] {
+            let len = common
+                .iter()
+                .zip(parent)
+                .take_while(|(a, b)| a == b)
+                .count();
+            common.truncate(len);
+        }
+
+        common.into_iter().map(|part| format!("{part}/")).collect()
+    }
+
+    /// Creates the folder and every folder above it unless they exist
+    async fn create_folders(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        created: &mut HashSet<String>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        let mut folder = String::new();
+        for part in path.split_terminator('/') {
+            folder.push_str(part);
+            folder.push('/');
+            if created.contains(&folder) {
+                continue;
+            }
+
+            let in_file = InFile::new(folder.clone(), 0, storage_id, user.id);
+            match self.repo.create_folder(in_file).await {
+                Ok(_) | Err(CloudBoostclicksError::AlreadyExists(_)) => {
+                    created.insert(folder.clone());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Client encrypted storages accept only files with an envelope and the stream header,
+    /// others don't accept envelopes at all
+    async fn check_client_encryption(
+        &self,
+        storage_id: Uuid,
+        envelope: Option<&ClientEnvelope>,
+        head: Option<&[u8]>,
+    ) -> CloudBoostclicksResult<()> {
+        let storage = StoragesRepository::new(self.db).get_by_id(storage_id).await?;
+        if !storage.settings.client_encrypted {
+            return match envelope {
+                Some(_) => Err(CloudBoostclicksError::NotClientEncrypted),
+                None => Ok(()),
+            };
+        }
+
+        let has_header = head.is_none_or(|head| head.starts_with(CLIENT_MAGIC));
+        if envelope.is_none() || !has_header {
+            return Err(CloudBoostclicksError::ClientEncryptionRequired);
+        }
+        Ok(())
+    }
+
+    /// Features reading the content or the names of files are off in client encrypted storages
+    async fn check_plaintext(&self, storage_id: Uuid) -> CloudBoostclicksResult<()> {
+        let storage = StoragesRepository::new(self.db).get_by_id(storage_id).await?;
+        if storage.settings.client_encrypted {
+            return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+        }
+        Ok(())
+    }
+
+    async fn finish_upload(&self, file_id: Uuid, version_id: Option<Uuid>) -> CloudBoostclicksResult<()> {
+        // files uploaded by chunks were hashed as the chunks came,
+        // the hasher counts their size as well since it may be unknown when they start
+        let (_, hash_state) = self.repo.upload_progress(file_id, version_id).await?;
+        let hasher = hash_state.as_deref().and_then(ResumableSha256::from_bytes);
+        let size = hasher.as_ref().map(|hasher| hasher.len() as i64);
+        let sha256 = hasher.map(ResumableSha256::finalize);
+
+        match version_id {
+            Some(version_id) => {
+                self.versions_repo
+                    .promote(version_id, sha256.as_deref(), size)
+                    .await
+            }
+            None => self.repo.set_as_uploaded(file_id, sha256.as_deref(), size).await,
+        }
+    }
+
     async fn download_file_by_id(
         &self,
         file_id: Uuid,
+        version_id: Option<Uuid>,
         storage_id: Uuid,
         user_id: Uuid,
     ) -> CloudBoostclicksResult<Vec<u8>> {
@@ -420,6 +1255,7 @@ impl<'d> FilesService<'d> {
         let message = {
             let download_file_data = DownloadFileData {
                 file_id,
+                version_id,
                 storage_id,
                 user_id,
             };
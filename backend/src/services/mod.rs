@@ -1,9 +1,24 @@
-﻿pub mod auth;
+﻿pub mod access_keys;
+pub mod app_passwords;
+pub mod archives;
+pub mod auth;
+pub mod changes;
+pub mod content;
+pub mod encryption;
+pub mod file_versions;
 pub mod files;
+pub mod s3;
+pub mod scrub;
+#[cfg(feature = "sftp")]
+pub mod sftp;
 pub mod shares;
+pub mod ssh_keys;
 pub mod storage_manager;
 pub mod storage_workers;
 pub mod storage_workers_scheduler;
 pub mod storages;
+pub mod transfers;
+pub mod trash;
 pub mod users;
+pub mod webdav;
 
@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::body::Bytes;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        access::check_access, channels::ClientSender, compression, hashing::sha256_hex,
+        jwt_manager::AuthUser,
+    },
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{
+        access::AccessType,
+        file_chunks::FileChunk,
+        files::{File, InFile},
+        upload_parts::UploadPart,
+    },
+    repositories::{
+        access::AccessRepository, files::FilesRepository, storages::StoragesRepository,
+        upload_parts::UploadPartsRepository,
+    },
+    schemas::{
+        files::{CopySchema, FileRangeSchema, InFolderSchema, ListQuery},
+        s3::{CompletedPart, ListObjectsParams, ListObjectsResult, ObjectEntry},
+    },
+    services::{files::FilesService, storage_manager::StorageManagerService},
+};
+
+/// Pending files of multipart uploads live under this folder until they are completed
+pub const MULTIPART_PREFIX: &str = ".multipart/";
+
+const MAX_PART_NUMBER: i32 = 10000;
+
+/// Maps S3 operations onto files, a bucket is a storage and its id is the bucket name
+pub struct S3Service<'d> {
+    db: &'d PgPool,
+    repo: FilesRepository<'d>,
+    parts_repo: UploadPartsRepository<'d>,
+    storages_repo: StoragesRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    files_service: FilesService<'d>,
+    config: Config,
+}
+
+impl<'d> S3Service<'d> {
+    pub fn new(db: &'d PgPool, config: Config, tx: ClientSender) -> Self {
+        let repo = FilesRepository::new(db);
+        let parts_repo = UploadPartsRepository::new(db);
+        let storages_repo = StoragesRepository::new(db);
+        let access_repo = AccessRepository::new(db);
+        let files_service = FilesService::new(db, config.clone(), tx);
+        Self {
+            db,
+            repo,
+            parts_repo,
+            storages_repo,
+            access_repo,
+            files_service,
+            config,
+        }
+    }
+
+    pub async fn list_buckets(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<Uuid>> {
+        let storages = self.storages_repo.list_by_user_id(user.id).await?;
+        Ok(storages.into_iter().map(|storage| storage.id).collect())
+    }
+
+    pub async fn head_bucket(&self, bucket: Uuid, user: &AuthUser) -> CloudBoostclicksResult<()> {
+        check_access(&self.access_repo, user.id, bucket, &AccessType::R).await
+    }
+
+    /// Keys are grouped into common prefixes by the delimiter.
+    ///
+    /// The `/` delimiter lists a single folder, other delimiters group the whole content under the prefix
+    pub async fn list_objects(
+        &self,
+        bucket: Uuid,
+        params: &ListObjectsParams,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ListObjectsResult> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, bucket, &AccessType::R).await?;
+
+        // 1. collecting keys and common prefixes, the latter have no entry
+        let prefix = params.prefix.as_str();
+        let folder = prefix.rsplit_once('/').map(|(folder, _)| folder).unwrap_or_default();
+        let mut keys: BTreeMap<String, Option<ObjectEntry>> = BTreeMap::new();
+
+        match params.delimiter.as_deref() {
+            Some("/") => {
+                let elements = self
+                    .repo
+                    .list_dir(bucket, folder, &ListQuery::default())
+                    .await?;
+                for element in elements {
+                    if element.is_file {
+                        keys.insert(element.path.clone(), Some(element.into()));
+                    } else {
+                        keys.insert(format!("{}/", element.path), None);
+                    }
+                }
+            }
+            delimiter => {
+                let folder = match folder {
+                    "" => String::new(),
+                    folder => format!("{folder}/"),
+                };
+                let files = self.repo.list_folder_content(bucket, &folder).await?;
+                for file in files.into_iter().filter(|file| file.path.starts_with(prefix)) {
+                    let common_prefix = delimiter
+                        .filter(|delimiter| !delimiter.is_empty())
+                        .and_then(|delimiter| {
+                            file.path[prefix.len()..]
+                                .find(delimiter)
+                                .map(|i| file.path[..prefix.len() + i + delimiter.len()].to_string())
+                        });
+                    match common_prefix {
+                        Some(common_prefix) => keys.insert(common_prefix, None),
+                        None => keys.insert(file.path.clone(), Some(file.into())),
+                    };
+                }
+            }
+        }
+
+        // 2. paging after the marker
+        let mut page = keys
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .filter(|(key, _)| params.marker.as_ref().is_none_or(|marker| key > marker));
+        let mut result = ListObjectsResult {
+            contents: Vec::new(),
+            common_prefixes: Vec::new(),
+            is_truncated: false,
+            next_marker: None,
+        };
+        for (key, entry) in page.by_ref().take(params.max_keys) {
+            result.next_marker = Some(key.clone());
+            match entry {
+                Some(entry) => result.contents.push(entry),
+                None => result.common_prefixes.push(key),
+            }
+        }
+        result.is_truncated = page.next().is_some();
+        if !result.is_truncated {
+            result.next_marker = None;
+        }
+
+        Ok(result)
+    }
+
+    /// Keys ending with `/` are folders kept without files
+    pub async fn head_object(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ObjectEntry> {
+        check_access(&self.access_repo, user.id, bucket, &AccessType::R).await?;
+
+        self.repo
+            .get_uploaded_file_by_path(key, bucket)
+            .await
+            .map(ObjectEntry::from)
+    }
+
+    pub async fn get_object(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<(ObjectEntry, Vec<u8>)> {
+        let entry = self.head_object(bucket, key, user).await?;
+        if key.ends_with('/') {
+            return Ok((entry, Vec::new()));
+        }
+
+        let file = self.files_service.download(key, bucket, None, user).await?;
+        Ok((entry, file.data))
+    }
+
+    /// A part of the object given by the `Range` header, `None` means the whole object
+    pub async fn get_object_range(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        range: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<(ObjectEntry, Option<FileRangeSchema>)> {
+        let entry = self.head_object(bucket, key, user).await?;
+        if key.ends_with('/') {
+            return Ok((entry, None));
+        }
+
+        let part = self
+            .files_service
+            .download_range(key, bucket, None, range, user)
+            .await?;
+        Ok((entry, part))
+    }
+
+    /// An empty object with a key ending with `/` creates a folder
+    pub async fn put_object(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        data: Bytes,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ObjectEntry> {
+        match key.strip_suffix('/') {
+            Some(_) if !data.is_empty() => return Err(CloudBoostclicksError::InvalidPath),
+            Some(folder) => {
+                let (parent_path, folder_name) = folder.rsplit_once('/').unwrap_or(("", folder));
+                let in_schema =
+                    InFolderSchema::new(bucket, parent_path.to_string(), folder_name.to_string());
+                match self.files_service.create_folder(in_schema, user).await {
+                    Ok(()) | Err(CloudBoostclicksError::AlreadyExists(_)) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+            None => {
+                self.files_service.replace(bucket, key, data, None, user).await?;
+            }
+        }
+
+        self.head_object(bucket, key, user).await
+    }
+
+    /// Copies share chunks with the source, an existing destination is moved into the trash
+    /// in the transaction of the copy
+    pub async fn copy_object(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        source_bucket: Uuid,
+        source_key: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ObjectEntry> {
+        // 0. copying onto itself only refreshes metadata, which isn't kept
+        let source = self.head_object(source_bucket, source_key, user).await?;
+        if source_bucket == bucket && source_key == key {
+            return Ok(source);
+        }
+
+        // 1. an object at the key is replaced, a folder of the same name stays
+        let overwrite = match self.repo.get_uploaded_file_by_path(key, bucket).await {
+            Ok(_) => true,
+            Err(CloudBoostclicksError::DoesNotExist(_)) => false,
+            Err(e) => return Err(e),
+        };
+
+        // 2. copying
+        let in_schema = CopySchema {
+            path: source_key.to_string(),
+            destination_path: key.to_string(),
+            destination_storage_id: Some(bucket),
+            overwrite,
+        };
+        self.files_service
+            .copy(in_schema, source_bucket, user)
+            .await?;
+
+        self.head_object(bucket, key, user).await
+    }
+
+    /// Deleting a missing key succeeds, a folder key is deleted only when nothing is left in the folder
+    pub async fn delete_object(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+
+        if key.ends_with('/') && !self.repo.list_folder_content(bucket, key).await?.is_empty() {
+            return Ok(());
+        }
+
+        match self
+            .files_service
+            .delete(key.trim_end_matches('/'), bucket, None, user)
+            .await
+        {
+            Ok(_) | Err(CloudBoostclicksError::DoesNotExist(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deletes keys one by one, failed keys don't stop the rest
+    pub async fn delete_objects(
+        &self,
+        bucket: Uuid,
+        keys: Vec<String>,
+        user: &AuthUser,
+    ) -> Vec<(String, CloudBoostclicksResult<()>)> {
+        let mut results = Vec::with_capacity(keys.len());
+        for key in keys {
+            let result = self.delete_object(bucket, &key, user).await;
+            results.push((key, result));
+        }
+        results
+    }
+
+    /// Creates a pending file, its id is the upload id
+    pub async fn create_multipart_upload(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Uuid> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+
+        // 1. validation
+        if !FilesService::validate_filepath(key) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+        let storage = self.storages_repo.get_by_id(bucket).await?;
+        if storage.settings.client_encrypted {
+            return Err(CloudBoostclicksError::ClientEncryptionRequired);
+        }
+
+        // 2. saving the pending file, every chunk of its parts is compressed on its own
+        let path = format!("{MULTIPART_PREFIX}{}/{key}", Uuid::new_v4());
+        let file = self
+            .repo
+            .create_file(InFile::new(path, 0, bucket, user.id))
+            .await?;
+        let codec = (storage.settings.compression && compression::is_compressible(key))
+            .then_some(compression::ZSTD);
+        self.repo.set_codec(file.id, codec).await?;
+
+        Ok(file.id)
+    }
+
+    /// Uploads the part as chunks of the pending file, returns the ETag of the part
+    pub async fn upload_part(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        upload_id: Uuid,
+        part_number: i32,
+        data: Bytes,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<String> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+        if !(1..=MAX_PART_NUMBER).contains(&part_number) {
+            return Err(CloudBoostclicksError::InvalidUploadPart);
+        }
+        let file = self.pending_upload(bucket, key, upload_id).await?;
+
+        // 1. uploading chunks, they are put in order when the upload is completed
+        let storage = self.storages_repo.get_by_id(bucket).await?;
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let data_key = storage_manager.data_key_for(&storage, file.id, None).await?;
+
+        let mut chunks = Vec::new();
+        for piece in data.chunks(storage_manager.chunk_size()) {
+            let compressed = file
+                .codec
+                .as_deref()
+                .map(|codec| compression::compress_chunk(codec, piece))
+                .transpose()?;
+            let content = compressed.as_deref().unwrap_or(piece);
+            let chunk = storage_manager
+                .upload_chunk(&storage, file.id, 0, content, data_key.as_ref())
+                .await?;
+            chunks.push(chunk);
+        }
+        let chunk_ids: Vec<Uuid> = chunks.iter().map(|chunk| chunk.id).collect();
+        if !chunks.is_empty() {
+            self.repo.create_chunks_batch(chunks).await?;
+        }
+
+        // 2. saving the part
+        let etag = sha256_hex(&data);
+        self.parts_repo
+            .upsert(file.id, part_number, &etag, data.len() as i64, &chunk_ids)
+            .await?;
+
+        Ok(etag)
+    }
+
+    /// Joins the chosen parts into the file, an existing file with the key is moved into the trash
+    pub async fn complete_multipart_upload(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        upload_id: Uuid,
+        parts: Vec<CompletedPart>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<ObjectEntry> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+        let file = self.pending_upload(bucket, key, upload_id).await?;
+
+        // 1. parts have to be uploaded and listed in ascending order
+        let uploaded = self.parts_repo.list(file.id).await?;
+        if parts.is_empty()
+            || parts
+                .windows(2)
+                .any(|pair| pair[0].part_number >= pair[1].part_number)
+        {
+            return Err(CloudBoostclicksError::InvalidUploadPart);
+        }
+
+        let mut chunk_ids = Vec::new();
+        let mut size = 0;
+        for part in &parts {
+            let uploaded = uploaded
+                .iter()
+                .find(|uploaded| uploaded.part_number == part.part_number)
+                .filter(|uploaded| uploaded.etag == part.etag)
+                .ok_or(CloudBoostclicksError::InvalidUploadPart)?;
+            chunk_ids.extend_from_slice(&uploaded.chunk_ids);
+            size += uploaded.size;
+        }
+        if chunk_ids.len() > i16::MAX as usize {
+            return Err(CloudBoostclicksError::InvalidUploadPart);
+        }
+
+        // 2. hashing the joined content and computing its parity like any other upload does
+        let storage = self.storages_repo.get_by_id(bucket).await?;
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let mut uploaded_chunks: HashMap<Uuid, FileChunk> = storage_manager
+            .list_chunks(file.id, None)
+            .await?
+            .into_iter()
+            .map(|chunk| (chunk.id, chunk))
+            .collect();
+        let chunks = chunk_ids
+            .iter()
+            .map(|id| uploaded_chunks.remove(id))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(CloudBoostclicksError::InvalidUploadPart)?;
+        let (sha256, parity_chunks) = storage_manager
+            .seal_joined(&storage, &file, chunks)
+            .await?;
+
+        // 3. turning the pending file into the uploaded one in place of the replaced object
+        if let Err(e) = self
+            .parts_repo
+            .complete(
+                file.id,
+                &chunk_ids,
+                parity_chunks.clone(),
+                key,
+                size,
+                &sha256,
+                user.id,
+            )
+            .await
+        {
+            storage_manager.delete_chunks(parity_chunks).await;
+            return Err(e);
+        }
+
+        self.head_object(bucket, key, user).await
+    }
+
+    pub async fn abort_multipart_upload(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        upload_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+        let file = self.pending_upload(bucket, key, upload_id).await?;
+
+        self.repo.delete_with_folders(file.id).await
+    }
+
+    pub async fn list_parts(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        upload_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<UploadPart>> {
+        check_access(&self.access_repo, user.id, bucket, &AccessType::W).await?;
+        let file = self.pending_upload(bucket, key, upload_id).await?;
+
+        self.parts_repo.list(file.id).await
+    }
+
+    /// The pending file of the upload, it has to belong to the same bucket and key
+    async fn pending_upload(
+        &self,
+        bucket: Uuid,
+        key: &str,
+        upload_id: Uuid,
+    ) -> CloudBoostclicksResult<File> {
+        let not_found = || CloudBoostclicksError::DoesNotExist("загрузка".to_string());
+
+        let file = self.repo.get_by_id(upload_id).await.map_err(|_| not_found())?;
+        let upload_key = file
+            .path
+            .strip_prefix(MULTIPART_PREFIX)
+            .and_then(|path| path.split_once('/'))
+            .map(|(_, upload_key)| upload_key);
+        if file.storage_id != bucket || file.is_uploaded || upload_key != Some(key) {
+            return Err(not_found());
+        }
+
+        Ok(file)
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        access::check_access, crypto::{self, DataKey}, hashing::sha256_hex, jwt_manager::AuthUser,
+        telegram_api::bot_api::TelegramBotApi,
+    },
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{access::AccessType, chunk_health::HealthReport, file_chunks::FileChunk},
+    repositories::{
+        access::AccessRepository, chunk_health::ChunkHealthRepository,
+        file_versions::FileVersionsRepository, files::FilesRepository,
+        storage_replicas::StorageReplicasRepository, storages::StoragesRepository,
+    },
+};
+
+use super::{
+    storage_manager::StorageManagerService, storage_workers_scheduler::StorageWorkersScheduler,
+};
+
+pub struct ScrubService<'d> {
+    db: &'d PgPool,
+    repo: ChunkHealthRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    config: Config,
+}
+
+impl<'d> ScrubService<'d> {
+    pub fn new(db: &'d PgPool, config: Config) -> Self {
+        Self {
+            db,
+            repo: ChunkHealthRepository::new(db),
+            access_repo: AccessRepository::new(db),
+            config,
+        }
+    }
+
+    /// Checks the next batch of chunks, returns how many telegram files were checked
+    pub async fn scrub_batch(&self) -> CloudBoostclicksResult<usize> {
+        let chunks = self
+            .repo
+            .list_due(self.config.scrub_batch_size, self.config.scrub_recheck_days)
+            .await?;
+
+        // chunks sharing a telegram file are checked once
+        let mut seen = HashSet::new();
+        for chunk in chunks {
+            if !seen.insert(chunk.telegram_file_id.clone()) {
+                continue;
+            }
+
+            // only a lost file or a wrong content mark the chunk as damaged,
+            // it's checked again with the next batch if telegram fails otherwise
+            let error = match self.check_chunk(&chunk).await {
+                Ok(()) => None,
+                Err(
+                    e @ (CloudBoostclicksError::TelegramFileNotFound
+                    | CloudBoostclicksError::ChecksumMismatch),
+                ) => {
+                    tracing::warn!(
+                        "chunk with telegram file \"{}\" of file \"{}\" is damaged: {e}",
+                        chunk.telegram_file_id,
+                        chunk.file_id
+                    );
+                    Some(e.to_string())
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "can't check chunk with telegram file \"{}\" of file \"{}\": {e}",
+                        chunk.telegram_file_id,
+                        chunk.file_id
+                    );
+                    continue;
+                }
+            };
+
+            self.repo
+                .record(&chunk.telegram_file_id, error.as_deref())
+                .await?;
+
+            if let Err(e) = self.restore_copies(&chunk).await {
+                tracing::error!(
+                    "can't restore copies of chunk \"{}\": {e}",
+                    chunk.telegram_file_id
+                );
+            }
+        }
+
+        Ok(seen.len())
+    }
+
+    /// Re-uploads the next batch of damaged chunks restoring them from parity chunks,
+    /// returns how many telegram files were replaced
+    pub async fn repair_batch(&self) -> CloudBoostclicksResult<usize> {
+        let chunks = self.repo.list_repairable(self.config.scrub_batch_size).await?;
+
+        let mut seen = HashSet::new();
+        let mut repaired = 0;
+        for chunk in chunks {
+            if !seen.insert(chunk.telegram_file_id.clone()) {
+                continue;
+            }
+
+            match self.repair_chunk(&chunk).await {
+                Ok(()) => repaired += 1,
+                Err(e) => tracing::error!(
+                    "can't repair chunk with telegram file \"{}\" of file \"{}\": {e}",
+                    chunk.telegram_file_id,
+                    chunk.file_id
+                ),
+            }
+        }
+
+        Ok(repaired)
+    }
+
+    pub async fn report(&self, storage_id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<HealthReport> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::A).await?;
+
+        let counters = self.repo.counters(storage_id).await?;
+        let damaged_files = self.repo.list_damaged_files(storage_id).await?;
+
+        Ok(HealthReport::new(counters, damaged_files))
+    }
+
+    async fn repair_chunk(&self, chunk: &FileChunk) -> CloudBoostclicksResult<()> {
+        // 1. getting the rest of the group
+        let storage = StoragesRepository::new(self.db)
+            .get_by_file_id(chunk.file_id)
+            .await?;
+        let (data_chunks, parity_chunks) = match chunk.version_id {
+            Some(version_id) => {
+                let versions_repo = FileVersionsRepository::new(self.db);
+                (
+                    versions_repo.list_chunks(version_id).await?,
+                    versions_repo.list_parity_chunks(version_id).await?,
+                )
+            }
+            None => {
+                let files_repo = FilesRepository::new(self.db);
+                (
+                    files_repo.list_chunks_of_file(chunk.file_id).await?,
+                    files_repo.list_parity_chunks_of_file(chunk.file_id).await?,
+                )
+            }
+        };
+        let Some(group_size) = parity_chunks.first().and_then(|c| c.parity_group_size) else {
+            return Err(CloudBoostclicksError::ChunksUnrecoverable);
+        };
+        let group = if chunk.is_parity() {
+            chunk.position
+        } else {
+            chunk.position / group_size
+        };
+
+        // 2. reconstructing the group
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let restored = storage_manager
+            .restore_group(storage.id, &data_chunks, &parity_chunks, group, &HashMap::new())
+            .await?;
+        let Some((_, data)) = restored.into_iter().find(|(c, _)| c.id == chunk.id) else {
+            return Err(CloudBoostclicksError::ChunksUnrecoverable);
+        };
+
+        // 3. uploading it again
+        let data_key = match &chunk.data_key {
+            Some(wrapped) => Some(DataKey::unwrap(&self.config.encryption_secret, wrapped)?),
+            None => None,
+        };
+        let new_chunk = storage_manager
+            .upload_chunk(
+                &storage,
+                chunk.file_id,
+                chunk.position as usize,
+                &data,
+                data_key.as_ref(),
+            )
+            .await?;
+        self.repo
+            .replace_telegram_file(&chunk.telegram_file_id, &new_chunk)
+            .await?;
+
+        tracing::info!(
+            "repaired chunk \"{}\" as \"{}\"",
+            chunk.telegram_file_id,
+            new_chunk.telegram_file_id
+        );
+
+        Ok(())
+    }
+
+    /// Drops copies of the chunk which telegram doesn't have anymore
+    /// and sends the chunk to every replica chat lacking a copy.
+    ///
+    /// Copies which can't be checked for now are kept
+    async fn restore_copies(&self, chunk: &FileChunk) -> CloudBoostclicksResult<()> {
+        let storage_id = StoragesRepository::new(self.db)
+            .get_by_file_id(chunk.file_id)
+            .await?
+            .id;
+        let replicas_repo = StorageReplicasRepository::new(self.db);
+        let scheduler = StorageWorkersScheduler::new(self.db, self.config.telegram_rate_limit);
+        let api = TelegramBotApi::new(&self.config.telegram_api_base_url);
+
+        for copy in replicas_repo
+            .list_copies(storage_id, &chunk.telegram_file_id)
+            .await?
+        {
+            let result = match copy.storage_worker_id {
+                Some(worker_id) => match scheduler.get_token_for_worker(worker_id).await {
+                    Ok(worker) => api
+                        .get_file(&copy.telegram_file_id, &worker.token)
+                        .await
+                        .map(|_| ()),
+                    Err(e) => Err(e),
+                },
+                None => Err(CloudBoostclicksError::DoesNotExist("бот копии".to_string())),
+            };
+
+            match result {
+                Ok(()) => (),
+                Err(
+                    e @ (CloudBoostclicksError::TelegramFileNotFound
+                    | CloudBoostclicksError::DoesNotExist(_)),
+                ) => {
+                    tracing::warn!(
+                        "copy \"{}\" of chunk \"{}\" is lost: {e}",
+                        copy.telegram_file_id,
+                        chunk.telegram_file_id
+                    );
+                    replicas_repo.delete_copy(copy.id).await?;
+                }
+                Err(e) => tracing::error!(
+                    "can't check copy \"{}\" of chunk \"{}\": {e}",
+                    copy.telegram_file_id,
+                    chunk.telegram_file_id
+                ),
+            }
+        }
+
+        if replicas_repo
+            .list_missing(storage_id, &chunk.telegram_file_id)
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+        let data = storage_manager
+            .download_chunk(storage_id, chunk.clone())
+            .await?
+            .data;
+        let payload = storage_manager.seal_chunk(chunk, data)?;
+        let copied = storage_manager
+            .replicate(storage_id, &chunk.telegram_file_id, &payload)
+            .await;
+        tracing::info!(
+            "made {copied} copies of chunk \"{}\"",
+            chunk.telegram_file_id
+        );
+
+        Ok(())
+    }
+
+    /// Asks telegram for the file and, if configured, downloads it to compare the checksum
+    async fn check_chunk(&self, chunk: &FileChunk) -> CloudBoostclicksResult<()> {
+        let Some(worker_id) = chunk.storage_worker_id else {
+            return Err(CloudBoostclicksError::DoesNotExist("бот чанка".to_string()));
+        };
+
+        let worker = StorageWorkersScheduler::new(self.db, self.config.telegram_rate_limit)
+            .get_token_for_worker(worker_id)
+            .await?;
+        let api = TelegramBotApi::new(&self.config.telegram_api_base_url);
+
+        if !self.config.scrub_download {
+            // encrypted chunks are stored with the nonce and the tag
+            let overhead = if chunk.data_key.is_some() { crypto::OVERHEAD as i64 } else { 0 };
+            let file_info = api.get_file(&chunk.telegram_file_id, &worker.token).await?;
+            return match (file_info.file_size, chunk.size) {
+                (Some(actual), Some(expected)) if actual != i64::from(expected) + overhead => {
+                    Err(CloudBoostclicksError::ChecksumMismatch)
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let payload = api.download(&chunk.telegram_file_id, worker.token).await?;
+        // a payload failing authentication is damaged the same as one with a wrong checksum
+        let data = match &chunk.data_key {
+            Some(wrapped) => DataKey::unwrap(&self.config.encryption_secret, wrapped)?
+                .decrypt(&payload)
+                .map_err(|_| CloudBoostclicksError::ChecksumMismatch)?,
+            None => payload,
+        };
+        match &chunk.sha256 {
+            Some(sha256) if *sha256 != sha256_hex(&data) => Err(CloudBoostclicksError::ChecksumMismatch),
+            _ => Ok(()),
+        }
+    }
+}
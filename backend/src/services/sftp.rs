@@ -0,0 +1,341 @@
+use axum::body::Bytes;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{channels::ClientSender, jwt_manager::AuthUser, sftp::Attrs},
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    repositories::storages::StoragesRepository,
+    schemas::{
+        files::{FileRangeSchema, InChunkSchema},
+        webdav::DavResource,
+    },
+    services::{files::FilesService, storage_manager::StorageManagerService, webdav::WebDavService},
+};
+
+/// A file being written, every chunk is uploaded as soon as it's full.
+///
+/// The last chunk is kept until the file is closed, so the upload is finished with it.
+/// An existing file of a storage without versions is replaced only when the upload is finished,
+/// till then the content goes to a temporary file next to it
+pub struct SftpUpload {
+    storage_id: Uuid,
+    path: String,
+    temp_path: Option<String>,
+    file_id: Option<Uuid>,
+    next_chunk: usize,
+    pending: Vec<u8>,
+    len: u64,
+}
+
+impl SftpUpload {
+    /// Bytes written so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Maps SFTP operations onto storages of the user.
+///
+/// The root directory lists the storages, `/<storage name>/<path>` is a file or a folder inside of it
+pub struct SftpService<'d> {
+    storages_repo: StoragesRepository<'d>,
+    files_service: FilesService<'d>,
+    webdav_service: WebDavService<'d>,
+    chunk_size: usize,
+}
+
+impl<'d> SftpService<'d> {
+    pub fn new(db: &'d PgPool, config: Config, tx: ClientSender) -> Self {
+        let storages_repo = StoragesRepository::new(db);
+        let chunk_size = StorageManagerService::new(
+            db,
+            &config.telegram_api_base_url,
+            &config.encryption_secret,
+            config.telegram_rate_limit,
+        )
+        .chunk_size();
+        let files_service = FilesService::new(db, config.clone(), tx.clone());
+        let webdav_service = WebDavService::new(db, config, tx);
+        Self {
+            storages_repo,
+            files_service,
+            webdav_service,
+            chunk_size,
+        }
+    }
+
+    /// Directory names of the storages, a storage id is appended to names which are not unique
+    async fn storages(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<(String, Uuid)>> {
+        let storages = self.storages_repo.list_by_user_id(user.id).await?;
+        let names: Vec<String> = storages
+            .iter()
+            .map(|storage| storage.name.replace('/', "_"))
+            .collect();
+
+        Ok(storages
+            .iter()
+            .zip(&names)
+            .map(|(storage, name)| {
+                if names.iter().filter(|other| *other == name).count() > 1 {
+                    (format!("{name} ({})", storage.id), storage.id)
+                } else {
+                    (name.clone(), storage.id)
+                }
+            })
+            .collect())
+    }
+
+    /// Splits a normalized path into the storage and the path inside of it, `None` is the root
+    async fn locate(
+        &self,
+        segments: &[String],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Option<(Uuid, String)>> {
+        let Some((storage_name, path)) = segments.split_first() else {
+            return Ok(None);
+        };
+
+        self.storages(user)
+            .await?
+            .into_iter()
+            .find(|(name, _)| name == storage_name)
+            .map(|(_, storage_id)| Some((storage_id, path.join("/"))))
+            .ok_or_else(|| CloudBoostclicksError::DoesNotExist("хранилище".to_string()))
+    }
+
+    /// Same as `locate` but the path has to point inside of a storage
+    async fn locate_in_storage(
+        &self,
+        segments: &[String],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<(Uuid, String)> {
+        match self.locate(segments, user).await? {
+            Some((storage_id, path)) if !path.is_empty() => Ok((storage_id, path)),
+            _ => Err(CloudBoostclicksError::InvalidPath),
+        }
+    }
+
+    pub async fn stat(&self, segments: &[String], user: &AuthUser) -> CloudBoostclicksResult<Attrs> {
+        let Some((storage_id, path)) = self.locate(segments, user).await? else {
+            return Ok(Attrs::dir(None));
+        };
+
+        let resource = self.webdav_service.resource(storage_id, &path, user).await?;
+        Ok(attrs(&resource))
+    }
+
+    /// Names and attributes of the directory members
+    pub async fn read_dir(
+        &self,
+        segments: &[String],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<(String, Attrs)>> {
+        let Some((storage_id, path)) = self.locate(segments, user).await? else {
+            return Ok(self
+                .storages(user)
+                .await?
+                .into_iter()
+                .map(|(name, _)| (name, Attrs::dir(None)))
+                .collect());
+        };
+
+        let mut resources = self
+            .webdav_service
+            .propfind(storage_id, &path, 1, user)
+            .await?
+            .into_iter();
+        if !resources.next().is_some_and(|resource| resource.is_collection) {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        Ok(resources
+            .map(|resource| (resource.name().to_string(), attrs(&resource)))
+            .collect())
+    }
+
+    /// The content of the file, it's read by ranges of chunks
+    pub async fn content(
+        &self,
+        segments: &[String],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<(Uuid, FileRangeSchema)> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        let content = self.webdav_service.content(storage_id, &path, user).await?;
+        Ok((storage_id, content))
+    }
+
+    /// Whether the path is an existing file, folders are reported as an error
+    pub async fn file_exists(&self, segments: &[String], user: &AuthUser) -> CloudBoostclicksResult<bool> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        match self.webdav_service.resource(storage_id, &path, user).await {
+            Ok(resource) if resource.is_collection => Err(CloudBoostclicksError::InvalidPath),
+            Ok(_) => Ok(true),
+            Err(CloudBoostclicksError::DoesNotExist(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Starts writing the file, `exists` tells whether it replaces an existing one
+    pub async fn start_upload(
+        &self,
+        segments: &[String],
+        exists: bool,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<SftpUpload> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        let storage = self.storages_repo.get_by_id(storage_id).await?;
+
+        let temp_path = (exists && !storage.settings.versioning).then(|| {
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            let name = format!(".{name}.{}.part", Uuid::new_v4());
+            match parent {
+                "" => name,
+                parent => format!("{parent}/{name}"),
+            }
+        });
+
+        Ok(SftpUpload {
+            storage_id,
+            path,
+            temp_path,
+            file_id: None,
+            next_chunk: 0,
+            pending: Vec::new(),
+            len: 0,
+        })
+    }
+
+    /// Appends data to the file, uploads chunks which are full and aren't the last one
+    pub async fn write_upload(
+        &self,
+        upload: &mut SftpUpload,
+        data: &[u8],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        upload.pending.extend_from_slice(data);
+        upload.len += data.len() as u64;
+
+        while upload.pending.len() > self.chunk_size {
+            let chunk: Vec<u8> = upload.pending.drain(..self.chunk_size).collect();
+            self.upload_chunk(upload, chunk, usize::MAX, user).await?;
+        }
+        Ok(())
+    }
+
+    /// Uploads the rest of the file and puts it in place of the replaced one
+    pub async fn finish_upload(
+        &self,
+        mut upload: SftpUpload,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        // 0. a file smaller than a chunk is uploaded at once
+        if upload.file_id.is_none() {
+            let data = Bytes::from(std::mem::take(&mut upload.pending));
+            return self
+                .webdav_service
+                .put(upload.storage_id, &upload.path, data, None, user)
+                .await
+                .map(|_| ());
+        }
+
+        // 1. the last chunk finishes the upload
+        let chunk = std::mem::take(&mut upload.pending);
+        let total_chunks = upload.next_chunk + 1;
+        self.upload_chunk(&mut upload, chunk, total_chunks, user).await?;
+
+        // 2. the replaced file goes to the trash
+        if let Some(temp_path) = &upload.temp_path {
+            self.files_service
+                .delete(&upload.path, upload.storage_id, None, user)
+                .await?;
+            self.files_service
+                .rename(temp_path, &upload.path, upload.storage_id, None, user)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn upload_chunk(
+        &self,
+        upload: &mut SftpUpload,
+        chunk: Vec<u8>,
+        total_chunks: usize,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        let in_schema = InChunkSchema {
+            storage_id: upload.storage_id,
+            path: upload.temp_path.clone().unwrap_or_else(|| upload.path.clone()),
+            size: None,
+            file_id: upload.file_id,
+            chunk_index: upload.next_chunk,
+            total_chunks,
+            chunk: Bytes::from(chunk),
+            envelope: None,
+            if_match: None,
+        };
+        upload.file_id = Some(self.files_service.upload_chunked(in_schema, user).await?);
+        upload.next_chunk += 1;
+        Ok(())
+    }
+
+    pub async fn mkdir(&self, segments: &[String], user: &AuthUser) -> CloudBoostclicksResult<()> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        self.webdav_service.mkcol(storage_id, &path, user).await
+    }
+
+    pub async fn remove(&self, segments: &[String], user: &AuthUser) -> CloudBoostclicksResult<()> {
+        if !self.file_exists(segments, user).await? {
+            return Err(CloudBoostclicksError::DoesNotExist("файл".to_string()));
+        }
+
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        self.webdav_service.delete(storage_id, &path, None, user).await
+    }
+
+    /// Removes a folder, only empty ones like `rmdir` does
+    pub async fn rmdir(&self, segments: &[String], user: &AuthUser) -> CloudBoostclicksResult<()> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        let resources = self
+            .webdav_service
+            .propfind(storage_id, &path, 1, user)
+            .await?;
+        if !resources[0].is_collection || resources.len() > 1 {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        self.webdav_service.delete(storage_id, &path, None, user).await
+    }
+
+    /// Moves a file or a folder inside of one storage, an existing destination is an error
+    pub async fn rename(
+        &self,
+        segments: &[String],
+        destination_segments: &[String],
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        let (storage_id, path) = self.locate_in_storage(segments, user).await?;
+        let (destination_storage_id, destination_path) =
+            self.locate_in_storage(destination_segments, user).await?;
+        if storage_id != destination_storage_id {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        self.webdav_service
+            .transfer(storage_id, &path, &destination_path, true, false, None, user)
+            .await
+            .map(|_| ())
+    }
+}
+
+fn attrs(resource: &DavResource) -> Attrs {
+    match (resource.is_collection, &resource.updated_at) {
+        (false, Some(updated_at)) => Attrs::file(resource.size as u64, updated_at),
+        _ => Attrs::dir(resource.updated_at.as_ref()),
+    }
+}
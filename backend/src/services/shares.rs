@@ -6,17 +6,23 @@ use crate::{
         access::check_access,
         channels::{ClientData, ClientMessage, ClientSender, DownloadFileData, StorageManagerData},
         jwt_manager::AuthUser,
-        zip::build_zip,
     },
     errors::{CloudBoostclicksError, CloudBoostclicksResult},
     models::{access::AccessType, files::FSElement, shares::Share},
-    repositories::{access::AccessRepository, files::FilesRepository, shares::SharesRepository},
-    schemas::shares::CreateShareSchema,
+    repositories::{
+        access::AccessRepository, files::FilesRepository, shares::SharesRepository,
+        storages::StoragesRepository,
+    },
+    schemas::{
+        files::{ArchiveEntry, DownloadedFileSchema, ListQuery},
+        shares::CreateShareSchema,
+    },
 };
 
 pub struct SharesService<'d> {
     shares_repo: SharesRepository<'d>,
     files_repo: FilesRepository<'d>,
+    storages_repo: StoragesRepository<'d>,
     access_repo: AccessRepository<'d>,
     tx: ClientSender,
 }
@@ -26,6 +32,7 @@ impl<'d> SharesService<'d> {
         Self {
             shares_repo: SharesRepository::new(db),
             files_repo: FilesRepository::new(db),
+            storages_repo: StoragesRepository::new(db),
             access_repo: AccessRepository::new(db),
             tx,
         }
@@ -94,12 +101,25 @@ impl<'d> SharesService<'d> {
         if !share.is_folder {
             return Err(CloudBoostclicksError::InvalidPath);
         }
+        let storage = self.storages_repo.get_by_id(share.storage_id).await?;
+        if storage.settings.client_encrypted {
+            return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+        }
 
         let prefix = share.path.trim_end_matches('/');
-        self.files_repo.list_dir(share.storage_id, prefix).await
+        let elements = self
+            .files_repo
+            .list_dir(share.storage_id, prefix, &ListQuery::default())
+            .await?;
+
+        // uploaders are members of the storage and aren't shown to anyone with the link
+        Ok(elements
+            .into_iter()
+            .map(FSElement::without_uploader)
+            .collect())
     }
 
-    pub async fn download_file(&self, share_id: Uuid) -> CloudBoostclicksResult<Vec<u8>> {
+    pub async fn download_file(&self, share_id: Uuid) -> CloudBoostclicksResult<DownloadedFileSchema> {
         let share = self.shares_repo.get_by_id(share_id).await?;
 
         if share.is_folder {
@@ -111,16 +131,27 @@ impl<'d> SharesService<'d> {
             .get_uploaded_file_by_path(&share.path, share.storage_id)
             .await?;
 
-        self.download_file_by_id(file.id, share.storage_id, Uuid::nil())
-            .await
+        let data = self
+            .download_file_by_id(file.id, share.storage_id, Uuid::nil())
+            .await?;
+        let etag = file.etag();
+        Ok(DownloadedFileSchema::new(data, file.sha256)
+            .with_validators(etag, file.updated_at)
+            .with_envelope(file.encrypted_key, file.client_metadata))
     }
 
-    pub async fn download_folder(&self, share_id: Uuid) -> CloudBoostclicksResult<Vec<u8>> {
+    /// Files of the shared folder, the archive itself is built by `ArchivesService`
+    pub async fn download_folder(&self, share_id: Uuid) -> CloudBoostclicksResult<Vec<ArchiveEntry>> {
         let share = self.shares_repo.get_by_id(share_id).await?;
 
         if !share.is_folder {
             return Err(CloudBoostclicksError::InvalidPath);
         }
+        // archives of client encrypted files would hold nothing but ciphertext
+        let storage = self.storages_repo.get_by_id(share.storage_id).await?;
+        if storage.settings.client_encrypted {
+            return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+        }
 
         let folder_path = share.path.trim_end_matches('/');
         let prefix = if folder_path.is_empty() {
@@ -131,29 +162,24 @@ impl<'d> SharesService<'d> {
 
         let files = self
             .files_repo
-            .list_files_in_folder(share.storage_id, &prefix)
+            .list_folder_content(share.storage_id, &prefix)
             .await?;
 
-        if files.is_empty() {
+        if files.is_empty() && !self.files_repo.folder_exists(share.storage_id, &prefix).await? {
             return Err(CloudBoostclicksError::DoesNotExist("папка".to_string()));
         }
 
-        let mut zipped_files = Vec::with_capacity(files.len());
-        for file in files {
-            let data = self
-                .download_file_by_id(file.id, share.storage_id, Uuid::nil())
-                .await?;
-
-            let rel_path = if prefix.is_empty() {
-                file.path.clone()
-            } else {
-                file.path.strip_prefix(&prefix).unwrap_or(&file.path).to_string()
-            };
-
-            zipped_files.push((rel_path, data));
-        }
-
-        build_zip(zipped_files)
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                let path = if prefix.is_empty() {
+                    file.path.clone()
+                } else {
+                    file.path.strip_prefix(&prefix).unwrap_or(&file.path).to_string()
+                };
+                ArchiveEntry { path, file }
+            })
+            .collect())
     }
 
     fn validate_path(path: &str) -> bool {
@@ -206,6 +232,7 @@ impl<'d> SharesService<'d> {
         let message = {
             let download_file_data = DownloadFileData {
                 file_id,
+                version_id: None,
                 storage_id,
                 user_id,
             };
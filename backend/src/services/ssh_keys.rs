@@ -0,0 +1,67 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{jwt_manager::AuthUser, ssh::PublicKey},
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::ssh_keys::SshKey,
+    repositories::{ssh_keys::SshKeysRepository, users::UsersRepository},
+    schemas::ssh_keys::InSshKeySchema,
+};
+
+/// Public keys users log in to SFTP with
+pub struct SshKeysService<'d> {
+    repo: SshKeysRepository<'d>,
+    #[cfg_attr(not(feature = "sftp"), allow(dead_code))]
+    users_repo: UsersRepository<'d>,
+}
+
+impl<'d> SshKeysService<'d> {
+    pub fn new(db: &'d PgPool) -> Self {
+        let repo = SshKeysRepository::new(db);
+        let users_repo = UsersRepository::new(db);
+        Self { repo, users_repo }
+    }
+
+    pub async fn create(
+        &self,
+        in_schema: InSshKeySchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<SshKey> {
+        let key = PublicKey::parse(&in_schema.public_key)
+            .ok_or(CloudBoostclicksError::InvalidPublicKey)?;
+        let name = in_schema
+            .name
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| key.comment.clone())
+            .unwrap_or_else(|| key.algorithm.clone());
+
+        self.repo
+            .create(user.id, &name, &key.to_openssh(), &key.fingerprint())
+            .await
+    }
+
+    pub async fn list(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<SshKey>> {
+        self.repo.list_by_user_id(user.id).await
+    }
+
+    pub async fn delete(&self, key_id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<()> {
+        self.repo.delete(key_id, user.id).await
+    }
+
+    /// Finds the key with the fingerprint sshd offers and its owner
+    #[cfg(feature = "sftp")]
+    pub async fn authenticate(
+        &self,
+        fingerprint: &str,
+    ) -> CloudBoostclicksResult<(SshKey, AuthUser)> {
+        let key = self
+            .repo
+            .get_by_fingerprint(fingerprint)
+            .await
+            .map_err(|_| CloudBoostclicksError::NotAuthenticated)?;
+        let user = self.users_repo.get_by_id(key.user_id).await?;
+
+        Ok((key, AuthUser::new(user.id, user.identifier())))
+    }
+}
@@ -1,32 +1,56 @@
-﻿use futures::future::join_all;
+﻿use std::{collections::HashMap, ops::Range};
+
+use futures::{future::join_all, Stream};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
     common::{
         channels::{DownloadFileData, UploadFileData},
+        compression::{self, Decompressor},
+        crypto::DataKey,
+        erasure,
+        hashing::{sha256_hex, ResumableSha256},
         telegram_api::bot_api::TelegramBotApi,
-        types::ChatId,
+        types::{ChatId, Position},
+    },
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{
+        file_chunks::FileChunk,
+        files::File,
+        storage_replicas::{ChunkCopy, StorageReplica},
+        storages::Storage,
+    },
+    repositories::{
+        file_versions::FileVersionsRepository, files::FilesRepository,
+        storage_replicas::StorageReplicasRepository, storages::StoragesRepository,
     },
-    errors::CloudBoostclicksResult,
-    models::file_chunks::FileChunk,
-    repositories::{files::FilesRepository, storages::StoragesRepository},
     schemas::files::DownloadedChunkSchema,
 };
 
 use super::storage_workers_scheduler::StorageWorkersScheduler;
 
+/// How many times a chunk is downloaded again if its content doesn't match the checksum
+const DOWNLOAD_ATTEMPTS: usize = 3;
+
 pub struct StorageManagerService<'d> {
     storages_repo: StoragesRepository<'d>,
     files_repo: FilesRepository<'d>,
+    replicas_repo: StorageReplicasRepository<'d>,
     telegram_baseurl: &'d str,
+    encryption_secret: &'d str,
     db: &'d PgPool,
     chunk_size: usize,
     rate_limit: u8,
 }
 
 impl<'d> StorageManagerService<'d> {
-    pub fn new(db: &'d PgPool, telegram_baseurl: &'d str, rate_limit: u8) -> Self {
+    pub fn new(
+        db: &'d PgPool,
+        telegram_baseurl: &'d str,
+        encryption_secret: &'d str,
+        rate_limit: u8,
+    ) -> Self {
         let files_repo = FilesRepository::new(db);
         let storages_repo = StoragesRepository::new(db);
         // 20 MB chunks: меньше запросов к Telegram Bot API (лимит ~50 MB на запрос, 20 req/min)
@@ -34,114 +58,853 @@ impl<'d> StorageManagerService<'d> {
         Self {
             storages_repo,
             files_repo,
+            replicas_repo: StorageReplicasRepository::new(db),
             chunk_size,
             telegram_baseurl,
+            encryption_secret,
             db,
             rate_limit,
         }
     }
 
+    /// Size files are split into before they go to Telegram
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
     pub async fn upload(&self, data: UploadFileData) -> CloudBoostclicksResult<()> {
         // 1. getting storage
         let storage = self.storages_repo.get_by_file_id(data.file_id).await?;
 
-        // 2. dividing file into chunks
-        let bytes_chunks = data.file_data.chunks(self.chunk_size);
+        // 2. computing checksum of the whole file
+        let sha256 = sha256_hex(&data.file_data);
 
-        // 3. uploading by chunks (последовательно, чтобы избежать rate-limit/timeout)
+        // 3. compressing the whole file if the storage asks for it and the file isn't compressed already
+        let path = self.files_repo.get_by_id(data.file_id).await?.path;
+        let compressed = if storage.settings.compression
+            && !storage.settings.client_encrypted
+            && compression::is_compressible(&path)
+        {
+            compression::compress(&data.file_data)?
+        } else {
+            None
+        };
+        let codec = compressed.as_ref().map(|_| compression::ZSTD);
+        let content: &[u8] = compressed.as_deref().unwrap_or(&data.file_data);
+
+        // 4. dividing file into chunks
+        let bytes_chunks = content.chunks(self.chunk_size);
+        let data_key = self
+            .data_key_for(&storage, data.file_id, data.version_id)
+            .await?;
+
+        // 5. uploading by chunks (последовательно, чтобы избежать rate-limit/timeout)
         let mut uploaded_chunks = Vec::new();
         for (position, bytes_chunk) in bytes_chunks.enumerate() {
             let chunk = self
                 .upload_chunk(
-                    storage.id,
-                    storage.chat_id,
+                    &storage,
                     data.file_id,
                     position,
                     bytes_chunk,
+                    data_key.as_ref(),
                 )
                 .await?;
-            uploaded_chunks.push(chunk);
+            uploaded_chunks.push(chunk.with_version(data.version_id));
+        }
+
+        // 6. uploading parity chunks for every group of data chunks
+        let parity_count = storage.settings.erasure_parity_chunks as usize;
+        if parity_count > 0 {
+            let group_size = storage.settings.erasure_data_chunks as usize;
+            let data_chunks: Vec<&[u8]> = content.chunks(self.chunk_size).collect();
+
+            for (group, group_chunks) in data_chunks.chunks(group_size).enumerate() {
+                let parity_chunks = self
+                    .upload_parity_group(
+                        &storage,
+                        data.file_id,
+                        data.version_id,
+                        group,
+                        group_chunks,
+                        data_key.as_ref(),
+                    )
+                    .await?;
+                uploaded_chunks.extend(parity_chunks);
+            }
+        }
+
+        // 7. saving chunks, the checksum and the codec to db
+        self.files_repo.create_chunks_batch(uploaded_chunks).await?;
+        match data.version_id {
+            Some(version_id) => {
+                let versions_repo = FileVersionsRepository::new(self.db);
+                versions_repo.set_sha256(version_id, &sha256).await?;
+                versions_repo.set_codec(version_id, codec).await
+            }
+            None => {
+                self.files_repo.set_sha256(data.file_id, &sha256).await?;
+                self.files_repo.set_codec(data.file_id, codec).await
+            }
+        }
+    }
+
+    /// Uploads parity chunks of the group closed by the data chunk at `position` of a file
+    /// uploaded by chunks. The chunk itself is given, earlier chunks of its group are downloaded
+    pub async fn upload_closed_group_parity(
+        &self,
+        storage: &Storage,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        position: usize,
+        content: &[u8],
+        data_key: Option<&DataKey>,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        let group_size = storage.settings.erasure_data_chunks as usize;
+        let group = position / group_size;
+
+        let mut contents = Vec::with_capacity(group_size);
+        for chunk in self.list_chunks(file_id, version_id).await? {
+            let chunk_position = chunk.position as usize;
+            if chunk_position >= group * group_size && chunk_position < position {
+                contents.push(self.download_chunk(storage.id, chunk).await?.data);
+            }
+        }
+        let mut contents: Vec<&[u8]> = contents.iter().map(Vec::as_slice).collect();
+        contents.push(content);
+
+        self.upload_parity_group(storage, file_id, version_id, group, &contents, data_key)
+            .await
+    }
+
+    /// Hashes a file joined from parts uploaded apart and uploads its parity chunks,
+    /// its data chunks are downloaded in the given order.
+    ///
+    /// Returns the checksum of the content and the parity chunks, which aren't saved yet
+    pub async fn seal_joined(
+        &self,
+        storage: &Storage,
+        file: &File,
+        chunks: Vec<FileChunk>,
+    ) -> CloudBoostclicksResult<(String, Vec<FileChunk>)> {
+        let parity_count = storage.settings.erasure_parity_chunks as usize;
+        let group_size = storage.settings.erasure_data_chunks as usize;
+        let data_key = self.data_key_for(storage, file.id, None).await?;
+        let mut decompressor = file.codec.as_deref().map(Decompressor::new).transpose()?;
+
+        let mut hasher = ResumableSha256::new();
+        let mut parity_chunks = Vec::new();
+        let mut group_contents = Vec::with_capacity(group_size);
+        let chunks_count = chunks.len();
+        let sealed = async {
+            for (position, chunk) in chunks.into_iter().enumerate() {
+                let data = self.download_chunk(storage.id, chunk).await?.data;
+                match decompressor.as_mut() {
+                    Some(decompressor) => hasher.update(&decompressor.feed(&data)?),
+                    None => hasher.update(&data),
+                }
+
+                if parity_count == 0 {
+                    continue;
+                }
+                group_contents.push(data);
+                if group_contents.len() == group_size || position + 1 == chunks_count {
+                    let contents: Vec<&[u8]> = group_contents.iter().map(Vec::as_slice).collect();
+                    let uploaded = self
+                        .upload_parity_group(
+                            storage,
+                            file.id,
+                            None,
+                            position / group_size,
+                            &contents,
+                            data_key.as_ref(),
+                        )
+                        .await?;
+                    parity_chunks.extend(uploaded);
+                    group_contents.clear();
+                }
+            }
+            Ok::<_, CloudBoostclicksError>(())
+        }
+        .await;
+
+        // parity chunks uploaded before the failure aren't referenced by anything
+        if let Err(e) = sealed {
+            self.delete_chunks(parity_chunks).await;
+            return Err(e);
+        }
+
+        Ok((hasher.finalize(), parity_chunks))
+    }
+
+    /// Encodes and uploads parity chunks of one erasure coding group of data chunks
+    async fn upload_parity_group(
+        &self,
+        storage: &Storage,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        group: usize,
+        group_chunks: &[&[u8]],
+        data_key: Option<&DataKey>,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        let parity_count = storage.settings.erasure_parity_chunks as usize;
+        let group_size = storage.settings.erasure_data_chunks as usize;
+
+        let mut uploaded_chunks = Vec::with_capacity(parity_count);
+        for (index, parity_chunk) in erasure::encode(group_chunks, parity_count)?
+            .iter()
+            .enumerate()
+        {
+            let chunk = self
+                .upload_chunk(storage, file_id, group, parity_chunk, data_key)
+                .await?;
+            uploaded_chunks.push(
+                chunk
+                    .with_version(version_id)
+                    .with_parity(index as i16, group_size as i16),
+            );
+        }
+        Ok(uploaded_chunks)
+    }
+
+    /// Picks the key chunks of the file are encrypted with, a new one is generated for a new file
+    pub async fn data_key_for(
+        &self,
+        storage: &Storage,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+    ) -> CloudBoostclicksResult<Option<DataKey>> {
+        if !storage.settings.encryption {
+            return Ok(None);
         }
 
-        // 4. saving chunks to db
-        self.files_repo.create_chunks_batch(uploaded_chunks).await
+        match self.files_repo.find_data_key(file_id, version_id).await? {
+            Some(wrapped) => DataKey::unwrap(self.encryption_secret, &wrapped).map(Some),
+            None => DataKey::generate(self.encryption_secret, storage.id).map(Some),
+        }
     }
 
+    /// Uploads the chunk to the storage chat, encrypting it if a data key is given
     pub async fn upload_chunk(
         &self,
-        storage_id: Uuid,
-        chat_id: ChatId,
+        storage: &Storage,
         file_id: Uuid,
         position: usize,
         bytes_chunk: &[u8],
+        data_key: Option<&DataKey>,
     ) -> CloudBoostclicksResult<FileChunk> {
+        let sha256 = sha256_hex(bytes_chunk);
+        let size = Some(bytes_chunk.len() as i32);
+
+        // the same content is already in the storage, so it's just another reference to it
+        if let Some(existing) = self
+            .files_repo
+            .find_chunk_by_sha256(storage.id, &sha256, data_key.is_some())
+            .await?
+        {
+            tracing::debug!(
+                "reusing telegram file \"{}\" for chunk with position \"{}\"",
+                existing.telegram_file_id,
+                position
+            );
+
+            return Ok(FileChunk::new(
+                Uuid::new_v4(),
+                file_id,
+                existing.telegram_file_id,
+                existing.storage_worker_id,
+                position as i16,
+                existing.message_id,
+                existing.chat_id,
+            )
+            .with_content(Some(sha256), size)
+            .with_data_key(existing.data_key));
+        }
+
+        let payload = match data_key {
+            Some(data_key) => data_key.encrypt(bytes_chunk)?,
+            None => bytes_chunk.to_vec(),
+        };
+
         let scheduler = StorageWorkersScheduler::new(self.db, self.rate_limit);
-        let worker = scheduler.get_token(storage_id).await?;
+        let worker = scheduler.get_token(storage.id).await?;
 
-        let document = TelegramBotApi::new(self.telegram_baseurl)
-            .upload(bytes_chunk, chat_id, worker.token.clone())
+        let message = TelegramBotApi::new(self.telegram_baseurl)
+            .upload(&payload, storage.chat_id, worker.token.clone())
             .await?;
 
         tracing::debug!(
             "[TELEGRAM API] uploaded chunk with file_id \"{}\" and position \"{}\"",
-            document.file_id,
+            message.document.file_id,
             position
         );
 
+        // copies failing here are made up by the scrub job
+        self.replicate(storage.id, &message.document.file_id, &payload)
+            .await;
+
         let chunk = FileChunk::new(
             Uuid::new_v4(),
             file_id,
-            document.file_id,
+            message.document.file_id,
             Some(worker.id),
             position as i16,
-        );
+            Some(message.message_id),
+            Some(message.chat.id),
+        )
+        .with_content(Some(sha256), size)
+        .with_data_key(data_key.map(|data_key| data_key.wrapped.clone()));
         Ok(chunk)
     }
 
+    /// Re-sends an already uploaded chunk into another chat without downloading it
+    pub async fn forward_chunk(
+        &self,
+        storage_id: Uuid,
+        chat_id: ChatId,
+        file_id: Uuid,
+        chunk: &FileChunk,
+    ) -> CloudBoostclicksResult<FileChunk> {
+        let (Some(message_id), Some(from_chat_id)) = (chunk.message_id, chunk.chat_id) else {
+            return Err(CloudBoostclicksError::DoesNotExist(
+                "сообщение чанка".to_string(),
+            ));
+        };
+
+        let scheduler = StorageWorkersScheduler::new(self.db, self.rate_limit);
+        let worker = scheduler.get_token(storage_id).await?;
+
+        let message = TelegramBotApi::new(self.telegram_baseurl)
+            .forward_message(chat_id, from_chat_id, message_id, worker.token.clone())
+            .await?;
+
+        tracing::debug!(
+            "[TELEGRAM API] forwarded chunk with file_id \"{}\" and position \"{}\"",
+            message.document.file_id,
+            chunk.position
+        );
+
+        Ok(FileChunk::new(
+            Uuid::new_v4(),
+            file_id,
+            message.document.file_id,
+            Some(worker.id),
+            chunk.position,
+            Some(message.message_id),
+            Some(message.chat.id),
+        )
+        .with_content(chunk.sha256.clone(), chunk.size)
+        .with_data_key(chunk.data_key.clone()))
+    }
+
     pub async fn download(&self, data: DownloadFileData) -> CloudBoostclicksResult<Vec<u8>> {
-        // 1. getting chunks
-        let chunks = self.files_repo.list_chunks_of_file(data.file_id).await?;
+        // 1. getting chunks, the expected checksum and the codec
+        let versions_repo = FileVersionsRepository::new(self.db);
+        let (chunks, sha256, codec) = match data.version_id {
+            Some(version_id) => {
+                let version = versions_repo.get_by_id(version_id).await?;
+                (
+                    versions_repo.list_chunks(version_id).await?,
+                    version.sha256,
+                    version.codec,
+                )
+            }
+            None => {
+                let file = self.files_repo.get_by_id(data.file_id).await?;
+                (
+                    self.files_repo.list_chunks_of_file(data.file_id).await?,
+                    file.sha256,
+                    file.codec,
+                )
+            }
+        };
 
         // 2. downloading by chunks
         let futures_: Vec<_> = chunks
-            .into_iter()
+            .iter()
+            .cloned()
             .map(|chunk| self.download_chunk(data.storage_id, chunk))
             .collect();
-        let mut chunks = join_all(futures_)
-            .await
+        let results = join_all(futures_).await;
+
+        let mut downloaded = HashMap::with_capacity(chunks.len());
+        let mut lost = Vec::new();
+        for (chunk, result) in chunks.iter().zip(results) {
+            match result {
+                Ok(downloaded_chunk) => {
+                    downloaded.insert(downloaded_chunk.position, downloaded_chunk.data);
+                }
+                Err(e) => lost.push((chunk.position, e)),
+            }
+        }
+
+        // 3. restoring lost chunks from parity chunks of their groups
+        if !lost.is_empty() {
+            let parity_chunks = match data.version_id {
+                Some(version_id) => versions_repo.list_parity_chunks(version_id).await?,
+                None => self.files_repo.list_parity_chunks_of_file(data.file_id).await?,
+            };
+            let Some(group_size) = parity_chunks.first().and_then(|c| c.parity_group_size) else {
+                return Err(lost.swap_remove(0).1);
+            };
+
+            let mut groups: Vec<Position> = lost
+                .iter()
+                .map(|(position, _)| position / group_size)
+                .collect();
+            groups.sort();
+            groups.dedup();
+
+            for group in groups {
+                tracing::warn!(
+                    "restoring group \"{group}\" of file \"{}\" from parity chunks",
+                    data.file_id
+                );
+                let restored = self
+                    .restore_group(data.storage_id, &chunks, &parity_chunks, group, &downloaded)
+                    .await?;
+                for (chunk, bytes) in restored {
+                    if !chunk.is_parity() {
+                        downloaded.insert(chunk.position, bytes);
+                    }
+                }
+            }
+        }
+
+        // 4. merging into single bytes slice in a right positions
+        let mut positions: Vec<Position> = downloaded.keys().copied().collect();
+        positions.sort();
+        let file: Vec<u8> = positions
             .into_iter()
-            .collect::<CloudBoostclicksResult<Vec<_>>>()?;
+            .filter_map(|position| downloaded.remove(&position))
+            .flatten()
+            .collect();
+        let file = match codec {
+            Some(codec) => compression::decompress(&codec, &file)?,
+            None => file,
+        };
+
+        // 5. verifying the whole file, files uploaded before checksums were stored don't have one
+        if let Some(sha256) = sha256 {
+            if sha256 != sha256_hex(&file) {
+                tracing::error!("checksum of file \"{}\" doesn't match", data.file_id);
+                return Err(CloudBoostclicksError::ChecksumMismatch);
+            }
+        }
 
-        // 3. sorting in a right positions and merging into single bytes slice
-        chunks.sort_by_key(|chunk| chunk.position);
-        let file = chunks.into_iter().flat_map(|chunk| chunk.data).collect();
         Ok(file)
     }
 
+    /// Yields the current content of the file chunk by chunk, so it never has to be in memory at once.
+    ///
+    /// Chunks are checked one by one, the checksum of the whole file isn't
+    pub fn stream_file<'a>(
+        &'a self,
+        storage_id: Uuid,
+        file: &File,
+    ) -> impl Stream<Item = CloudBoostclicksResult<Vec<u8>>> + Send + 'a {
+        self.stream_content(storage_id, file.id, None, file.codec.clone(), 0..u64::MAX)
+    }
+
+    /// Yields `range` of the content of the file or of its version, only chunks covering
+    /// the range are downloaded.
+    ///
+    /// Compressed chunks don't line up with the content, so those are read from the first one
+    pub fn stream_content<'a>(
+        &'a self,
+        storage_id: Uuid,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+        codec: Option<String>,
+        range: Range<u64>,
+    ) -> impl Stream<Item = CloudBoostclicksResult<Vec<u8>>> + Send + 'a {
+        async_stream::try_stream! {
+            let chunks = self.list_chunks(file_id, version_id).await?;
+            let mut decompressor = codec.as_deref().map(Decompressor::new).transpose()?;
+            let mut parity_chunks = None;
+            let mut offset = 0;
+
+            for chunk in &chunks {
+                if offset >= range.end {
+                    break;
+                }
+                // a plain chunk is as long as its part of the content
+                if let (None, Some(size)) = (&decompressor, chunk.size) {
+                    if offset + size as u64 <= range.start {
+                        offset += size as u64;
+                        continue;
+                    }
+                }
+
+                let data = match self.download_chunk(storage_id, chunk.clone()).await {
+                    Ok(downloaded) => downloaded.data,
+                    Err(e) => {
+                        tracing::warn!(
+                            "restoring chunk with position \"{}\" of file \"{file_id}\" ({e})",
+                            chunk.position
+                        );
+                        if parity_chunks.is_none() {
+                            parity_chunks = Some(self.list_parity_chunks(file_id, version_id).await?);
+                        }
+                        let parity = parity_chunks.as_deref().unwrap_or_default();
+                        let group = chunk.position / parity.first().and_then(|c| c.parity_group_size).unwrap_or(1);
+
+                        self.restore_group(storage_id, &chunks, parity, group, &HashMap::new())
+                            .await?
+                            .into_iter()
+                            .find(|(c, _)| !c.is_parity() && c.position == chunk.position)
+                            .map(|(_, data)| data)
+                            .ok_or(CloudBoostclicksError::ChunksUnrecoverable)?
+                    }
+                };
+                let mut data = match decompressor.as_mut() {
+                    Some(decompressor) => decompressor.feed(&data)?,
+                    None => data,
+                };
+
+                // trimming the parts out of the range
+                let len = data.len() as u64;
+                let start = range.start.saturating_sub(offset).min(len) as usize;
+                let end = range.end.saturating_sub(offset).min(len) as usize;
+                offset += len;
+                if start < end {
+                    data.truncate(end);
+                    data.drain(..start);
+                    yield data;
+                }
+            }
+        }
+    }
+
+    /// Data chunks of the file or of its version in order
+    pub async fn list_chunks(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        let mut chunks = match version_id {
+            Some(version_id) => FileVersionsRepository::new(self.db).list_chunks(version_id).await?,
+            None => self.files_repo.list_chunks_of_file(file_id).await?,
+        };
+        chunks.sort_by_key(|chunk| chunk.position);
+
+        Ok(chunks)
+    }
+
+    async fn list_parity_chunks(
+        &self,
+        file_id: Uuid,
+        version_id: Option<Uuid>,
+    ) -> CloudBoostclicksResult<Vec<FileChunk>> {
+        match version_id {
+            Some(version_id) => FileVersionsRepository::new(self.db).list_parity_chunks(version_id).await,
+            None => self.files_repo.list_parity_chunks_of_file(file_id).await,
+        }
+    }
+
+    /// Downloads the chunk from the storage chat, falling back to its copies in replica chats
     pub async fn download_chunk(
         &self,
         storage_id: Uuid,
         chunk: FileChunk,
     ) -> CloudBoostclicksResult<DownloadedChunkSchema> {
+        let error = match self
+            .download_telegram_file(
+                storage_id,
+                chunk.storage_worker_id,
+                &chunk.telegram_file_id,
+                &chunk,
+            )
+            .await
+        {
+            Ok(data) => {
+                tracing::debug!(
+                    "[TELEGRAM API] downloaded chunk with file_id \"{}\" and position \"{}\"",
+                    chunk.file_id,
+                    chunk.position
+                );
+                return Ok(DownloadedChunkSchema::new(chunk.position, data));
+            }
+            Err(e) => e,
+        };
+
+        let copies = self
+            .replicas_repo
+            .list_copies(storage_id, &chunk.telegram_file_id)
+            .await?;
+        for copy in copies {
+            tracing::warn!(
+                "chunk with file_id \"{}\" and position \"{}\" is unavailable ({error}), trying replica \"{}\"",
+                chunk.file_id,
+                chunk.position,
+                copy.replica_id
+            );
+
+            match self
+                .download_telegram_file(
+                    storage_id,
+                    copy.storage_worker_id,
+                    &copy.telegram_file_id,
+                    &chunk,
+                )
+                .await
+            {
+                Ok(data) => return Ok(DownloadedChunkSchema::new(chunk.position, data)),
+                Err(e) => tracing::error!(
+                    "[TELEGRAM API] can't download copy \"{}\": {e}",
+                    copy.telegram_file_id
+                ),
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Restores every chunk of the erasure coding group from the ones which can still be downloaded.
+    ///
+    /// Content of chunks given in `known` is used as is, the rest is downloaded until there is enough
+    /// to reconstruct the group
+    pub async fn restore_group(
+        &self,
+        storage_id: Uuid,
+        data_chunks: &[FileChunk],
+        parity_chunks: &[FileChunk],
+        group: Position,
+        known: &HashMap<Position, Vec<u8>>,
+    ) -> CloudBoostclicksResult<Vec<(FileChunk, Vec<u8>)>> {
+        // 1. collecting chunks of the group, data chunks first
+        let mut parity_chunks: Vec<&FileChunk> = parity_chunks
+            .iter()
+            .filter(|chunk| chunk.position == group)
+            .collect();
+        parity_chunks.sort_by_key(|chunk| chunk.parity_index);
+
+        let Some(group_size) = parity_chunks.first().and_then(|c| c.parity_group_size) else {
+            return Err(CloudBoostclicksError::ChunksUnrecoverable);
+        };
+        let start = group * group_size;
+
+        let mut group_chunks: Vec<&FileChunk> = data_chunks
+            .iter()
+            .filter(|chunk| (start..start + group_size).contains(&chunk.position))
+            .collect();
+        group_chunks.sort_by_key(|chunk| chunk.position);
+        let data_count = group_chunks.len();
+        group_chunks.extend(parity_chunks);
+
+        // 2. getting shards until there are as many as data chunks
+        let mut shards = Vec::with_capacity(group_chunks.len());
+        let mut present = 0;
+        for chunk in &group_chunks {
+            let shard = if present >= data_count {
+                None
+            } else if let Some(data) = known.get(&chunk.position).filter(|_| !chunk.is_parity()) {
+                Some(data.clone())
+            } else {
+                self.download_chunk(storage_id, (*chunk).clone())
+                    .await
+                    .map(|downloaded| downloaded.data)
+                    .ok()
+            };
+
+            present += usize::from(shard.is_some());
+            shards.push(shard);
+        }
+
+        // 3. reconstructing the rest
+        let shard_len = group_chunks
+            .iter()
+            .filter_map(|chunk| chunk.size)
+            .max()
+            .unwrap_or(0) as usize;
+        erasure::reconstruct(&mut shards, data_count, shard_len)?;
+
+        Ok(group_chunks
+            .into_iter()
+            .zip(shards)
+            .map(|(chunk, shard)| {
+                let mut data = shard.unwrap_or_default();
+                data.truncate(chunk.size.unwrap_or(shard_len as i32) as usize);
+                (chunk.clone(), data)
+            })
+            .collect())
+    }
+
+    /// Encrypts the content of the chunk the same way it's stored in telegram
+    pub fn seal_chunk(&self, chunk: &FileChunk, data: Vec<u8>) -> CloudBoostclicksResult<Vec<u8>> {
+        match &chunk.data_key {
+            Some(wrapped) => DataKey::unwrap(self.encryption_secret, wrapped)?.encrypt(&data),
+            None => Ok(data),
+        }
+    }
+
+    /// Downloads the telegram file holding the chunk's content, decrypting and verifying it
+    async fn download_telegram_file(
+        &self,
+        storage_id: Uuid,
+        worker_id: Option<Uuid>,
+        telegram_file_id: &str,
+        chunk: &FileChunk,
+    ) -> CloudBoostclicksResult<Vec<u8>> {
+        let data_key = match &chunk.data_key {
+            Some(wrapped) => Some(DataKey::unwrap(self.encryption_secret, wrapped)?),
+            None => None,
+        };
+
         let scheduler = StorageWorkersScheduler::new(self.db, self.rate_limit);
-        let token_schema = if let Some(worker_id) = chunk.storage_worker_id {
+        let token_schema = if let Some(worker_id) = worker_id {
             scheduler.get_token_for_worker(worker_id).await?
         } else {
             scheduler.get_token(storage_id).await?
         };
 
-        let file = TelegramBotApi::new(self.telegram_baseurl)
-            .download(&chunk.telegram_file_id, token_schema.token.clone())
+        let api = TelegramBotApi::new(self.telegram_baseurl);
+        for attempt in 1..=DOWNLOAD_ATTEMPTS {
+            let payload = api
+                .download(telegram_file_id, token_schema.token.clone())
+                .await?;
+            let data = match &data_key {
+                Some(data_key) => data_key.decrypt(&payload)?,
+                None => payload,
+            };
+
+            if let Some(sha256) = &chunk.sha256 {
+                if *sha256 != sha256_hex(&data) {
+                    tracing::warn!(
+                        "[TELEGRAM API] telegram file \"{telegram_file_id}\" is corrupted, attempt {attempt}"
+                    );
+                    continue;
+                }
+            }
+
+            return Ok(data);
+        }
+
+        Err(CloudBoostclicksError::ChecksumMismatch)
+    }
+
+    /// Sends the stored content of a telegram file to every replica chat of the storage lacking a copy of it,
+    /// returns how many copies were made
+    pub async fn replicate(&self, storage_id: Uuid, source_telegram_file_id: &str, data: &[u8]) -> usize {
+        let replicas = match self
+            .replicas_repo
+            .list_missing(storage_id, source_telegram_file_id)
             .await
-            .map(|data| DownloadedChunkSchema::new(chunk.position, data))?;
+        {
+            Ok(replicas) => replicas,
+            Err(e) => {
+                tracing::error!("can't list replicas of storage \"{storage_id}\": {e}");
+                return 0;
+            }
+        };
+
+        let mut copied = 0;
+        for replica in replicas {
+            match self
+                .upload_copy(storage_id, &replica, source_telegram_file_id, data)
+                .await
+            {
+                Ok(()) => copied += 1,
+                Err(e) => tracing::error!(
+                    "[TELEGRAM API] can't copy \"{source_telegram_file_id}\" to chat \"{}\": {e}",
+                    replica.chat_id
+                ),
+            }
+        }
+
+        copied
+    }
+
+    async fn upload_copy(
+        &self,
+        storage_id: Uuid,
+        replica: &StorageReplica,
+        source_telegram_file_id: &str,
+        data: &[u8],
+    ) -> CloudBoostclicksResult<()> {
+        let scheduler = StorageWorkersScheduler::new(self.db, self.rate_limit);
+        let worker = scheduler.get_token(storage_id).await?;
+
+        let message = TelegramBotApi::new(self.telegram_baseurl)
+            .upload(data, replica.chat_id, worker.token.clone())
+            .await?;
 
         tracing::debug!(
-            "[TELEGRAM API] downloaded chunk with file_id \"{}\" and position \"{}\"",
-            chunk.file_id,
-            chunk.position
+            "[TELEGRAM API] copied \"{source_telegram_file_id}\" to chat \"{}\"",
+            replica.chat_id
         );
 
-        Ok(file)
+        self.replicas_repo
+            .create_copy(&ChunkCopy {
+                id: Uuid::new_v4(),
+                replica_id: replica.id,
+                source_telegram_file_id: source_telegram_file_id.to_string(),
+                telegram_file_id: message.document.file_id,
+                storage_worker_id: Some(worker.id),
+                message_id: Some(message.message_id),
+                chat_id: Some(message.chat.id),
+            })
+            .await
     }
-}
 
+    /// Removes telegram messages of chunks which are not referenced by any file anymore
+    /// together with their copies in replica chats.
+    ///
+    /// Chunks uploaded before message ids were tracked are skipped.
+    pub async fn delete_chunks(&self, chunks: Vec<FileChunk>) {
+        let telegram_file_ids: Vec<&str> = chunks
+            .iter()
+            .map(|chunk| chunk.telegram_file_id.as_str())
+            .collect();
+        let copies = match self.replicas_repo.take_copies(&telegram_file_ids).await {
+            Ok(copies) => copies,
+            Err(e) => {
+                tracing::error!("can't take copies of deleted chunks: {e}");
+                Vec::new()
+            }
+        };
+
+        let messages = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.message_id,
+                    chunk.chat_id,
+                    chunk.storage_worker_id,
+                    &chunk.telegram_file_id,
+                )
+            })
+            .chain(copies.iter().map(|copy| {
+                (
+                    copy.message_id,
+                    copy.chat_id,
+                    copy.storage_worker_id,
+                    &copy.telegram_file_id,
+                )
+            }));
+
+        let scheduler = StorageWorkersScheduler::new(self.db, self.rate_limit);
+        let api = TelegramBotApi::new(self.telegram_baseurl);
+
+        for (message_id, chat_id, worker_id, telegram_file_id) in messages {
+            let (Some(message_id), Some(chat_id), Some(worker_id)) = (message_id, chat_id, worker_id)
+            else {
+                continue;
+            };
+
+            let result = match scheduler.get_token_for_worker(worker_id).await {
+                Ok(worker) => api.delete_message(chat_id, message_id, worker.token).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(_) => tracing::debug!(
+                    "[TELEGRAM API] deleted message \"{message_id}\" of chunk \"{telegram_file_id}\""
+                ),
+                Err(e) => tracing::error!(
+                    "[TELEGRAM API] can't delete message \"{message_id}\" in chat \"{chat_id}\": {e}"
+                ),
+            }
+        }
+    }
+}
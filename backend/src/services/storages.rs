@@ -6,25 +6,35 @@ use crate::{
     errors::{CloudBoostclicksError, CloudBoostclicksResult},
     models::{
         access::{AccessType, UserWithAccess},
-        storages::{InStorage, Storage, StorageWithInfo},
+        storage_replicas::StorageReplica,
+        storages::{DedupStats, InStorage, Storage, StorageWithInfo},
+    },
+    repositories::{
+        access::AccessRepository, storage_replicas::StorageReplicasRepository,
+        storages::StoragesRepository,
     },
-    repositories::{access::AccessRepository, storages::StoragesRepository},
     schemas::{
         access::{GrantAccess, RestrictAccess},
-        storages::InStorageSchema,
+        storages::{InReplicaSchema, InStorageSchema, StorageSettingsSchema},
     },
 };
 
 pub struct StoragesService<'d> {
     repo: StoragesRepository<'d>,
     access_repo: AccessRepository<'d>,
+    replicas_repo: StorageReplicasRepository<'d>,
 }
 
 impl<'d> StoragesService<'d> {
     pub fn new(db: &'d PgPool) -> Self {
         let repo = StoragesRepository::new(db);
         let access_repo = AccessRepository::new(db);
-        Self { repo, access_repo }
+        let replicas_repo = StorageReplicasRepository::new(db);
+        Self {
+            repo,
+            access_repo,
+            replicas_repo,
+        }
     }
 
     pub async fn create(
@@ -42,7 +52,11 @@ impl<'d> StoragesService<'d> {
         }
 
         // creating storage
-        let in_model = InStorage::new(in_schema.name, in_schema.chat_id);
+        let in_model = InStorage::new(
+            in_schema.name,
+            in_schema.chat_id,
+            in_schema.client_encrypted,
+        );
         let storage = self.repo.create(in_model).await?;
 
         // setting user as the storage admin
@@ -68,6 +82,80 @@ impl<'d> StoragesService<'d> {
         self.repo.get_by_id(id).await
     }
 
+    pub async fn dedup_stats(&self, id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<DedupStats> {
+        check_access(&self.access_repo, user.id, id, &AccessType::R).await?;
+
+        self.repo.dedup_stats(id).await
+    }
+
+    pub async fn update_settings(
+        &self,
+        id: Uuid,
+        in_schema: StorageSettingsSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Storage> {
+        check_access(&self.access_repo, user.id, id, &AccessType::A).await?;
+
+        // the server can't encrypt or compress what is already encrypted by clients
+        let settings = self.repo.get_by_id(id).await?.settings;
+        if settings.client_encrypted
+            && (in_schema.encryption == Some(true) || in_schema.compression == Some(true))
+        {
+            return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+        }
+
+        // the galois field limits a group to 256 chunks
+        let data_chunks = in_schema
+            .erasure_data_chunks
+            .unwrap_or(settings.erasure_data_chunks);
+        let parity_chunks = in_schema
+            .erasure_parity_chunks
+            .unwrap_or(settings.erasure_parity_chunks);
+        if data_chunks < 1 || parity_chunks < 0 || data_chunks + parity_chunks > 256 {
+            return Err(CloudBoostclicksError::InvalidErasureCoding);
+        }
+
+        self.repo.update_settings(id, in_schema).await
+    }
+
+    pub async fn list_replicas(
+        &self,
+        id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<StorageReplica>> {
+        check_access(&self.access_repo, user.id, id, &AccessType::A).await?;
+
+        self.replicas_repo.list(id).await
+    }
+
+    /// Existing chunks get copied to the new replica by the scrub job
+    pub async fn add_replica(
+        &self,
+        id: Uuid,
+        in_schema: InReplicaSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<StorageReplica> {
+        check_access(&self.access_repo, user.id, id, &AccessType::A).await?;
+
+        let storage = self.repo.get_by_id(id).await?;
+        if storage.chat_id == in_schema.chat_id {
+            return Err(CloudBoostclicksError::ReplicaInStorageChat);
+        }
+
+        self.replicas_repo.create(id, in_schema.chat_id).await
+    }
+
+    pub async fn remove_replica(
+        &self,
+        id: Uuid,
+        replica_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        check_access(&self.access_repo, user.id, id, &AccessType::A).await?;
+
+        self.replicas_repo.delete(replica_id, id).await
+    }
+
     pub async fn delete(&self, id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<()> {
         check_access(&self.access_repo, user.id, id, &AccessType::A).await?;
 
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{access::check_access, jwt_manager::AuthUser},
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{
+        access::AccessType,
+        file_chunks::FileChunk,
+        files::{File, InFile},
+        storages::Storage,
+        transfers::{InTransfer, Transfer},
+    },
+    repositories::{
+        access::AccessRepository, files::FilesRepository,
+        storage_workers::StorageWorkersRepository, storages::StoragesRepository,
+        transfers::TransfersRepository,
+    },
+    schemas::{files::ClientEnvelope, transfers::InTransferSchema},
+};
+
+use super::storage_manager::StorageManagerService;
+
+/// Moves data between storages in the background.
+///
+/// Chunks are reused when both storages have the same bot, otherwise they are forwarded
+/// into the destination chat and, as the last resort, downloaded and uploaded again.
+///
+/// A folder isn't transferred atomically: files moved before a failure stay in the destination,
+/// only the file being moved is deleted. A transfer runs in the process which started it,
+/// so transfers left unfinished by a restart are failed by `fail_interrupted`.
+pub struct TransfersService<'d> {
+    db: &'d PgPool,
+    repo: TransfersRepository<'d>,
+    files_repo: FilesRepository<'d>,
+    storages_repo: StoragesRepository<'d>,
+    storage_workers_repo: StorageWorkersRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    config: Config,
+}
+
+impl<'d> TransfersService<'d> {
+    pub fn new(db: &'d PgPool, config: Config) -> Self {
+        Self {
+            db,
+            repo: TransfersRepository::new(db),
+            files_repo: FilesRepository::new(db),
+            storages_repo: StoragesRepository::new(db),
+            storage_workers_repo: StorageWorkersRepository::new(db),
+            access_repo: AccessRepository::new(db),
+            config,
+        }
+    }
+
+    pub async fn create(
+        &self,
+        in_schema: InTransferSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Transfer> {
+        // 0. checking access
+        check_access(
+            &self.access_repo,
+            user.id,
+            in_schema.source_storage_id,
+            &AccessType::R,
+        )
+        .await?;
+        check_access(
+            &self.access_repo,
+            user.id,
+            in_schema.destination_storage_id,
+            &AccessType::W,
+        )
+        .await?;
+
+        // 1. validation
+        if !Self::validate_path(&in_schema.source_path)
+            || !Self::validate_path(&in_schema.destination_path)
+            || in_schema.destination_path.trim_end_matches('/').is_empty()
+        {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+        if !self
+            .storage_workers_repo
+            .storage_has_any(in_schema.destination_storage_id)
+            .await?
+        {
+            return Err(CloudBoostclicksError::StorageDoesNotHaveWorkers);
+        }
+        let source = self.storages_repo.get_by_id(in_schema.source_storage_id).await?;
+        let destination = self
+            .storages_repo
+            .get_by_id(in_schema.destination_storage_id)
+            .await?;
+        if source.settings.client_encrypted != destination.settings.client_encrypted {
+            return Err(CloudBoostclicksError::UnavailableForClientEncryption);
+        }
+
+        // 2. registering the job
+        let in_model = InTransfer::new(
+            user.id,
+            in_schema.source_storage_id,
+            in_schema.source_path,
+            in_schema.destination_storage_id,
+            in_schema.destination_path,
+        );
+        let transfer = self.repo.create(in_model).await?;
+
+        // 3. running it in the background
+        let (db, config, id) = (self.db.clone(), self.config.clone(), transfer.id);
+        tokio::spawn(async move {
+            TransfersService::new(&db, config).run(id).await;
+        });
+
+        Ok(transfer)
+    }
+
+    /// Fails transfers of the previous run of the server, nothing runs them anymore
+    pub async fn fail_interrupted(&self) -> CloudBoostclicksResult<u64> {
+        self.repo
+            .fail_unfinished(&CloudBoostclicksError::TransferInterrupted.to_string())
+            .await
+    }
+
+    pub async fn get(&self, id: Uuid, user: &AuthUser) -> CloudBoostclicksResult<Transfer> {
+        let transfer = self.repo.get_by_id(id).await?;
+
+        if transfer.user_id != user.id {
+            return Err(CloudBoostclicksError::DoesNotExist(format!(
+                "перенос с id \"{id}\""
+            )));
+        }
+
+        Ok(transfer)
+    }
+
+    pub async fn list(&self, user: &AuthUser) -> CloudBoostclicksResult<Vec<Transfer>> {
+        self.repo.list_by_user_id(user.id).await
+    }
+
+    async fn run(&self, id: Uuid) {
+        let result = self.transfer(id).await;
+
+        if let Err(e) = &result {
+            tracing::error!("transfer \"{id}\" failed: {e}");
+        }
+
+        if let Err(e) = self.repo.finish(id, result.err().map(|e| e.to_string())).await {
+            tracing::error!("can't finish transfer \"{id}\": {e}");
+        }
+    }
+
+    async fn transfer(&self, id: Uuid) -> CloudBoostclicksResult<()> {
+        let transfer = self.repo.get_by_id(id).await?;
+
+        // 1. collecting what is going to be transferred
+        let (source_path, is_folder, files) = self
+            .files_repo
+            .list_element(&transfer.source_path, transfer.source_storage_id)
+            .await?;
+        let mut files_with_chunks = Vec::with_capacity(files.len());
+        for file in files {
+            let chunks = self.files_repo.list_chunks_of_file(file.id).await?;
+            files_with_chunks.push((file, chunks));
+        }
+        let total_chunks = files_with_chunks.iter().map(|(_, c)| c.len()).sum::<usize>() as i32;
+
+        self.repo.set_running(id, total_chunks).await?;
+
+        // 2. the cheap way: the destination has the same bots, so chunks can be referenced as is
+        match self
+            .files_repo
+            .copy(
+                &source_path,
+                transfer.source_storage_id,
+                &transfer.destination_path,
+                transfer.destination_storage_id,
+                transfer.user_id,
+                false,
+            )
+            .await
+        {
+            Ok(_) => return self.repo.add_progress(id, total_chunks).await,
+            Err(CloudBoostclicksError::StoragesDoNotShareWorkers) => (),
+            Err(e) => return Err(e),
+        }
+
+        // 3. the expensive way: moving chunks one by one
+        let destination = self
+            .storages_repo
+            .get_by_id(transfer.destination_storage_id)
+            .await?;
+        let destination_path = transfer.destination_path.trim_end_matches('/');
+        let mut same_bots = HashMap::new();
+
+        for (file, chunks) in files_with_chunks {
+            let new_path = if is_folder {
+                format!("{destination_path}/{}", &file.path[source_path.len()..])
+            } else {
+                destination_path.to_string()
+            };
+
+            if file.path.ends_with('/') {
+                let in_file = InFile::new(new_path, 0, destination.id, transfer.user_id);
+                match self.files_repo.create_folder(in_file).await {
+                    Ok(_) | Err(CloudBoostclicksError::AlreadyExists(_)) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let in_file = InFile::new(new_path, file.size, destination.id, transfer.user_id);
+            let new_file = self.files_repo.create_file(in_file).await?;
+            if let Some(envelope) =
+                ClientEnvelope::from_fields(file.encrypted_key.clone(), file.client_metadata.clone())
+            {
+                self.files_repo.set_envelope(new_file.id, &envelope).await?;
+            }
+            if file.codec.is_some() {
+                self.files_repo
+                    .set_codec(new_file.id, file.codec.as_deref())
+                    .await?;
+            }
+
+            let result = self
+                .transfer_file(id, &file, chunks, new_file.id, &destination, &mut same_bots)
+                .await;
+            if let Err(e) = result {
+                // fallback logic: deleting partially transferred file
+                let _ = self.files_repo.delete_with_folders(new_file.id).await;
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn transfer_file(
+        &self,
+        id: Uuid,
+        file: &File,
+        chunks: Vec<FileChunk>,
+        new_file_id: Uuid,
+        destination: &Storage,
+        same_bots: &mut HashMap<Uuid, Option<Uuid>>,
+    ) -> CloudBoostclicksResult<()> {
+        let storage_manager = StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        );
+
+        let mut new_chunks = Vec::with_capacity(chunks.len());
+        let mut data_key = None;
+        for chunk in chunks {
+            // 1. reusing the telegram file if the destination got the same bot
+            let same_bot = match chunk.storage_worker_id {
+                Some(worker_id) => match same_bots.get(&worker_id) {
+                    Some(same_bot) => *same_bot,
+                    None => {
+                        let same_bot = self
+                            .storage_workers_repo
+                            .get_same_bot_in_storage(worker_id, destination.id)
+                            .await?;
+                        same_bots.insert(worker_id, same_bot);
+                        same_bot
+                    }
+                },
+                None => None,
+            };
+
+            let new_chunk = if let Some(worker_id) = same_bot {
+                FileChunk::new(
+                    Uuid::new_v4(),
+                    new_file_id,
+                    chunk.telegram_file_id,
+                    Some(worker_id),
+                    chunk.position,
+                    chunk.message_id,
+                    chunk.chat_id,
+                )
+                .with_content(chunk.sha256, chunk.size)
+                .with_data_key(chunk.data_key)
+            } else {
+                // 2. forwarding the message, works when the bot is a member of both chats
+                match storage_manager
+                    .forward_chunk(destination.id, destination.chat_id, new_file_id, &chunk)
+                    .await
+                {
+                    Ok(new_chunk) => new_chunk,
+                    Err(e) => {
+                        tracing::debug!(
+                            "can't forward chunk of file \"{}\", re-uploading it: {e}",
+                            file.id
+                        );
+
+                        // 3. downloading and uploading it again
+                        let position = chunk.position;
+                        let data = storage_manager
+                            .download_chunk(file.storage_id, chunk)
+                            .await?
+                            .data;
+                        if data_key.is_none() {
+                            data_key = storage_manager
+                                .data_key_for(destination, new_file_id, None)
+                                .await?;
+                        }
+                        storage_manager
+                            .upload_chunk(
+                                destination,
+                                new_file_id,
+                                position as usize,
+                                &data,
+                                data_key.as_ref(),
+                            )
+                            .await?
+                    }
+                }
+            };
+
+            new_chunks.push(new_chunk);
+            self.repo.add_progress(id, 1).await?;
+        }
+
+        self.files_repo.create_chunks_batch(new_chunks).await?;
+        if let Some(sha256) = &file.sha256 {
+            self.files_repo.set_sha256(new_file_id, sha256).await?;
+        }
+        self.files_repo.set_as_uploaded(new_file_id, None, None).await
+    }
+
+    fn validate_path(path: &str) -> bool {
+        !path.starts_with(r"/") && !path.contains(r"//")
+    }
+}
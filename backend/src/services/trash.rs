@@ -0,0 +1,113 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{access::check_access, jwt_manager::AuthUser},
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::{access::AccessType, trash::TrashItem},
+    repositories::{access::AccessRepository, trash::TrashRepository},
+    schemas::trash::RestoreSchema,
+};
+
+use super::storage_manager::StorageManagerService;
+
+pub struct TrashService<'d> {
+    db: &'d PgPool,
+    repo: TrashRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    config: Config,
+}
+
+impl<'d> TrashService<'d> {
+    pub fn new(db: &'d PgPool, config: Config) -> Self {
+        Self {
+            db,
+            repo: TrashRepository::new(db),
+            access_repo: AccessRepository::new(db),
+            config,
+        }
+    }
+
+    pub async fn list(
+        &self,
+        storage_id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<TrashItem>> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        self.repo.list(storage_id).await
+    }
+
+    pub async fn restore(
+        &self,
+        storage_id: Uuid,
+        id: Uuid,
+        in_schema: RestoreSchema,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        // 0. checking access
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        // 1. path validation
+        if let Some(path) = &in_schema.path {
+            if path.starts_with('/') || path.contains("//") || path.trim_end_matches('/').is_empty()
+            {
+                return Err(CloudBoostclicksError::InvalidPath);
+            }
+        }
+
+        // 2. restoring
+        self.repo
+            .restore(id, storage_id, in_schema.path.as_deref())
+            .await
+    }
+
+    pub async fn purge(
+        &self,
+        storage_id: Uuid,
+        id: Uuid,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::W).await?;
+
+        if !self.repo.exists(id, storage_id).await? {
+            return Err(CloudBoostclicksError::DoesNotExist("элемент корзины".to_string()));
+        }
+
+        self._purge(id).await
+    }
+
+    /// Purges everything which has been in trash longer than the retention period
+    pub async fn purge_expired(&self) -> CloudBoostclicksResult<()> {
+        let ids = self
+            .repo
+            .list_expired(self.config.trash_retention_days)
+            .await?;
+
+        // an item which can't be purged is retried with the next run, the rest go on
+        for id in ids {
+            tracing::debug!("purging trash item \"{id}\"");
+            if let Err(e) = self._purge(id).await {
+                tracing::error!("can't purge trash item \"{id}\": {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn _purge(&self, id: Uuid) -> CloudBoostclicksResult<()> {
+        let unreferenced_chunks = self.repo.purge(id).await?;
+
+        StorageManagerService::new(
+            self.db,
+            &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
+            self.config.telegram_rate_limit,
+        )
+        .delete_chunks(unreferenced_chunks)
+        .await;
+
+        Ok(())
+    }
+}
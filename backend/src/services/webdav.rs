@@ -0,0 +1,183 @@
+use axum::body::Bytes;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    common::{access::check_access, channels::ClientSender, jwt_manager::AuthUser},
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    models::access::AccessType,
+    repositories::{access::AccessRepository, files::FilesRepository},
+    schemas::{
+        files::{DownloadedFileSchema, FileRangeSchema, InFolderSchema, ListQuery},
+        webdav::DavResource,
+    },
+    services::files::FilesService,
+};
+
+/// Maps WebDAV methods onto files of a storage, paths are relative to the storage root
+pub struct WebDavService<'d> {
+    repo: FilesRepository<'d>,
+    access_repo: AccessRepository<'d>,
+    files_service: FilesService<'d>,
+}
+
+impl<'d> WebDavService<'d> {
+    pub fn new(db: &'d PgPool, config: Config, tx: ClientSender) -> Self {
+        let repo = FilesRepository::new(db);
+        let access_repo = AccessRepository::new(db);
+        let files_service = FilesService::new(db, config, tx);
+        Self {
+            repo,
+            access_repo,
+            files_service,
+        }
+    }
+
+    /// Finds a file or a folder, the empty path is the root of the storage
+    pub async fn resource(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<DavResource> {
+        check_access(&self.access_repo, user.id, storage_id, &AccessType::R).await?;
+
+        if path.is_empty() {
+            return Ok(DavResource::collection(path));
+        }
+
+        match self.repo.get_uploaded_file_by_path(path, storage_id).await {
+            Ok(file) => return Ok(file.into()),
+            Err(CloudBoostclicksError::DoesNotExist(_)) => (),
+            Err(e) => return Err(e),
+        }
+
+        if self.repo.folder_exists(storage_id, &format!("{path}/")).await? {
+            Ok(DavResource::collection(path))
+        } else {
+            Err(CloudBoostclicksError::DoesNotExist(
+                "файл или папка".to_string(),
+            ))
+        }
+    }
+
+    /// The resource itself followed by its members when `depth` isn't zero
+    pub async fn propfind(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        depth: u8,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Vec<DavResource>> {
+        let resource = self.resource(storage_id, path, user).await?;
+        if !resource.is_collection || depth == 0 {
+            return Ok(vec![resource]);
+        }
+
+        let members = self
+            .repo
+            .list_dir(storage_id, path, &ListQuery::default())
+            .await?;
+
+        Ok(std::iter::once(resource)
+            .chain(members.into_iter().map(DavResource::from))
+            .collect())
+    }
+
+    pub async fn get(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<DownloadedFileSchema> {
+        self.files_service.download(path, storage_id, None, user).await
+    }
+
+    /// A part of the file given by the `Range` header, `None` means the whole file
+    pub async fn range(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        range: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<Option<FileRangeSchema>> {
+        self.files_service
+            .download_range(path, storage_id, None, range, user)
+            .await
+    }
+
+    /// The whole content of the file to be streamed
+    pub async fn content(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<FileRangeSchema> {
+        self.files_service.content(path, storage_id, None, user).await
+    }
+
+    /// Creates or replaces the file, returns whether it was created
+    pub async fn put(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        data: Bytes,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<bool> {
+        self.files_service
+            .replace(storage_id, path, data, if_match, user)
+            .await
+    }
+
+    pub async fn mkcol(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        let (parent_path, folder_name) = path.rsplit_once('/').unwrap_or(("", path));
+        let in_schema =
+            InFolderSchema::new(storage_id, parent_path.to_string(), folder_name.to_string());
+
+        self.files_service.create_folder(in_schema, user).await
+    }
+
+    pub async fn delete(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<()> {
+        self.files_service
+            .delete(path, storage_id, if_match, user)
+            .await
+            .map(|_| ())
+    }
+
+    /// Moves or copies a resource, returns whether the destination was created.
+    ///
+    /// An existing destination is moved into the trash when overwriting is allowed,
+    /// in the same transaction as the move or the copy. `If-Match` is compared with the source
+    #[allow(clippy::too_many_arguments)]
+    pub async fn transfer(
+        &self,
+        storage_id: Uuid,
+        path: &str,
+        destination_path: &str,
+        is_move: bool,
+        overwrite: bool,
+        if_match: Option<&str>,
+        user: &AuthUser,
+    ) -> CloudBoostclicksResult<bool> {
+        if destination_path.is_empty() {
+            return Err(CloudBoostclicksError::InvalidPath);
+        }
+
+        self.files_service
+            .transfer(path, destination_path, storage_id, is_move, overwrite, if_match, user)
+            .await
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use sqlx::PgPool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        channels::ClientSender,
+        jwt_manager::AuthUser,
+        sftp::{self, Attrs, Reader},
+    },
+    config::Config,
+    errors::{CloudBoostclicksError, CloudBoostclicksResult},
+    schemas::files::FileRangeSchema,
+    services::{
+        content::{ContentService, ContentStream},
+        sftp::{SftpService, SftpUpload},
+        ssh_keys::SshKeysService,
+    },
+};
+
+/// Packets are limited by the biggest write clients send plus the header
+const MAX_PACKET_SIZE: u32 = 1024 * 1024;
+/// OpenSSH asks for 32 KiB, bigger reads are shortened as the protocol allows
+const MAX_READ_SIZE: usize = 256 * 1024;
+
+/// Prints the `authorized_keys` line for the key sshd offers or nothing when it's unknown
+pub async fn authorized_keys(db: &PgPool, fingerprint: &str) {
+    let Ok((key, _)) = SshKeysService::new(db).authenticate(fingerprint).await else {
+        return;
+    };
+    let exe = std::env::current_exe()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| "cloud_boostclicks".to_string());
+
+    println!(
+        "command=\"{exe} sftp-server {}\",restrict {}",
+        key.fingerprint, key.public_key
+    );
+}
+
+enum Request {
+    RealPath(String),
+    Stat(String),
+    FStat(u32),
+    SetStat,
+    Open { path: String, flags: u32 },
+    Close(u32),
+    Read { handle: u32, offset: u64, len: u32 },
+    Write { handle: u32, offset: u64, data: Vec<u8> },
+    OpenDir(String),
+    ReadDir(u32),
+    Remove(String),
+    Mkdir(String),
+    Rmdir(String),
+    Rename { path: String, destination: String },
+    Unsupported,
+}
+
+impl Request {
+    fn parse(kind: u8, reader: &mut Reader) -> Option<Self> {
+        let request = match kind {
+            sftp::FXP_REALPATH => Self::RealPath(reader.string()?),
+            sftp::FXP_STAT | sftp::FXP_LSTAT => Self::Stat(reader.string()?),
+            sftp::FXP_FSTAT => Self::FStat(sftp::parse_handle(reader.bytes()?)?),
+            sftp::FXP_SETSTAT | sftp::FXP_FSETSTAT => Self::SetStat,
+            sftp::FXP_OPEN => {
+                let path = reader.string()?;
+                let flags = reader.u32()?;
+                reader.attrs()?;
+                Self::Open { path, flags }
+            }
+            sftp::FXP_CLOSE => Self::Close(sftp::parse_handle(reader.bytes()?)?),
+            sftp::FXP_READ => Self::Read {
+                handle: sftp::parse_handle(reader.bytes()?)?,
+                offset: reader.u64()?,
+                len: reader.u32()?,
+            },
+            sftp::FXP_WRITE => Self::Write {
+                handle: sftp::parse_handle(reader.bytes()?)?,
+                offset: reader.u64()?,
+                data: reader.bytes()?.to_vec(),
+            },
+            sftp::FXP_OPENDIR => Self::OpenDir(reader.string()?),
+            sftp::FXP_READDIR => Self::ReadDir(sftp::parse_handle(reader.bytes()?)?),
+            sftp::FXP_REMOVE => Self::Remove(reader.string()?),
+            sftp::FXP_MKDIR => Self::Mkdir(reader.string()?),
+            sftp::FXP_RMDIR => Self::Rmdir(reader.string()?),
+            sftp::FXP_RENAME => Self::Rename {
+                path: reader.string()?,
+                destination: reader.string()?,
+            },
+            _ => Self::Unsupported,
+        };
+        Some(request)
+    }
+}
+
+/// Files are read by ranges of chunks and written only sequentially, chunk by chunk
+enum Handle {
+    Dir(Option<Vec<(String, Attrs)>>),
+    Reader {
+        storage_id: Uuid,
+        content: FileRangeSchema,
+        /// The offset of the buffered bytes and the stream of the bytes after them
+        stream: Option<(u64, ContentStream)>,
+        buffered: Vec<u8>,
+    },
+    Writer {
+        upload: SftpUpload,
+        /// The content of the file opened without truncating, it goes first on the first write
+        prefill: Option<(Uuid, FileRangeSchema)>,
+        append: bool,
+        dirty: bool,
+    },
+}
+
+/// One SFTP session of a user over stdin and stdout, sshd runs it as a subsystem
+pub struct SftpServer {
+    db: PgPool,
+    config: Config,
+    tx: ClientSender,
+    user: AuthUser,
+    handles: HashMap<u32, Handle>,
+    next_handle: u32,
+}
+
+impl SftpServer {
+    /// Starts a session for the owner of the key, `None` when the key was deleted meanwhile
+    pub async fn new(
+        db: PgPool,
+        config: Config,
+        tx: ClientSender,
+        fingerprint: &str,
+    ) -> Option<Self> {
+        let (_, user) = SshKeysService::new(&db)
+            .authenticate(fingerprint)
+            .await
+            .ok()?;
+
+        Some(Self {
+            db,
+            config,
+            tx,
+            user,
+            handles: HashMap::new(),
+            next_handle: 0,
+        })
+    }
+
+    pub async fn run(&mut self) {
+        let mut stdin = tokio::io::stdin();
+        let mut stdout = tokio::io::stdout();
+
+        while let Ok(len) = stdin.read_u32().await {
+            if len == 0 || len > MAX_PACKET_SIZE {
+                tracing::error!("sftp packet of {len} bytes is rejected");
+                break;
+            }
+            let mut packet = vec![0; len as usize];
+            if stdin.read_exact(&mut packet).await.is_err() {
+                break;
+            }
+
+            let response = self.handle_packet(&packet).await;
+            if stdout.write_all(&response).await.is_err() || stdout.flush().await.is_err() {
+                break;
+            }
+        }
+
+        // files which were written but not closed before the client went away
+        for handle in self.handles.keys().copied().collect::<Vec<_>>() {
+            if let Err(e) = self.close(handle).await {
+                tracing::error!("can't save an unclosed sftp file: {e}");
+            }
+        }
+    }
+
+    async fn handle_packet(&mut self, packet: &[u8]) -> Vec<u8> {
+        let mut reader = Reader::new(packet);
+        let kind = reader.u8().unwrap_or_default();
+        if kind == sftp::FXP_INIT {
+            return sftp::version();
+        }
+
+        let Some(id) = reader.u32() else {
+            return sftp::status(0, sftp::FX_BAD_MESSAGE, "неверный пакет");
+        };
+        let Some(request) = Request::parse(kind, &mut reader) else {
+            return sftp::status(id, sftp::FX_BAD_MESSAGE, "неверный пакет");
+        };
+
+        match self.execute(id, request).await {
+            Ok(response) => response,
+            Err(e) => sftp::error_status(id, &e),
+        }
+    }
+
+    async fn execute(&mut self, id: u32, request: Request) -> CloudBoostclicksResult<Vec<u8>> {
+        let service = SftpService::new(&self.db, self.config.clone(), self.tx.clone());
+        let ok = sftp::status(id, sftp::FX_OK, "");
+
+        match request {
+            Request::RealPath(path) => {
+                let path = format!("/{}", sftp::normalize(&path).join("/"));
+                Ok(sftp::name(id, &[(path, Attrs::dir(None))]))
+            }
+            Request::Stat(path) => {
+                let attrs = service.stat(&sftp::normalize(&path), &self.user).await?;
+                Ok(sftp::attrs(id, &attrs))
+            }
+            Request::FStat(handle) => {
+                let attrs = match self.handles.get(&handle) {
+                    Some(Handle::Dir(_)) => Attrs::dir(None),
+                    Some(Handle::Reader { content, .. }) => {
+                        Attrs::file(content.len, &content.last_modified)
+                    }
+                    Some(Handle::Writer {
+                        prefill: Some((_, content)),
+                        ..
+                    }) => Attrs::file(content.len, &content.last_modified),
+                    Some(Handle::Writer { upload, .. }) => {
+                        Attrs::file(upload.len(), &chrono::Utc::now().naive_utc())
+                    }
+                    None => return Ok(unknown_handle(id)),
+                };
+                Ok(sftp::attrs(id, &attrs))
+            }
+            Request::SetStat => Ok(ok),
+            Request::Open { path, flags } => {
+                let segments = sftp::normalize(&path);
+                let writable = flags & (sftp::FXF_WRITE | sftp::FXF_APPEND) != 0;
+
+                // 0. checking the file like open(2) does
+                let exists = !writable || service.file_exists(&segments, &self.user).await?;
+                if exists && writable && flags & sftp::FXF_EXCL != 0 {
+                    return Err(CloudBoostclicksError::AlreadyExists("файл".to_string()));
+                }
+                if !exists && flags & sftp::FXF_CREAT == 0 {
+                    return Err(CloudBoostclicksError::DoesNotExist("файл".to_string()));
+                }
+
+                // 1. a read file is streamed when it's read
+                if !writable {
+                    let (storage_id, content) = service.content(&segments, &self.user).await?;
+                    let handle = self.insert(Handle::Reader {
+                        storage_id,
+                        content,
+                        stream: None,
+                        buffered: Vec::new(),
+                    });
+                    return Ok(sftp::handle(id, handle));
+                }
+
+                // 2. a written one keeps its content unless it's truncated
+                let truncated = !exists || flags & sftp::FXF_TRUNC != 0;
+                let prefill = match truncated {
+                    true => None,
+                    false => Some(service.content(&segments, &self.user).await?),
+                };
+                let upload = service.start_upload(&segments, exists, &self.user).await?;
+
+                let handle = self.insert(Handle::Writer {
+                    upload,
+                    prefill,
+                    append: flags & sftp::FXF_APPEND != 0,
+                    dirty: truncated,
+                });
+                Ok(sftp::handle(id, handle))
+            }
+            Request::Close(handle) => {
+                if !self.handles.contains_key(&handle) {
+                    return Ok(unknown_handle(id));
+                }
+                self.close(handle).await?;
+                Ok(ok)
+            }
+            Request::Read { handle, offset, len } => {
+                let content_service = ContentService::new(self.db.clone(), self.config.clone());
+                let Some(Handle::Reader {
+                    storage_id,
+                    content,
+                    stream,
+                    buffered,
+                }) = self.handles.get_mut(&handle)
+                else {
+                    return match self.handles.contains_key(&handle) {
+                        true => Ok(sftp::status(id, sftp::FX_PERMISSION_DENIED, "файл открыт для записи")),
+                        false => Ok(unknown_handle(id)),
+                    };
+                };
+                if offset >= content.len {
+                    return Ok(sftp::status(id, sftp::FX_EOF, ""));
+                }
+
+                // 0. a read which doesn't continue the previous one starts a new stream
+                let position = stream.as_ref().map(|(position, _)| *position);
+                let skipped = position
+                    .and_then(|position| usize::try_from(offset.checked_sub(position)?).ok())
+                    .filter(|skipped| *skipped <= buffered.len());
+                let (position, chunks) = match (stream, skipped) {
+                    (Some((position, chunks)), Some(skipped)) => {
+                        buffered.drain(..skipped);
+                        *position = offset;
+                        (position, chunks)
+                    }
+                    (stream, _) => {
+                        let range = FileRangeSchema {
+                            range: offset..content.len,
+                            ..content.clone()
+                        };
+                        buffered.clear();
+                        let (position, chunks) =
+                            stream.insert((offset, content_service.range(*storage_id, &range)));
+                        (position, chunks)
+                    }
+                };
+
+                // 1. buffering the bytes asked for
+                let len = (len as usize).min(MAX_READ_SIZE);
+                while buffered.len() < len {
+                    match chunks.next().await {
+                        Some(bytes) => buffered.extend_from_slice(&bytes?),
+                        None => break,
+                    }
+                }
+                if buffered.is_empty() {
+                    return Ok(sftp::status(id, sftp::FX_EOF, ""));
+                }
+
+                let read: Vec<u8> = buffered.drain(..len.min(buffered.len())).collect();
+                *position += read.len() as u64;
+                Ok(sftp::data(id, &read))
+            }
+            Request::Write {
+                handle,
+                offset,
+                data: written,
+            } => {
+                let Some(Handle::Writer {
+                    upload,
+                    prefill,
+                    append,
+                    dirty,
+                }) = self.handles.get_mut(&handle)
+                else {
+                    return match self.handles.contains_key(&handle) {
+                        true => Ok(sftp::status(id, sftp::FX_PERMISSION_DENIED, "файл открыт для чтения")),
+                        false => Ok(unknown_handle(id)),
+                    };
+                };
+
+                // 0. the kept content goes first
+                if let Some((storage_id, content)) = prefill.take() {
+                    let mut chunks = ContentService::new(self.db.clone(), self.config.clone())
+                        .range(storage_id, &content);
+                    while let Some(bytes) = chunks.next().await {
+                        service.write_upload(upload, &bytes?, &self.user).await?;
+                    }
+                }
+
+                // 1. the upload is appended only
+                if !*append && offset != upload.len() {
+                    return Ok(sftp::status(
+                        id,
+                        sftp::FX_OP_UNSUPPORTED,
+                        "файлы записываются только подряд",
+                    ));
+                }
+                service.write_upload(upload, &written, &self.user).await?;
+                *dirty = true;
+                Ok(ok)
+            }
+            Request::OpenDir(path) => {
+                let entries = service
+                    .read_dir(&sftp::normalize(&path), &self.user)
+                    .await?;
+                let handle = self.insert(Handle::Dir(Some(entries)));
+                Ok(sftp::handle(id, handle))
+            }
+            Request::ReadDir(handle) => match self.handles.get_mut(&handle) {
+                Some(Handle::Dir(entries)) => match entries.take() {
+                    Some(entries) if !entries.is_empty() => Ok(sftp::name(id, &entries)),
+                    _ => Ok(sftp::status(id, sftp::FX_EOF, "")),
+                },
+                _ => Ok(unknown_handle(id)),
+            },
+            Request::Remove(path) => {
+                service.remove(&sftp::normalize(&path), &self.user).await?;
+                Ok(ok)
+            }
+            Request::Mkdir(path) => {
+                service.mkdir(&sftp::normalize(&path), &self.user).await?;
+                Ok(ok)
+            }
+            Request::Rmdir(path) => {
+                service.rmdir(&sftp::normalize(&path), &self.user).await?;
+                Ok(ok)
+            }
+            Request::Rename { path, destination } => {
+                service
+                    .rename(
+                        &sftp::normalize(&path),
+                        &sftp::normalize(&destination),
+                        &self.user,
+                    )
+                    .await?;
+                Ok(ok)
+            }
+            Request::Unsupported => Ok(sftp::status(
+                id,
+                sftp::FX_OP_UNSUPPORTED,
+                "операция не поддерживается",
+            )),
+        }
+    }
+
+    fn insert(&mut self, handle: Handle) -> u32 {
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.handles.insert(self.next_handle, handle);
+        self.next_handle
+    }
+
+    /// Forgets the handle, uploads the file if it was written
+    async fn close(&mut self, handle: u32) -> CloudBoostclicksResult<()> {
+        match self.handles.remove(&handle) {
+            Some(Handle::Writer {
+                upload,
+                dirty: true,
+                ..
+            }) => {
+                SftpService::new(&self.db, self.config.clone(), self.tx.clone())
+                    .finish_upload(upload, &self.user)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn unknown_handle(id: u32) -> Vec<u8> {
+    sftp::status(id, sftp::FX_FAILURE, "неизвестный дескриптор")
+}
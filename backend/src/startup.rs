@@ -73,7 +73,7 @@ pub async fn init_db(db: &PgPool) {
         CREATE TABLE IF NOT EXISTS storage_workers (
             id         UUID         PRIMARY KEY,
             name       VARCHAR(255) NOT NULL,
-            token      VARCHAR(255) NOT NULL UNIQUE,
+            token      VARCHAR(255) NOT NULL,
             user_id    UUID         NOT NULL REFERENCES users
                                             ON DELETE CASCADE 
                                             ON UPDATE CASCADE,
@@ -176,6 +176,15 @@ pub async fn init_db(db: &PgPool) {
             FROM file_chunks fc
         ) sub
         WHERE fc.id = sub.id AND fc.storage_worker_id IS NULL;
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS message_id BigInt,
+            ADD COLUMN IF NOT EXISTS chat_id    BigInt;
+    ",
+        "
+        CREATE INDEX IF NOT EXISTS file_chunks_telegram_file_id_idx
+            ON file_chunks (telegram_file_id);
     ",
         "
         CREATE TABLE IF NOT EXISTS storage_workers_usages (
@@ -185,6 +194,347 @@ pub async fn init_db(db: &PgPool) {
                                                 ON UPDATE CASCADE,
             dt                 TIMESTAMP DEFAULT NOW()
         );
+    ",
+        "
+        DO
+        $$
+        BEGIN
+        IF NOT EXISTS (
+            SELECT *
+            FROM pg_type typ
+            INNER JOIN pg_namespace nsp ON nsp.oid = typ.typnamespace
+            WHERE nsp.nspname = current_schema() AND typ.typname = 'transfer_status'
+        ) THEN
+            CREATE TYPE transfer_status AS ENUM ('pending', 'running', 'done', 'failed');
+        END IF;
+        END;
+        $$;
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS transfers (
+            id                     UUID            PRIMARY KEY,
+            user_id                UUID            NOT NULL REFERENCES users
+                                                        ON DELETE CASCADE
+                                                        ON UPDATE CASCADE,
+            source_storage_id      UUID            NOT NULL REFERENCES storages
+                                                        ON DELETE CASCADE
+                                                        ON UPDATE CASCADE,
+            source_path            VARCHAR         NOT NULL,
+            destination_storage_id UUID            NOT NULL REFERENCES storages
+                                                        ON DELETE CASCADE
+                                                        ON UPDATE CASCADE,
+            destination_path       VARCHAR         NOT NULL,
+            status                 transfer_status NOT NULL,
+            total_chunks           INT             NOT NULL DEFAULT 0,
+            transferred_chunks     INT             NOT NULL DEFAULT 0,
+            error                  VARCHAR,
+            created_at             TIMESTAMP       DEFAULT NOW(),
+            finished_at            TIMESTAMP
+        );
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS trash (
+            id         UUID      PRIMARY KEY,
+            storage_id UUID      NOT NULL REFERENCES storages
+                                        ON DELETE CASCADE
+                                        ON UPDATE CASCADE,
+            path       VARCHAR   NOT NULL,
+            is_folder  bool      NOT NULL,
+            deleted_by UUID      REFERENCES users
+                                        ON DELETE SET NULL
+                                        ON UPDATE CASCADE,
+            deleted_at TIMESTAMP NOT NULL DEFAULT NOW()
+        );
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS trash_id UUID REFERENCES trash
+                ON DELETE CASCADE
+                ON UPDATE CASCADE;
+    ",
+        "
+        ALTER TABLE files DROP CONSTRAINT IF EXISTS files_path_storage_id_key;
+    ",
+        "
+        CREATE UNIQUE INDEX IF NOT EXISTS files_alive_path_storage_id_key
+            ON files (path, storage_id)
+            WHERE trash_id IS NULL;
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS versioning bool NOT NULL DEFAULT false;
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS version INT NOT NULL DEFAULT 1;
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS file_versions (
+            id          UUID      PRIMARY KEY,
+            file_id     UUID      NOT NULL REFERENCES files
+                                        ON DELETE CASCADE
+                                        ON UPDATE CASCADE,
+            version     INT,
+            size        BigInt    NOT NULL,
+            archived_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+            UNIQUE (file_id, version)
+        );
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS version_id UUID REFERENCES file_versions
+                ON DELETE CASCADE
+                ON UPDATE CASCADE;
+    ",
+        // files uploaded before tracking uploaders are attributed to the only admin of their storage
+        "
+        DO
+        $$
+        BEGIN
+        IF NOT EXISTS (
+            SELECT *
+            FROM information_schema.columns
+            WHERE table_schema = current_schema() AND table_name = 'files' AND column_name = 'uploaded_by'
+        ) THEN
+            ALTER TABLE files
+                ADD COLUMN created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+                ADD COLUMN updated_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+                ADD COLUMN uploaded_by UUID REFERENCES users
+                    ON DELETE SET NULL
+                    ON UPDATE CASCADE;
+
+            UPDATE files f
+            SET uploaded_by = a.user_id
+            FROM (
+                SELECT storage_id, (ARRAY_AGG(user_id))[1] AS user_id
+                FROM access
+                WHERE access_type = 'a'
+                GROUP BY storage_id
+                HAVING COUNT(*) = 1
+            ) a
+            WHERE f.storage_id = a.storage_id;
+        END IF;
+        END;
+        $$;
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+            ADD COLUMN IF NOT EXISTS uploaded_by UUID REFERENCES users
+                ON DELETE SET NULL
+                ON UPDATE CASCADE;
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS sha256 VARCHAR(64);
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS sha256 VARCHAR(64);
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS sha256 VARCHAR(64);
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS size INT;
+    ",
+        "
+        CREATE INDEX IF NOT EXISTS file_chunks_sha256_idx
+            ON file_chunks (sha256);
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS checked_at  TIMESTAMP,
+            ADD COLUMN IF NOT EXISTS is_healthy  bool,
+            ADD COLUMN IF NOT EXISTS check_error VARCHAR;
+    ",
+        "
+        CREATE INDEX IF NOT EXISTS file_chunks_checked_at_idx
+            ON file_chunks (checked_at NULLS FIRST);
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS storage_replicas (
+            id         UUID      PRIMARY KEY,
+            storage_id UUID      NOT NULL REFERENCES storages
+                                        ON DELETE CASCADE
+                                        ON UPDATE CASCADE,
+            chat_id    BigInt    NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+            UNIQUE (storage_id, chat_id)
+        );
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS chunk_copies (
+            id                      UUID         PRIMARY KEY,
+            replica_id              UUID         NOT NULL REFERENCES storage_replicas
+                                                        ON DELETE CASCADE
+                                                        ON UPDATE CASCADE,
+            source_telegram_file_id VARCHAR(255) NOT NULL,
+            telegram_file_id        VARCHAR(255) NOT NULL,
+            storage_worker_id       UUID         REFERENCES storage_workers
+                                                        ON DELETE CASCADE
+                                                        ON UPDATE CASCADE,
+            message_id              BigInt,
+            chat_id                 BigInt,
+
+            UNIQUE (replica_id, source_telegram_file_id)
+        );
+    ",
+        "
+        CREATE INDEX IF NOT EXISTS chunk_copies_source_telegram_file_id_idx
+            ON chunk_copies (source_telegram_file_id);
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS erasure_data_chunks   SMALLINT NOT NULL DEFAULT 4,
+            ADD COLUMN IF NOT EXISTS erasure_parity_chunks SMALLINT NOT NULL DEFAULT 0;
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS parity_index      SMALLINT,
+            ADD COLUMN IF NOT EXISTS parity_group_size SMALLINT;
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS encryption bool NOT NULL DEFAULT false;
+    ",
+        "
+        ALTER TABLE file_chunks
+            ADD COLUMN IF NOT EXISTS data_key BYTEA;
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS client_encrypted bool NOT NULL DEFAULT false;
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS encrypted_key   TEXT,
+            ADD COLUMN IF NOT EXISTS client_metadata TEXT;
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS encrypted_key   TEXT,
+            ADD COLUMN IF NOT EXISTS client_metadata TEXT;
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS compression bool NOT NULL DEFAULT false;
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS codec TEXT;
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS codec TEXT;
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS access_keys (
+            id         TEXT      PRIMARY KEY,
+            user_id    UUID      NOT NULL REFERENCES users
+                                    ON DELETE CASCADE
+                                    ON UPDATE CASCADE,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        );
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS upload_parts (
+            file_id     UUID      NOT NULL REFERENCES files
+                                    ON DELETE CASCADE
+                                    ON UPDATE CASCADE,
+            part_number INT       NOT NULL,
+            etag        TEXT      NOT NULL,
+            size        BigInt    NOT NULL,
+            chunk_ids   UUID[]    NOT NULL,
+            uploaded_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+            PRIMARY KEY (file_id, part_number)
+        );
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS ssh_keys (
+            id          UUID         PRIMARY KEY,
+            user_id     UUID         NOT NULL REFERENCES users
+                                        ON DELETE CASCADE
+                                        ON UPDATE CASCADE,
+            name        VARCHAR(255) NOT NULL,
+            public_key  TEXT         NOT NULL,
+            fingerprint TEXT         NOT NULL UNIQUE,
+            created_at  TIMESTAMP    NOT NULL DEFAULT NOW()
+        );
+    ",
+        "
+        DO
+        $$
+        BEGIN
+        IF NOT EXISTS (
+            SELECT *
+            FROM pg_type typ
+            INNER JOIN pg_namespace nsp ON nsp.oid = typ.typnamespace
+            WHERE nsp.nspname = current_schema() AND typ.typname = 'change_kind'
+        ) THEN
+            CREATE TYPE change_kind AS ENUM ('create', 'update', 'move', 'delete');
+        END IF;
+        END;
+        $$;
+    ",
+        "
+        ALTER TABLE storages
+            ADD COLUMN IF NOT EXISTS last_change BigInt NOT NULL DEFAULT 0;
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS changes (
+            storage_id UUID        NOT NULL REFERENCES storages
+                                        ON DELETE CASCADE
+                                        ON UPDATE CASCADE,
+            seq        BigInt      NOT NULL,
+            kind       change_kind NOT NULL,
+            path       VARCHAR     NOT NULL,
+            old_path   VARCHAR,
+            is_folder  bool        NOT NULL,
+            sha256     VARCHAR,
+            version    INT,
+            changed_at TIMESTAMP   NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (storage_id, seq)
+        );
+    ",
+        "
+        CREATE INDEX IF NOT EXISTS changes_changed_at_idx ON changes (changed_at);
+    ",
+        "
+        ALTER TABLE files
+            ADD COLUMN IF NOT EXISTS hash_state BYTEA;
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS hash_state BYTEA;
+    ",
+        "
+        ALTER TABLE file_versions
+            ADD COLUMN IF NOT EXISTS if_match TEXT;
+    ",
+        "
+        ALTER TABLE storage_workers
+            DROP CONSTRAINT IF EXISTS storage_workers_token_key;
+    ",
+        "
+        CREATE UNIQUE INDEX IF NOT EXISTS storage_workers_token_storage_idx
+            ON storage_workers (token, COALESCE(storage_id, '00000000-0000-0000-0000-000000000000'));
+    ",
+        "
+        CREATE TABLE IF NOT EXISTS app_passwords (
+            id            UUID         PRIMARY KEY,
+            user_id       UUID         NOT NULL REFERENCES users
+                                          ON DELETE CASCADE
+                                          ON UPDATE CASCADE,
+            name          VARCHAR(255) NOT NULL,
+            password_hash TEXT         NOT NULL UNIQUE,
+            created_at    TIMESTAMP    NOT NULL DEFAULT NOW()
+        );
     ",
         r#"
         CREATE OR REPLACE FUNCTION public.regexp_quote(IN TEXT)
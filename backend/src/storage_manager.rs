@@ -43,6 +43,7 @@ impl StorageManager {
         let result = StorageManagerService::new(
             &self.db,
             &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
             self.config.telegram_rate_limit,
         )
         .upload(data)
@@ -55,6 +56,7 @@ impl StorageManager {
         let result = StorageManagerService::new(
             &self.db,
             &self.config.telegram_api_base_url,
+            &self.config.encryption_secret,
             self.config.telegram_rate_limit,
         )
         .download(data)
@@ -348,11 +348,16 @@ const getFSLayer = async (storage_id, path) => {
  * @param {string} path
  * @returns {Promise<Blob>}
  */
-const streamDownload = async (url, authToken, onProgress) => {
+const streamDownload = async (url, authToken, onProgress, body) => {
 	const headers = new Headers()
 	if (authToken) headers.append('Authorization', authToken)
+	if (body) headers.append('Content-Type', 'application/json')
 
-	const response = await fetch(url, { method: 'get', headers })
+	const response = await fetch(url, {
+		method: body ? 'post' : 'get',
+		headers,
+		body: body ? JSON.stringify(body) : undefined,
+	})
 
 	if (response.status === 401) {
 		handleUnauthorized()
@@ -413,6 +418,19 @@ const downloadFolder = async (storage_id, path, onProgress) => {
 	return await streamDownload(url, getAuthToken(), onProgress)
 }
 
+/**
+ *
+ * @param {string} storage_id
+ * @param {string[]} paths
+ * @param {'zip' | 'tar' | 'tar.gz' | 'tar.zst'} [format]
+ * @returns {Promise<Blob>}
+ */
+const downloadArchive = async (storage_id, paths, format = 'zip', onProgress) => {
+	const apiBase = import.meta.env.VITE_API_BASE || '/api'
+	const url = `${apiBase}/storages/${storage_id}/files/download_archive`
+	return await streamDownload(url, getAuthToken(), onProgress, { paths, format })
+}
+
 /**
  *
  * @param {string} storage_id
@@ -595,6 +613,7 @@ const API = {
 		getFSLayer,
 		download,
 		downloadFolder,
+		downloadArchive,
 		deleteFile,
 		createShare,
 		getShareByPath,